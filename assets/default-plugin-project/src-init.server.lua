@@ -0,0 +1,6 @@
+local toolbar = plugin:CreateToolbar("{project_name}")
+local button = toolbar:CreateButton("Hello", "Say hello from {project_name}", "")
+
+button.Click:Connect(function()
+	print("Hello world, from {project_name}!")
+end)
\ No newline at end of file
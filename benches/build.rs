@@ -9,7 +9,15 @@ pub fn benchmark_small_place(c: &mut Criterion) {
     bench_build_place(c, "Small Place", "test-projects/benchmark_small_place")
 }
 
-criterion_group!(benches, benchmark_small_place);
+pub fn benchmark_property_heavy(c: &mut Criterion) {
+    bench_build_place(
+        c,
+        "Property Heavy",
+        "test-projects/benchmark_property_heavy",
+    )
+}
+
+criterion_group!(benches, benchmark_small_place, benchmark_property_heavy);
 criterion_main!(benches);
 
 fn bench_build_place(c: &mut Criterion, name: &str, path: &str) {
@@ -37,6 +45,21 @@ fn place_setup<P: AsRef<Path>>(input_path: P) -> (TempDir, BuildCommand) {
         project: input,
         watch: false,
         output,
+        shared_string_threshold: None,
+        profile_out: None,
+        format_version: None,
+        max_output_size: None,
+        strict: false,
+        stamp_mtime: false,
+        fixed_mtime: None,
+        resolve_content: None,
+        manifest_out: None,
+        since: None,
+        changed_out: None,
+        respect_archivable: false,
+        strip_defaults: false,
+        stdout: false,
+        read_retries: None,
     };
 
     (dir, options)
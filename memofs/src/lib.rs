@@ -25,6 +25,8 @@ mod std_backend;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
 
 pub use in_memory_fs::InMemoryFs;
 pub use noop_backend::NoopBackend;
@@ -141,6 +143,8 @@ pub enum VfsEvent {
 struct VfsInner {
     backend: Box<dyn VfsBackend>,
     watch_enabled: bool,
+    read_retries: u32,
+    read_retry_backoff: Duration,
 }
 
 impl VfsInner {
@@ -205,13 +209,77 @@ impl VfsInner {
     }
 }
 
+/// Tells whether an error might go away if the operation is tried again, like
+/// a file being temporarily locked, as opposed to the file not existing.
+fn is_transient(err: &io::Error) -> bool {
+    let transient_kind = matches!(
+        err.kind(),
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    );
+
+    // Files that are busy or locked by another program don't have an
+    // ErrorKind of their own, so they're picked out by their OS error code.
+    transient_kind || err.raw_os_error().map_or(false, is_transient_os_error)
+}
+
+#[cfg(unix)]
+fn is_transient_os_error(code: i32) -> bool {
+    const EBUSY: i32 = 16;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    const EAGAIN: i32 = 11;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    const EAGAIN: i32 = 35;
+
+    code == EBUSY || code == EAGAIN
+}
+
+#[cfg(windows)]
+fn is_transient_os_error(code: i32) -> bool {
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+
+    code == ERROR_SHARING_VIOLATION || code == ERROR_LOCK_VIOLATION
+}
+
+#[cfg(not(any(unix, windows)))]
+fn is_transient_os_error(_code: i32) -> bool {
+    false
+}
+
+/// Calls `attempt` until it succeeds, fails with an error that isn't
+/// transient, or has been retried `retries` times, waiting between tries.
+fn retry_transient<T>(
+    retries: u32,
+    backoff: Duration,
+    mut attempt: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut retries_left = retries;
+    let mut backoff = backoff;
+
+    loop {
+        match attempt() {
+            Err(err) if retries_left > 0 && is_transient(&err) => {
+                retries_left -= 1;
+                thread::sleep(backoff);
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
 /// A virtual filesystem with a configurable backend.
 ///
 /// All operations on the Vfs take a lock on an internal backend. For performing
 /// large batches of operations, it might be more performant to call `lock()`
 /// and use [`VfsLock`](struct.VfsLock.html) instead.
+///
+/// Cloning a Vfs is cheap and creates another handle to the same backend and
+/// settings, which is useful for handing it to other threads.
+#[derive(Clone)]
 pub struct Vfs {
-    inner: Mutex<VfsInner>,
+    inner: Arc<Mutex<VfsInner>>,
 }
 
 impl Vfs {
@@ -225,10 +293,12 @@ impl Vfs {
         let lock = VfsInner {
             backend: Box::new(backend),
             watch_enabled: true,
+            read_retries: 0,
+            read_retry_backoff: Duration::from_millis(0),
         };
 
         Self {
-            inner: Mutex::new(lock),
+            inner: Arc::new(Mutex::new(lock)),
         }
     }
 
@@ -249,6 +319,21 @@ impl Vfs {
         inner.watch_enabled = enabled;
     }
 
+    /// Retries reads that fail with errors that might be temporary, like
+    /// timeouts from network filesystems or files locked by another program,
+    /// up to `retries` times. The first retry waits for `backoff`, and each one
+    /// after that waits twice as long as the last. Other errors, like the file
+    /// not existing, are never retried.
+    ///
+    /// Reads aren't retried by default.
+    ///
+    /// The VFS isn't locked while waiting to retry a read.
+    pub fn set_read_retries(&self, retries: u32, backoff: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.read_retries = retries;
+        inner.read_retry_backoff = backoff;
+    }
+
     /// Read a file from the VFS, or the underlying backend if it isn't
     /// resident.
     ///
@@ -258,7 +343,12 @@ impl Vfs {
     #[inline]
     pub fn read<P: AsRef<Path>>(&self, path: P) -> io::Result<Arc<Vec<u8>>> {
         let path = path.as_ref();
-        self.inner.lock().unwrap().read(path)
+        let (retries, backoff) = {
+            let inner = self.inner.lock().unwrap();
+            (inner.read_retries, inner.read_retry_backoff)
+        };
+
+        retry_transient(retries, backoff, || self.inner.lock().unwrap().read(path))
     }
 
     /// Write a file to the VFS and the underlying backend.
@@ -347,6 +437,21 @@ impl VfsLock<'_> {
         self.inner.watch_enabled = enabled;
     }
 
+    /// Retries reads that fail with errors that might be temporary, like
+    /// timeouts from network filesystems or files locked by another program,
+    /// up to `retries` times. The first retry waits for `backoff`, and each one
+    /// after that waits twice as long as the last. Other errors, like the file
+    /// not existing, are never retried.
+    ///
+    /// Reads aren't retried by default.
+    ///
+    /// Because the lock is held until it's dropped, other threads can't use
+    /// the VFS while a read through this lock is waiting to be retried.
+    pub fn set_read_retries(&mut self, retries: u32, backoff: Duration) {
+        self.inner.read_retries = retries;
+        self.inner.read_retry_backoff = backoff;
+    }
+
     /// Read a file from the VFS, or the underlying backend if it isn't
     /// resident.
     ///
@@ -356,7 +461,10 @@ impl VfsLock<'_> {
     #[inline]
     pub fn read<P: AsRef<Path>>(&mut self, path: P) -> io::Result<Arc<Vec<u8>>> {
         let path = path.as_ref();
-        self.inner.read(path)
+        let retries = self.inner.read_retries;
+        let backoff = self.inner.read_retry_backoff;
+
+        retry_transient(retries, backoff, || self.inner.read(path))
     }
 
     /// Write a file to the VFS and the underlying backend.
@@ -431,3 +539,132 @@ impl VfsLock<'_> {
         self.inner.commit_event(event)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A backend that fails the first few reads of any file with an error like
+    /// the ones a busy network filesystem can raise.
+    struct FlakyFs {
+        inner: InMemoryFs,
+        failure: fn() -> io::Error,
+        failures_left: usize,
+        reads: Arc<AtomicUsize>,
+    }
+
+    impl sealed::Sealed for FlakyFs {}
+
+    impl VfsBackend for FlakyFs {
+        fn read(&mut self, path: &Path) -> io::Result<Vec<u8>> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+
+            if self.failures_left > 0 {
+                self.failures_left -= 1;
+                return Err((self.failure)());
+            }
+
+            self.inner.read(path)
+        }
+
+        fn write(&mut self, path: &Path, data: &[u8]) -> io::Result<()> {
+            self.inner.write(path, data)
+        }
+
+        fn read_dir(&mut self, path: &Path) -> io::Result<ReadDir> {
+            self.inner.read_dir(path)
+        }
+
+        fn metadata(&mut self, path: &Path) -> io::Result<Metadata> {
+            self.inner.metadata(path)
+        }
+
+        fn remove_file(&mut self, path: &Path) -> io::Result<()> {
+            self.inner.remove_file(path)
+        }
+
+        fn remove_dir_all(&mut self, path: &Path) -> io::Result<()> {
+            self.inner.remove_dir_all(path)
+        }
+
+        fn event_receiver(&self) -> crossbeam_channel::Receiver<VfsEvent> {
+            self.inner.event_receiver()
+        }
+
+        fn watch(&mut self, path: &Path) -> io::Result<()> {
+            self.inner.watch(path)
+        }
+
+        fn unwatch(&mut self, path: &Path) -> io::Result<()> {
+            self.inner.unwatch(path)
+        }
+    }
+
+    fn flaky_vfs(failures: usize) -> (Vfs, Arc<AtomicUsize>) {
+        flaky_vfs_with(failures, || {
+            io::Error::new(io::ErrorKind::TimedOut, "timed out")
+        })
+    }
+
+    fn flaky_vfs_with(failures: usize, failure: fn() -> io::Error) -> (Vfs, Arc<AtomicUsize>) {
+        let mut inner = InMemoryFs::new();
+        inner
+            .load_snapshot("/foo.txt", VfsSnapshot::file("hello"))
+            .unwrap();
+
+        let reads = Arc::new(AtomicUsize::new(0));
+        let vfs = Vfs::new(FlakyFs {
+            inner,
+            failure,
+            failures_left: failures,
+            reads: Arc::clone(&reads),
+        });
+        vfs.set_watch_enabled(false);
+
+        (vfs, reads)
+    }
+
+    #[test]
+    fn transient_errors_are_retried() {
+        let (vfs, reads) = flaky_vfs(2);
+        vfs.set_read_retries(2, Duration::from_millis(1));
+
+        assert_eq!(vfs.read("/foo.txt").unwrap().as_slice(), b"hello");
+        assert_eq!(reads.load(Ordering::SeqCst), 3);
+    }
+
+    #[cfg(any(unix, windows))]
+    #[test]
+    fn locked_files_are_retried() {
+        // EBUSY on unix, and ERROR_SHARING_VIOLATION on Windows.
+        let (vfs, reads) = flaky_vfs_with(2, || {
+            io::Error::from_raw_os_error(if cfg!(windows) { 32 } else { 16 })
+        });
+        vfs.set_read_retries(2, Duration::from_millis(1));
+
+        assert_eq!(vfs.read("/foo.txt").unwrap().as_slice(), b"hello");
+        assert_eq!(reads.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn retries_run_out() {
+        let (vfs, reads) = flaky_vfs(2);
+        vfs.set_read_retries(1, Duration::from_millis(1));
+
+        let err = vfs.read("/foo.txt").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(reads.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn missing_files_are_not_retried() {
+        let (vfs, reads) = flaky_vfs(0);
+        vfs.set_read_retries(5, Duration::from_millis(1));
+
+        let err = vfs.read("/missing.txt").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+    }
+}
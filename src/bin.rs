@@ -7,10 +7,15 @@ use librojo::cli::{self, GlobalOptions, Options, Subcommand};
 
 fn run(global: GlobalOptions, subcommand: Subcommand) -> anyhow::Result<()> {
     match subcommand {
-        Subcommand::Init(init_options) => cli::init(init_options)?,
+        Subcommand::Init(init_options) => cli::init(global, init_options)?,
         Subcommand::Serve(serve_options) => cli::serve(global, serve_options)?,
         Subcommand::Build(build_options) => cli::build(build_options)?,
+        Subcommand::Check(check_options) => cli::check(check_options)?,
+        Subcommand::Stats(stats_options) => cli::stats(stats_options)?,
+        Subcommand::Metrics(metrics_options) => cli::metrics(metrics_options)?,
+        Subcommand::Bench(bench_options) => cli::bench(bench_options)?,
         Subcommand::Upload(upload_options) => cli::upload(upload_options)?,
+        Subcommand::RevertTo(revert_options) => cli::revert_to(revert_options)?,
         Subcommand::Doc => cli::doc()?,
         Subcommand::Plugin(plugin_options) => cli::plugin(plugin_options)?,
     }
@@ -64,11 +69,12 @@ fn main() {
 
     let options = Options::from_args();
 
-    let log_filter = match options.global.verbosity {
-        0 => "info",
-        1 => "info,librojo=debug",
-        2 => "info,librojo=trace",
-        _ => "trace",
+    let log_filter = match options.global.log_filter() {
+        Ok(log_filter) => log_filter,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
     };
 
     let log_env = env_logger::Env::default().default_filter_or(log_filter);
@@ -1,6 +1,10 @@
 use std::{
-    fs,
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    path::PathBuf,
     sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
 use crossbeam_channel::{select, Receiver, RecvError, Sender};
@@ -11,8 +15,11 @@ use rbx_dom_weak::{RbxId, RbxValue};
 use crate::{
     error::ErrorDisplay,
     message_queue::MessageQueue,
+    serve_session::ServeSessionOptions,
     snapshot::{
-        apply_patch_set, compute_patch_set, AppliedPatchSet, InstigatingSource, PatchSet, RojoTree,
+        apply_patch_set, apply_transforms, compute_patch_set, filter_patch_set_by_class,
+        migrate_properties, AppliedPatchSet, InstigatingSource, PatchSet, PropertyMigration,
+        RojoTree,
     },
     snapshot_middleware::{snapshot_from_vfs, snapshot_project_node},
 };
@@ -44,18 +51,35 @@ pub struct ChangeProcessor {
 impl ChangeProcessor {
     /// Spin up the ChangeProcessor, connecting it to the given tree, VFS, and
     /// outbound message queue.
+    ///
+    /// See `ServeSessionOptions` for how `options` change the way filesystem
+    /// and client changes are handled.
     pub fn start(
         tree: Arc<Mutex<RojoTree>>,
         vfs: Arc<Vfs>,
         message_queue: Arc<MessageQueue<AppliedPatchSet>>,
         tree_mutation_receiver: Receiver<PatchSet>,
+        options: ServeSessionOptions,
     ) -> Self {
+        let ServeSessionOptions {
+            sync_classes,
+            reverse_dry_run,
+            max_changes,
+            property_migration,
+        } = options;
+
         let (shutdown_sender, shutdown_receiver) = crossbeam_channel::bounded(1);
         let vfs_receiver = vfs.event_receiver();
         let task = JobThreadContext {
             tree,
             vfs,
             message_queue,
+            sync_classes,
+            reverse_dry_run,
+            max_changes,
+            property_migration,
+            write_debounce: REVERSE_WRITE_DEBOUNCE,
+            pending_writes: RefCell::new(HashMap::new()),
         };
 
         let job_thread = jod_thread::Builder::new()
@@ -64,6 +88,8 @@ impl ChangeProcessor {
                 log::trace!("ChangeProcessor thread started");
 
                 loop {
+                    let flush_timer = task.pending_write_timer();
+
                     select! {
                         recv(vfs_receiver) -> event => {
                             task.handle_vfs_event(event?);
@@ -71,8 +97,12 @@ impl ChangeProcessor {
                         recv(tree_mutation_receiver) -> patch_set => {
                             task.handle_tree_event(patch_set?);
                         },
+                        recv(flush_timer) -> _ => {
+                            task.flush_pending_writes(Some(Instant::now()));
+                        },
                         recv(shutdown_receiver) -> _ => {
                             log::trace!("ChangeProcessor shutdown signal received...");
+                            task.flush_pending_writes(None);
                             return Ok(());
                         },
                     }
@@ -109,6 +139,40 @@ struct JobThreadContext {
     /// Whenever changes are applied to the DOM, we should push those changes
     /// into this message queue to inform any connected clients.
     message_queue: Arc<MessageQueue<AppliedPatchSet>>,
+
+    /// If set, only changes to instances with these classes will be applied
+    /// when the filesystem changes.
+    sync_classes: Option<HashSet<String>>,
+
+    /// If set, changes from clients are logged as the file operations they
+    /// would cause instead of being written to the filesystem.
+    reverse_dry_run: bool,
+
+    /// If set, changes from the filesystem that would touch more instances
+    /// than this are not applied.
+    max_changes: Option<usize>,
+
+    /// If set, runs on every snapshot before it's compared to the tree.
+    property_migration: Option<PropertyMigration>,
+
+    /// The debounce period for `pending_writes`. Only tests set this to
+    /// anything other than `REVERSE_WRITE_DEBOUNCE`.
+    write_debounce: Duration,
+
+    /// Writes to files caused by changes from clients that are waiting for
+    /// `write_debounce` to pass without another change to the same file, so
+    /// that a burst of changes results in just one write.
+    pending_writes: RefCell<HashMap<PathBuf, PendingWrite>>,
+}
+
+/// How long a file must go without changes from a client before they're
+/// written to it.
+const REVERSE_WRITE_DEBOUNCE: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+struct PendingWrite {
+    contents: String,
+    deadline: Instant,
 }
 
 impl JobThreadContext {
@@ -128,30 +192,17 @@ impl JobThreadContext {
 
             match event {
                 VfsEvent::Create(path) | VfsEvent::Write(path) | VfsEvent::Remove(path) => {
-                    // Find the nearest ancestor to this path that has
-                    // associated instances in the tree. This helps make sure
-                    // that we handle additions correctly, especially if we
-                    // receive events for descendants of a large tree being
-                    // created all at once.
-                    let mut current_path = path.as_path();
-                    let affected_ids = loop {
-                        let ids = tree.get_ids_at_path(&current_path);
-
-                        log::trace!("Path {} affects IDs {:?}", current_path.display(), ids);
-
-                        if !ids.is_empty() {
-                            break ids.to_vec();
-                        }
-
-                        log::trace!("Trying parent path...");
-                        match current_path.parent() {
-                            Some(parent) => current_path = parent,
-                            None => break Vec::new(),
-                        }
-                    };
+                    let affected_ids = tree.ids_affected_by(&path);
 
                     for id in affected_ids {
-                        if let Some(patch) = compute_and_apply_changes(&mut tree, &self.vfs, id) {
+                        if let Some(patch) = compute_and_apply_changes(
+                            &mut tree,
+                            &self.vfs,
+                            id,
+                            self.sync_classes.as_ref(),
+                            self.max_changes,
+                            self.property_migration.as_ref(),
+                        ) {
                             applied_patches.push(patch);
                         }
                     }
@@ -167,17 +218,169 @@ impl JobThreadContext {
         self.message_queue.push_messages(&applied_patches);
     }
 
-    fn handle_tree_event(&self, patch_set: PatchSet) {
+    fn handle_tree_event(&self, mut patch_set: PatchSet) {
         log::trace!("Applying PatchSet from client: {:#?}", patch_set);
 
         let applied_patch = {
             let mut tree = self.tree.lock().unwrap();
 
-            for &id in &patch_set.removed_instances {
-                if let Some(instance) = tree.get_instance(id) {
+            remove_read_only_changes(&tree, &mut patch_set);
+
+            let mut pending_writes = self.pending_writes.borrow_mut();
+
+            for operation in file_operations_for_patch(&tree, &patch_set) {
+                match operation {
+                    FileOperation::Write { path, contents } => {
+                        // Later changes replace earlier ones, so only the last
+                        // state of the file is written when the burst ends.
+                        let deadline = Instant::now() + self.write_debounce;
+                        pending_writes.insert(path, PendingWrite { contents, deadline });
+                    }
+                    FileOperation::Remove { path } => {
+                        pending_writes.remove(&path);
+                        self.perform(FileOperation::Remove { path });
+                    }
+                }
+            }
+
+            apply_patch_set(&mut tree, patch_set)
+        };
+
+        self.message_queue.push_messages(&[applied_patch]);
+    }
+
+    /// Returns a channel that fires when the next pending write is due, or
+    /// never if there aren't any.
+    fn pending_write_timer(&self) -> Receiver<Instant> {
+        let pending_writes = self.pending_writes.borrow();
+
+        match pending_writes.values().map(|write| write.deadline).min() {
+            Some(deadline) => {
+                crossbeam_channel::after(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => crossbeam_channel::never(),
+        }
+    }
+
+    /// Writes out the pending writes whose debounce period is over at `now`,
+    /// or all of them if `now` is `None`. Returns how many files were written.
+    fn flush_pending_writes(&self, now: Option<Instant>) -> usize {
+        let mut pending_writes = self.pending_writes.borrow_mut();
+
+        let due: Vec<PathBuf> = pending_writes
+            .iter()
+            .filter(|(_, write)| now.map_or(true, |now| write.deadline <= now))
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        for path in &due {
+            let write = pending_writes.remove(path).unwrap();
+
+            self.perform(FileOperation::Write {
+                path: path.clone(),
+                contents: write.contents,
+            });
+        }
+
+        due.len()
+    }
+
+    fn perform(&self, operation: FileOperation) {
+        if self.reverse_dry_run {
+            log::info!("Dry run: {}", operation);
+        } else {
+            operation.execute();
+        }
+    }
+}
+
+/// A change to the filesystem that's needed to reflect a client's change to
+/// the tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FileOperation {
+    Write { path: PathBuf, contents: String },
+    Remove { path: PathBuf },
+}
+
+impl FileOperation {
+    fn execute(&self) {
+        match self {
+            FileOperation::Write { path, contents } => fs::write(path, contents).unwrap(),
+            FileOperation::Remove { path } => fs::remove_file(path).unwrap(),
+        }
+    }
+}
+
+/// Describes the operation as something that would happen, for dry runs.
+impl fmt::Display for FileOperation {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileOperation::Write { path, .. } => {
+                write!(formatter, "would write {}", path.display())
+            }
+            FileOperation::Remove { path } => write!(formatter, "would delete {}", path.display()),
+        }
+    }
+}
+
+/// Figures out what needs to happen to the filesystem for a patch set that came
+/// from a client, logging any changes that can't be represented.
+fn file_operations_for_patch(tree: &RojoTree, patch_set: &PatchSet) -> Vec<FileOperation> {
+    let mut operations = Vec::new();
+
+    for &id in &patch_set.removed_instances {
+        if let Some(instance) = tree.get_instance(id) {
+            if let Some(instigating_source) = &instance.metadata().instigating_source {
+                match instigating_source {
+                    InstigatingSource::Path(path) => {
+                        operations.push(FileOperation::Remove { path: path.clone() })
+                    }
+                    InstigatingSource::ProjectNode(_, _, _, _) => {
+                        log::warn!("Cannot remove instance {}, it's from a project file", id);
+                    }
+                }
+            } else {
+                // TODO
+                log::warn!(
+                    "Cannot remove instance {}, it is not an instigating source.",
+                    id
+                );
+            }
+        } else {
+            log::warn!("Cannot remove instance {}, it does not exist.", id);
+        }
+    }
+
+    for update in &patch_set.updated_instances {
+        let id = update.id;
+
+        if let Some(instance) = tree.get_instance(id) {
+            if update.changed_name.is_some() {
+                log::warn!("Cannot rename instances yet.");
+            }
+
+            if update.changed_class_name.is_some() {
+                log::warn!("Cannot change ClassName yet.");
+            }
+
+            if update.changed_metadata.is_some() {
+                log::warn!("Cannot change metadata yet.");
+            }
+
+            for (key, changed_value) in &update.changed_properties {
+                if key == "Source" {
                     if let Some(instigating_source) = &instance.metadata().instigating_source {
                         match instigating_source {
-                            InstigatingSource::Path(path) => fs::remove_file(path).unwrap(),
+                            InstigatingSource::Path(path) => {
+                                if let Some(RbxValue::String { value }) = changed_value {
+                                    operations.push(FileOperation::Write {
+                                        path: path.clone(),
+                                        contents: value.clone(),
+                                    });
+                                } else {
+                                    log::warn!("Cannot change Source to non-string value.");
+                                }
+                            }
                             InstigatingSource::ProjectNode(_, _, _, _) => {
                                 log::warn!(
                                     "Cannot remove instance {}, it's from a project file",
@@ -186,76 +389,82 @@ impl JobThreadContext {
                             }
                         }
                     } else {
-                        // TODO
                         log::warn!(
-                            "Cannot remove instance {}, it is not an instigating source.",
+                            "Cannot update instance {}, it is not an instigating source.",
                             id
                         );
                     }
                 } else {
-                    log::warn!("Cannot remove instance {}, it does not exist.", id);
+                    log::warn!("Cannot change properties besides BaseScript.Source.");
                 }
             }
+        } else {
+            log::warn!("Cannot update instance {}, it does not exist.", id);
+        }
+    }
 
-            for update in &patch_set.updated_instances {
-                let id = update.id;
-
-                if let Some(instance) = tree.get_instance(id) {
-                    if update.changed_name.is_some() {
-                        log::warn!("Cannot rename instances yet.");
-                    }
-
-                    if update.changed_class_name.is_some() {
-                        log::warn!("Cannot change ClassName yet.");
-                    }
+    operations
+}
 
-                    if update.changed_metadata.is_some() {
-                        log::warn!("Cannot change metadata yet.");
-                    }
+/// Returns whether the instance with the given ID, or any of its ancestors, was
+/// marked as read-only.
+fn is_read_only(tree: &RojoTree, id: RbxId) -> bool {
+    let mut current_id = Some(id);
 
-                    for (key, changed_value) in &update.changed_properties {
-                        if key == "Source" {
-                            if let Some(instigating_source) =
-                                &instance.metadata().instigating_source
-                            {
-                                match instigating_source {
-                                    InstigatingSource::Path(path) => {
-                                        if let Some(RbxValue::String { value }) = changed_value {
-                                            fs::write(path, value).unwrap();
-                                        } else {
-                                            log::warn!("Cannot change Source to non-string value.");
-                                        }
-                                    }
-                                    InstigatingSource::ProjectNode(_, _, _, _) => {
-                                        log::warn!(
-                                            "Cannot remove instance {}, it's from a project file",
-                                            id
-                                        );
-                                    }
-                                }
-                            } else {
-                                log::warn!(
-                                    "Cannot update instance {}, it is not an instigating source.",
-                                    id
-                                );
-                            }
-                        } else {
-                            log::warn!("Cannot change properties besides BaseScript.Source.");
-                        }
-                    }
-                } else {
-                    log::warn!("Cannot update instance {}, it does not exist.", id);
+    while let Some(id) = current_id {
+        match tree.get_instance(id) {
+            Some(instance) => {
+                if instance.metadata().read_only {
+                    return true;
                 }
+
+                current_id = instance.parent();
             }
+            None => return false,
+        }
+    }
 
-            apply_patch_set(&mut tree, patch_set)
-        };
+    false
+}
 
-        self.message_queue.push_messages(&[applied_patch]);
+/// Removes all changes to read-only instances from a patch set that came from a
+/// client, returning the IDs of the instances whose changes were rejected.
+fn remove_read_only_changes(tree: &RojoTree, patch_set: &mut PatchSet) -> Vec<RbxId> {
+    let mut skipped = Vec::new();
+
+    patch_set.removed_instances.retain(|&id| {
+        if is_read_only(tree, id) {
+            skipped.push(id);
+            false
+        } else {
+            true
+        }
+    });
+
+    patch_set.updated_instances.retain(|update| {
+        if is_read_only(tree, update.id) {
+            skipped.push(update.id);
+            false
+        } else {
+            true
+        }
+    });
+
+    for id in &skipped {
+        log::warn!("Ignoring change to instance {}, it is read-only.", id);
     }
+
+    skipped
 }
 
-fn compute_and_apply_changes(tree: &mut RojoTree, vfs: &Vfs, id: RbxId) -> Option<AppliedPatchSet> {
+fn compute_and_apply_changes(
+    tree: &mut RojoTree,
+    vfs: &Vfs,
+    id: RbxId,
+    sync_classes: Option<&HashSet<String>>,
+    max_changes: Option<usize>,
+    property_migration: Option<&PropertyMigration>,
+) -> Option<AppliedPatchSet> {
     let metadata = tree
         .get_metadata(id)
         .expect("metadata missing for instance present in tree");
@@ -274,14 +483,14 @@ fn compute_and_apply_changes(tree: &mut RojoTree, vfs: &Vfs, id: RbxId) -> Optio
 
     // How we process a file change event depends on what created this
     // file/folder in the first place.
-    let applied_patch_set = match instigating_source {
+    let mut patch_set = match instigating_source {
         InstigatingSource::Path(path) => match vfs.metadata(path).with_not_found() {
             Ok(Some(_)) => {
                 // Our instance was previously created from a path and that
                 // path still exists. We can generate a snapshot starting at
                 // that path and use it as the source for our patch.
 
-                let snapshot = match snapshot_from_vfs(&metadata.context, &vfs, &path) {
+                let mut snapshot = match snapshot_from_vfs(&metadata.context, &vfs, &path) {
                     Ok(Some(snapshot)) => snapshot,
                     Ok(None) => {
                         log::error!(
@@ -297,8 +506,13 @@ fn compute_and_apply_changes(tree: &mut RojoTree, vfs: &Vfs, id: RbxId) -> Optio
                     }
                 };
 
-                let patch_set = compute_patch_set(&snapshot, &tree, id);
-                apply_patch_set(tree, patch_set)
+                apply_transforms(&mut snapshot);
+
+                if let Some(migration) = property_migration {
+                    migrate_properties(&mut snapshot, migration);
+                }
+
+                compute_patch_set(&snapshot, &tree, id)
             }
             Ok(None) => {
                 // Our instance was previously created from a path, but that
@@ -310,7 +524,7 @@ fn compute_and_apply_changes(tree: &mut RojoTree, vfs: &Vfs, id: RbxId) -> Optio
                 let mut patch_set = PatchSet::new();
                 patch_set.removed_instances.push(id);
 
-                apply_patch_set(tree, patch_set)
+                patch_set
             }
             Err(err) => {
                 log::error!("Error processing filesystem change: {}", ErrorDisplay(err));
@@ -332,7 +546,7 @@ fn compute_and_apply_changes(tree: &mut RojoTree, vfs: &Vfs, id: RbxId) -> Optio
                 parent_class.as_ref().map(|name| name.as_str()),
             );
 
-            let snapshot = match snapshot_result {
+            let mut snapshot = match snapshot_result {
                 Ok(Some(snapshot)) => snapshot,
                 Ok(None) => {
                     log::error!("Snapshot did not return an instance from a project node.");
@@ -345,10 +559,282 @@ fn compute_and_apply_changes(tree: &mut RojoTree, vfs: &Vfs, id: RbxId) -> Optio
                 }
             };
 
-            let patch_set = compute_patch_set(&snapshot, &tree, id);
-            apply_patch_set(tree, patch_set)
+            apply_transforms(&mut snapshot);
+
+            if let Some(migration) = property_migration {
+                migrate_properties(&mut snapshot, migration);
+            }
+
+            compute_patch_set(&snapshot, &tree, id)
         }
     };
 
-    Some(applied_patch_set)
+    if let Some(class_names) = sync_classes {
+        filter_patch_set_by_class(&mut patch_set, tree, class_names);
+    }
+
+    if let Some(max_changes) = max_changes {
+        let count = patch_set.changed_instance_count(tree);
+
+        if count > max_changes {
+            log::error!(
+                "Not syncing a change that would touch {} instances, which is more than --max-changes ({}).",
+                count,
+                max_changes
+            );
+            log::error!("Restart rojo serve to sync it anyway.");
+            return None;
+        }
+    }
+
+    Some(apply_patch_set(tree, patch_set))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::path::Path;
+
+    use futures::Future;
+    use maplit::hashmap;
+    use rbx_dom_weak::RbxInstanceProperties;
+    use tempfile::tempdir;
+
+    use crate::snapshot::{InstanceContext, InstancePropertiesWithMeta, PatchUpdate};
+
+    #[test]
+    fn read_only_edit_is_skipped() {
+        crate::log_capture::init();
+
+        let dir = tempdir().unwrap();
+        let script_path = dir.path().join("foo.lua");
+        fs::write(&script_path, "-- generated").unwrap();
+        fs::write(dir.path().join("foo.meta.json"), r#"{ "readOnly": true }"#).unwrap();
+
+        let vfs = Vfs::new_default();
+        vfs.set_watch_enabled(false);
+
+        let snapshot = snapshot_from_vfs(&InstanceContext::default(), &vfs, &script_path)
+            .unwrap()
+            .unwrap();
+
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "ROOT".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: Default::default(),
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+        let patch_set = compute_patch_set(&snapshot, &tree, root_id);
+        apply_patch_set(&mut tree, patch_set);
+
+        assert!(tree.get_metadata(root_id).unwrap().read_only);
+
+        let message_queue = Arc::new(MessageQueue::new());
+        let context = JobThreadContext {
+            tree: Arc::new(Mutex::new(tree)),
+            vfs: Arc::new(vfs),
+            message_queue: Arc::clone(&message_queue),
+            sync_classes: None,
+            reverse_dry_run: false,
+            max_changes: None,
+            property_migration: None,
+            write_debounce: REVERSE_WRITE_DEBOUNCE,
+            pending_writes: RefCell::new(HashMap::new()),
+        };
+
+        let edit = PatchSet {
+            updated_instances: vec![PatchUpdate {
+                id: root_id,
+                changed_name: None,
+                changed_class_name: None,
+                changed_properties: hashmap! {
+                    "Source".to_owned() => Some(RbxValue::String {
+                        value: "-- edited in Studio".to_owned(),
+                    }),
+                },
+                changed_attributes: Default::default(),
+                changed_ref_paths: Default::default(),
+                changed_metadata: None,
+            }],
+            ..Default::default()
+        };
+
+        {
+            let tree = context.tree.lock().unwrap();
+            let mut edit = edit.clone();
+            assert_eq!(remove_read_only_changes(&tree, &mut edit), vec![root_id]);
+            assert!(edit.updated_instances.is_empty());
+        }
+
+        context.handle_tree_event(edit);
+
+        assert_eq!(fs::read_to_string(&script_path).unwrap(), "-- generated");
+
+        let (_cursor, messages) = message_queue.subscribe(0).unwrap().wait().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].updated.is_empty());
+    }
+
+    /// Creates a context for a tree made from the script at `script_path`,
+    /// returning it along with the ID of the script's instance.
+    fn script_context(script_path: &Path, reverse_dry_run: bool) -> (JobThreadContext, RbxId) {
+        let vfs = Vfs::new_default();
+        vfs.set_watch_enabled(false);
+
+        let snapshot = snapshot_from_vfs(&InstanceContext::default(), &vfs, script_path)
+            .unwrap()
+            .unwrap();
+
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "ROOT".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: Default::default(),
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+        let patch_set = compute_patch_set(&snapshot, &tree, root_id);
+        apply_patch_set(&mut tree, patch_set);
+
+        let context = JobThreadContext {
+            tree: Arc::new(Mutex::new(tree)),
+            vfs: Arc::new(vfs),
+            message_queue: Arc::new(MessageQueue::new()),
+            sync_classes: None,
+            reverse_dry_run,
+            max_changes: None,
+            property_migration: None,
+            write_debounce: REVERSE_WRITE_DEBOUNCE,
+            pending_writes: RefCell::new(HashMap::new()),
+        };
+
+        (context, root_id)
+    }
+
+    fn source_edit(id: RbxId, source: &str) -> PatchSet {
+        PatchSet {
+            updated_instances: vec![PatchUpdate {
+                id,
+                changed_name: None,
+                changed_class_name: None,
+                changed_properties: hashmap! {
+                    "Source".to_owned() => Some(RbxValue::String {
+                        value: source.to_owned(),
+                    }),
+                },
+                changed_attributes: Default::default(),
+                changed_ref_paths: Default::default(),
+                changed_metadata: None,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reverse_dry_run_does_not_write() {
+        crate::log_capture::init();
+
+        let dir = tempdir().unwrap();
+        let script_path = dir.path().join("foo.lua");
+        fs::write(&script_path, "-- from disk").unwrap();
+
+        let (context, id) = script_context(&script_path, true);
+        let edit = source_edit(id, "-- edited in Studio");
+
+        {
+            let tree = context.tree.lock().unwrap();
+            let operations = file_operations_for_patch(&tree, &edit);
+
+            assert_eq!(operations.len(), 1);
+            assert_eq!(
+                operations[0].to_string(),
+                format!("would write {}", script_path.display())
+            );
+        }
+
+        context.handle_tree_event(edit);
+        context.flush_pending_writes(None);
+
+        assert_eq!(fs::read_to_string(&script_path).unwrap(), "-- from disk");
+    }
+
+    #[test]
+    fn rapid_edits_are_written_once() {
+        crate::log_capture::init();
+
+        let dir = tempdir().unwrap();
+        let script_path = dir.path().join("foo.lua");
+        fs::write(&script_path, "-- from disk").unwrap();
+
+        // A debounce period this long can't pass while the test runs, so the
+        // test decides when it's over by passing times to flush_pending_writes.
+        let (mut context, id) = script_context(&script_path, false);
+        context.write_debounce = Duration::from_secs(60 * 60);
+
+        context.handle_tree_event(source_edit(id, "-- edit 1"));
+        context.handle_tree_event(source_edit(id, "-- edit 2"));
+        context.handle_tree_event(source_edit(id, "-- edit 3"));
+
+        // Nothing is due yet, since the last edit just happened.
+        assert_eq!(context.flush_pending_writes(Some(Instant::now())), 0);
+        assert_eq!(fs::read_to_string(&script_path).unwrap(), "-- from disk");
+
+        let after_debounce = Instant::now() + context.write_debounce;
+        assert_eq!(context.flush_pending_writes(Some(after_debounce)), 1);
+        assert_eq!(fs::read_to_string(&script_path).unwrap(), "-- edit 3");
+
+        assert_eq!(context.flush_pending_writes(None), 0);
+    }
+
+    #[test]
+    fn changes_over_budget_are_not_applied() {
+        crate::log_capture::init();
+
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir(&src).unwrap();
+        for name in &["a.lua", "b.lua", "c.lua"] {
+            fs::write(src.join(name), "return nil").unwrap();
+        }
+
+        let vfs = Vfs::new_default();
+        vfs.set_watch_enabled(false);
+
+        let snapshot = snapshot_from_vfs(&InstanceContext::default(), &vfs, &src)
+            .unwrap()
+            .unwrap();
+
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "ROOT".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: Default::default(),
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+        let patch_set = compute_patch_set(&snapshot, &tree, root_id);
+        apply_patch_set(&mut tree, patch_set);
+
+        fs::remove_file(src.join("a.lua")).unwrap();
+        fs::remove_file(src.join("b.lua")).unwrap();
+        vfs.commit_event(&VfsEvent::Remove(src.join("a.lua")))
+            .unwrap();
+        vfs.commit_event(&VfsEvent::Remove(src.join("b.lua")))
+            .unwrap();
+
+        // Removing two scripts is over a budget of one, so nothing changes.
+        assert!(compute_and_apply_changes(&mut tree, &vfs, root_id, None, Some(1), None).is_none());
+        assert_eq!(tree.get_instance(root_id).unwrap().children().len(), 3);
+
+        let applied =
+            compute_and_apply_changes(&mut tree, &vfs, root_id, None, Some(2), None).unwrap();
+        assert_eq!(applied.removed.len(), 2);
+        assert_eq!(tree.get_instance(root_id).unwrap().children().len(), 1);
+    }
 }
@@ -0,0 +1,189 @@
+use std::{
+    fmt,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use memofs::Vfs;
+use rbx_dom_weak::RbxInstanceProperties;
+use thiserror::Error;
+
+use crate::{
+    cli::BenchCommand,
+    snapshot::{
+        apply_patch_set, apply_transforms, compute_patch_set, InstanceContext,
+        InstancePropertiesWithMeta, RojoTree,
+    },
+    snapshot_middleware::{snapshot_from_vfs, SnapshotError},
+};
+
+#[derive(Debug, Error)]
+enum Error {
+    #[error("At least one iteration is required to benchmark a project")]
+    NoIterations,
+}
+
+pub fn bench(options: BenchCommand) -> Result<(), anyhow::Error> {
+    if options.iterations == 0 {
+        return Err(Error::NoIterations.into());
+    }
+
+    let vfs = Vfs::new_default();
+    vfs.set_watch_enabled(false);
+
+    let results = run_benchmark(&vfs, &options.absolute_project(), options.iterations)?;
+    print!("{}", results);
+
+    Ok(())
+}
+
+/// The timings of every iteration of a benchmark, along with how many
+/// instances each iteration produced.
+#[derive(Debug, Clone)]
+pub struct BenchResults {
+    timings: Vec<Duration>,
+    instance_count: usize,
+}
+
+impl BenchResults {
+    pub fn min(&self) -> Duration {
+        self.sorted_timings()[0]
+    }
+
+    pub fn median(&self) -> Duration {
+        let timings = self.sorted_timings();
+        timings[timings.len() / 2]
+    }
+
+    pub fn max(&self) -> Duration {
+        *self.sorted_timings().last().unwrap()
+    }
+
+    /// The number of instances processed per second, based on the median
+    /// iteration.
+    pub fn throughput(&self) -> f64 {
+        self.instance_count as f64 / self.median().as_secs_f64()
+    }
+
+    fn sorted_timings(&self) -> Vec<Duration> {
+        let mut timings = self.timings.clone();
+        timings.sort();
+        timings
+    }
+}
+
+impl fmt::Display for BenchResults {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(formatter, "Iterations: {}", self.timings.len())?;
+        writeln!(formatter, "Instances:  {}", self.instance_count)?;
+        writeln!(formatter)?;
+        writeln!(formatter, "{:<8} {:>12}", "", "Time")?;
+        writeln!(formatter, "{:<8} {:>10.3}ms", "min", millis(self.min()))?;
+        writeln!(
+            formatter,
+            "{:<8} {:>10.3}ms",
+            "median",
+            millis(self.median())
+        )?;
+        writeln!(formatter, "{:<8} {:>10.3}ms", "max", millis(self.max()))?;
+        writeln!(formatter)?;
+        writeln!(
+            formatter,
+            "Throughput: {:.0} instances/sec",
+            self.throughput()
+        )
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Snapshots the project at the given path and patches an empty tree to match
+/// it, the same way `rojo serve` starts, timing each iteration.
+///
+/// Files are cached by the VFS after the first iteration, so later iterations
+/// mostly measure snapshotting and patching instead of reading files.
+pub fn run_benchmark(
+    vfs: &Vfs,
+    project_path: &Path,
+    iterations: usize,
+) -> Result<BenchResults, SnapshotError> {
+    let mut timings = Vec::with_capacity(iterations);
+    let mut instance_count = 0;
+
+    for _ in 0..iterations {
+        let start = Instant::now();
+
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "ROOT".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: Default::default(),
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+
+        let mut snapshot = snapshot_from_vfs(&InstanceContext::default(), vfs, project_path)?
+            .expect("snapshot did not return an instance");
+        apply_transforms(&mut snapshot);
+
+        let patch_set = compute_patch_set(&snapshot, &tree, root_id);
+        apply_patch_set(&mut tree, patch_set);
+
+        timings.push(start.elapsed());
+        instance_count = tree.descendants(root_id).count() + 1;
+    }
+
+    Ok(BenchResults {
+        timings,
+        instance_count,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use maplit::hashmap;
+    use memofs::{InMemoryFs, VfsSnapshot};
+
+    #[test]
+    fn small_project() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "bench",
+                        "tree": {
+                            "$path": "src"
+                        }
+                    }
+                "#),
+                "src" => VfsSnapshot::dir(hashmap! {
+                    "a.lua" => VfsSnapshot::file("return 1"),
+                    "b.lua" => VfsSnapshot::file("return 2"),
+                }),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        vfs.set_watch_enabled(false);
+
+        let results = run_benchmark(&vfs, Path::new("/foo"), 3).unwrap();
+
+        assert_eq!(results.timings.len(), 3);
+        assert_eq!(results.instance_count, 3);
+        assert!(results.min() <= results.median());
+        assert!(results.median() <= results.max());
+
+        let output = results.to_string();
+        assert!(output.contains("Iterations: 3"));
+        assert!(output.contains("median"));
+        assert!(output.contains("instances/sec"));
+    }
+}
@@ -1,24 +1,58 @@
 use std::{
-    fs::File,
-    io::{BufWriter, Write},
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    iter,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
 };
 
 use memofs::Vfs;
+use rbx_dom_weak::{RbxId, RbxTree, RbxValue, RbxValueType, SharedString};
+use rbx_reflection::RbxPropertyTypeDescriptor;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::runtime::Runtime;
 
-use crate::{cli::BuildCommand, serve_session::ServeSession, snapshot::RojoTree};
+use crate::{
+    cli::{set_read_retries, BuildCommand},
+    json,
+    project::{PathOverlap, RootKind},
+    reflection_cache,
+    serve_session::ServeSession,
+    snapshot::{
+        apply_attribute_changes, strip_default_properties, AttributeValue, InstancePath,
+        InstigatingSource, RojoTree, ATTRIBUTES_PROPERTY,
+    },
+    trace,
+};
+
+/// The attribute that `--stamp-mtime` sets on instances made from files.
+const MTIME_ATTRIBUTE: &str = "__mtime";
+
+/// The Content properties of mesh classes that can refer to local files that
+/// `--resolve-content` rewrites to uploaded assets.
+const MESH_CONTENT_PROPERTIES: &[(&str, &[&str])] = &[
+    ("MeshPart", &["MeshId", "TextureID"]),
+    ("SpecialMesh", &["MeshId", "TextureId"]),
+];
 
+/// Places larger than this are slow to open, and usually contain files that
+/// were added to the project by accident.
+const DEFAULT_MAX_OUTPUT_SIZE: u64 = 100 * 1024 * 1024;
+
+/// The kinds of file that Rojo can build.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum OutputKind {
+pub enum OutputKind {
     Rbxmx,
     Rbxlx,
     Rbxm,
     Rbxl,
 }
 
-fn detect_output_kind(options: &BuildCommand) -> Option<OutputKind> {
-    let extension = options.output.extension()?.to_str()?;
+fn detect_output_kind(output: &Path) -> Option<OutputKind> {
+    let extension = output.extension()?.to_str()?;
 
     match extension {
         "rbxlx" => Some(OutputKind::Rbxlx),
@@ -29,10 +63,159 @@ fn detect_output_kind(options: &BuildCommand) -> Option<OutputKind> {
     }
 }
 
+impl OutputKind {
+    /// Whether this kind of output is a place, as opposed to a model.
+    fn is_place(self) -> bool {
+        match self {
+            OutputKind::Rbxlx | OutputKind::Rbxl => true,
+            OutputKind::Rbxmx | OutputKind::Rbxm => false,
+        }
+    }
+
+    /// The versions of the file format that Rojo's serializers can write for
+    /// this kind of output. rbx_xml and rbx_binary each only write the newest
+    /// version of their format.
+    fn supported_format_versions(self) -> &'static [u16] {
+        match self {
+            OutputKind::Rbxmx | OutputKind::Rbxlx => &[4],
+            OutputKind::Rbxm | OutputKind::Rbxl => &[0],
+        }
+    }
+}
+
+/// A destination for built files. The CLI writes to a file or to stdout, but
+/// programs that use Rojo as a library can provide their own.
+pub trait OutputSink {
+    /// Receives the complete contents of the built file. This is called again
+    /// every time the project is rebuilt.
+    fn write(&mut self, kind: OutputKind, contents: &[u8]) -> io::Result<()>;
+}
+
+/// Writes built files to a path, replacing anything that's already there.
+#[derive(Debug)]
+pub struct FileSink {
+    path: PathBuf,
+}
+
+impl FileSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileSink { path: path.into() }
+    }
+}
+
+impl OutputSink for FileSink {
+    fn write(&mut self, _kind: OutputKind, contents: &[u8]) -> io::Result<()> {
+        log::trace!("Opening output file for write");
+        let mut file = BufWriter::new(File::create(&self.path)?);
+        file.write_all(contents)?;
+        file.flush()?;
+
+        let filename = self
+            .path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("<invalid utf-8>");
+        log::info!("Built project to {}", filename);
+
+        Ok(())
+    }
+}
+
+/// Writes built files to stdout.
+#[derive(Debug)]
+pub struct StdoutSink;
+
+impl OutputSink for StdoutSink {
+    fn write(&mut self, _kind: OutputKind, contents: &[u8]) -> io::Result<()> {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        stdout.write_all(contents)?;
+        stdout.flush()
+    }
+}
+
 #[derive(Debug, Error)]
 enum Error {
     #[error("Could not detect what kind of file to build. Expected output file to end in .rbxl, .rbxlx, .rbxm, or .rbxmx.")]
     UnknownOutputKind,
+
+    #[error("This project's rootKind is {root_kind}, so it can only be built to {root_kind} files, not {kind:?} files")]
+    WrongOutputKind {
+        root_kind: RootKind,
+        kind: OutputKind,
+    },
+
+    #[error("Format version {version} can't be used for {kind:?} files. Supported versions: {supported:?}")]
+    UnsupportedFormatVersion {
+        version: u16,
+        kind: OutputKind,
+        supported: &'static [u16],
+    },
+
+    #[error(
+        "{} is {size} bytes, which is larger than the limit of {max_size} bytes. \
+         Large files are usually caused by binary assets that were added to the project by accident. \
+         The limit can be changed with --max-output-size.",
+        output.display()
+    )]
+    OutputTooLarge {
+        output: PathBuf,
+        size: u64,
+        max_size: u64,
+    },
+
+    #[error(
+        "{} and {} have overlapping $path values ({} and {}). \
+         If this is intentional, set allowPathAliasing to true in the project.",
+        overlap.first_node,
+        overlap.second_node,
+        overlap.first_path.display(),
+        overlap.second_path.display()
+    )]
+    OverlappingPaths { overlap: PathOverlap },
+
+    #[error("Could not read content manifest {}: {source}", path.display())]
+    InvalidContentManifest {
+        path: PathBuf,
+        source: anyhow::Error,
+    },
+
+    #[error("Could not read instance manifest {}: {source}", path.display())]
+    InvalidInstanceManifest {
+        path: PathBuf,
+        source: anyhow::Error,
+    },
+
+    #[error("{instance_path}.{property} is set to {value:?}, which is not a valid asset URL")]
+    InvalidMeshContent {
+        instance_path: String,
+        property: String,
+        value: String,
+    },
+
+    #[error(
+        "{instance_path}.{property} refers to the local file {value:?}. \
+         Pass a manifest of uploaded assets with --resolve-content to replace it with an asset ID."
+    )]
+    UnresolvedMeshContent {
+        instance_path: String,
+        property: String,
+        value: String,
+    },
+
+    #[error("{instance_path}.{property} refers to {}, which does not exist", file.display())]
+    MissingLocalMesh {
+        instance_path: String,
+        property: String,
+        file: PathBuf,
+    },
+
+    #[error("{instance_path}.{property} refers to {}, which is not in the content manifest", file.display())]
+    NotInContentManifest {
+        instance_path: String,
+        property: String,
+        file: PathBuf,
+    },
 }
 
 fn xml_encode_config() -> rbx_xml::EncodeOptions {
@@ -42,82 +225,1578 @@ fn xml_encode_config() -> rbx_xml::EncodeOptions {
 pub fn build(options: BuildCommand) -> Result<(), anyhow::Error> {
     log::trace!("Constructing in-memory filesystem");
 
+    let _tracing = options.profile_out.as_ref().map(|_| trace::enable_scoped());
+
     let vfs = Vfs::new_default();
     vfs.set_watch_enabled(options.watch);
+    set_read_retries(&vfs, options.read_retries);
 
     let session = ServeSession::new(vfs, &options.absolute_project())?;
     let mut cursor = session.message_queue().cursor();
 
-    {
-        let tree = session.tree();
-        write_model(&tree, &options)?;
+    if let Some(root_kind) = session.root_kind() {
+        check_output_kind(root_kind, &options.output)?;
     }
 
+    check_path_overlaps(&session, options.strict)?;
+
+    let mut sink: Box<dyn OutputSink> = if options.stdout {
+        Box::new(StdoutSink)
+    } else {
+        Box::new(FileSink::new(&options.output))
+    };
+
+    let size = write_output(&session, &options, sink.as_mut())?;
+    check_output_size(&options, size)?;
+    write_profile(&options)?;
+
     if options.watch {
         let mut rt = Runtime::new().unwrap();
 
         loop {
-            let receiver = session.message_queue().subscribe(cursor);
-            let (new_cursor, _patch_set) = rt.block_on(receiver).unwrap();
-            cursor = new_cursor;
+            // If so many changes happened while building that the queue
+            // dropped some of them, there's already something new to build.
+            cursor = match session.message_queue().subscribe(cursor) {
+                Ok(receiver) => rt.block_on(receiver).unwrap().0,
+                Err(_) => session.message_queue().cursor(),
+            };
+
+            let size = write_output(&session, &options, sink.as_mut())?;
+            check_output_size(&options, size)?;
+            write_profile(&options)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds the project described by `options` once, handing the result to
+/// `sink` instead of writing it to `options.output`. The kind of file to build
+/// is still picked from the extension of `options.output`.
+pub fn build_to_sink(
+    options: &BuildCommand,
+    sink: &mut dyn OutputSink,
+) -> Result<(), anyhow::Error> {
+    let vfs = Vfs::new_default();
+    vfs.set_watch_enabled(false);
+    set_read_retries(&vfs, options.read_retries);
+
+    let session = ServeSession::new(vfs, options.absolute_project())?;
+
+    if let Some(root_kind) = session.root_kind() {
+        check_output_kind(root_kind, &options.output)?;
+    }
+
+    check_path_overlaps(&session, options.strict)?;
+
+    let size = write_output(&session, options, sink)?;
+    check_output_size(options, size)
+}
+
+/// Makes sure that a project of the given kind is being built to a file of the
+/// same kind.
+fn check_output_kind(root_kind: RootKind, output: &Path) -> Result<(), Error> {
+    let kind = detect_output_kind(output).ok_or(Error::UnknownOutputKind)?;
+    let is_place = root_kind == RootKind::Place;
+
+    if kind.is_place() != is_place {
+        return Err(Error::WrongOutputKind { root_kind, kind });
+    }
+
+    Ok(())
+}
+
+/// With --strict, fails the build if any project nodes have overlapping `$path`
+/// values, which are otherwise only warned about when the project is loaded.
+fn check_path_overlaps(session: &ServeSession, strict: bool) -> Result<(), Error> {
+    if !strict {
+        return Ok(());
+    }
+
+    match session.path_overlaps().into_iter().next() {
+        Some(overlap) => Err(Error::OverlappingPaths { overlap }),
+        None => Ok(()),
+    }
+}
+
+/// Writes the session's tree to the sink, stamping modification times and
+/// resolving mesh content first if the user asked for them. Returns the size
+/// of the built file.
+fn write_output(
+    session: &ServeSession,
+    options: &BuildCommand,
+    sink: &mut dyn OutputSink,
+) -> Result<u64, anyhow::Error> {
+    let tree = session.tree();
+
+    // Stripping defaults changes properties, so it's done on a copy to avoid
+    // disturbing the tree that's being served.
+    let stripped_tree;
+    let source_tree = if options.strip_defaults {
+        let mut copy = tree.clone();
+        strip_default_properties(&mut copy);
+        stripped_tree = copy;
+        &stripped_tree
+    } else {
+        &*tree
+    };
+
+    let mut output_tree = if options.stamp_mtime || options.fixed_mtime.is_some() {
+        Cow::Owned(stamp_mtimes(source_tree, options.fixed_mtime)?)
+    } else {
+        Cow::Borrowed(source_tree.inner())
+    };
+
+    if options.respect_archivable {
+        remove_unarchivable(output_tree.to_mut());
+    }
+
+    let manifest = match &options.resolve_content {
+        Some(path) => Some(read_content_manifest(path)?),
+        None => None,
+    };
+
+    let problems = resolve_mesh_content(
+        &mut output_tree,
+        session.vfs(),
+        session.project_folder(),
+        manifest.as_ref(),
+    )?;
+
+    for problem in problems {
+        if options.strict {
+            return Err(problem.into());
+        }
+
+        log::warn!("{}", problem);
+    }
+
+    let size = write_tree(
+        &output_tree,
+        &options.output,
+        sink,
+        options.shared_string_threshold,
+        options.format_version,
+    )?;
+
+    if let Some(manifest_path) = &options.manifest_out {
+        let file = BufWriter::new(File::create(manifest_path)?);
+        json::to_writer_sorted(file, &InstanceManifest::from_tree(&tree))?;
+    }
+
+    if let Some(manifest_path) = &options.since {
+        let manifest = InstanceManifest::read(manifest_path)?;
+        let changes = changed_since(&tree, &manifest);
+
+        log::info!(
+            "{} instance(s) changed and {} removed since {}",
+            changes.changed.len(),
+            changes.removed.len(),
+            manifest_path.display()
+        );
+
+        for path in &changes.changed {
+            log::info!("Changed: {}", path);
+        }
+
+        for path in &changes.removed {
+            log::info!("Removed: {}", path);
+        }
+
+        if let Some(folder) = &options.changed_out {
+            write_changed_instances(&tree, &changes.changed, folder)?;
+        }
+    }
+
+    Ok(size)
+}
+
+/// Every instance in a built project, keyed by its path in the tree, with the
+/// hex-encoded `RojoTree::instance_hash` of each. If siblings share a name,
+/// their hashes are joined with commas in the order they're in the tree.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct InstanceManifest {
+    pub instances: BTreeMap<String, String>,
+}
+
+impl InstanceManifest {
+    pub fn from_tree(tree: &RojoTree) -> Self {
+        let mut instances: BTreeMap<String, String> = BTreeMap::new();
+
+        for (path, id) in all_instance_paths(tree) {
+            let hash = format!("{:016x}", tree.instance_hash(id));
+
+            instances
+                .entry(path.to_string())
+                .and_modify(|existing| {
+                    existing.push(',');
+                    existing.push_str(&hash);
+                })
+                .or_insert(hash);
+        }
+
+        InstanceManifest { instances }
+    }
+
+    fn read(path: &Path) -> Result<Self, Error> {
+        let invalid = |source: anyhow::Error| Error::InvalidInstanceManifest {
+            path: path.to_path_buf(),
+            source,
+        };
+
+        let contents = fs::read(path).map_err(|err| invalid(err.into()))?;
+        serde_json::from_slice(&contents).map_err(|err| invalid(err.into()))
+    }
+}
+
+/// How a tree differs from an earlier manifest.
+#[derive(Debug, Default)]
+struct ManifestChanges {
+    /// Instances that are new, or whose own contents changed. Changes to an
+    /// instance's descendants don't count as changes to it.
+    changed: Vec<InstancePath>,
+
+    /// Paths in the manifest that no instance has anymore.
+    removed: Vec<String>,
+}
+
+fn changed_since(tree: &RojoTree, manifest: &InstanceManifest) -> ManifestChanges {
+    let current = InstanceManifest::from_tree(tree);
+    let mut changes = ManifestChanges::default();
+
+    for (path, _) in all_instance_paths(tree) {
+        let key = path.to_string();
+
+        if manifest.instances.get(&key) != current.instances.get(&key) {
+            changes.changed.push(path);
+        }
+    }
+
+    changes.removed = manifest
+        .instances
+        .keys()
+        .filter(|path| !current.instances.contains_key(*path))
+        .cloned()
+        .collect();
+
+    changes
+}
+
+/// Lists the path of every instance in the tree, parents before children.
+fn all_instance_paths(tree: &RojoTree) -> Vec<(InstancePath, RbxId)> {
+    let root_id = tree.get_root_id();
+
+    iter::once(root_id)
+        .chain(tree.descendants(root_id).map(|instance| instance.id()))
+        .map(|id| (tree.instance_path(id).unwrap(), id))
+        .collect()
+}
+
+/// Writes each of the given instances, along with its descendants, to its own
+/// model file in `folder`, named after its path in the tree.
+fn write_changed_instances(
+    tree: &RojoTree,
+    changed: &[InstancePath],
+    folder: &Path,
+) -> Result<(), anyhow::Error> {
+    fs::create_dir_all(folder)?;
+
+    let mut used_names = HashSet::new();
+
+    for path in changed {
+        let base_name: String = path
+            .names
+            .join(".")
+            .chars()
+            .map(|c| if "/\\:*?\"<>|".contains(c) { '_' } else { c })
+            .collect();
+
+        let mut file_name = format!("{}.rbxm", base_name);
+        let mut suffix = 2;
+        while !used_names.insert(file_name.clone()) {
+            file_name = format!("{}-{}.rbxm", base_name, suffix);
+            suffix += 1;
+        }
+
+        let mut file = BufWriter::new(File::create(folder.join(&file_name))?);
+        rbx_binary::encode(tree.inner(), &[path.id], &mut file)?;
+        file.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Copies the tree, giving every instance made from a file or folder an
+/// attribute holding when its path was last modified. If `fixed_mtime` is
+/// given, it's used for every instance instead of reading the filesystem.
+fn stamp_mtimes(tree: &RojoTree, fixed_mtime: Option<u64>) -> io::Result<RbxTree> {
+    let mut stamped = tree.inner().clone();
+    let ids: Vec<RbxId> = stamped.iter_all_ids().collect();
+
+    for id in ids {
+        let path = match tree
+            .get_metadata(id)
+            .and_then(|metadata| metadata.instigating_source.as_ref())
+        {
+            Some(InstigatingSource::Path(path)) => path,
+            _ => continue,
+        };
+
+        let mtime = match fixed_mtime {
+            Some(mtime) => mtime as f64,
+            None => fs::metadata(path)?
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs_f64())
+                .unwrap_or(0.0),
+        };
+
+        let instance = stamped.get_instance_mut(id).unwrap();
+        let changes = iter::once((
+            MTIME_ATTRIBUTE.to_owned(),
+            Some(AttributeValue::double(mtime)),
+        ))
+        .collect();
+
+        let attributes =
+            apply_attribute_changes(instance.properties.get(ATTRIBUTES_PROPERTY), changes);
+        instance
+            .properties
+            .insert(ATTRIBUTES_PROPERTY.to_owned(), attributes);
+    }
+
+    Ok(stamped)
+}
+
+/// Removes instances whose Archivable property is false, along with their
+/// descendants, the same way that Studio leaves them out when saving. Refs to
+/// removed instances from instances that are kept are cleared with a warning.
+///
+/// The root is always kept, since there'd be nothing left to build otherwise.
+fn remove_unarchivable(tree: &mut RbxTree) {
+    let root_id = tree.get_root_id();
+    let mut removed = HashSet::new();
+    let mut removed_roots = Vec::new();
+
+    // Parents are visited before their descendants, so anything inside of an
+    // instance that's being removed is marked as removed too.
+    for instance in tree.descendants(root_id) {
+        let id = instance.get_id();
+        let parent_removed = instance
+            .get_parent_id()
+            .map_or(false, |parent_id| removed.contains(&parent_id));
+
+        if parent_removed {
+            removed.insert(id);
+        } else if let Some(RbxValue::Bool { value: false }) = instance.properties.get("Archivable")
+        {
+            removed.insert(id);
+            removed_roots.push(id);
+        }
+    }
+
+    let mut dangling_refs = Vec::new();
+
+    for id in tree.iter_all_ids().filter(|id| !removed.contains(id)) {
+        let instance = tree.get_instance(id).unwrap();
+
+        for (property, value) in &instance.properties {
+            if let RbxValue::Ref {
+                value: Some(target),
+            } = value
+            {
+                if removed.contains(target) {
+                    dangling_refs.push((id, property.clone(), *target));
+                }
+            }
+        }
+    }
+
+    for (id, property, target) in dangling_refs {
+        log::warn!(
+            "{}.{} refers to {}, which isn't archivable, so it was cleared.",
+            InstancePath::in_tree(tree, id).unwrap(),
+            property,
+            InstancePath::in_tree(tree, target).unwrap()
+        );
+
+        tree.get_instance_mut(id)
+            .unwrap()
+            .properties
+            .insert(property, RbxValue::Ref { value: None });
+    }
+
+    for id in removed_roots {
+        tree.remove_instance(id);
+    }
+}
+
+/// Maps paths of local files, relative to the project folder, to the IDs of
+/// the assets they were uploaded as.
+type ContentManifest = HashMap<PathBuf, u64>;
+
+fn read_content_manifest(path: &Path) -> Result<ContentManifest, Error> {
+    let invalid = |source: anyhow::Error| Error::InvalidContentManifest {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let contents = fs::read(path).map_err(|err| invalid(err.into()))?;
+    serde_json::from_slice(&contents).map_err(|err| invalid(err.into()))
+}
+
+/// What a mesh's Content property refers to.
+#[derive(Debug, PartialEq, Eq)]
+enum MeshContent<'a> {
+    /// Nothing, or an asset URL that Roblox understands.
+    Asset,
+
+    /// A path to a file in the project.
+    Local(&'a str),
+
+    /// Something that looks like a URL, but isn't one that Roblox can load.
+    Invalid,
+}
+
+impl<'a> MeshContent<'a> {
+    fn classify(value: &'a str) -> Self {
+        if value.is_empty() {
+            return MeshContent::Asset;
+        }
+
+        let asset_id_prefix = "rbxassetid://";
+
+        if value.starts_with(asset_id_prefix) {
+            let id = &value[asset_id_prefix.len()..];
+
+            if !id.is_empty() && id.bytes().all(|byte| byte.is_ascii_digit()) {
+                return MeshContent::Asset;
+            }
+
+            return MeshContent::Invalid;
+        }
+
+        let has_location = |prefix: &str| value.starts_with(prefix) && value.len() > prefix.len();
+
+        if ["rbxasset://", "rbxthumb://", "http://", "https://"]
+            .iter()
+            .any(|prefix| has_location(prefix))
+        {
+            MeshContent::Asset
+        } else if value.contains("://") {
+            MeshContent::Invalid
+        } else {
+            MeshContent::Local(value)
+        }
+    }
+}
+
+/// Checks that the mesh and texture properties of MeshPart and SpecialMesh
+/// instances hold asset URLs. With a manifest, local paths are rewritten to
+/// the IDs that they were uploaded as, and local files that don't exist or
+/// aren't in the manifest fail the build.
+///
+/// Problems that don't fail the build on their own are returned, so that they
+/// can be reported as warnings or errors depending on `--strict`. The tree is
+/// only copied if something needs to be rewritten.
+fn resolve_mesh_content(
+    tree: &mut Cow<'_, RbxTree>,
+    vfs: &Vfs,
+    project_folder: &Path,
+    manifest: Option<&ContentManifest>,
+) -> Result<Vec<Error>, Error> {
+    let mut problems = Vec::new();
+    let mut rewrites = Vec::new();
+
+    for id in tree.iter_all_ids() {
+        let instance = tree.get_instance(id).unwrap();
+
+        let properties = match MESH_CONTENT_PROPERTIES
+            .iter()
+            .find(|(class_name, _)| *class_name == instance.class_name)
+        {
+            Some((_, properties)) => *properties,
+            None => continue,
+        };
+
+        for &property in properties {
+            let value = match instance.properties.get(property) {
+                Some(RbxValue::Content { value }) => value,
+                _ => continue,
+            };
+
+            match MeshContent::classify(value) {
+                MeshContent::Asset => {}
+                MeshContent::Invalid => problems.push(Error::InvalidMeshContent {
+                    instance_path: InstancePath::in_tree(tree, id).unwrap().to_string(),
+                    property: property.to_owned(),
+                    value: value.clone(),
+                }),
+                MeshContent::Local(local_path) => {
+                    let manifest = match manifest {
+                        Some(manifest) => manifest,
+                        None => {
+                            problems.push(Error::UnresolvedMeshContent {
+                                instance_path: InstancePath::in_tree(tree, id).unwrap().to_string(),
+                                property: property.to_owned(),
+                                value: value.clone(),
+                            });
+                            continue;
+                        }
+                    };
+
+                    let file = project_folder.join(local_path);
+
+                    if vfs.metadata(&file).is_err() {
+                        return Err(Error::MissingLocalMesh {
+                            instance_path: InstancePath::in_tree(tree, id).unwrap().to_string(),
+                            property: property.to_owned(),
+                            file,
+                        });
+                    }
+
+                    match manifest.get(Path::new(local_path)) {
+                        Some(asset_id) => {
+                            rewrites.push((id, property, format!("rbxassetid://{}", asset_id)));
+                        }
+                        None => {
+                            return Err(Error::NotInContentManifest {
+                                instance_path: InstancePath::in_tree(tree, id).unwrap().to_string(),
+                                property: property.to_owned(),
+                                file,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if !rewrites.is_empty() {
+        let tree = tree.to_mut();
+
+        for (id, property, value) in rewrites {
+            let instance = tree.get_instance_mut(id).unwrap();
+            instance
+                .properties
+                .insert(property.to_owned(), RbxValue::Content { value });
+        }
+    }
 
-            let tree = session.tree();
-            write_model(&tree, &options)?;
+    Ok(problems)
+}
+
+/// Warns if the file that was just built is larger than the configured limit,
+/// or fails the build in strict mode.
+fn check_output_size(options: &BuildCommand, size: u64) -> Result<(), anyhow::Error> {
+    let max_size = options.max_output_size.unwrap_or(DEFAULT_MAX_OUTPUT_SIZE);
+
+    if let Some(err) = output_size_error(&options.output, size, max_size) {
+        if options.strict {
+            return Err(err.into());
         }
+
+        log::warn!("{}", err);
+    }
+
+    Ok(())
+}
+
+fn output_size_error(output: &Path, size: u64, max_size: u64) -> Option<Error> {
+    if size > max_size {
+        Some(Error::OutputTooLarge {
+            output: output.to_path_buf(),
+            size,
+            max_size,
+        })
+    } else {
+        None
+    }
+}
+
+/// Writes out the trace of everything that's happened since the last profile
+/// was written, if the user asked for one. With --watch, each rebuild replaces
+/// the profile with one of just that rebuild.
+fn write_profile(options: &BuildCommand) -> Result<(), anyhow::Error> {
+    if let Some(profile_path) = &options.profile_out {
+        let file = BufWriter::new(File::create(profile_path)?);
+        trace::write_json(file)?;
+        trace::clear();
+
+        log::info!("Wrote build profile to {}", profile_path.display());
     }
 
     Ok(())
 }
 
-fn write_model(tree: &RojoTree, options: &BuildCommand) -> Result<(), anyhow::Error> {
-    let output_kind = detect_output_kind(&options).ok_or(Error::UnknownOutputKind)?;
+/// Writes the given tree to `output`, picking a file format based on its
+/// extension.
+pub(super) fn write_model(
+    tree: &RojoTree,
+    output: &Path,
+    shared_string_threshold: Option<usize>,
+    format_version: Option<u16>,
+) -> Result<(), anyhow::Error> {
+    write_tree(
+        tree.inner(),
+        output,
+        &mut FileSink::new(output),
+        shared_string_threshold,
+        format_version,
+    )?;
+
+    Ok(())
+}
+
+/// Serializes the given tree into `sink`, picking a file format based on the
+/// extension of `output`. Returns the size of the serialized file.
+fn write_tree(
+    tree: &RbxTree,
+    output: &Path,
+    sink: &mut dyn OutputSink,
+    shared_string_threshold: Option<usize>,
+    format_version: Option<u16>,
+) -> Result<u64, anyhow::Error> {
+    let _span = trace::span("serialize", || format!("write {}", output.display()));
+
+    let output_kind = detect_output_kind(output).ok_or(Error::UnknownOutputKind)?;
     log::debug!("Hoping to generate file of type {:?}", output_kind);
 
+    if let Some(version) = format_version {
+        let supported = output_kind.supported_format_versions();
+
+        if !supported.contains(&version) {
+            return Err(Error::UnsupportedFormatVersion {
+                version,
+                kind: output_kind,
+                supported,
+            }
+            .into());
+        }
+    }
+
     let root_id = tree.get_root_id();
 
-    log::trace!("Opening output file for write");
-    let file = File::create(&options.output)?;
-    let mut file = BufWriter::new(file);
+    // SharedString deduplication rewrites property values, so we do it on a
+    // copy of the tree to avoid disturbing the tree that's being served.
+    let xml_tree = match (output_kind, shared_string_threshold) {
+        (OutputKind::Rbxmx, Some(threshold)) | (OutputKind::Rbxlx, Some(threshold)) => {
+            let mut deduped_tree = tree.clone();
+            dedup_shared_strings(&mut deduped_tree, threshold);
+            Cow::Owned(deduped_tree)
+        }
+        _ => Cow::Borrowed(tree),
+    };
+
+    let mut contents = Vec::new();
 
     match output_kind {
         OutputKind::Rbxmx => {
             // Model files include the root instance of the tree and all its
             // descendants.
 
-            rbx_xml::to_writer(&mut file, tree.inner(), &[root_id], xml_encode_config())?;
+            rbx_xml::to_writer(&mut contents, &xml_tree, &[root_id], xml_encode_config())?;
         }
         OutputKind::Rbxlx => {
             // Place files don't contain an entry for the DataModel, but our
             // RbxTree representation does.
 
             let root_instance = tree.get_instance(root_id).unwrap();
-            let top_level_ids = root_instance.children();
+            let top_level_ids = root_instance.get_children_ids();
 
-            rbx_xml::to_writer(&mut file, tree.inner(), top_level_ids, xml_encode_config())?;
+            rbx_xml::to_writer(&mut contents, &xml_tree, top_level_ids, xml_encode_config())?;
         }
         OutputKind::Rbxm => {
-            rbx_binary::encode(tree.inner(), &[root_id], &mut file)?;
+            if shared_string_threshold.is_some() {
+                log::warn!("SharedString deduplication is only supported for XML output.");
+            }
+
+            rbx_binary::encode(tree, &[root_id], &mut contents)?;
         }
         OutputKind::Rbxl => {
+            if shared_string_threshold.is_some() {
+                log::warn!("SharedString deduplication is only supported for XML output.");
+            }
+
             log::warn!("Support for building binary places (rbxl) is still experimental.");
             log::warn!("Using the XML place format (rbxlx) is recommended instead.");
             log::warn!("For more info, see https://github.com/LPGhatguy/rojo/issues/180");
 
             let root_instance = tree.get_instance(root_id).unwrap();
-            let top_level_ids = root_instance.children();
+            let top_level_ids = root_instance.get_children_ids();
 
-            rbx_binary::encode(tree.inner(), top_level_ids, &mut file)?;
+            rbx_binary::encode(tree, top_level_ids, &mut contents)?;
         }
     }
 
-    file.flush()?;
+    sink.write(output_kind, &contents)?;
+
+    Ok(contents.len() as u64)
+}
 
-    let filename = options
-        .output
-        .file_name()
-        .and_then(|name| name.to_str())
-        .unwrap_or("<invalid utf-8>");
-    log::info!("Built project to {}", filename);
+/// Rewrites BinaryString values of at least `threshold` bytes that appear more
+/// than once in the tree into SharedString values. rbx_xml writes each
+/// distinct SharedString into the file only once, keyed by its hash, which can
+/// shrink places that contain lots of repeated data.
+///
+/// Properties that Roblox knows to be of another type are left alone, since
+/// changing their type would make the resulting file load incorrectly.
+fn dedup_shared_strings(tree: &mut RbxTree, threshold: usize) {
+    let mut occurrences: HashMap<&[u8], usize> = HashMap::new();
+    let mut candidates = Vec::new();
 
-    Ok(())
+    for id in tree.iter_all_ids() {
+        let instance = tree.get_instance(id).unwrap();
+
+        for (key, value) in &instance.properties {
+            if let RbxValue::BinaryString { value } = value {
+                if value.len() >= threshold && can_be_shared_string(&instance.class_name, key) {
+                    *occurrences.entry(value.as_slice()).or_default() += 1;
+                    candidates.push((id, key.clone()));
+                }
+            }
+        }
+    }
+
+    let candidates: Vec<_> = candidates
+        .into_iter()
+        .filter(|(id, key)| {
+            let instance = tree.get_instance(*id).unwrap();
+
+            match instance.properties.get(key) {
+                Some(RbxValue::BinaryString { value }) => occurrences[value.as_slice()] > 1,
+                _ => false,
+            }
+        })
+        .collect();
+
+    log::debug!(
+        "Converting {} BinaryString values to SharedString",
+        candidates.len()
+    );
+
+    for (id, key) in candidates {
+        let instance = tree.get_instance_mut(id).unwrap();
+        let property = instance.properties.get_mut(&key).unwrap();
+
+        if let RbxValue::BinaryString { value } = property {
+            let data = std::mem::take(value);
+
+            *property = RbxValue::SharedString {
+                value: SharedString::new(data),
+            };
+        }
+    }
+}
+
+/// Tells whether the given property can hold a SharedString value, which is
+/// true if the property is unknown to us or is already a SharedString.
+fn can_be_shared_string(class_name: &str, property_name: &str) -> bool {
+    match reflection_cache::property_type(class_name, property_name) {
+        Some(RbxPropertyTypeDescriptor::Data(value_type)) => {
+            *value_type == RbxValueType::SharedString
+        }
+        Some(_) => false,
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use maplit::hashmap;
+    use memofs::{InMemoryFs, VfsSnapshot};
+    use rbx_dom_weak::RbxInstanceProperties;
+
+    use crate::{serve_session::ServeSessionError, snapshot::InstancePropertiesWithMeta};
+
+    #[test]
+    fn dedup_identical_binary_strings() {
+        let data = vec![7u8; 1024];
+
+        let mut tree = RbxTree::new(RbxInstanceProperties {
+            name: "Root".to_owned(),
+            class_name: "Folder".to_owned(),
+            properties: HashMap::new(),
+        });
+        let root_id = tree.get_root_id();
+
+        for name in &["A", "B"] {
+            let mut properties = HashMap::new();
+            properties.insert(
+                "MeshData".to_owned(),
+                RbxValue::BinaryString {
+                    value: data.clone(),
+                },
+            );
+
+            tree.insert_instance(
+                RbxInstanceProperties {
+                    name: (*name).to_owned(),
+                    class_name: "Folder".to_owned(),
+                    properties,
+                },
+                root_id,
+            );
+        }
+
+        dedup_shared_strings(&mut tree, 512);
+
+        let mut output = Vec::new();
+        rbx_xml::to_writer(&mut output, &tree, &[root_id], xml_encode_config()).unwrap();
+
+        let output_str = std::str::from_utf8(&output).unwrap();
+        assert_eq!(output_str.matches("<SharedString md5=").count(), 1);
+
+        let decoded = rbx_xml::from_reader_default(output.as_slice()).unwrap();
+        let decoded_root = decoded.get_instance(decoded.get_root_id()).unwrap();
+        let decoded_model = decoded
+            .get_instance(decoded_root.get_children_ids()[0])
+            .unwrap();
+
+        assert_eq!(decoded_model.get_children_ids().len(), 2);
+
+        for child_id in decoded_model.get_children_ids() {
+            let child = decoded.get_instance(*child_id).unwrap();
+
+            match child.properties.get("MeshData") {
+                Some(RbxValue::SharedString { value }) => assert_eq!(value.data(), &data[..]),
+                other => panic!("Expected MeshData to be a SharedString, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn dedup_ignores_small_and_unique_values() {
+        let mut tree = RbxTree::new(RbxInstanceProperties {
+            name: "Root".to_owned(),
+            class_name: "Folder".to_owned(),
+            properties: HashMap::new(),
+        });
+        let root_id = tree.get_root_id();
+
+        {
+            let root = tree.get_instance_mut(root_id).unwrap();
+            root.properties.insert(
+                "Small".to_owned(),
+                RbxValue::BinaryString { value: vec![1; 4] },
+            );
+            root.properties.insert(
+                "Unique".to_owned(),
+                RbxValue::BinaryString {
+                    value: vec![2; 1024],
+                },
+            );
+        }
+
+        dedup_shared_strings(&mut tree, 512);
+
+        let root = tree.get_instance(root_id).unwrap();
+        for value in root.properties.values() {
+            assert_eq!(value.get_type(), RbxValueType::BinaryString);
+        }
+    }
+
+    #[test]
+    fn profile_contains_major_phases() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "profiled",
+                        "tree": {
+                            "$path": "src"
+                        }
+                    }
+                "#),
+                "src" => VfsSnapshot::dir(hashmap! {
+                    "main.lua" => VfsSnapshot::file("print('hi')"),
+                }),
+            }),
+        )
+        .unwrap();
+
+        let _tracing = trace::enable_scoped();
+
+        let vfs = Vfs::new(imfs);
+        vfs.set_watch_enabled(false);
+        let session = ServeSession::new(vfs, "/foo").unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        write_model(&session.tree(), &dir.path().join("out.rbxmx"), None, None).unwrap();
+
+        let mut output = Vec::new();
+        trace::write_json(&mut output).unwrap();
+        let profile: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        let events = profile["traceEvents"].as_array().unwrap();
+
+        let has_span = |prefix: &str| {
+            let has_phase = |phase: &str| {
+                events.iter().any(|event| {
+                    event["name"].as_str().unwrap().starts_with(prefix)
+                        && event["ph"] == phase
+                        && event["ts"].is_u64()
+                })
+            };
+
+            has_phase("B") && has_phase("E")
+        };
+
+        assert!(has_span("load /foo/default.project.json"));
+        assert!(has_span("snapshot_project /foo/default.project.json"));
+        assert!(has_span("snapshot_dir /foo/src"));
+        assert!(has_span("snapshot_lua /foo/src/main.lua"));
+        assert!(has_span("compute_patch_set"));
+        assert!(has_span("apply_patch_set"));
+        assert!(has_span("write "));
+    }
+
+    #[test]
+    fn format_version_is_written_to_header() {
+        let tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "Root".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+
+        for &version in OutputKind::Rbxmx.supported_format_versions() {
+            let xml_path = dir.path().join("out.rbxmx");
+            write_model(&tree, &xml_path, None, Some(version)).unwrap();
+            let xml = fs::read_to_string(&xml_path).unwrap();
+            assert!(xml.contains(&format!(r#"<roblox version="{}""#, version)));
+        }
+
+        for &version in OutputKind::Rbxm.supported_format_versions() {
+            // Binary files start with an 8 byte magic number and a 6 byte
+            // signature, followed by the version as a little endian u16.
+            let binary_path = dir.path().join("out.rbxm");
+            write_model(&tree, &binary_path, None, Some(version)).unwrap();
+            let binary = fs::read(&binary_path).unwrap();
+            assert_eq!(&binary[14..16], &version.to_le_bytes());
+        }
+
+        let unsupported = OutputKind::Rbxmx
+            .supported_format_versions()
+            .iter()
+            .max()
+            .unwrap()
+            + 1;
+        let err = write_model(
+            &tree,
+            &dir.path().join("bad.rbxmx"),
+            None,
+            Some(unsupported),
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains(&format!("Format version {}", unsupported)));
+    }
+
+    fn session_with_root(
+        root_kind: &str,
+        root_class: &str,
+    ) -> Result<ServeSession, ServeSessionError> {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(format!(r#"
+                    {{
+                        "name": "rooted",
+                        "rootKind": "{}",
+                        "tree": {{
+                            "$className": "{}",
+                            "Workspace": {{
+                                "$className": "Workspace"
+                            }}
+                        }}
+                    }}
+                "#, root_kind, root_class)),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        vfs.set_watch_enabled(false);
+
+        ServeSession::new(vfs, "/foo")
+    }
+
+    fn session_with_aliased_paths(allow_path_aliasing: bool) -> ServeSession {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(format!(r#"
+                    {{
+                        "name": "aliased",
+                        "allowPathAliasing": {},
+                        "tree": {{
+                            "$className": "DataModel",
+                            "ReplicatedStorage": {{
+                                "$path": "shared"
+                            }},
+                            "ServerStorage": {{
+                                "$path": "shared"
+                            }}
+                        }}
+                    }}
+                "#, allow_path_aliasing)),
+                "shared" => VfsSnapshot::empty_dir(),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        vfs.set_watch_enabled(false);
+
+        ServeSession::new(vfs, "/foo").unwrap()
+    }
+
+    #[test]
+    fn overlapping_paths_fail_strict_builds() {
+        let session = session_with_aliased_paths(false);
+
+        check_path_overlaps(&session, false).unwrap();
+
+        let err = check_path_overlaps(&session, true).unwrap_err().to_string();
+        assert!(err.contains("aliased/ReplicatedStorage"));
+        assert!(err.contains("aliased/ServerStorage"));
+    }
+
+    #[test]
+    fn path_aliasing_can_be_allowed_in_strict_builds() {
+        let session = session_with_aliased_paths(true);
+
+        check_path_overlaps(&session, true).unwrap();
+    }
+
+    #[test]
+    fn model_root_builds_to_model() {
+        let session = session_with_root("model", "Folder").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.rbxm");
+
+        check_output_kind(RootKind::Model, &output).unwrap();
+        write_model(&session.tree(), &output, None, None).unwrap();
+
+        // Binary model files start with this magic number.
+        let contents = fs::read(&output).unwrap();
+        assert!(contents.starts_with(b"<roblox!"));
+
+        let err = check_output_kind(RootKind::Model, &dir.path().join("out.rbxlx")).unwrap_err();
+        assert!(matches!(err, Error::WrongOutputKind { .. }));
+    }
+
+    #[test]
+    fn place_root_builds_to_place() {
+        let session = session_with_root("place", "DataModel").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.rbxlx");
+
+        check_output_kind(RootKind::Place, &output).unwrap();
+        write_model(&session.tree(), &output, None, None).unwrap();
+
+        // Places contain the DataModel's children, but not the DataModel.
+        let contents = fs::read_to_string(&output).unwrap();
+        assert!(contents.contains(r#"class="Workspace""#));
+        assert!(!contents.contains(r#"class="DataModel""#));
+
+        let err = check_output_kind(RootKind::Place, &dir.path().join("out.rbxmx")).unwrap_err();
+        assert!(matches!(err, Error::WrongOutputKind { .. }));
+    }
+
+    #[test]
+    fn wrong_root_class_is_rejected() {
+        let result = session_with_root("place", "Folder");
+        assert!(matches!(
+            result,
+            Err(ServeSessionError::WrongRootClass { .. })
+        ));
+    }
+
+    #[test]
+    fn large_output_is_reported() {
+        // Binary files are compressed, so the data needs to be noisy to take
+        // up space.
+        let mut state = 1u32;
+        let data = (0..64 * 1024)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+
+        let mut properties = HashMap::new();
+        properties.insert(
+            "MeshData".to_owned(),
+            RbxValue::BinaryString { value: data },
+        );
+
+        let tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "Root".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties,
+            },
+            metadata: Default::default(),
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.rbxm");
+        write_model(&tree, &output, None, None).unwrap();
+
+        let size = fs::metadata(&output).unwrap().len();
+
+        let err = output_size_error(&output, size, 1024).unwrap();
+        assert!(matches!(err, Error::OutputTooLarge { max_size: 1024, .. }));
+        assert!(err.to_string().contains("--max-output-size"));
+
+        assert!(output_size_error(&output, size, DEFAULT_MAX_OUTPUT_SIZE).is_none());
+    }
+
+    #[test]
+    fn fixed_mtime_is_stamped() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "stamped",
+                        "tree": {
+                            "$className": "Folder",
+                            "Scripts": {
+                                "$path": "src"
+                            }
+                        }
+                    }
+                "#),
+                "src" => VfsSnapshot::dir(hashmap! {
+                    "main.lua" => VfsSnapshot::file("print('hi')"),
+                }),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        vfs.set_watch_enabled(false);
+        let session = ServeSession::new(vfs, "/foo").unwrap();
+
+        let tree = session.tree();
+        let stamped = stamp_mtimes(&tree, Some(1_600_000_000)).unwrap();
+
+        let expected = apply_attribute_changes(
+            None,
+            hashmap! {
+                MTIME_ATTRIBUTE.to_owned() => Some(AttributeValue::double(1_600_000_000.0)),
+            },
+        );
+
+        let root = stamped.get_instance(stamped.get_root_id()).unwrap();
+        let scripts = stamped.get_instance(root.get_children_ids()[0]).unwrap();
+        let main = stamped.get_instance(scripts.get_children_ids()[0]).unwrap();
+
+        // The root comes from the project file itself, so it's stamped too.
+        // Scripts comes from a node inside the project, so it isn't.
+        assert_eq!(root.properties.get(ATTRIBUTES_PROPERTY), Some(&expected));
+        assert_eq!(scripts.properties.get(ATTRIBUTES_PROPERTY), None);
+        assert_eq!(main.name, "main");
+        assert_eq!(main.properties.get(ATTRIBUTES_PROPERTY), Some(&expected));
+    }
+
+    #[derive(Default)]
+    struct MemorySink {
+        writes: Vec<(OutputKind, Vec<u8>)>,
+    }
+
+    impl OutputSink for MemorySink {
+        fn write(&mut self, kind: OutputKind, contents: &[u8]) -> io::Result<()> {
+            self.writes.push((kind, contents.to_vec()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn custom_sink_matches_file_output() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "sunk",
+                        "tree": {
+                            "$path": "src"
+                        }
+                    }
+                "#),
+                "src" => VfsSnapshot::dir(hashmap! {
+                    "main.lua" => VfsSnapshot::file("print('hi')"),
+                }),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        vfs.set_watch_enabled(false);
+        let session = ServeSession::new(vfs, "/foo").unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.rbxmx");
+
+        let options = BuildCommand {
+            project: PathBuf::from("/foo"),
+            watch: false,
+            output: output.clone(),
+            stdout: false,
+            shared_string_threshold: None,
+            profile_out: None,
+            format_version: None,
+            max_output_size: None,
+            strict: false,
+            stamp_mtime: false,
+            fixed_mtime: None,
+            resolve_content: None,
+            manifest_out: None,
+            since: None,
+            changed_out: None,
+            respect_archivable: false,
+            strip_defaults: false,
+            read_retries: None,
+        };
+
+        let file_size = write_output(&session, &options, &mut FileSink::new(&output)).unwrap();
+
+        let mut sink = MemorySink::default();
+        let sink_size = write_output(&session, &options, &mut sink).unwrap();
+
+        let contents = fs::read(&output).unwrap();
+        assert_eq!(sink.writes, vec![(OutputKind::Rbxmx, contents)]);
+        assert_eq!(sink_size, file_size);
+    }
+
+    fn session_with_mesh(mesh_id: &str) -> ServeSession {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(format!(r#"
+                    {{
+                        "name": "meshes",
+                        "tree": {{
+                            "$className": "Folder",
+                            "Rock": {{
+                                "$className": "MeshPart",
+                                "$properties": {{
+                                    "MeshId": "{}",
+                                    "TextureID": "rbxassetid://456"
+                                }}
+                            }}
+                        }}
+                    }}
+                "#, mesh_id)),
+                "meshes" => VfsSnapshot::dir(hashmap! {
+                    "rock.mesh" => VfsSnapshot::file("mesh data"),
+                }),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        vfs.set_watch_enabled(false);
+
+        ServeSession::new(vfs, "/foo").unwrap()
+    }
+
+    fn rock_mesh_id(tree: &RbxTree) -> &RbxValue {
+        let root = tree.get_instance(tree.get_root_id()).unwrap();
+        let rock = tree.get_instance(root.get_children_ids()[0]).unwrap();
+
+        &rock.properties["MeshId"]
+    }
+
+    #[test]
+    fn local_mesh_is_resolved_from_manifest() {
+        let manifest = hashmap! {
+            PathBuf::from("meshes/rock.mesh") => 123,
+        };
+
+        let session = session_with_mesh("meshes/rock.mesh");
+        let tree = session.tree();
+        let mut output_tree = Cow::Borrowed(tree.inner());
+
+        let problems = resolve_mesh_content(
+            &mut output_tree,
+            session.vfs(),
+            session.project_folder(),
+            Some(&manifest),
+        )
+        .unwrap();
+
+        assert!(problems.is_empty());
+        assert_eq!(
+            rock_mesh_id(&output_tree),
+            &RbxValue::Content {
+                value: "rbxassetid://123".to_owned()
+            }
+        );
+
+        // The tree being served keeps referring to the local file.
+        assert_eq!(
+            rock_mesh_id(tree.inner()),
+            &RbxValue::Content {
+                value: "meshes/rock.mesh".to_owned()
+            }
+        );
+
+        // Without a manifest, the local path is only reported.
+        let mut output_tree = Cow::Borrowed(tree.inner());
+        let problems = resolve_mesh_content(
+            &mut output_tree,
+            session.vfs(),
+            session.project_folder(),
+            None,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            problems.as_slice(),
+            [Error::UnresolvedMeshContent { .. }]
+        ));
+        assert!(matches!(output_tree, Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn missing_local_mesh_is_an_error() {
+        let manifest = hashmap! {
+            PathBuf::from("meshes/rock.mesh") => 123,
+        };
+
+        let session = session_with_mesh("meshes/missing.mesh");
+        let tree = session.tree();
+        let mut output_tree = Cow::Borrowed(tree.inner());
+
+        let err = resolve_mesh_content(
+            &mut output_tree,
+            session.vfs(),
+            session.project_folder(),
+            Some(&manifest),
+        )
+        .unwrap_err();
+
+        match &err {
+            Error::MissingLocalMesh {
+                instance_path,
+                property,
+                ..
+            } => {
+                assert_eq!(instance_path, "meshes/Rock");
+                assert_eq!(property, "MeshId");
+            }
+            other => panic!("Expected MissingLocalMesh, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn mesh_content_urls() {
+        assert_eq!(MeshContent::classify(""), MeshContent::Asset);
+        assert_eq!(
+            MeshContent::classify("rbxassetid://123"),
+            MeshContent::Asset
+        );
+        assert_eq!(
+            MeshContent::classify("rbxasset://fonts/sword.mesh"),
+            MeshContent::Asset
+        );
+        assert_eq!(
+            MeshContent::classify("https://www.roblox.com/asset/?id=123"),
+            MeshContent::Asset
+        );
+        assert_eq!(
+            MeshContent::classify("rbxthumb://type=Asset&id=123&w=150&h=150"),
+            MeshContent::Asset
+        );
+        assert_eq!(
+            MeshContent::classify("meshes/rock.mesh"),
+            MeshContent::Local("meshes/rock.mesh")
+        );
+        assert_eq!(
+            MeshContent::classify("rbxassetid://rock"),
+            MeshContent::Invalid
+        );
+        assert_eq!(MeshContent::classify("rbxassetid://"), MeshContent::Invalid);
+        assert_eq!(
+            MeshContent::classify("ftp://rock.mesh"),
+            MeshContent::Invalid
+        );
+    }
+
+    fn scripts_session(b_source: &str) -> ServeSession {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "scripts",
+                        "tree": {
+                            "$path": "src"
+                        }
+                    }
+                "#),
+                "src" => VfsSnapshot::dir(hashmap! {
+                    "a.lua" => VfsSnapshot::file("return 'a'"),
+                    "b.lua" => VfsSnapshot::file(b_source),
+                }),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        vfs.set_watch_enabled(false);
+        ServeSession::new(vfs, "/foo").unwrap()
+    }
+
+    #[test]
+    fn only_changed_script_is_flagged() {
+        let manifest = InstanceManifest::from_tree(&scripts_session("return 'b'").tree());
+        assert_eq!(manifest.instances.len(), 3);
+
+        let session = scripts_session("return 'changed'");
+        let tree = session.tree();
+        let changes = changed_since(&tree, &manifest);
+
+        let changed: Vec<String> = changes
+            .changed
+            .iter()
+            .map(|path| path.to_string())
+            .collect();
+        assert_eq!(changed, vec!["scripts/b".to_owned()]);
+        assert!(changes.removed.is_empty());
+
+        let folder = tempfile::tempdir().unwrap();
+        write_changed_instances(&tree, &changes.changed, folder.path()).unwrap();
+
+        let written: Vec<_> = fs::read_dir(folder.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(written, vec!["scripts.b.rbxm"]);
+    }
+
+    #[test]
+    fn unarchivable_instances_are_omitted() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "archivable",
+                        "tree": {
+                            "$className": "Folder",
+                            "Hidden": {
+                                "$className": "Folder",
+                                "$properties": {
+                                    "Archivable": false
+                                },
+                                "HiddenChild": {
+                                    "$className": "Folder"
+                                }
+                            },
+                            "Shown": {
+                                "$className": "Folder"
+                            }
+                        }
+                    }
+                "#),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        vfs.set_watch_enabled(false);
+        let session = ServeSession::new(vfs, "/foo").unwrap();
+
+        let build = |respect_archivable| {
+            let options = BuildCommand {
+                project: PathBuf::from("/foo"),
+                watch: false,
+                output: PathBuf::from("out.rbxmx"),
+                stdout: false,
+                shared_string_threshold: None,
+                profile_out: None,
+                format_version: None,
+                max_output_size: None,
+                strict: false,
+                stamp_mtime: false,
+                fixed_mtime: None,
+                resolve_content: None,
+                manifest_out: None,
+                since: None,
+                changed_out: None,
+                respect_archivable,
+                strip_defaults: false,
+                read_retries: None,
+            };
+
+            let mut sink = MemorySink::default();
+            write_output(&session, &options, &mut sink).unwrap();
+            String::from_utf8(sink.writes.remove(0).1).unwrap()
+        };
+
+        let everything = build(false);
+        assert!(everything.contains("HiddenChild"));
+        assert!(everything.contains("Shown"));
+
+        let archivable = build(true);
+        assert!(!archivable.contains("Hidden"));
+        assert!(archivable.contains("Shown"));
+
+        // Refs into the removed subtree are cleared.
+        let mut tree = session.tree().inner().clone();
+        let root_id = tree.get_root_id();
+        let hidden_child_id = tree
+            .descendants(root_id)
+            .find(|instance| instance.name == "HiddenChild")
+            .unwrap()
+            .get_id();
+
+        let mut properties = HashMap::new();
+        properties.insert(
+            "Value".to_owned(),
+            RbxValue::Ref {
+                value: Some(hidden_child_id),
+            },
+        );
+        let pointer_id = tree.insert_instance(
+            RbxInstanceProperties {
+                name: "Pointer".to_owned(),
+                class_name: "ObjectValue".to_owned(),
+                properties,
+            },
+            root_id,
+        );
+
+        remove_unarchivable(&mut tree);
+
+        assert!(tree.get_instance(hidden_child_id).is_none());
+        assert_eq!(
+            tree.get_instance(pointer_id)
+                .unwrap()
+                .properties
+                .get("Value"),
+            Some(&RbxValue::Ref { value: None })
+        );
+    }
 }
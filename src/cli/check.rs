@@ -0,0 +1,419 @@
+use memofs::Vfs;
+use rbx_dom_weak::{RbxId, RbxValue};
+use rbx_reflection::{get_enum_descriptor, RbxPropertyTypeDescriptor};
+use thiserror::Error;
+
+use crate::{
+    cli::CheckCommand,
+    reflection_cache::{self, class_properties},
+    serve_session::ServeSession,
+    snapshot::RojoTree,
+};
+
+/// The classes of the containers that Scripts run inside of by default.
+const SERVER_CONTAINERS: &[&str] = &["Workspace", "ServerScriptService"];
+
+/// The classes of the containers that LocalScripts run inside of by default.
+/// LocalScripts in StarterGui, StarterPack, and StarterPlayer's containers are
+/// copied into each player before they run.
+const CLIENT_CONTAINERS: &[&str] = &[
+    "ReplicatedFirst",
+    "StarterGui",
+    "StarterPack",
+    "StarterPlayerScripts",
+    "StarterCharacterScripts",
+];
+
+#[derive(Debug, Error)]
+enum Error {
+    #[error("Found {count} schema problem(s) in the project")]
+    SchemaProblems { count: usize },
+}
+
+pub fn check(options: CheckCommand) -> Result<(), anyhow::Error> {
+    let vfs = Vfs::new_default();
+    vfs.set_watch_enabled(false);
+
+    let session = ServeSession::new(vfs, options.absolute_project())?;
+
+    if options.schema {
+        let problems = find_schema_problems(&session.tree());
+
+        for problem in &problems {
+            println!("{}", problem);
+        }
+
+        if !problems.is_empty() {
+            return Err(Error::SchemaProblems {
+                count: problems.len(),
+            }
+            .into());
+        }
+    }
+
+    if options.warn_unreachable {
+        let mut containers = ScriptContainers::default();
+        containers.server.extend(options.server_containers);
+        containers.client.extend(options.client_containers);
+
+        for script in find_unreachable_scripts(&session.tree(), &containers) {
+            log::warn!("{}", script);
+        }
+    }
+
+    log::info!("No problems found in project {}", session.project_name());
+
+    Ok(())
+}
+
+/// A mismatch between an instance in a Rojo tree and the reflection database.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SchemaProblem {
+    #[error("{path}: {class_name} is not a known class")]
+    UnknownClass { path: String, class_name: String },
+
+    #[error("{path}: {property} is not a valid property of {class_name}")]
+    InvalidProperty {
+        path: String,
+        class_name: String,
+        property: String,
+    },
+
+    #[error("{path}: {value} is not a valid {enum_name} item for {class_name}.{property}")]
+    InvalidEnumItem {
+        path: String,
+        class_name: String,
+        property: String,
+        enum_name: String,
+        value: u32,
+    },
+}
+
+/// Checks every instance in the tree against the reflection database, finding
+/// properties that don't exist on the instance's class and enum values that
+/// aren't items of the property's enum.
+///
+/// The reflection database doesn't describe which properties are required, so
+/// missing properties are never reported.
+pub fn find_schema_problems(tree: &RojoTree) -> Vec<SchemaProblem> {
+    let mut problems = Vec::new();
+    let root_id = tree.get_root_id();
+
+    check_instance(tree, root_id, &mut problems);
+
+    problems
+}
+
+fn check_instance(tree: &RojoTree, id: RbxId, problems: &mut Vec<SchemaProblem>) {
+    let instance = tree.get_instance(id).unwrap();
+    let path = tree.instance_path(id).unwrap().to_string();
+
+    let class_name = instance.class_name();
+
+    if class_properties(class_name).is_some() {
+        // Sort so that problems are reported in a stable order.
+        let mut property_names: Vec<_> = instance.properties().keys().collect();
+        property_names.sort();
+
+        for property in property_names {
+            match reflection_cache::property_type(class_name, property) {
+                None => problems.push(SchemaProblem::InvalidProperty {
+                    path: path.clone(),
+                    class_name: class_name.to_owned(),
+                    property: property.clone(),
+                }),
+                Some(RbxPropertyTypeDescriptor::Enum(enum_name)) => {
+                    if let RbxValue::Enum { value } = instance.properties()[property] {
+                        if !is_enum_item(enum_name, value) {
+                            problems.push(SchemaProblem::InvalidEnumItem {
+                                path: path.clone(),
+                                class_name: class_name.to_owned(),
+                                property: property.clone(),
+                                enum_name: enum_name.to_string(),
+                                value,
+                            });
+                        }
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+    } else {
+        problems.push(SchemaProblem::UnknownClass {
+            path: path.clone(),
+            class_name: class_name.to_owned(),
+        });
+    }
+
+    for &child_id in instance.children() {
+        check_instance(tree, child_id, problems);
+    }
+}
+
+/// Which containers each kind of script runs inside of, by class name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptContainers {
+    pub server: Vec<String>,
+    pub client: Vec<String>,
+}
+
+impl Default for ScriptContainers {
+    fn default() -> Self {
+        let owned = |classes: &[&str]| classes.iter().map(|&class| class.to_owned()).collect();
+
+        ScriptContainers {
+            server: owned(SERVER_CONTAINERS),
+            client: owned(CLIENT_CONTAINERS),
+        }
+    }
+}
+
+/// A script that isn't inside of any container that would run it.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{path}: {class_name} is not inside of a container that runs it, like {expected}")]
+pub struct UnreachableScript {
+    pub path: String,
+    pub class_name: String,
+
+    /// The first container that would run this kind of script.
+    pub expected: String,
+}
+
+/// Finds Scripts that aren't inside of a server container and LocalScripts
+/// that aren't inside of a client container.
+///
+/// This is only a guess. Scripts can be moved into place while the game runs,
+/// and ModuleScripts are never reported, since they run wherever they're
+/// required from.
+pub fn find_unreachable_scripts(
+    tree: &RojoTree,
+    containers: &ScriptContainers,
+) -> Vec<UnreachableScript> {
+    let root_id = tree.get_root_id();
+    let mut unreachable = Vec::new();
+
+    for instance in tree.descendants(root_id) {
+        let allowed = match instance.class_name() {
+            "Script" => &containers.server,
+            "LocalScript" => &containers.client,
+            _ => continue,
+        };
+
+        let mut ancestor = instance.parent();
+        let mut reachable = false;
+
+        while let Some(ancestor_id) = ancestor {
+            let ancestor_instance = tree.get_instance(ancestor_id).unwrap();
+
+            if allowed
+                .iter()
+                .any(|class| class == ancestor_instance.class_name())
+            {
+                reachable = true;
+                break;
+            }
+
+            ancestor = ancestor_instance.parent();
+        }
+
+        if !reachable {
+            unreachable.push(UnreachableScript {
+                path: tree.instance_path(instance.id()).unwrap().to_string(),
+                class_name: instance.class_name().to_owned(),
+                expected: allowed.first().cloned().unwrap_or_default(),
+            });
+        }
+    }
+
+    unreachable
+}
+
+/// Tells whether the given value is one of the items of the enum with the
+/// given name. Enums that aren't in the reflection database aren't checked.
+fn is_enum_item(enum_name: &str, value: u32) -> bool {
+    match get_enum_descriptor(enum_name) {
+        Some(descriptor) => descriptor.iter_items().any(|(_, item)| item == value),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use maplit::hashmap;
+    use memofs::{InMemoryFs, VfsSnapshot};
+
+    #[test]
+    fn invalid_property_is_reported() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "checked",
+                        "tree": {
+                            "$className": "Folder",
+                            "Part": {
+                                "$className": "Part",
+                                "$properties": {
+                                    "Transparency": 0.5
+                                }
+                            },
+                            "Folder": {
+                                "$className": "Folder",
+                                "$properties": {
+                                    "Transparency": {
+                                        "Type": "Float32",
+                                        "Value": 0.5
+                                    }
+                                }
+                            }
+                        }
+                    }
+                "#),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        vfs.set_watch_enabled(false);
+        let session = ServeSession::new(vfs, "/foo").unwrap();
+
+        let problems = find_schema_problems(&session.tree());
+
+        assert_eq!(
+            problems,
+            vec![SchemaProblem::InvalidProperty {
+                path: "checked/Folder".to_owned(),
+                class_name: "Folder".to_owned(),
+                property: "Transparency".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn invalid_enum_item_is_reported() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "checked",
+                        "tree": {
+                            "$className": "Folder",
+                            "Valid": {
+                                "$className": "Part",
+                                "$properties": {
+                                    "Material": "Neon"
+                                }
+                            },
+                            "Invalid": {
+                                "$className": "Part",
+                                "$properties": {
+                                    "Material": {
+                                        "Type": "Enum",
+                                        "Value": 12345
+                                    }
+                                }
+                            }
+                        }
+                    }
+                "#),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        vfs.set_watch_enabled(false);
+        let session = ServeSession::new(vfs, "/foo").unwrap();
+
+        let problems = find_schema_problems(&session.tree());
+
+        assert_eq!(
+            problems,
+            vec![SchemaProblem::InvalidEnumItem {
+                path: "checked/Invalid".to_owned(),
+                class_name: "Part".to_owned(),
+                property: "Material".to_owned(),
+                enum_name: "Material".to_owned(),
+                value: 12345,
+            }]
+        );
+        assert_eq!(
+            problems[0].to_string(),
+            "checked/Invalid: 12345 is not a valid Material item for Part.Material"
+        );
+    }
+
+    #[test]
+    fn script_in_replicated_storage_is_unreachable() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "checked",
+                        "tree": {
+                            "$className": "DataModel",
+                            "ReplicatedStorage": {
+                                "$className": "ReplicatedStorage",
+                                "$path": "shared"
+                            },
+                            "ServerScriptService": {
+                                "$className": "ServerScriptService",
+                                "$path": "server"
+                            },
+                            "StarterPlayer": {
+                                "$className": "StarterPlayer",
+                                "StarterPlayerScripts": {
+                                    "$className": "StarterPlayerScripts",
+                                    "$path": "client"
+                                }
+                            }
+                        }
+                    }
+                "#),
+                "shared" => VfsSnapshot::dir(hashmap! {
+                    "Misplaced.server.lua" => VfsSnapshot::file("print(1)"),
+                    "Module.lua" => VfsSnapshot::file("return {}"),
+                }),
+                "server" => VfsSnapshot::dir(hashmap! {
+                    "Main.server.lua" => VfsSnapshot::file("print(2)"),
+                }),
+                "client" => VfsSnapshot::dir(hashmap! {
+                    "Main.client.lua" => VfsSnapshot::file("print(3)"),
+                }),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        vfs.set_watch_enabled(false);
+        let session = ServeSession::new(vfs, "/foo").unwrap();
+
+        let unreachable = find_unreachable_scripts(&session.tree(), &ScriptContainers::default());
+
+        assert_eq!(
+            unreachable,
+            vec![UnreachableScript {
+                path: "checked/ReplicatedStorage/Misplaced".to_owned(),
+                class_name: "Script".to_owned(),
+                expected: "Workspace".to_owned(),
+            }]
+        );
+        assert_eq!(
+            unreachable[0].to_string(),
+            "checked/ReplicatedStorage/Misplaced: Script is not inside of a container that runs \
+             it, like Workspace"
+        );
+
+        // Containers can be added to the defaults.
+        let mut containers = ScriptContainers::default();
+        containers.server.push("ReplicatedStorage".to_owned());
+        assert!(find_unreachable_scripts(&session.tree(), &containers).is_empty());
+    }
+}
@@ -7,7 +7,7 @@ use std::{
 
 use thiserror::Error;
 
-use crate::cli::{InitCommand, InitKind};
+use crate::cli::{GlobalOptions, InitCommand, InitKind};
 
 static MODEL_PROJECT: &str =
     include_str!("../../assets/default-model-project/default.project.json");
@@ -20,6 +20,18 @@ static PLACE_PROJECT: &str =
 static PLACE_README: &str = include_str!("../../assets/default-place-project/README.md");
 static PLACE_GIT_IGNORE: &str = include_str!("../../assets/default-place-project/gitignore.txt");
 
+static PLUGIN_PROJECT: &str =
+    include_str!("../../assets/default-plugin-project/default.project.json");
+static PLUGIN_README: &str = include_str!("../../assets/default-plugin-project/README.md");
+static PLUGIN_INIT: &str = include_str!("../../assets/default-plugin-project/src-init.server.lua");
+static PLUGIN_GIT_IGNORE: &str = include_str!("../../assets/default-plugin-project/gitignore.txt");
+
+static LIBRARY_PROJECT: &str =
+    include_str!("../../assets/default-library-project/default.project.json");
+static LIBRARY_README: &str = include_str!("../../assets/default-library-project/README.md");
+static LIBRARY_GIT_IGNORE: &str =
+    include_str!("../../assets/default-library-project/gitignore.txt");
+
 #[derive(Debug, Error)]
 enum Error {
     #[error("A project file named default.project.json already exists in this folder")]
@@ -29,7 +41,71 @@ enum Error {
     GitInit,
 }
 
-pub fn init(options: InitCommand) -> Result<(), anyhow::Error> {
+/// The files that make up a starter project.
+struct Template {
+    /// What kind of project this is, used when telling the user what we're
+    /// creating.
+    description: &'static str,
+
+    /// The contents of default.project.json.
+    project: &'static str,
+
+    /// Every other file in the project, with paths relative to the project's
+    /// folder. Folders are created as needed.
+    files: Vec<(&'static str, &'static str)>,
+
+    git_ignore: &'static str,
+}
+
+impl Template {
+    fn for_kind(kind: InitKind) -> Self {
+        match kind {
+            InitKind::Place => Template {
+                description: "place",
+                project: PLACE_PROJECT,
+                files: vec![
+                    ("README.md", PLACE_README),
+                    (
+                        "src/shared/Hello.lua",
+                        "return function()\n\tprint(\"Hello, world!\")\nend",
+                    ),
+                    (
+                        "src/server/init.server.lua",
+                        "print(\"Hello world, from server!\")",
+                    ),
+                    (
+                        "src/client/init.client.lua",
+                        "print(\"Hello world, from client!\")",
+                    ),
+                ],
+                git_ignore: PLACE_GIT_IGNORE,
+            },
+            InitKind::Model => Template {
+                description: "model",
+                project: MODEL_PROJECT,
+                files: vec![("README.md", MODEL_README), ("src/init.lua", MODEL_INIT)],
+                git_ignore: MODEL_GIT_IGNORE,
+            },
+            InitKind::Plugin => Template {
+                description: "plugin",
+                project: PLUGIN_PROJECT,
+                files: vec![
+                    ("README.md", PLUGIN_README),
+                    ("src/init.server.lua", PLUGIN_INIT),
+                ],
+                git_ignore: PLUGIN_GIT_IGNORE,
+            },
+            InitKind::Library => Template {
+                description: "library",
+                project: LIBRARY_PROJECT,
+                files: vec![("README.md", LIBRARY_README), ("src/init.lua", MODEL_INIT)],
+                git_ignore: LIBRARY_GIT_IGNORE,
+            },
+        }
+    }
+}
+
+pub fn init(global: GlobalOptions, options: InitCommand) -> Result<(), anyhow::Error> {
     let base_path = options.absolute_path();
     fs::create_dir_all(&base_path)?;
 
@@ -43,75 +119,46 @@ pub fn init(options: InitCommand) -> Result<(), anyhow::Error> {
         name: project_name.to_owned(),
     };
 
-    match options.kind {
-        InitKind::Place => init_place(&base_path, project_params),
-        InitKind::Model => init_model(&base_path, project_params),
-    }
+    init_template(
+        &base_path,
+        Template::for_kind(options.kind),
+        project_params,
+        global.quiet,
+    )
 }
 
-fn init_place(base_path: &Path, project_params: ProjectParams) -> Result<(), anyhow::Error> {
-    eprintln!("Creating new place project '{}'", project_params.name);
-
-    let project_file = project_params.render_template(PLACE_PROJECT);
-    try_create_project(base_path, &project_file)?;
-
-    let readme = project_params.render_template(PLACE_README);
-    write_if_not_exists(&base_path.join("README.md"), &readme)?;
-
-    let src = base_path.join("src");
-    fs::create_dir_all(&src)?;
-
-    let src_shared = src.join("shared");
-    fs::create_dir_all(src.join(&src_shared))?;
-
-    let src_server = src.join("server");
-    fs::create_dir_all(src.join(&src_server))?;
-
-    let src_client = src.join("client");
-    fs::create_dir_all(src.join(&src_client))?;
-
-    write_if_not_exists(
-        &src_shared.join("Hello.lua"),
-        "return function()\n\tprint(\"Hello, world!\")\nend",
-    )?;
-
-    write_if_not_exists(
-        &src_server.join("init.server.lua"),
-        "print(\"Hello world, from server!\")",
-    )?;
-
-    write_if_not_exists(
-        &src_client.join("init.client.lua"),
-        "print(\"Hello world, from client!\")",
-    )?;
-
-    let git_ignore = project_params.render_template(PLACE_GIT_IGNORE);
-    try_git_init(base_path, &git_ignore)?;
-
-    eprintln!("Created project successfully.");
-
-    Ok(())
-}
-
-fn init_model(base_path: &Path, project_params: ProjectParams) -> Result<(), anyhow::Error> {
-    eprintln!("Creating new model project '{}'", project_params.name);
+fn init_template(
+    base_path: &Path,
+    template: Template,
+    project_params: ProjectParams,
+    quiet: bool,
+) -> Result<(), anyhow::Error> {
+    if !quiet {
+        eprintln!(
+            "Creating new {} project '{}'",
+            template.description, project_params.name
+        );
+    }
 
-    let project_file = project_params.render_template(MODEL_PROJECT);
+    let project_file = project_params.render_template(template.project);
     try_create_project(base_path, &project_file)?;
 
-    let readme = project_params.render_template(MODEL_README);
-    write_if_not_exists(&base_path.join("README.md"), &readme)?;
+    for (relative_path, contents) in &template.files {
+        let path = base_path.join(relative_path);
 
-    let src = base_path.join("src");
-    fs::create_dir_all(&src)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
 
-    let init = project_params.render_template(MODEL_INIT);
-    write_if_not_exists(&src.join("init.lua"), &init)?;
+        write_if_not_exists(&path, &project_params.render_template(contents))?;
+    }
 
-    let git_ignore = project_params.render_template(MODEL_GIT_IGNORE);
+    let git_ignore = project_params.render_template(template.git_ignore);
     try_git_init(base_path, &git_ignore)?;
 
-    eprintln!("Created project successfully.");
+    if !quiet {
+        eprintln!("Created project successfully.");
+    }
 
     Ok(())
 }
@@ -211,3 +258,53 @@ fn try_create_project(base_path: &Path, contents: &str) -> Result<(), anyhow::Er
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    use tempfile::tempdir;
+
+    use crate::{
+        cli::ColorChoice,
+        project::{Project, ProjectNode},
+    };
+
+    #[test]
+    fn library_template() {
+        let dir = tempdir().unwrap();
+        let base_path = dir.path().join("my-library");
+
+        let global = GlobalOptions {
+            verbosity: 0,
+            quiet: true,
+            color: ColorChoice::Never,
+        };
+
+        init(
+            global,
+            InitCommand {
+                path: base_path.clone(),
+                kind: InitKind::Library,
+            },
+        )
+        .unwrap();
+
+        for file in &["default.project.json", "README.md", "src/init.lua"] {
+            assert!(base_path.join(file).is_file(), "{} was not created", file);
+        }
+
+        let init_source = fs::read_to_string(base_path.join("src/init.lua")).unwrap();
+        assert!(init_source.contains("Hello world, from my-library!"));
+
+        let project = Project::load_fuzzy(&base_path).unwrap().unwrap();
+        assert_eq!(project.name, "my-library");
+        assert_eq!(project.tree.class_name.as_deref(), Some("DataModel"));
+
+        let replicated_storage = &project.tree.children["ReplicatedStorage"];
+        let library: &ProjectNode = &replicated_storage.children["my-library"];
+        assert_eq!(library.path, Some(PathBuf::from("src")));
+    }
+}
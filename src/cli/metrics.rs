@@ -0,0 +1,172 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use memofs::Vfs;
+use rbx_dom_weak::RbxValue;
+use serde::Serialize;
+
+use crate::{
+    cli::MetricsCommand,
+    serve_session::ServeSession,
+    snapshot::{InstanceWithMeta, RojoTree},
+    snapshot_middleware::middleware_name,
+};
+
+pub fn metrics(options: MetricsCommand) -> Result<(), anyhow::Error> {
+    let vfs = Vfs::new_default();
+    vfs.set_watch_enabled(false);
+
+    let session = ServeSession::new(vfs, options.absolute_project())?;
+    let scripts =
+        ScriptMetrics::from_tree(&session.tree(), session.vfs(), session.project_folder())?;
+
+    let file = BufWriter::new(File::create(&options.output)?);
+    write_csv(file, &scripts)?;
+
+    log::info!(
+        "Wrote metrics for {} script(s) to {}",
+        scripts.len(),
+        options.output.display()
+    );
+
+    Ok(())
+}
+
+/// One row of the CSV written by `rojo metrics`.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct ScriptMetrics {
+    /// Where the script is in the tree, like `ReplicatedStorage/Shared/Util`.
+    pub path: String,
+    pub class_name: String,
+
+    /// The file the script was made from, relative to the project folder, or
+    /// empty if it didn't come from a script file, like scripts inside of
+    /// models.
+    pub file: String,
+
+    pub lines: usize,
+    pub bytes: usize,
+}
+
+impl ScriptMetrics {
+    /// Measures every script in the tree, sorted by their paths in the tree.
+    pub fn from_tree(tree: &RojoTree, vfs: &Vfs, project_folder: &Path) -> io::Result<Vec<Self>> {
+        let root_id = tree.get_root_id();
+        let mut scripts = Vec::new();
+
+        for instance in tree.descendants(root_id) {
+            match instance.class_name() {
+                "Script" | "LocalScript" | "ModuleScript" => {}
+                _ => continue,
+            }
+
+            let source = match instance.properties().get("Source") {
+                Some(RbxValue::String { value }) => value.as_str(),
+                _ => "",
+            };
+
+            let file = match script_file(instance, vfs)? {
+                Some(path) => path
+                    .strip_prefix(project_folder)
+                    .unwrap_or(path)
+                    .display()
+                    .to_string(),
+                None => String::new(),
+            };
+
+            scripts.push(ScriptMetrics {
+                path: tree.instance_path(instance.id()).unwrap().to_string(),
+                class_name: instance.class_name().to_owned(),
+                file,
+                lines: source.lines().count(),
+                bytes: source.len(),
+            });
+        }
+
+        scripts.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(scripts)
+    }
+}
+
+/// Finds the Lua file that a script was made from. Scripts made from init
+/// scripts have their folder as their source, so we look through the script's
+/// relevant paths instead.
+fn script_file<'a>(instance: InstanceWithMeta<'a>, vfs: &Vfs) -> io::Result<Option<&'a Path>> {
+    for path in &instance.metadata().relevant_paths {
+        if let Some(".lua") | Some(".luau") = middleware_name(vfs, path)? {
+            return Ok(Some(path));
+        }
+    }
+
+    Ok(None)
+}
+
+fn write_csv(output: impl Write, scripts: &[ScriptMetrics]) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(output);
+
+    for script in scripts {
+        writer.serialize(script)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use maplit::hashmap;
+    use memofs::{InMemoryFs, VfsSnapshot};
+
+    #[test]
+    fn rows_for_each_script() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "metrics",
+                        "tree": {
+                            "$className": "Folder",
+                            "Shared": {
+                                "$path": "src"
+                            }
+                        }
+                    }
+                "#),
+                "src" => VfsSnapshot::dir(hashmap! {
+                    "main.server.lua" => VfsSnapshot::file("print(1)\nprint(2)\nprint(3)\n"),
+                    "data.txt" => VfsSnapshot::file("not a script"),
+                    "Util" => VfsSnapshot::dir(hashmap! {
+                        "init.lua" => VfsSnapshot::file("return {}"),
+                    }),
+                }),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        vfs.set_watch_enabled(false);
+        let session = ServeSession::new(vfs, "/foo").unwrap();
+
+        let scripts =
+            ScriptMetrics::from_tree(&session.tree(), session.vfs(), session.project_folder())
+                .unwrap();
+
+        let mut output = Vec::new();
+        write_csv(&mut output, &scripts).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "path,class_name,file,lines,bytes\n\
+             metrics/Shared/Util,ModuleScript,src/Util/init.lua,1,9\n\
+             metrics/Shared/main,Script,src/main.server.lua,3,27\n"
+        );
+    }
+}
@@ -1,10 +1,15 @@
 //! Defines Rojo's CLI through structopt types.
 
+mod bench;
 mod build;
+mod check;
 mod doc;
 mod init;
+mod metrics;
 mod plugin;
+mod revert_to;
 mod serve;
+mod stats;
 mod upload;
 
 use std::{
@@ -14,16 +19,23 @@ use std::{
     fmt,
     path::{Path, PathBuf},
     str::FromStr,
+    time::Duration,
 };
 
+use memofs::Vfs;
 use structopt::StructOpt;
 use thiserror::Error;
 
+pub use self::bench::*;
 pub use self::build::*;
+pub use self::check::*;
 pub use self::doc::*;
 pub use self::init::*;
+pub use self::metrics::*;
 pub use self::plugin::*;
+pub use self::revert_to::*;
 pub use self::serve::*;
+pub use self::stats::*;
 pub use self::upload::*;
 
 /// Command line options that Rojo accepts, defined using the structopt crate.
@@ -44,11 +56,38 @@ pub struct GlobalOptions {
     #[structopt(long("verbose"), short, global(true), parse(from_occurrences))]
     pub verbosity: u8,
 
+    /// Only print errors and output that was asked for, like the problems
+    /// found by `rojo check`. Can't be combined with --verbose.
+    #[structopt(long, short, global(true))]
+    pub quiet: bool,
+
     /// Set color behavior. Valid values are auto, always, and never.
     #[structopt(long("color"), global(true), default_value("auto"))]
     pub color: ColorChoice,
 }
 
+impl GlobalOptions {
+    /// The log filter to use when RUST_LOG isn't set.
+    pub fn log_filter(&self) -> Result<&'static str, QuietAndVerboseError> {
+        if self.quiet {
+            if self.verbosity > 0 {
+                return Err(QuietAndVerboseError);
+            }
+
+            return Ok("error");
+        }
+
+        let filter = match self.verbosity {
+            0 => "info",
+            1 => "info,librojo=debug",
+            2 => "info,librojo=trace",
+            _ => "trace",
+        };
+
+        Ok(filter)
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum ColorChoice {
     Auto,
@@ -97,6 +136,10 @@ pub struct ColorChoiceParseError {
     attempted: String,
 }
 
+#[derive(Debug, Error)]
+#[error("--quiet and --verbose can't be used together")]
+pub struct QuietAndVerboseError;
+
 #[derive(Debug, StructOpt)]
 pub enum Subcommand {
     /// Creates a new Rojo project.
@@ -108,9 +151,26 @@ pub enum Subcommand {
     /// Generates a model or place file from the project.
     Build(BuildCommand),
 
+    /// Loads the project and reports any problems with it.
+    Check(CheckCommand),
+
+    /// Counts the instances of each class in the project and the files that
+    /// each middleware handled.
+    Stats(StatsCommand),
+
+    /// Writes a CSV file listing every script in the project with its line
+    /// count.
+    Metrics(MetricsCommand),
+
+    /// Times how long it takes to snapshot and patch the project.
+    Bench(BenchCommand),
+
     /// Generates a place or model file out of the project and uploads it to Roblox.
     Upload(UploadCommand),
 
+    /// Generates a place or model file from a previous version of the project.
+    RevertTo(RevertToCommand),
+
     /// Open Rojo's documentation in your browser.
     Doc,
 
@@ -125,8 +185,9 @@ pub struct InitCommand {
     #[structopt(default_value = "")]
     pub path: PathBuf,
 
-    /// The kind of project to create, 'place' or 'model'. Defaults to place.
-    #[structopt(long, default_value = "place")]
+    /// The template to create the project from: 'place' (or 'game'), 'model',
+    /// 'plugin', or 'library'. Defaults to place.
+    #[structopt(long, visible_alias = "template", default_value = "place")]
     pub kind: InitKind,
 }
 
@@ -142,21 +203,37 @@ pub enum InitKind {
     /// A place that matches what File -> New does in Roblox Studio.
     Place,
 
-    /// An empty model, suitable for a library or plugin.
+    /// An empty model.
     Model,
+
+    /// A model with a plugin entry point.
+    Plugin,
+
+    /// A place with a module in ReplicatedStorage, for developing a library.
+    Library,
 }
 
+/// Every name that can be given to `--kind`, in the order they're listed to
+/// the user.
+const INIT_KIND_NAMES: &[(&str, InitKind)] = &[
+    ("place", InitKind::Place),
+    ("game", InitKind::Place),
+    ("model", InitKind::Model),
+    ("plugin", InitKind::Plugin),
+    ("library", InitKind::Library),
+];
+
 impl FromStr for InitKind {
     type Err = InitKindParseError;
 
     fn from_str(source: &str) -> Result<Self, Self::Err> {
-        match source {
-            "place" => Ok(InitKind::Place),
-            "model" => Ok(InitKind::Model),
-            _ => Err(InitKindParseError {
+        INIT_KIND_NAMES
+            .iter()
+            .find(|(name, _)| *name == source)
+            .map(|&(_, kind)| kind)
+            .ok_or_else(|| InitKindParseError {
                 attempted: source.to_owned(),
-            }),
-        }
+            })
     }
 }
 
@@ -170,10 +247,13 @@ impl Error for InitKindParseError {}
 
 impl fmt::Display for InitKindParseError {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let valid_kinds: Vec<_> = INIT_KIND_NAMES.iter().map(|(name, _)| *name).collect();
+
         write!(
             formatter,
-            "Invalid init kind '{}'. Valid kinds are: place, model",
-            self.attempted
+            "Invalid init kind '{}'. Valid kinds are: {}",
+            self.attempted,
+            valid_kinds.join(", ")
         )
     }
 }
@@ -190,6 +270,43 @@ pub struct ServeCommand {
     /// it has none.
     #[structopt(long)]
     pub port: Option<u16>,
+
+    /// A comma-separated list of classes to sync changes for, like
+    /// `Script,LocalScript,ModuleScript`. Defaults to syncing all instances.
+    #[structopt(long = "sync-classes", use_delimiter = true)]
+    pub sync_classes: Vec<String>,
+
+    /// The largest request body, in bytes, that the server will accept.
+    /// Defaults to 4 MiB.
+    #[structopt(long)]
+    pub max_body_size: Option<usize>,
+
+    /// Log the files that changes from Studio would write or delete instead of
+    /// touching them. Changes to files are still synced into Studio.
+    #[structopt(long)]
+    pub reverse_dry_run: bool,
+
+    /// The most instances that a single change from the filesystem can touch.
+    /// Larger changes, like deleting a whole folder, aren't synced until Rojo
+    /// is restarted. Defaults to no limit.
+    #[structopt(long)]
+    pub max_changes: Option<usize>,
+
+    /// Reject all changes from Studio. Clients are told that the server
+    /// doesn't support writing.
+    #[structopt(long)]
+    pub read_only: bool,
+
+    /// Host a page at http://localhost:<port>/ for browsing the served
+    /// instance tree.
+    #[structopt(long)]
+    pub web_ui: bool,
+
+    /// Retry reads of files that time out or are locked by another program,
+    /// like on a slow network drive, up to this many times. Defaults to not
+    /// retrying.
+    #[structopt(long)]
+    pub read_retries: Option<u32>,
 }
 
 impl ServeCommand {
@@ -209,9 +326,92 @@ pub struct BuildCommand {
     #[structopt(long, short)]
     pub output: PathBuf,
 
+    /// Write the built file to stdout instead of to --output. The kind of
+    /// file to build is still picked from the extension of --output.
+    #[structopt(long)]
+    pub stdout: bool,
+
     /// Whether to automatically rebuild when any input files change.
     #[structopt(long)]
     pub watch: bool,
+
+    /// If set, identical BinaryString values at least this many bytes long
+    /// are written once as a SharedString. Only affects XML output.
+    #[structopt(long)]
+    pub shared_string_threshold: Option<usize>,
+
+    /// If set, writes a profile of the build to this path in the Chrome Trace
+    /// Event format, which can be opened in chrome://tracing or Perfetto. With
+    /// --watch, the profile is replaced after every rebuild.
+    #[structopt(long)]
+    pub profile_out: Option<PathBuf>,
+
+    /// If set, the version of the file format to write. Rojo errors if it
+    /// can't write that version for the output file's format.
+    #[structopt(long)]
+    pub format_version: Option<u16>,
+
+    /// The size in bytes above which Rojo warns about the built file. Defaults
+    /// to 100 MiB.
+    #[structopt(long)]
+    pub max_output_size: Option<u64>,
+
+    /// If set, problems that are normally warnings, like the built file being
+    /// larger than --max-output-size, fail the build instead.
+    #[structopt(long)]
+    pub strict: bool,
+
+    /// Give every instance made from a file or folder a `__mtime` attribute
+    /// holding when it was last modified, in seconds since the Unix epoch.
+    /// This makes builds depend on when files were touched.
+    #[structopt(long)]
+    pub stamp_mtime: bool,
+
+    /// Use this number of seconds since the Unix epoch as the `__mtime` of
+    /// every instance instead of reading it from the filesystem, so that
+    /// builds stay reproducible. Implies --stamp-mtime.
+    #[structopt(long)]
+    pub fixed_mtime: Option<u64>,
+
+    /// Path to a JSON file mapping local mesh and texture paths, relative to
+    /// the project folder, to the IDs of the assets they were uploaded as.
+    /// MeshPart and SpecialMesh properties that refer to those paths are
+    /// rewritten to the uploaded assets.
+    #[structopt(long)]
+    pub resolve_content: Option<PathBuf>,
+
+    /// Write a manifest of the instances in the built file, with a hash of
+    /// each one, to this path. A later build can compare against it with
+    /// --since.
+    #[structopt(long)]
+    pub manifest_out: Option<PathBuf>,
+
+    /// Path to a manifest written by --manifest-out in an earlier build.
+    /// Instances that are new or have changed since then are reported.
+    #[structopt(long)]
+    pub since: Option<PathBuf>,
+
+    /// With --since, also write each instance that changed to this folder as
+    /// its own .rbxm file.
+    #[structopt(long, requires = "since")]
+    pub changed_out: Option<PathBuf>,
+
+    /// Leave out instances whose Archivable property is false, along with
+    /// their descendants, like Studio does when saving. Refs to them from
+    /// instances that are kept are cleared.
+    #[structopt(long)]
+    pub respect_archivable: bool,
+
+    /// Leave out properties that are set to the default value for their
+    /// class, like Studio does when saving.
+    #[structopt(long)]
+    pub strip_defaults: bool,
+
+    /// Retry reads of files that time out or are locked by another program,
+    /// like on a slow network drive, up to this many times. Defaults to not
+    /// retrying.
+    #[structopt(long)]
+    pub read_retries: Option<u32>,
 }
 
 impl BuildCommand {
@@ -220,6 +420,129 @@ impl BuildCommand {
     }
 }
 
+/// Load a Rojo project and report any problems with it.
+#[derive(Debug, StructOpt)]
+pub struct CheckCommand {
+    /// Path to the project to check. Defaults to the current directory.
+    #[structopt(default_value = "")]
+    pub project: PathBuf,
+
+    /// Whether to also check every instance's properties against the
+    /// properties that Roblox defines for its class.
+    #[structopt(long)]
+    pub schema: bool,
+
+    /// Whether to warn about Scripts and LocalScripts that aren't inside of a
+    /// container where Roblox would run them, like a Script in
+    /// ReplicatedStorage.
+    #[structopt(long)]
+    pub warn_unreachable: bool,
+
+    /// The class of another container that Scripts run in, for
+    /// --warn-unreachable. Can be given more than once.
+    #[structopt(long = "server-container", number_of_values = 1)]
+    pub server_containers: Vec<String>,
+
+    /// The class of another container that LocalScripts run in, for
+    /// --warn-unreachable. Can be given more than once.
+    #[structopt(long = "client-container", number_of_values = 1)]
+    pub client_containers: Vec<String>,
+}
+
+impl CheckCommand {
+    pub fn absolute_project(&self) -> Cow<'_, Path> {
+        resolve_path(&self.project)
+    }
+}
+
+/// Summarize what a Rojo project is made of.
+#[derive(Debug, StructOpt)]
+pub struct StatsCommand {
+    /// Path to the project to summarize. Defaults to the current directory.
+    #[structopt(default_value = "")]
+    pub project: PathBuf,
+}
+
+impl StatsCommand {
+    pub fn absolute_project(&self) -> Cow<'_, Path> {
+        resolve_path(&self.project)
+    }
+}
+
+/// Write a CSV file listing every script in a project.
+#[derive(Debug, StructOpt)]
+pub struct MetricsCommand {
+    /// Path to the project to measure. Defaults to the current directory.
+    #[structopt(default_value = "")]
+    pub project: PathBuf,
+
+    /// Where to write the CSV file.
+    #[structopt(long, short)]
+    pub output: PathBuf,
+}
+
+impl MetricsCommand {
+    pub fn absolute_project(&self) -> Cow<'_, Path> {
+        resolve_path(&self.project)
+    }
+}
+
+/// Measure how long Rojo takes to turn a project into instances.
+#[derive(Debug, StructOpt)]
+pub struct BenchCommand {
+    /// Path to the project to benchmark. Defaults to the current directory.
+    #[structopt(default_value = "")]
+    pub project: PathBuf,
+
+    /// How many times to snapshot and patch the project.
+    #[structopt(long, default_value = "10")]
+    pub iterations: usize,
+}
+
+impl BenchCommand {
+    pub fn absolute_project(&self) -> Cow<'_, Path> {
+        resolve_path(&self.project)
+    }
+}
+
+/// Build a Rojo project as it was at an earlier point in time.
+///
+/// The current version of the project is built first, then patched to match
+/// the target version, which is either a git ref or the path to another
+/// project.
+#[derive(Debug, StructOpt)]
+pub struct RevertToCommand {
+    /// The git ref to revert to, like `HEAD~1` or `v1.2.0`. If this is a path
+    /// to a project, that project is used instead.
+    pub target: String,
+
+    /// Path to the current project. Defaults to the current directory.
+    #[structopt(long, default_value = "")]
+    pub project: PathBuf,
+
+    /// Where to output the result.
+    #[structopt(long, short)]
+    pub output: PathBuf,
+}
+
+impl RevertToCommand {
+    pub fn absolute_project(&self) -> Cow<'_, Path> {
+        resolve_path(&self.project)
+    }
+
+    /// If the target of this command is a path that exists, returns the
+    /// absolute form of it.
+    pub fn target_path(&self) -> Option<PathBuf> {
+        let path = resolve_path(Path::new(&self.target));
+
+        if path.exists() {
+            Some(path.into_owned())
+        } else {
+            None
+        }
+    }
+}
+
 /// Build and upload a Rojo project to Roblox.com.
 #[derive(Debug, StructOpt)]
 pub struct UploadCommand {
@@ -285,6 +608,16 @@ impl fmt::Display for UploadKindParseError {
     }
 }
 
+/// How long Rojo waits before the first retry of a read when --read-retries
+/// is set. Each retry after that waits twice as long as the last.
+const READ_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+fn set_read_retries(vfs: &Vfs, retries: Option<u32>) {
+    if let Some(retries) = retries {
+        vfs.set_read_retries(retries, READ_RETRY_BACKOFF);
+    }
+}
+
 fn resolve_path(path: &Path) -> Cow<'_, Path> {
     if path.is_absolute() {
         Cow::Borrowed(path)
@@ -295,10 +628,15 @@ fn resolve_path(path: &Path) -> Cow<'_, Path> {
 
 #[derive(Debug, StructOpt)]
 pub enum PluginSubcommand {
-    /// Install the plugin in Roblox Studio's plugins folder. If the plugin is
-    /// already installed, installing it again will overwrite the current plugin
-    /// file.
-    Install,
+    /// Install the plugin in Roblox Studio's plugins folder, or the folder in
+    /// the ROJO_PLUGINS_DIR environment variable if it's set. If a different
+    /// version of the plugin is already installed, --force is required to
+    /// replace it.
+    Install {
+        /// Replace a different version of the plugin if one is installed.
+        #[structopt(long)]
+        force: bool,
+    },
 
     /// Removes the plugin if it is installed.
     Uninstall,
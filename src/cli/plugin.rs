@@ -1,11 +1,12 @@
 use std::{
-    fs::{self, File},
-    io::BufWriter,
+    env, fs,
+    path::{Path, PathBuf},
 };
 
 use anyhow::Result;
 use memofs::{InMemoryFs, Vfs, VfsSnapshot};
 use roblox_install::RobloxStudio;
+use thiserror::Error;
 
 use crate::{
     cli::{PluginCommand, PluginSubcommand},
@@ -15,49 +16,92 @@ use crate::{
 static PLUGIN_BINCODE: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/plugin.bincode"));
 static PLUGIN_FILE_NAME: &str = "RojoManagedPlugin.rbxm";
 
+/// If set, the plugin is installed into this folder instead of Roblox Studio's
+/// plugins folder.
+static PLUGINS_DIR_VAR: &str = "ROJO_PLUGINS_DIR";
+
+#[derive(Debug, Error)]
+enum Error {
+    #[error(
+        "A different version of Rojo's plugin is already installed at {}. \
+         Pass --force to replace it.",
+        .path.display()
+    )]
+    DifferentVersionInstalled { path: PathBuf },
+}
+
 pub fn plugin(options: PluginCommand) -> Result<()> {
     match options.subcommand {
-        PluginSubcommand::Install => install_plugin(),
+        PluginSubcommand::Install { force } => install_plugin(force),
         PluginSubcommand::Uninstall => uninstall_plugin(),
     }
 }
 
-pub fn install_plugin() -> Result<()> {
-    let plugin_snapshot: VfsSnapshot = bincode::deserialize(PLUGIN_BINCODE)
-        .expect("Rojo's plugin was not properly packed into Rojo's binary");
+/// Finds the folder that plugins should be installed into.
+fn plugins_folder() -> Result<PathBuf> {
+    if let Some(path) = env::var_os(PLUGINS_DIR_VAR) {
+        return Ok(PathBuf::from(path));
+    }
 
     let studio = RobloxStudio::locate()?;
+    Ok(studio.plugins_path().to_path_buf())
+}
 
-    let plugins_folder_path = studio.plugins_path();
+pub fn install_plugin(force: bool) -> Result<()> {
+    install_plugin_into(&plugins_folder()?, force)
+}
 
+/// Installs the plugin into the given plugins folder, creating it if needed.
+fn install_plugin_into(plugins_folder_path: &Path, force: bool) -> Result<()> {
     if !plugins_folder_path.exists() {
         log::debug!("Creating Roblox Studio plugins folder");
         fs::create_dir(plugins_folder_path)?;
     }
 
+    let plugin_path = plugins_folder_path.join(PLUGIN_FILE_NAME);
+    let contents = build_plugin()?;
+
+    if plugin_path.exists() {
+        if fs::read(&plugin_path)? == contents {
+            log::info!("Rojo's plugin is already up to date");
+            return Ok(());
+        }
+
+        if !force {
+            return Err(Error::DifferentVersionInstalled { path: plugin_path }.into());
+        }
+
+        log::info!("Replacing the installed version of Rojo's plugin");
+    }
+
+    log::debug!("Writing plugin to {}", plugin_path.display());
+    fs::write(&plugin_path, contents)?;
+
+    Ok(())
+}
+
+/// Builds the plugin that's packed into Rojo's binary into a model file.
+fn build_plugin() -> Result<Vec<u8>> {
+    let plugin_snapshot: VfsSnapshot = bincode::deserialize(PLUGIN_BINCODE)
+        .expect("Rojo's plugin was not properly packed into Rojo's binary");
+
     let mut in_memory_fs = InMemoryFs::new();
     in_memory_fs.load_snapshot("/plugin", plugin_snapshot)?;
 
     let vfs = Vfs::new(in_memory_fs);
     let session = ServeSession::new(vfs, "/plugin")?;
 
-    let plugin_path = plugins_folder_path.join(PLUGIN_FILE_NAME);
-    log::debug!("Writing plugin to {}", plugin_path.display());
-
-    let mut file = BufWriter::new(File::create(plugin_path)?);
-
     let tree = session.tree();
     let root_id = tree.get_root_id();
 
-    rbx_binary::encode(tree.inner(), &[root_id], &mut file)?;
+    let mut contents = Vec::new();
+    rbx_binary::encode(tree.inner(), &[root_id], &mut contents)?;
 
-    Ok(())
+    Ok(contents)
 }
 
 fn uninstall_plugin() -> Result<()> {
-    let studio = RobloxStudio::locate()?;
-
-    let plugin_path = studio.plugins_path().join(PLUGIN_FILE_NAME);
+    let plugin_path = plugins_folder()?.join(PLUGIN_FILE_NAME);
 
     if plugin_path.exists() {
         log::debug!("Removing existing plugin from {}", plugin_path.display());
@@ -68,3 +112,34 @@ fn uninstall_plugin() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn installed_plugin(plugins_folder: &Path) -> Vec<u8> {
+        fs::read(plugins_folder.join(PLUGIN_FILE_NAME)).unwrap()
+    }
+
+    #[test]
+    fn install_requires_force_to_replace() {
+        let dir = tempfile::tempdir().unwrap();
+        let plugins_folder = dir.path().join("Plugins");
+
+        install_plugin_into(&plugins_folder, false).unwrap();
+        let built = installed_plugin(&plugins_folder);
+        assert!(built.starts_with(b"<roblox!"));
+
+        // Installing the same version again is fine.
+        install_plugin_into(&plugins_folder, false).unwrap();
+
+        fs::write(plugins_folder.join(PLUGIN_FILE_NAME), b"an older version").unwrap();
+
+        let err = install_plugin_into(&plugins_folder, false).unwrap_err();
+        assert!(err.to_string().contains("--force"));
+        assert_eq!(installed_plugin(&plugins_folder), b"an older version");
+
+        install_plugin_into(&plugins_folder, true).unwrap();
+        assert_eq!(installed_plugin(&plugins_folder), built);
+    }
+}
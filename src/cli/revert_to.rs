@@ -0,0 +1,258 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
+
+use memofs::Vfs;
+use thiserror::Error;
+
+use crate::{
+    cli::{build::write_model, RevertToCommand},
+    serve_session::{ServeSession, ServeSessionError},
+    snapshot::{apply_patch_set, compute_patch_set, InstanceSnapshot},
+};
+
+#[derive(Debug, Error)]
+enum Error {
+    #[error("Running `git {args}` failed: {stderr}")]
+    Git { args: String, stderr: String },
+
+    #[error("The project at {} is not inside of the git repository at {}", .project.display(), .repo.display())]
+    ProjectNotInRepo { project: PathBuf, repo: PathBuf },
+}
+
+pub fn revert_to(options: RevertToCommand) -> Result<(), anyhow::Error> {
+    let project_path = fs::canonicalize(options.absolute_project())?;
+
+    let session = match options.target_path() {
+        Some(target_path) => {
+            log::debug!("Reverting to project at {}", target_path.display());
+
+            revert_session(
+                Vfs::new_default(),
+                &project_path,
+                Vfs::new_default(),
+                &target_path,
+            )?
+        }
+        None => {
+            log::debug!("Reverting to git ref {}", options.target);
+
+            let worktree = GitWorktree::checkout(&project_path, &options.target)?;
+            let target_path = worktree.path().join(worktree.relative_path(&project_path)?);
+
+            revert_session(
+                Vfs::new_default(),
+                &project_path,
+                Vfs::new_default(),
+                &target_path,
+            )?
+        }
+    };
+
+    let tree = session.tree();
+    write_model(&tree, &options.output, None, None)?;
+
+    Ok(())
+}
+
+/// Starts a serve session for the project at `current_path`, then patches its
+/// tree so that it matches the project at `target_path`.
+///
+/// The file watcher of the returned session isn't aware of the patch, so it
+/// should only be used to read the reverted tree.
+fn revert_session(
+    current_vfs: Vfs,
+    current_path: &Path,
+    target_vfs: Vfs,
+    target_path: &Path,
+) -> Result<ServeSession, ServeSessionError> {
+    current_vfs.set_watch_enabled(false);
+    target_vfs.set_watch_enabled(false);
+
+    let current = ServeSession::new(current_vfs, current_path)?;
+    let target = ServeSession::new(target_vfs, target_path)?;
+
+    {
+        let target_tree = target.tree();
+        let target_inner = target_tree.inner();
+        let target_snapshot = InstanceSnapshot::from_tree(target_inner, target_inner.get_root_id());
+
+        let mut current_tree = current.tree();
+        let root_id = current_tree.get_root_id();
+
+        log::trace!("Computing patch from current project to target");
+        let patch_set = compute_patch_set(&target_snapshot, &current_tree, root_id);
+
+        log::debug!(
+            "Reverting: {} additions, {} removals, {} updates",
+            patch_set.added_instances.len(),
+            patch_set.removed_instances.len(),
+            patch_set.updated_instances.len()
+        );
+
+        apply_patch_set(&mut current_tree, patch_set);
+    }
+
+    Ok(current)
+}
+
+/// A temporary git worktree containing a checkout of a project at a given ref.
+/// The worktree is removed when this value is dropped.
+struct GitWorktree {
+    repo_root: PathBuf,
+    path: PathBuf,
+}
+
+impl GitWorktree {
+    fn checkout(project_path: &Path, git_ref: &str) -> Result<Self, Error> {
+        let project_dir = if project_path.is_dir() {
+            project_path
+        } else {
+            project_path.parent().unwrap_or(project_path)
+        };
+
+        let repo_root = PathBuf::from(git(project_dir, &["rev-parse", "--show-toplevel"])?.trim());
+        let path = env::temp_dir().join(format!("rojo-revert-{}", process::id()));
+
+        let path_str = path.to_string_lossy().into_owned();
+        git(
+            &repo_root,
+            &["worktree", "add", "--detach", &path_str, git_ref],
+        )?;
+
+        Ok(GitWorktree { repo_root, path })
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Turns a path inside of the main repository checkout into the matching
+    /// path relative to the root of the worktree.
+    fn relative_path<'a>(&self, path: &'a Path) -> Result<&'a Path, Error> {
+        path.strip_prefix(&self.repo_root)
+            .map_err(|_| Error::ProjectNotInRepo {
+                project: path.to_owned(),
+                repo: self.repo_root.clone(),
+            })
+    }
+}
+
+impl Drop for GitWorktree {
+    fn drop(&mut self) {
+        let path_str = self.path.to_string_lossy().into_owned();
+
+        if let Err(err) = git(
+            &self.repo_root,
+            &["worktree", "remove", "--force", &path_str],
+        ) {
+            log::warn!("Could not clean up temporary git worktree: {}", err);
+        }
+    }
+}
+
+fn git(working_dir: &Path, args: &[&str]) -> Result<String, Error> {
+    log::trace!("Running git {}", args.join(" "));
+
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(working_dir)
+        .output()
+        .map_err(|err| Error::Git {
+            args: args.join(" "),
+            stderr: err.to_string(),
+        })?;
+
+    if !output.status.success() {
+        return Err(Error::Git {
+            args: args.join(" "),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use maplit::hashmap;
+    use memofs::{InMemoryFs, VfsSnapshot};
+    use rbx_dom_weak::RbxId;
+
+    use crate::snapshot::RojoTree;
+
+    fn project_vfs(source: &str, extra_module: &str) -> Vfs {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "revert",
+                        "tree": {
+                            "$path": "src"
+                        }
+                    }
+                "#),
+                "src" => VfsSnapshot::dir(hashmap! {
+                    "main.server.lua" => VfsSnapshot::file(source),
+                    extra_module => VfsSnapshot::file("return nil"),
+                }),
+            }),
+        )
+        .unwrap();
+
+        Vfs::new(imfs)
+    }
+
+    fn assert_trees_match(left: &RojoTree, left_id: RbxId, right: &RojoTree, right_id: RbxId) {
+        let left_instance = left.get_instance(left_id).unwrap();
+        let right_instance = right.get_instance(right_id).unwrap();
+
+        assert_eq!(left_instance.name(), right_instance.name());
+        assert_eq!(left_instance.class_name(), right_instance.class_name());
+        assert_eq!(left_instance.properties(), right_instance.properties());
+
+        let mut left_children: Vec<_> = left_instance.children().to_vec();
+        let mut right_children: Vec<_> = right_instance.children().to_vec();
+        assert_eq!(left_children.len(), right_children.len());
+
+        left_children.sort_by_key(|&id| left.get_instance(id).unwrap().name().to_owned());
+        right_children.sort_by_key(|&id| right.get_instance(id).unwrap().name().to_owned());
+
+        for (&left_child, &right_child) in left_children.iter().zip(&right_children) {
+            assert_trees_match(left, left_child, right, right_child);
+        }
+    }
+
+    #[test]
+    fn reverted_matches_target_build() {
+        crate::log_capture::init();
+
+        let reverted = revert_session(
+            project_vfs("print('new')", "added.lua"),
+            Path::new("/foo"),
+            project_vfs("print('old')", "removed.lua"),
+            Path::new("/foo"),
+        )
+        .unwrap();
+
+        let target_vfs = project_vfs("print('old')", "removed.lua");
+        target_vfs.set_watch_enabled(false);
+        let target = ServeSession::new(target_vfs, "/foo").unwrap();
+
+        let reverted_tree = reverted.tree();
+        let target_tree = target.tree();
+
+        assert_trees_match(
+            &reverted_tree,
+            reverted_tree.get_root_id(),
+            &target_tree,
+            target_tree.get_root_id(),
+        );
+    }
+}
@@ -8,8 +8,8 @@ use memofs::Vfs;
 use termcolor::{BufferWriter, Color, ColorChoice, ColorSpec, WriteColor};
 
 use crate::{
-    cli::{GlobalOptions, ServeCommand},
-    serve_session::ServeSession,
+    cli::{set_read_retries, GlobalOptions, ServeCommand},
+    serve_session::{ServeSession, ServeSessionOptions},
     web::LiveServer,
 };
 
@@ -17,23 +17,47 @@ const DEFAULT_PORT: u16 = 34872;
 
 pub fn serve(global: GlobalOptions, options: ServeCommand) -> Result<()> {
     let vfs = Vfs::new_default();
-
-    let session = Arc::new(ServeSession::new(vfs, &options.absolute_project())?);
+    set_read_retries(&vfs, options.read_retries);
+
+    let session_options = ServeSessionOptions {
+        sync_classes: if options.sync_classes.is_empty() {
+            None
+        } else {
+            Some(options.sync_classes.iter().cloned().collect())
+        },
+        reverse_dry_run: options.reverse_dry_run,
+        max_changes: options.max_changes,
+        property_migration: None,
+    };
+
+    let session = Arc::new(ServeSession::with_options(
+        vfs,
+        &options.absolute_project(),
+        session_options,
+    )?);
 
     let port = options
         .port
         .or_else(|| session.project_port())
         .unwrap_or(DEFAULT_PORT);
 
-    let server = LiveServer::new(session);
+    let mut server = LiveServer::new(session)
+        .read_only(options.read_only)
+        .web_ui(options.web_ui);
+
+    if let Some(max_body_size) = options.max_body_size {
+        server = server.max_body_size(max_body_size);
+    }
 
-    let _ = show_start_message(port, global.color.into());
+    if !global.quiet {
+        let _ = show_start_message(port, options.web_ui, global.color.into());
+    }
     server.start(port);
 
     Ok(())
 }
 
-fn show_start_message(port: u16, color: ColorChoice) -> io::Result<()> {
+fn show_start_message(port: u16, web_ui: bool, color: ColorChoice) -> io::Result<()> {
     let writer = BufferWriter::stdout(color);
     let mut buffer = writer.buffer();
 
@@ -48,16 +72,20 @@ fn show_start_message(port: u16, color: ColorChoice) -> io::Result<()> {
     buffer.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
     writeln!(&mut buffer, "{}", port)?;
 
-    writeln!(&mut buffer)?;
+    if web_ui {
+        writeln!(&mut buffer)?;
 
-    buffer.set_color(&ColorSpec::new())?;
-    write!(&mut buffer, "Visit ")?;
+        buffer.set_color(&ColorSpec::new())?;
+        write!(&mut buffer, "Visit ")?;
 
-    buffer.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
-    write!(&mut buffer, "http://localhost:{}/", port)?;
+        buffer.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+        write!(&mut buffer, "http://localhost:{}/", port)?;
 
-    buffer.set_color(&ColorSpec::new())?;
-    writeln!(&mut buffer, " in your browser for more information.")?;
+        buffer.set_color(&ColorSpec::new())?;
+        writeln!(&mut buffer, " in your browser to browse the instance tree.")?;
+    } else {
+        buffer.set_color(&ColorSpec::new())?;
+    }
 
     writer.print(&buffer)?;
 
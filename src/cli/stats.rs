@@ -0,0 +1,232 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt, io,
+    iter::once,
+    path::PathBuf,
+};
+
+use memofs::Vfs;
+
+use crate::{
+    cli::StatsCommand,
+    serve_session::ServeSession,
+    snapshot::{InstigatingSource, RojoTree},
+    snapshot_middleware::middleware_name,
+};
+
+pub fn stats(options: StatsCommand) -> Result<(), anyhow::Error> {
+    let vfs = Vfs::new_default();
+    vfs.set_watch_enabled(false);
+
+    let session = ServeSession::new(vfs, options.absolute_project())?;
+    let stats = ProjectStats::from_tree(&session.tree(), session.vfs())?;
+
+    print!("{}", stats);
+
+    Ok(())
+}
+
+/// What a project is made of: how many instances of each class it has, and how
+/// many files each middleware turned into instances.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ProjectStats {
+    pub classes: BTreeMap<String, usize>,
+    pub middleware: BTreeMap<&'static str, usize>,
+}
+
+impl ProjectStats {
+    pub fn from_tree(tree: &RojoTree, vfs: &Vfs) -> io::Result<Self> {
+        let mut stats = ProjectStats::default();
+
+        // Several instances can come from the same file, like the chunks of a
+        // large JSON file, but each file should only be counted once.
+        let mut sources: HashSet<(&'static str, PathBuf)> = HashSet::new();
+
+        let root_id = tree.get_root_id();
+        let root = tree.get_instance(root_id).unwrap();
+
+        for instance in once(root).chain(tree.descendants(root_id)) {
+            *stats
+                .classes
+                .entry(instance.class_name().to_owned())
+                .or_default() += 1;
+
+            match &instance.metadata().instigating_source {
+                Some(InstigatingSource::Path(path)) => {
+                    if let Some(name) = middleware_name(vfs, path)? {
+                        sources.insert((name, path.clone()));
+                    }
+                }
+                Some(InstigatingSource::ProjectNode(project_path, _, node, _)) => {
+                    sources.insert(("project", project_path.clone()));
+
+                    // Instances for nodes with a $path are made by whichever
+                    // middleware handles that path.
+                    if let Some(node_path) = &node.path {
+                        let path = project_path.with_file_name(node_path);
+
+                        if let Some(name) = middleware_name(vfs, &path)? {
+                            sources.insert((name, path));
+                        }
+                    }
+                }
+                None => {}
+            }
+        }
+
+        for (name, _) in sources {
+            *stats.middleware.entry(name).or_default() += 1;
+        }
+
+        Ok(stats)
+    }
+}
+
+impl fmt::Display for ProjectStats {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write_table(
+            formatter,
+            ("Class", "Instances"),
+            self.classes
+                .iter()
+                .map(|(name, &count)| (name.as_str(), count)),
+        )?;
+        writeln!(formatter)?;
+        write_table(
+            formatter,
+            ("Middleware", "Files"),
+            self.middleware.iter().map(|(&name, &count)| (name, count)),
+        )
+    }
+}
+
+/// Writes rows of names and counts as two aligned columns, with the largest
+/// counts first.
+fn write_table<'a>(
+    formatter: &mut fmt::Formatter,
+    headers: (&str, &str),
+    rows: impl Iterator<Item = (&'a str, usize)>,
+) -> fmt::Result {
+    let mut rows: Vec<_> = rows.collect();
+    rows.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+    let name_width = rows
+        .iter()
+        .map(|(name, _)| name.len())
+        .chain(once(headers.0.len()))
+        .max()
+        .unwrap();
+
+    let count_width = rows
+        .iter()
+        .map(|(_, count)| count.to_string().len())
+        .chain(once(headers.1.len()))
+        .max()
+        .unwrap();
+
+    writeln!(
+        formatter,
+        "{:<name_width$}  {:>count_width$}",
+        headers.0,
+        headers.1,
+        name_width = name_width,
+        count_width = count_width
+    )?;
+
+    for (name, count) in rows {
+        writeln!(
+            formatter,
+            "{:<name_width$}  {:>count_width$}",
+            name,
+            count,
+            name_width = name_width,
+            count_width = count_width
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use maplit::{btreemap, hashmap};
+    use memofs::{InMemoryFs, VfsSnapshot};
+
+    #[test]
+    fn counts_classes_and_middleware() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "stats",
+                        "tree": {
+                            "$className": "DataModel",
+                            "ReplicatedStorage": {
+                                "$className": "ReplicatedStorage",
+                                "Shared": {
+                                    "$path": "src"
+                                }
+                            }
+                        }
+                    }
+                "#),
+                "src" => VfsSnapshot::dir(hashmap! {
+                    "a.lua" => VfsSnapshot::file("return 1"),
+                    "b.server.lua" => VfsSnapshot::file("print(2)"),
+                    "data.json" => VfsSnapshot::file("[1, 2, 3]"),
+                    "Util" => VfsSnapshot::dir(hashmap! {
+                        "init.lua" => VfsSnapshot::file("return {}"),
+                    }),
+                }),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        vfs.set_watch_enabled(false);
+        let session = ServeSession::new(vfs, "/foo").unwrap();
+
+        let stats = ProjectStats::from_tree(&session.tree(), session.vfs()).unwrap();
+
+        assert_eq!(
+            stats,
+            ProjectStats {
+                classes: btreemap! {
+                    "DataModel".to_owned() => 1,
+                    "Folder".to_owned() => 1,
+                    "ModuleScript".to_owned() => 3,
+                    "ReplicatedStorage".to_owned() => 1,
+                    "Script".to_owned() => 1,
+                },
+                middleware: btreemap! {
+                    ".json" => 1,
+                    ".lua" => 2,
+                    "directory" => 1,
+                    "init script" => 1,
+                    "project" => 1,
+                },
+            }
+        );
+
+        assert_eq!(
+            stats.to_string(),
+            "Class              Instances\n\
+             ModuleScript               3\n\
+             DataModel                  1\n\
+             Folder                     1\n\
+             ReplicatedStorage          1\n\
+             Script                     1\n\
+             \n\
+             Middleware   Files\n\
+             .lua             2\n\
+             .json            1\n\
+             directory        1\n\
+             init script      1\n\
+             project          1\n"
+        );
+    }
+}
@@ -0,0 +1,266 @@
+//! Helpers for reading JSON files that users write and writing JSON files that
+//! Rojo produces, like build profiles.
+//!
+//! Output from these helpers always has its object keys sorted and uses the
+//! same formatting, so that running a command twice with the same input creates
+//! byte-for-byte identical files that diff well.
+//!
+//! Files ending in `.json5` are read leniently: they can contain comments,
+//! trailing commas, unquoted object keys, and single-quoted strings. Files
+//! ending in `.json` are always read as strict JSON.
+
+use std::{io::Write, path::Path};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Tells whether the file at the given path should be read as JSON5.
+pub fn is_json5(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |extension| extension == "json5")
+}
+
+/// Deserializes the contents of the file at `path`, as JSON5 if `is_json5`
+/// says so and as strict JSON otherwise.
+pub fn from_slice_for_path<T: DeserializeOwned>(
+    slice: &[u8],
+    path: &Path,
+) -> serde_json::Result<T> {
+    if is_json5(path) {
+        serde_json::from_slice(&json5_to_json(slice))
+    } else {
+        serde_json::from_slice(slice)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Container {
+    Object,
+    Array,
+}
+
+/// Rewrites the parts of JSON5 that serde_json understands into strict JSON.
+/// Comments and trailing commas are replaced with spaces, so error positions
+/// from serde_json stay on the right line.
+///
+/// Only comments, trailing commas, unquoted keys, and single-quoted strings
+/// are handled. Anything else, like hexadecimal numbers, is passed through and
+/// reported by serde_json as an error.
+fn json5_to_json(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut containers = Vec::new();
+    let mut expect_key = false;
+    let mut index = 0;
+
+    while index < input.len() {
+        let byte = input[index];
+
+        match byte {
+            b'"' | b'\'' => {
+                index = copy_string(input, index, &mut output);
+                expect_key = false;
+                continue;
+            }
+            b'/' if matches!(input.get(index + 1), Some(b'/') | Some(b'*')) => {
+                index = blank_comment(input, index, &mut output);
+                continue;
+            }
+            b',' => {
+                match next_significant(input, index + 1) {
+                    Some(b'}') | Some(b']') => output.push(b' '),
+                    _ => output.push(b','),
+                }
+                expect_key = containers.last() == Some(&Container::Object);
+            }
+            b'{' => {
+                containers.push(Container::Object);
+                expect_key = true;
+                output.push(byte);
+            }
+            b'[' => {
+                containers.push(Container::Array);
+                expect_key = false;
+                output.push(byte);
+            }
+            b'}' | b']' => {
+                containers.pop();
+                expect_key = false;
+                output.push(byte);
+            }
+            b':' => {
+                expect_key = false;
+                output.push(byte);
+            }
+            _ if expect_key && is_identifier_byte(byte) => {
+                let end = input[index..]
+                    .iter()
+                    .position(|&byte| !is_identifier_byte(byte))
+                    .map_or(input.len(), |length| index + length);
+
+                output.push(b'"');
+                output.extend_from_slice(&input[index..end]);
+                output.push(b'"');
+                expect_key = false;
+                index = end;
+                continue;
+            }
+            _ => output.push(byte),
+        }
+
+        index += 1;
+    }
+
+    output
+}
+
+fn is_identifier_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'$'
+}
+
+/// Copies the string starting at `start` into `output` as a double-quoted
+/// string and returns the index just after it.
+fn copy_string(input: &[u8], start: usize, output: &mut Vec<u8>) -> usize {
+    let quote = input[start];
+    let mut index = start + 1;
+    output.push(b'"');
+
+    while index < input.len() {
+        match input[index] {
+            b'\\' if quote == b'\'' && input.get(index + 1) == Some(&b'\'') => {
+                output.push(b'\'');
+                index += 2;
+            }
+            b'\\' => {
+                output.extend_from_slice(&input[index..(index + 2).min(input.len())]);
+                index += 2;
+            }
+            b'"' if quote == b'\'' => {
+                output.extend_from_slice(b"\\\"");
+                index += 1;
+            }
+            byte if byte == quote => {
+                output.push(b'"');
+                return index + 1;
+            }
+            byte => {
+                output.push(byte);
+                index += 1;
+            }
+        }
+    }
+
+    index
+}
+
+/// Replaces the comment starting at `start` with spaces, keeping newlines, and
+/// returns the index just after it.
+fn blank_comment(input: &[u8], start: usize, output: &mut Vec<u8>) -> usize {
+    let end = comment_end(input, start);
+
+    for &byte in &input[start..end] {
+        output.push(if byte == b'\n' { b'\n' } else { b' ' });
+    }
+
+    end
+}
+
+fn comment_end(input: &[u8], start: usize) -> usize {
+    let rest = &input[start + 2..];
+
+    let length = if input[start + 1] == b'/' {
+        rest.iter().position(|&byte| byte == b'\n')
+    } else {
+        rest.windows(2)
+            .position(|window| window == b"*/")
+            .map(|position| position + 2)
+    };
+
+    length.map_or(input.len(), |length| start + 2 + length)
+}
+
+/// Finds the next byte at or after `start` that isn't whitespace or part of a
+/// comment.
+fn next_significant(input: &[u8], mut start: usize) -> Option<u8> {
+    while let Some(&byte) = input.get(start) {
+        match byte {
+            b'/' if matches!(input.get(start + 1), Some(b'/') | Some(b'*')) => {
+                start = comment_end(input, start);
+            }
+            _ if byte.is_ascii_whitespace() => start += 1,
+            _ => return Some(byte),
+        }
+    }
+
+    None
+}
+
+/// Serializes `value` as pretty-printed JSON with sorted object keys, followed
+/// by a newline.
+pub fn to_writer_sorted<W, T>(mut writer: W, value: &T) -> serde_json::Result<()>
+where
+    W: Write,
+    T: Serialize + ?Sized,
+{
+    // serde_json::Value stores objects in a BTreeMap, since we don't enable
+    // serde_json's preserve_order feature. Going through it sorts the keys of
+    // every object, including ones that came from HashMaps.
+    let value = serde_json::to_value(value)?;
+
+    serde_json::to_writer_pretty(&mut writer, &value)?;
+    writer.write_all(b"\n").map_err(serde_json::Error::io)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use maplit::hashmap;
+
+    #[test]
+    fn output_is_sorted_and_identical() {
+        let write = |keys: &[&str]| {
+            let map: HashMap<_, _> = keys
+                .iter()
+                .map(|&key| (key, hashmap! { "z" => 1, "a" => 2 }))
+                .collect();
+
+            let mut output = Vec::new();
+            to_writer_sorted(&mut output, &map).unwrap();
+            output
+        };
+
+        let first = write(&["gamma", "alpha", "beta"]);
+        let second = write(&["beta", "gamma", "alpha"]);
+        assert_eq!(first, second);
+
+        let output = String::from_utf8(first).unwrap();
+        let alpha = output.find("alpha").unwrap();
+        let beta = output.find("beta").unwrap();
+        let gamma = output.find("gamma").unwrap();
+        assert!(alpha < beta && beta < gamma);
+        assert!(output.find("\"a\"").unwrap() < output.find("\"z\"").unwrap());
+        assert!(output.ends_with("}\n"));
+    }
+
+    #[test]
+    fn json5_translation() {
+        let input = br#"{
+            // A comment with a "quote", and a comma,
+            name: 'It\'s "here"',
+            $path: "src/*not a comment*/", /* trailing */
+            list: [true, false, null, 1,],
+        }"#;
+
+        let value: serde_json::Value = serde_json::from_slice(&json5_to_json(input)).unwrap();
+
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "name": "It's \"here\"",
+                "$path": "src/*not a comment*/",
+                "list": [true, false, null, 1],
+            })
+        );
+    }
+}
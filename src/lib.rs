@@ -4,6 +4,8 @@
 
 pub mod cli;
 
+#[cfg(test)]
+mod log_capture;
 #[cfg(test)]
 mod tree_view;
 
@@ -11,15 +13,19 @@ mod auth_cookie;
 mod change_processor;
 mod error;
 mod glob;
+mod json;
 mod lua_ast;
 mod message_queue;
 mod multimap;
 mod path_serializer;
 mod project;
+mod reflection_cache;
+mod resolution;
 mod serve_session;
 mod session_id;
 mod snapshot;
 mod snapshot_middleware;
+mod trace;
 mod web;
 
 pub use project::*;
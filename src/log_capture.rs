@@ -0,0 +1,71 @@
+//! Lets tests check what was logged while they ran.
+//!
+//! `log` only allows one logger per process and every test in the crate shares
+//! a process, so tests that want log output should call `init` instead of
+//! `env_logger::try_init`. Output still goes through env_logger as usual.
+
+use std::{cell::RefCell, sync::Once};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+thread_local! {
+    static CAPTURED: RefCell<Option<Vec<(Level, String)>>> = RefCell::new(None);
+}
+
+struct CaptureLogger {
+    inner: env_logger::Logger,
+}
+
+impl Log for CaptureLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        // Records need to be captured even if RUST_LOG would filter them out.
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        CAPTURED.with(|captured| {
+            if let Some(captured) = captured.borrow_mut().as_mut() {
+                captured.push((record.level(), record.args().to_string()));
+            }
+        });
+
+        if self.inner.matches(record) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Installs the capturing logger if it isn't installed already.
+pub fn init() {
+    static INIT: Once = Once::new();
+
+    INIT.call_once(|| {
+        let inner = env_logger::Builder::from_default_env()
+            .is_test(true)
+            .build();
+
+        log::set_boxed_logger(Box::new(CaptureLogger { inner }))
+            .expect("another logger was installed before log_capture::init");
+        log::set_max_level(LevelFilter::Trace);
+    });
+}
+
+/// Runs `f`, returning its result along with every message that was logged on
+/// the current thread while it ran. Messages logged on other threads, like
+/// middleware running with a timeout, aren't captured.
+pub fn capture<F, R>(f: F) -> (R, Vec<(Level, String)>)
+where
+    F: FnOnce() -> R,
+{
+    init();
+
+    CAPTURED.with(|captured| *captured.borrow_mut() = Some(Vec::new()));
+    let result = f();
+    let logs = CAPTURED.with(|captured| captured.borrow_mut().take().unwrap_or_default());
+
+    (result, logs)
+}
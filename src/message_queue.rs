@@ -1,23 +1,64 @@
 use std::{
+    collections::VecDeque,
     mem,
     sync::{Mutex, RwLock},
 };
 
 use futures::sync::oneshot;
+use thiserror::Error;
+
+/// Returned when asking for messages that a bounded queue no longer has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("Message cursor {cursor} is too old; the oldest message still kept is at cursor {oldest}")]
+pub struct ExpiredCursor {
+    pub cursor: u32,
+    pub oldest: u32,
+}
 
 struct Listener<T> {
     sender: oneshot::Sender<(u32, Vec<T>)>,
     cursor: u32,
 }
 
+/// The messages that a queue still has, which are always the most recent ones.
+#[derive(Default)]
+struct History<T> {
+    /// The cursor of the first message in `messages`. Messages before it were
+    /// dropped to stay under the queue's limit.
+    first_cursor: u32,
+    messages: VecDeque<T>,
+}
+
+impl<T: Clone> History<T> {
+    fn cursor(&self) -> u32 {
+        self.first_cursor + self.messages.len() as u32
+    }
+
+    fn check_cursor(&self, cursor: u32) -> Result<(), ExpiredCursor> {
+        if cursor < self.first_cursor {
+            Err(ExpiredCursor {
+                cursor,
+                oldest: self.first_cursor,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn messages_since(&self, cursor: u32) -> Vec<T> {
+        let skip = cursor.saturating_sub(self.first_cursor) as usize;
+        self.messages.iter().skip(skip).cloned().collect()
+    }
+}
+
 fn fire_listener_if_ready<T: Clone>(
-    messages: &[T],
+    history: &History<T>,
     listener: Listener<T>,
 ) -> Result<(), Listener<T>> {
-    let current_cursor = messages.len() as u32;
+    let current_cursor = history.cursor();
 
     if listener.cursor < current_cursor {
-        let new_messages = messages[(listener.cursor as usize)..].to_vec();
+        let new_messages = history.messages_since(listener.cursor);
         let _ = listener.sender.send((current_cursor, new_messages));
         Ok(())
     } else {
@@ -27,51 +68,82 @@ fn fire_listener_if_ready<T: Clone>(
 
 /// A message queue with persistent history that can be subscribed to.
 ///
+/// Queues made with `with_max_messages` only keep that many of the most recent
+/// messages, like a ring buffer. Cursors keep counting up as old messages are
+/// dropped, so asking for messages after a cursor that's too old is an error.
+///
 /// Definitely non-optimal. This would ideally be a lockless mpmc queue.
 #[derive(Default)]
 pub struct MessageQueue<T> {
-    messages: RwLock<Vec<T>>,
+    history: RwLock<History<T>>,
     message_listeners: Mutex<Vec<Listener<T>>>,
+    max_messages: Option<usize>,
 }
 
 impl<T: Clone> MessageQueue<T> {
     pub fn new() -> MessageQueue<T> {
         MessageQueue {
-            messages: RwLock::new(Vec::new()),
+            history: RwLock::new(History {
+                first_cursor: 0,
+                messages: VecDeque::new(),
+            }),
             message_listeners: Mutex::new(Vec::new()),
+            max_messages: None,
+        }
+    }
+
+    /// Creates a queue that only keeps the given number of the most recent
+    /// messages.
+    pub fn with_max_messages(max_messages: usize) -> MessageQueue<T> {
+        MessageQueue {
+            max_messages: Some(max_messages),
+            ..MessageQueue::new()
         }
     }
 
     pub fn push_messages(&self, new_messages: &[T]) {
         let mut message_listeners = self.message_listeners.lock().unwrap();
-        let mut messages = self.messages.write().unwrap();
-        messages.extend_from_slice(new_messages);
+        let mut history = self.history.write().unwrap();
+        history.messages.extend(new_messages.iter().cloned());
 
         let mut remaining_listeners = Vec::new();
 
         for listener in message_listeners.drain(..) {
-            match fire_listener_if_ready(&messages, listener) {
+            match fire_listener_if_ready(&history, listener) {
                 Ok(_) => {}
                 Err(listener) => remaining_listeners.push(listener),
             }
         }
 
+        // Listeners are fired first so that they get every new message, even
+        // if there are more of them than the queue keeps.
+        if let Some(max_messages) = self.max_messages {
+            while history.messages.len() > max_messages {
+                history.messages.pop_front();
+                history.first_cursor += 1;
+            }
+        }
+
         // Without this annotation, Rust gets confused since the first argument
         // is a MutexGuard, but the second is a Vec.
         mem::replace::<Vec<_>>(&mut message_listeners, remaining_listeners);
     }
 
     /// Subscribe to any messages occurring after the given message cursor.
-    pub fn subscribe(&self, cursor: u32) -> oneshot::Receiver<(u32, Vec<T>)> {
+    pub fn subscribe(
+        &self,
+        cursor: u32,
+    ) -> Result<oneshot::Receiver<(u32, Vec<T>)>, ExpiredCursor> {
         let (sender, receiver) = oneshot::channel();
 
         let listener = {
             let listener = Listener { sender, cursor };
 
-            let messages = self.messages.read().unwrap();
+            let history = self.history.read().unwrap();
+            history.check_cursor(cursor)?;
 
-            match fire_listener_if_ready(&messages, listener) {
-                Ok(_) => return receiver,
+            match fire_listener_if_ready(&history, listener) {
+                Ok(_) => return Ok(receiver),
                 Err(listener) => listener,
             }
         };
@@ -79,7 +151,16 @@ impl<T: Clone> MessageQueue<T> {
         let mut message_listeners = self.message_listeners.lock().unwrap();
         message_listeners.push(listener);
 
-        receiver
+        Ok(receiver)
+    }
+
+    /// Returns every message after the given cursor along with the current
+    /// cursor, without waiting for new messages if there aren't any.
+    pub fn messages_since(&self, cursor: u32) -> Result<(u32, Vec<T>), ExpiredCursor> {
+        let history = self.history.read().unwrap();
+        history.check_cursor(cursor)?;
+
+        Ok((history.cursor(), history.messages_since(cursor)))
     }
 
     /// Subscribe to any messages being pushed into the queue.
@@ -88,15 +169,40 @@ impl<T: Clone> MessageQueue<T> {
     /// instead.
     #[cfg(test)]
     pub fn subscribe_any(&self) -> oneshot::Receiver<(u32, Vec<T>)> {
-        let cursor = {
-            let messages = self.messages.read().unwrap();
-            messages.len() as u32
-        };
-
-        self.subscribe(cursor)
+        self.subscribe(self.cursor()).unwrap()
     }
 
     pub fn cursor(&self) -> u32 {
-        self.messages.read().unwrap().len() as u32
+        self.history.read().unwrap().cursor()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn messages_since_cursor() {
+        let queue = MessageQueue::with_max_messages(2);
+
+        queue.push_messages(&["first"]);
+        queue.push_messages(&["second"]);
+
+        assert_eq!(queue.messages_since(0), Ok((2, vec!["first", "second"])));
+        assert_eq!(queue.messages_since(1), Ok((2, vec!["second"])));
+        assert_eq!(queue.messages_since(2), Ok((2, vec![])));
+
+        // Only the two most recent messages are kept.
+        queue.push_messages(&["third"]);
+
+        assert_eq!(
+            queue.messages_since(0),
+            Err(ExpiredCursor {
+                cursor: 0,
+                oldest: 1
+            })
+        );
+        assert!(queue.subscribe(0).is_err());
+        assert_eq!(queue.messages_since(1), Ok((3, vec!["second", "third"])));
     }
 }
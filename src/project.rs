@@ -1,14 +1,14 @@
 use std::{
     collections::{BTreeMap, HashMap, HashSet},
-    fs, io,
-    path::{Path, PathBuf},
+    fmt, fs, io,
+    path::{Component, Path, PathBuf},
 };
 
 use rbx_dom_weak::UnresolvedRbxValue;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::glob::Glob;
+use crate::{glob::Glob, json};
 
 static PROJECT_FILENAME: &str = "default.project.json";
 
@@ -30,11 +30,23 @@ enum Error {
         source: serde_json::Error,
         path: PathBuf,
     },
+
+    #[error(
+        "The $path of {node} in project {}, {}, is outside of the folder the project is in. \
+         Set allowPathsOutsideProject to true to allow this.",
+        .project.display(), .path.display()
+    )]
+    PathOutsideProject {
+        node: String,
+        path: PathBuf,
+        project: PathBuf,
+    },
 }
 
 /// Contains all of the configuration for a Rojo-managed project.
 ///
-/// Project files are stored in `.project.json` files.
+/// Project files are stored in `.project.json` files, or `.project.json5` files
+/// for projects written as JSON5.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct Project {
@@ -63,6 +75,87 @@ pub struct Project {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub glob_ignore_paths: Vec<Glob>,
 
+    /// If specified, whether this project describes a place or a model. Rojo
+    /// checks that the root instance is a DataModel for places and isn't one
+    /// for models, and only builds the project to matching files. If unset,
+    /// any root is allowed and can be built to any kind of file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root_kind: Option<RootKind>,
+
+    /// Defines what Rojo should do when a `$path` in this project refers to a
+    /// file or directory that does not exist. If unset, Rojo will use the
+    /// value inherited from a parent project, or `error`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub missing_path_behavior: Option<MissingPathBehavior>,
+
+    /// Defines what Rojo should do when an update changes the type of one of
+    /// an instance's properties, which usually means that a file describes the
+    /// property incorrectly. If unset, Rojo will use the value inherited from a
+    /// parent project, or `allow`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub property_type_change_behavior: Option<PropertyTypeChangeBehavior>,
+
+    /// Defines what Rojo should do with files that it doesn't know how to turn
+    /// into instances. If unset, Rojo will use the value inherited from a
+    /// parent project, or `ignore`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unknown_file_behavior: Option<UnknownFileBehavior>,
+
+    /// Whether Rojo should keep a leading UTF-8 byte order mark in the
+    /// contents of Lua files instead of stripping it. If unset, Rojo will use
+    /// the value inherited from a parent project, or `false`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_source_bom: Option<bool>,
+
+    /// The kind of script that Lua files without a `.server` or `.client`
+    /// suffix turn into. If unset, Rojo will use the value inherited from a
+    /// parent project, or `module`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_script_kind: Option<ScriptKind>,
+
+    /// If specified, ModuleScripts generated from data files, like `.json`
+    /// files, whose Source would be longer than this many bytes are split into
+    /// several child ModuleScripts. If unset, Rojo will use the value inherited
+    /// from a parent project, or never split them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_generated_source_size: Option<usize>,
+
+    /// If specified, JSON models that nest instances more than this many
+    /// levels below their top-level instance fail to load. If unset, Rojo
+    /// will use the value inherited from a parent project, or only limit them
+    /// as much as the JSON parser does.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_model_depth: Option<usize>,
+
+    /// If specified, the number of seconds that Rojo may spend turning a single
+    /// file into instances before giving up on it. If unset, Rojo will use the
+    /// value inherited from a parent project, or 30 seconds. Setting this to 0
+    /// turns off the limit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub middleware_timeout: Option<u64>,
+
+    /// If set to `true`, Rojo will not warn when multiple nodes in this project
+    /// have `$path` values that refer to the same or overlapping locations.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_path_aliasing: bool,
+
+    /// If set to `true`, `$path` values in this project may refer to files and
+    /// directories outside of the folder that the project file is in.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_paths_outside_project: bool,
+
+    /// A list of rules that set properties on every instance of a class after
+    /// the project has been turned into instances. Rules are applied in order,
+    /// so later rules override earlier ones.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transforms: Vec<TransformRule>,
+
+    /// How Rojo should compare the values of specific string properties when
+    /// deciding whether they changed, keyed by `ClassName.PropertyName`.
+    /// Properties that aren't listed are compared exactly.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub property_comparisons: HashMap<String, PropertyComparison>,
+
     /// The path to the file that this project came from. Relative paths in the
     /// project should be considered relative to the parent of this field, also
     /// given by `Project::folder_location`.
@@ -75,7 +168,18 @@ impl Project {
     pub fn is_project_file(path: &Path) -> bool {
         path.file_name()
             .and_then(|name| name.to_str())
-            .map(|name| name.ends_with(".project.json"))
+            .map(|name| name.ends_with(".project.json") || name.ends_with(".project.json5"))
+            .unwrap_or(false)
+    }
+
+    /// Tells whether the given path is a local overlay for a project, like
+    /// `default.project.local.json`, as named by `local_overlay_path`.
+    pub fn is_local_overlay(path: &Path) -> bool {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| {
+                name.ends_with(".project.local.json") || name.ends_with(".project.local.json5")
+            })
             .unwrap_or(false)
     }
 
@@ -108,17 +212,79 @@ impl Project {
         }
     }
 
+    /// Gives the path of the local overlay for the project at the given path.
+    /// The overlay for `default.project.json` is `default.project.local.json`,
+    /// and the overlay for `default.project.json5` is
+    /// `default.project.local.json5`.
+    ///
+    /// Overlays are meant to be left out of source control, so that each
+    /// person working on a project can change settings without editing the
+    /// shared project file.
+    pub fn local_overlay_path(project_file_location: &Path) -> PathBuf {
+        let file_name = project_file_location
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        let extension = if json::is_json5(project_file_location) {
+            "json5"
+        } else {
+            "json"
+        };
+
+        let suffix = format!(".{}", extension);
+        let stem = if file_name.ends_with(&suffix) {
+            &file_name[..file_name.len() - suffix.len()]
+        } else {
+            file_name
+        };
+
+        project_file_location.with_file_name(format!("{}.local.{}", stem, extension))
+    }
+
     pub fn load_from_slice(
         contents: &[u8],
         project_file_location: &Path,
     ) -> Result<Self, ProjectError> {
-        let mut project: Self =
-            serde_json::from_slice(&contents).map_err(|source| Error::Json {
-                source,
-                path: project_file_location.to_owned(),
+        Self::load_with_overlay(contents, None, project_file_location)
+    }
+
+    /// Loads a project like `load_from_slice`, deep-merging the contents of its
+    /// local overlay into it first if there is one. Values from the overlay
+    /// replace values in the project, except for objects, which are merged.
+    pub fn load_with_overlay(
+        contents: &[u8],
+        overlay_contents: Option<&[u8]>,
+        project_file_location: &Path,
+    ) -> Result<Self, ProjectError> {
+        let mut value: serde_json::Value =
+            json::from_slice_for_path(contents, project_file_location).map_err(|source| {
+                Error::Json {
+                    source,
+                    path: project_file_location.to_owned(),
+                }
             })?;
 
+        if let Some(overlay_contents) = overlay_contents {
+            let overlay_path = Self::local_overlay_path(project_file_location);
+            let overlay =
+                json::from_slice_for_path(overlay_contents, &overlay_path).map_err(|source| {
+                    Error::Json {
+                        source,
+                        path: overlay_path.clone(),
+                    }
+                })?;
+
+            log::debug!("Applying project overlay {}", overlay_path.display());
+            merge_json(&mut value, overlay);
+        }
+
+        let mut project: Self = serde_json::from_value(value).map_err(|source| Error::Json {
+            source,
+            path: project_file_location.to_owned(),
+        })?;
+
         project.file_location = project_file_location.to_path_buf();
+        project.normalize_paths()?;
         project.check_compatibility();
         Ok(project)
     }
@@ -133,25 +299,94 @@ impl Project {
         }
     }
 
-    fn load_exact(project_file_location: &Path) -> Result<Self, Error> {
-        let contents = fs::read_to_string(project_file_location)?;
+    fn load_exact(project_file_location: &Path) -> Result<Self, ProjectError> {
+        let contents = fs::read(project_file_location).map_err(Error::from)?;
 
-        let mut project: Project =
-            serde_json::from_str(&contents).map_err(|source| Error::Json {
-                source,
-                path: project_file_location.to_owned(),
-            })?;
+        let overlay_path = Self::local_overlay_path(project_file_location);
+        let overlay_contents = match fs::read(&overlay_path) {
+            Ok(contents) => Some(contents),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(Error::from(err).into()),
+        };
 
-        project.file_location = project_file_location.to_path_buf();
-        project.check_compatibility();
+        Self::load_with_overlay(
+            &contents,
+            overlay_contents.as_deref(),
+            project_file_location,
+        )
+    }
 
-        Ok(project)
+    /// Normalizes every `$path` in the project so that they're written the same
+    /// way on every platform, and checks that they stay inside of the
+    /// project's folder unless `allow_paths_outside_project` is set.
+    fn normalize_paths(&mut self) -> Result<(), Error> {
+        let project_folder = normalize_path(self.folder_location());
+        let mut outside_paths = Vec::new();
+
+        self.tree
+            .normalize_paths(&self.name, &project_folder, &mut outside_paths);
+
+        match outside_paths.into_iter().next() {
+            Some((node, path)) if !self.allow_paths_outside_project => {
+                Err(Error::PathOutsideProject {
+                    node,
+                    path,
+                    project: self.file_location.clone(),
+                })
+            }
+            _ => Ok(()),
+        }
     }
 
     /// Checks if there are any compatibility issues with this project file and
     /// warns the user if there are any.
     fn check_compatibility(&self) {
         self.tree.validate_reserved_names();
+
+        if !self.allow_path_aliasing {
+            for overlap in self.find_path_overlaps() {
+                log::warn!(
+                    "Multiple project nodes refer to overlapping paths, which may cause \
+                     the same files to be turned into instances more than once:"
+                );
+                log::warn!(
+                    "  {} has $path {}",
+                    overlap.first_node,
+                    overlap.first_path.display()
+                );
+                log::warn!(
+                    "  {} has $path {}",
+                    overlap.second_node,
+                    overlap.second_path.display()
+                );
+                log::warn!("If this is intentional, set allowPathAliasing to true in the project.");
+            }
+        }
+    }
+
+    /// Finds all pairs of nodes in this project whose `$path` values refer to
+    /// the same location, or where one is contained inside the other.
+    pub fn find_path_overlaps(&self) -> Vec<PathOverlap> {
+        let mut node_paths = Vec::new();
+        self.tree
+            .collect_paths(&self.name, self.folder_location(), &mut node_paths);
+
+        let mut overlaps = Vec::new();
+
+        for (index, (first_node, first_path)) in node_paths.iter().enumerate() {
+            for (second_node, second_path) in &node_paths[index + 1..] {
+                if first_path.starts_with(second_path) || second_path.starts_with(first_path) {
+                    overlaps.push(PathOverlap {
+                        first_node: first_node.clone(),
+                        first_path: first_path.clone(),
+                        second_node: second_node.clone(),
+                        second_path: second_path.clone(),
+                    });
+                }
+            }
+        }
+
+        overlaps
     }
 
     pub fn folder_location(&self) -> &Path {
@@ -159,6 +394,220 @@ impl Project {
     }
 }
 
+/// Describes two project nodes whose `$path` values overlap, as found by
+/// `Project::find_path_overlaps`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathOverlap {
+    /// The instance path of the first node, like `Project/ReplicatedStorage`.
+    pub first_node: String,
+
+    /// The resolved `$path` of the first node.
+    pub first_path: PathBuf,
+
+    /// The instance path of the second node.
+    pub second_node: String,
+
+    /// The resolved `$path` of the second node.
+    pub second_path: PathBuf,
+}
+
+/// A rule from a project's `transforms` list, like
+/// `{ "classMatch": "Part", "set": { "CastShadow": false } }`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct TransformRule {
+    /// The ClassName of the instances that this rule applies to.
+    pub class_match: String,
+
+    /// If set to `true`, this rule also applies to instances whose class
+    /// inherits from `class_match`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub include_subclasses: bool,
+
+    /// The properties to set on each matching instance.
+    pub set: HashMap<String, UnresolvedRbxValue>,
+}
+
+/// The kinds of file that a project can describe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RootKind {
+    /// The root instance must be a DataModel, which is built to a place file.
+    Place,
+
+    /// The root instance must not be a DataModel, and is built to a model
+    /// file.
+    Model,
+}
+
+impl RootKind {
+    /// Tells whether an instance of the given class can be the root of a
+    /// project of this kind.
+    pub fn allows_root_class(self, class_name: &str) -> bool {
+        match self {
+            RootKind::Place => class_name == "DataModel",
+            RootKind::Model => class_name != "DataModel",
+        }
+    }
+}
+
+impl fmt::Display for RootKind {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RootKind::Place => write!(formatter, "place"),
+            RootKind::Model => write!(formatter, "model"),
+        }
+    }
+}
+
+/// The possible behaviors when a project node's `$path` does not exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MissingPathBehavior {
+    /// Fail to load the project.
+    Error,
+
+    /// Log a warning and leave out the node that referred to the path.
+    Warn,
+
+    /// Create an empty instance using the node's `$className`, which must be
+    /// specified.
+    CreateEmpty,
+}
+
+impl Default for MissingPathBehavior {
+    fn default() -> Self {
+        MissingPathBehavior::Error
+    }
+}
+
+impl MissingPathBehavior {
+    pub(crate) fn is_default(&self) -> bool {
+        *self == MissingPathBehavior::default()
+    }
+}
+
+/// The possible behaviors when an update gives a property a value of a
+/// different type than it had before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PropertyTypeChangeBehavior {
+    /// Apply the change without saying anything.
+    Allow,
+
+    /// Apply the change and log a warning.
+    Warn,
+
+    /// Log an error and keep the property's old value.
+    Reject,
+}
+
+impl Default for PropertyTypeChangeBehavior {
+    fn default() -> Self {
+        PropertyTypeChangeBehavior::Allow
+    }
+}
+
+impl PropertyTypeChangeBehavior {
+    pub(crate) fn is_default(&self) -> bool {
+        *self == PropertyTypeChangeBehavior::default()
+    }
+}
+
+/// The ways that the values of string properties can be compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PropertyComparison {
+    /// Values must be exactly the same.
+    Exact,
+
+    /// Leading and trailing whitespace is ignored.
+    Trimmed,
+
+    /// Differences in ASCII letter case are ignored.
+    CaseInsensitive,
+}
+
+impl Default for PropertyComparison {
+    fn default() -> Self {
+        PropertyComparison::Exact
+    }
+}
+
+impl PropertyComparison {
+    pub fn matches(self, a: &str, b: &str) -> bool {
+        match self {
+            PropertyComparison::Exact => a == b,
+            PropertyComparison::Trimmed => a.trim() == b.trim(),
+            PropertyComparison::CaseInsensitive => a.eq_ignore_ascii_case(b),
+        }
+    }
+}
+
+/// The possible behaviors for files that no middleware knows how to handle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UnknownFileBehavior {
+    /// Turn the file into a ModuleScript with the file's contents as its
+    /// Source.
+    ModuleScript,
+
+    /// Turn the file into a StringValue with the file's contents as its Value.
+    StringValue,
+
+    /// Leave the file out of the project.
+    Ignore,
+
+    /// Fail to load the project.
+    Error,
+}
+
+impl Default for UnknownFileBehavior {
+    fn default() -> Self {
+        UnknownFileBehavior::Ignore
+    }
+}
+
+impl UnknownFileBehavior {
+    pub(crate) fn is_default(&self) -> bool {
+        *self == UnknownFileBehavior::default()
+    }
+}
+
+/// The kinds of scripts that a Lua file can turn into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ScriptKind {
+    /// A ModuleScript.
+    Module,
+
+    /// A Script, which runs on the server.
+    Server,
+
+    /// A LocalScript, which runs on the client.
+    Client,
+}
+
+impl Default for ScriptKind {
+    fn default() -> Self {
+        ScriptKind::Module
+    }
+}
+
+impl ScriptKind {
+    pub fn class_name(self) -> &'static str {
+        match self {
+            ScriptKind::Module => "ModuleScript",
+            ScriptKind::Server => "Script",
+            ScriptKind::Client => "LocalScript",
+        }
+    }
+
+    pub(crate) fn is_default(&self) -> bool {
+        *self == ScriptKind::default()
+    }
+}
+
 /// Describes an instance and its descendants in a project.
 #[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct ProjectNode {
@@ -215,9 +664,73 @@ pub struct ProjectNode {
         skip_serializing_if = "Option::is_none"
     )]
     pub path: Option<PathBuf>,
+
+    /// If set to `true`, a `$path` that doesn't exist causes a warning and
+    /// leaves this node out, no matter what `missingPathBehavior` is. This is
+    /// meant for folders that are only present on some machines.
+    #[serde(
+        rename = "$optional",
+        default,
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub optional: bool,
 }
 
 impl ProjectNode {
+    /// Collects the instance path and resolved `$path` of this node and all of
+    /// its descendants that have a `$path`.
+    fn collect_paths(
+        &self,
+        node_name: &str,
+        project_folder: &Path,
+        output: &mut Vec<(String, PathBuf)>,
+    ) {
+        if let Some(path) = &self.path {
+            output.push((node_name.to_owned(), project_folder.join(path)));
+        }
+
+        for (child_name, child) in &self.children {
+            let child_node_name = format!("{}/{}", node_name, child_name);
+            child.collect_paths(&child_node_name, project_folder, output);
+        }
+    }
+
+    /// Normalizes the `$path` of this node and all of its descendants,
+    /// collecting the instance path and `$path` of any node whose `$path` is
+    /// outside of the project folder.
+    fn normalize_paths(
+        &mut self,
+        node_name: &str,
+        project_folder: &Path,
+        outside_paths: &mut Vec<(String, PathBuf)>,
+    ) {
+        if let Some(path) = &mut self.path {
+            *path = normalize_path(path);
+
+            // Normalized relative paths can only have `..` components at the
+            // start, so they leave the project folder exactly when they begin
+            // with one.
+            let is_outside = if path.is_absolute() {
+                !path.starts_with(project_folder)
+            } else {
+                path.components().next() == Some(Component::ParentDir)
+            };
+
+            if is_outside {
+                outside_paths.push((node_name.to_owned(), path.clone()));
+            }
+
+            if path.as_os_str().is_empty() {
+                *path = PathBuf::from(".");
+            }
+        }
+
+        for (child_name, child) in &mut self.children {
+            let child_node_name = format!("{}/{}", node_name, child_name);
+            child.normalize_paths(&child_node_name, project_folder, outside_paths);
+        }
+    }
+
     fn validate_reserved_names(&self) {
         for (name, child) in &self.children {
             if name.starts_with('$') {
@@ -234,3 +747,320 @@ impl ProjectNode {
         }
     }
 }
+
+/// Turns backslashes into path separators, then removes trailing separators,
+/// `.` components, and any `..` components that follow a normal component.
+///
+/// This is lexical, so the result refers to the same file as long as no
+/// component before a `..` is a symlink. Backslashes are valid in file names
+/// on Unix, but project files are shared between platforms, so we always treat
+/// them as separators.
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    let unified = path.to_string_lossy().replace('\\', "/");
+    let mut components: Vec<Component> = Vec::new();
+
+    for component in Path::new(&unified).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match components.last() {
+                Some(Component::Normal(_)) => {
+                    components.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => components.push(component),
+            },
+            _ => components.push(component),
+        }
+    }
+
+    components.iter().collect()
+}
+
+/// Merges `overlay` into `base`. Objects are merged key by key, and any other
+/// value in the overlay replaces the one in the base.
+fn merge_json(base: &mut serde_json::Value, overlay: serde_json::Value) {
+    match (base, overlay) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn path_overlap_same_directory() {
+        let project = Project::load_from_slice(
+            br#"
+                {
+                    "name": "overlap",
+                    "tree": {
+                        "$className": "DataModel",
+                        "ReplicatedStorage": {
+                            "Shared": {
+                                "$path": "src/shared"
+                            }
+                        },
+                        "ServerStorage": {
+                            "AlsoShared": {
+                                "$path": "src/shared"
+                            }
+                        }
+                    }
+                }
+            "#,
+            Path::new("/foo/default.project.json"),
+        )
+        .unwrap();
+
+        let overlaps = project.find_path_overlaps();
+
+        assert_eq!(
+            overlaps,
+            vec![PathOverlap {
+                first_node: "overlap/ReplicatedStorage/Shared".to_owned(),
+                first_path: PathBuf::from("/foo/src/shared"),
+                second_node: "overlap/ServerStorage/AlsoShared".to_owned(),
+                second_path: PathBuf::from("/foo/src/shared"),
+            }]
+        );
+    }
+
+    #[test]
+    fn path_overlap_nested_directory() {
+        let project = Project::load_from_slice(
+            br#"
+                {
+                    "name": "overlap",
+                    "tree": {
+                        "$path": "src",
+                        "Inner": {
+                            "$path": "src/inner"
+                        },
+                        "Other": {
+                            "$path": "other"
+                        }
+                    }
+                }
+            "#,
+            Path::new("/foo/default.project.json"),
+        )
+        .unwrap();
+
+        let overlaps = project.find_path_overlaps();
+
+        assert_eq!(overlaps.len(), 1);
+        assert_eq!(overlaps[0].first_node, "overlap");
+        assert_eq!(overlaps[0].second_node, "overlap/Inner");
+    }
+
+    #[test]
+    fn local_overlay_is_merged() {
+        let project = Project::load_with_overlay(
+            br#"
+                {
+                    "name": "shared",
+                    "servePort": 34872,
+                    "tree": {
+                        "$className": "DataModel",
+                        "ReplicatedStorage": {
+                            "$path": "src/shared"
+                        },
+                        "ServerScriptService": {
+                            "$path": "src/server"
+                        }
+                    }
+                }
+            "#,
+            Some(
+                br#"
+                    {
+                        "servePort": 40000,
+                        "tree": {
+                            "ReplicatedStorage": {
+                                "$path": "local/shared"
+                            }
+                        }
+                    }
+                "#,
+            ),
+            Path::new("/foo/default.project.json"),
+        )
+        .unwrap();
+
+        assert_eq!(project.name, "shared");
+        assert_eq!(project.serve_port, Some(40000));
+        assert_eq!(project.tree.class_name.as_deref(), Some("DataModel"));
+
+        let replicated_storage = &project.tree.children["ReplicatedStorage"];
+        assert_eq!(
+            replicated_storage.path.as_deref(),
+            Some(Path::new("local/shared"))
+        );
+
+        let server = &project.tree.children["ServerScriptService"];
+        assert_eq!(server.path.as_deref(), Some(Path::new("src/server")));
+    }
+
+    #[test]
+    fn local_overlay_path() {
+        assert_eq!(
+            Project::local_overlay_path(Path::new("/foo/default.project.json")),
+            PathBuf::from("/foo/default.project.local.json")
+        );
+        assert_eq!(
+            Project::local_overlay_path(Path::new("/foo/default.project.json5")),
+            PathBuf::from("/foo/default.project.local.json5")
+        );
+    }
+
+    #[test]
+    fn is_local_overlay() {
+        assert!(Project::is_local_overlay(Path::new(
+            "/foo/default.project.local.json"
+        )));
+        assert!(Project::is_local_overlay(Path::new(
+            "/foo/default.project.local.json5"
+        )));
+        assert!(!Project::is_local_overlay(Path::new(
+            "/foo/default.project.json"
+        )));
+        assert!(!Project::is_local_overlay(Path::new("/foo/local.json")));
+    }
+
+    #[test]
+    fn json5_project() {
+        let strict = Project::load_from_slice(
+            br#"
+                {
+                    "name": "lenient",
+                    "servePort": 1234,
+                    "tree": {
+                        "$className": "DataModel",
+                        "ReplicatedStorage": {
+                            "$path": "src"
+                        }
+                    }
+                }
+            "#,
+            Path::new("/foo/default.project.json"),
+        )
+        .unwrap();
+
+        let lenient = Project::load_from_slice(
+            br#"
+                // Comments, trailing commas, and unquoted keys are allowed.
+                {
+                    name: 'lenient',
+                    servePort: 1234, /* the usual port is 34872 */
+                    tree: {
+                        $className: "DataModel",
+                        ReplicatedStorage: {
+                            $path: "src",
+                        },
+                    },
+                }
+            "#,
+            Path::new("/foo/default.project.json5"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            Project {
+                file_location: strict.file_location.clone(),
+                ..lenient
+            },
+            strict
+        );
+
+        // Plain .json files are still strict.
+        assert!(Project::load_from_slice(
+            br#"{ "name": "strict", "tree": { "$path": "src", }, }"#,
+            Path::new("/foo/default.project.json"),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn backslash_paths_are_normalized() {
+        let project = Project::load_from_slice(
+            br#"
+                {
+                    "name": "normalized",
+                    "tree": {
+                        "$className": "Folder",
+                        "Shared": {
+                            "$path": "src\\shared\\"
+                        },
+                        "Here": {
+                            "$path": "./src/../lib/"
+                        }
+                    }
+                }
+            "#,
+            Path::new("/foo/default.project.json"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            project.tree.children["Shared"].path,
+            Some(PathBuf::from("src/shared"))
+        );
+        assert_eq!(
+            project.tree.children["Here"].path,
+            Some(PathBuf::from("lib"))
+        );
+    }
+
+    #[test]
+    fn paths_outside_project_are_rejected() {
+        let source = |allow: bool| {
+            format!(
+                r#"
+                    {{
+                        "name": "escape",
+                        "allowPathsOutsideProject": {},
+                        "tree": {{
+                            "$className": "Folder",
+                            "Escape": {{
+                                "$path": "src/../../escape"
+                            }}
+                        }}
+                    }}
+                "#,
+                allow
+            )
+        };
+
+        let err = Project::load_from_slice(
+            source(false).as_bytes(),
+            Path::new("/foo/default.project.json"),
+        )
+        .unwrap_err();
+
+        let message = err.to_string();
+        assert!(message.contains("escape/Escape"), "{}", message);
+        assert!(message.contains("allowPathsOutsideProject"), "{}", message);
+
+        let project = Project::load_from_slice(
+            source(true).as_bytes(),
+            Path::new("/foo/default.project.json"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            project.tree.children["Escape"].path,
+            Some(PathBuf::from("../escape"))
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! A cache over the reflection database that stores every property of a class,
+//! including the properties it inherits from its superclasses.
+//!
+//! Looking up a property directly through rbx_reflection means walking the
+//! superclass chain, which adds up quickly for property-heavy projects. The
+//! cache is shared between threads, so snapshots running in parallel can reuse
+//! each other's work.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use lazy_static::lazy_static;
+use rbx_reflection::{get_class_descriptor, RbxPropertyDescriptor, RbxPropertyTypeDescriptor};
+
+lazy_static! {
+    static ref CLASSES: RwLock<HashMap<String, Option<Arc<ClassProperties>>>> =
+        RwLock::new(HashMap::new());
+}
+
+/// Every property known for a class, keyed by name.
+#[derive(Debug)]
+pub struct ClassProperties {
+    properties: HashMap<&'static str, &'static RbxPropertyDescriptor>,
+}
+
+impl ClassProperties {
+    /// Collects the properties of the given class and all of its superclasses.
+    /// Returns `None` if the class isn't in the reflection database.
+    fn uncached(class_name: &str) -> Option<Self> {
+        let mut current_class = Some(get_class_descriptor(class_name)?);
+        let mut properties = HashMap::new();
+
+        while let Some(class_descriptor) = current_class {
+            for (name, descriptor) in class_descriptor.iter_property_descriptors() {
+                // Properties defined on a class take precedence over properties
+                // with the same name on its superclasses.
+                properties.entry(name).or_insert(descriptor);
+            }
+
+            current_class = class_descriptor.superclass().and_then(get_class_descriptor);
+        }
+
+        Some(ClassProperties { properties })
+    }
+
+    pub fn get(&self, property_name: &str) -> Option<&'static RbxPropertyDescriptor> {
+        self.properties.get(property_name).copied()
+    }
+
+    pub fn property_type(&self, property_name: &str) -> Option<&'static RbxPropertyTypeDescriptor> {
+        self.get(property_name)
+            .map(|descriptor| descriptor.property_type())
+    }
+}
+
+/// Returns the properties of the given class, or `None` if the class isn't in
+/// the reflection database. Results are computed once per class and then
+/// shared.
+pub fn class_properties(class_name: &str) -> Option<Arc<ClassProperties>> {
+    if let Some(cached) = CLASSES.read().unwrap().get(class_name) {
+        return cached.clone();
+    }
+
+    // Building the map happens outside of the write lock. If two threads race
+    // to fill in the same class, they'll compute equal values and one of them
+    // wins.
+    let computed = ClassProperties::uncached(class_name).map(Arc::new);
+
+    CLASSES
+        .write()
+        .unwrap()
+        .entry(class_name.to_owned())
+        .or_insert(computed)
+        .clone()
+}
+
+/// Finds the type of a property on a class or any of its superclasses.
+pub fn property_type(
+    class_name: &str,
+    property_name: &str,
+) -> Option<&'static RbxPropertyTypeDescriptor> {
+    class_properties(class_name)?.property_type(property_name)
+}
+
+/// Tells whether the class is `superclass` or inherits from it. Classes that
+/// aren't in the reflection database are only their own class.
+pub fn is_subclass_of(class_name: &str, superclass: &str) -> bool {
+    if class_name == superclass {
+        return true;
+    }
+
+    let mut current_class = get_class_descriptor(class_name);
+
+    while let Some(class_descriptor) = current_class {
+        if class_descriptor.name() == superclass {
+            return true;
+        }
+
+        current_class = class_descriptor.superclass().and_then(get_class_descriptor);
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{collections::HashSet, thread};
+
+    use rbx_reflection::iter_class_descriptors;
+
+    /// Looks up a property's type by walking the superclass chain, the way Rojo
+    /// did before this cache existed.
+    fn uncached_property_type(
+        class_name: &str,
+        property_name: &str,
+    ) -> Option<&'static RbxPropertyTypeDescriptor> {
+        let mut current_class = get_class_descriptor(class_name);
+
+        while let Some(class_descriptor) = current_class {
+            if let Some(property) = class_descriptor.get_property_descriptor(property_name) {
+                return Some(property.property_type());
+            }
+
+            current_class = class_descriptor.superclass().and_then(get_class_descriptor);
+        }
+
+        None
+    }
+
+    #[test]
+    fn cached_matches_uncached() {
+        let property_names: HashSet<&str> = iter_class_descriptors()
+            .flat_map(|(_, class)| class.iter_property_descriptors().map(|(name, _)| name))
+            .collect();
+
+        for (class_name, _) in iter_class_descriptors() {
+            for property_name in &property_names {
+                assert_eq!(
+                    property_type(class_name, property_name),
+                    uncached_property_type(class_name, property_name),
+                    "{}.{}",
+                    class_name,
+                    property_name
+                );
+            }
+        }
+
+        assert!(class_properties("NotARealClass").is_none());
+        assert_eq!(property_type("NotARealClass", "Name"), None);
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let handles: Vec<_> = (0..4)
+            .map(|_| thread::spawn(|| class_properties("Part").unwrap()))
+            .collect();
+
+        let results: Vec<_> = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect();
+
+        for result in &results[1..] {
+            assert!(Arc::ptr_eq(&results[0], result));
+        }
+
+        assert!(results[0].get("Transparency").is_some());
+        assert!(results[0].get("Name").is_some());
+    }
+}
@@ -0,0 +1,332 @@
+//! Turns property values written in project, model, and meta files into
+//! concrete values.
+//!
+//! Most of the work is done by rbx_reflection, but it doesn't know about every
+//! friendly form that Rojo accepts. Colors can be written as a hex string like
+//! `"#FF8000"` or as an array of three numbers from 0 to 1, and both forms
+//! produce whichever of Color3 or Color3uint8 the property actually is.
+//! NumberRanges can be written as `[min, max]`.
+//!
+//! NumberSequences can be written as a list of keypoints, like
+//! `[{ "time": 0, "value": 1 }, { "time": 1, "value": 0 }]`. Those can't be
+//! read as an `UnresolvedRbxValue`, so files that accept them read them as
+//! `Keypoint`s and resolve them with `resolve_number_sequence`.
+
+use rbx_dom_weak::{
+    AmbiguousRbxValue, NumberSequence, NumberSequenceKeypoint, RbxValue, RbxValueType,
+    UnresolvedRbxValue,
+};
+use rbx_reflection::{try_resolve_value, RbxPropertyTypeDescriptor, ValueResolveError};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::reflection_cache;
+
+/// Resolves a value for the given property using the reflection database.
+pub fn resolve_value(
+    class_name: &str,
+    property_name: &str,
+    value: &UnresolvedRbxValue,
+) -> Result<RbxValue, ValueResolveError> {
+    if let UnresolvedRbxValue::Ambiguous(ambiguous) = value {
+        let property_type = reflection_cache::property_type(class_name, property_name);
+
+        if let Some(RbxPropertyTypeDescriptor::Data(value_type)) = property_type {
+            if let Some(resolved) = resolve_color(*value_type, ambiguous) {
+                return Ok(resolved);
+            }
+
+            if let (RbxValueType::NumberRange, AmbiguousRbxValue::Float2(min, max)) =
+                (value_type, ambiguous)
+            {
+                return Ok(RbxValue::NumberRange {
+                    value: (*min as f32, *max as f32),
+                });
+            }
+        }
+    }
+
+    try_resolve_value(class_name, property_name, value)
+}
+
+/// One keypoint of a NumberSequence written as a list of keypoints.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Keypoint {
+    pub time: f32,
+    pub value: f32,
+
+    #[serde(default)]
+    pub envelope: f32,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum NumberSequenceError {
+    #[error("{class_name}.{property} is not a NumberSequence property")]
+    NotANumberSequence {
+        class_name: String,
+        property: String,
+    },
+
+    #[error("NumberSequences need at least two keypoints")]
+    TooFewKeypoints,
+
+    #[error("the first keypoint must be at time 0, but it's at time {time}")]
+    FirstNotAtZero { time: f32 },
+
+    #[error("the last keypoint must be at time 1, but it's at time {time}")]
+    LastNotAtOne { time: f32 },
+
+    #[error("keypoint times must be ascending, but time {time} comes after time {previous}")]
+    NotAscending { previous: f32, time: f32 },
+}
+
+/// Turns a list of keypoints into a NumberSequence for the given property.
+/// Like in Roblox, the keypoints have to start at time 0, end at time 1, and
+/// be in order.
+pub fn resolve_number_sequence(
+    class_name: &str,
+    property_name: &str,
+    keypoints: &[Keypoint],
+) -> Result<RbxValue, NumberSequenceError> {
+    match reflection_cache::property_type(class_name, property_name) {
+        Some(RbxPropertyTypeDescriptor::Data(RbxValueType::NumberSequence)) => {}
+        _ => {
+            return Err(NumberSequenceError::NotANumberSequence {
+                class_name: class_name.to_owned(),
+                property: property_name.to_owned(),
+            })
+        }
+    }
+
+    let (first, last) = match keypoints {
+        [first, .., last] => (first, last),
+        _ => return Err(NumberSequenceError::TooFewKeypoints),
+    };
+
+    if first.time != 0.0 {
+        return Err(NumberSequenceError::FirstNotAtZero { time: first.time });
+    }
+
+    if last.time != 1.0 {
+        return Err(NumberSequenceError::LastNotAtOne { time: last.time });
+    }
+
+    for pair in keypoints.windows(2) {
+        if pair[1].time < pair[0].time {
+            return Err(NumberSequenceError::NotAscending {
+                previous: pair[0].time,
+                time: pair[1].time,
+            });
+        }
+    }
+
+    Ok(RbxValue::NumberSequence {
+        value: NumberSequence {
+            keypoints: keypoints
+                .iter()
+                .map(|keypoint| NumberSequenceKeypoint {
+                    time: keypoint.time,
+                    value: keypoint.value,
+                    envelope: keypoint.envelope,
+                })
+                .collect(),
+        },
+    })
+}
+
+fn resolve_color(value_type: RbxValueType, value: &AmbiguousRbxValue) -> Option<RbxValue> {
+    let bytes = match value {
+        AmbiguousRbxValue::String(hex) => parse_hex_color(hex)?,
+        AmbiguousRbxValue::Float3(r, g, b) if value_type == RbxValueType::Color3uint8 => {
+            [float_to_byte(*r)?, float_to_byte(*g)?, float_to_byte(*b)?]
+        }
+        _ => return None,
+    };
+
+    match value_type {
+        RbxValueType::Color3uint8 => Some(RbxValue::Color3uint8 { value: bytes }),
+        RbxValueType::Color3 => Some(RbxValue::Color3 {
+            value: [
+                f32::from(bytes[0]) / 255.0,
+                f32::from(bytes[1]) / 255.0,
+                f32::from(bytes[2]) / 255.0,
+            ],
+        }),
+        _ => None,
+    }
+}
+
+/// Parses colors like `#FF8000`. The `#` is optional.
+fn parse_hex_color(hex: &str) -> Option<[u8; 3]> {
+    let hex = if hex.starts_with('#') { &hex[1..] } else { hex };
+
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+
+    let channel = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).ok();
+
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Turns a color component from 0 to 1 into a byte. Components outside of that
+/// range aren't clamped, since they're usually bytes written by mistake, like
+/// `[255, 128, 0]`.
+fn float_to_byte(value: f64) -> Option<u8> {
+    if (0.0..=1.0).contains(&value) {
+        Some((value * 255.0).round() as u8)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ambiguous_string(value: &str) -> UnresolvedRbxValue {
+        UnresolvedRbxValue::Ambiguous(AmbiguousRbxValue::String(value.to_owned()))
+    }
+
+    #[test]
+    fn color3uint8_from_hex() {
+        let resolved = resolve_value("Part", "Color3uint8", &ambiguous_string("#FF8001")).unwrap();
+        assert_eq!(
+            resolved,
+            RbxValue::Color3uint8 {
+                value: [255, 128, 1]
+            }
+        );
+
+        let from_floats = UnresolvedRbxValue::Ambiguous(AmbiguousRbxValue::Float3(
+            1.0,
+            128.0 / 255.0,
+            1.0 / 255.0,
+        ));
+        assert_eq!(
+            resolve_value("Part", "Color3uint8", &from_floats).unwrap(),
+            resolved
+        );
+
+        // The same hex gives a Color3 for Color3 properties, which turns back
+        // into exactly the same bytes.
+        match resolve_value("Part", "Color", &ambiguous_string("FF8001")).unwrap() {
+            RbxValue::Color3 { value } => {
+                let bytes: Vec<u8> = value
+                    .iter()
+                    .map(|&c| float_to_byte(c.into()).unwrap())
+                    .collect();
+                assert_eq!(bytes, vec![255, 128, 1]);
+            }
+            other => panic!("expected a Color3, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn color3uint8_components_out_of_range() {
+        let bytes = UnresolvedRbxValue::Ambiguous(AmbiguousRbxValue::Float3(255.0, 128.0, 0.0));
+        assert!(resolve_value("Part", "Color3uint8", &bytes).is_err());
+
+        let negative = UnresolvedRbxValue::Ambiguous(AmbiguousRbxValue::Float3(-0.5, 0.0, 0.0));
+        assert!(resolve_value("Part", "Color3uint8", &negative).is_err());
+    }
+
+    #[test]
+    fn other_values_are_unchanged() {
+        assert_eq!(
+            resolve_value("StringValue", "Value", &ambiguous_string("#FF8000")).unwrap(),
+            RbxValue::String {
+                value: "#FF8000".to_owned()
+            }
+        );
+
+        assert!(resolve_value("Part", "Color", &ambiguous_string("#FF80")).is_err());
+    }
+
+    #[test]
+    fn number_range_from_pair() {
+        let pair = UnresolvedRbxValue::Ambiguous(AmbiguousRbxValue::Float2(0.5, 2.0));
+
+        assert_eq!(
+            resolve_value("ParticleEmitter", "Lifetime", &pair).unwrap(),
+            RbxValue::NumberRange { value: (0.5, 2.0) }
+        );
+    }
+
+    #[test]
+    fn number_sequence_from_keypoints() {
+        let keypoint = |time, value, envelope| Keypoint {
+            time,
+            value,
+            envelope,
+        };
+
+        let keypoints = [
+            keypoint(0.0, 1.0, 0.0),
+            keypoint(0.25, 2.0, 0.5),
+            keypoint(1.0, 0.0, 0.0),
+        ];
+
+        assert_eq!(
+            resolve_number_sequence("ParticleEmitter", "Size", &keypoints).unwrap(),
+            RbxValue::NumberSequence {
+                value: NumberSequence {
+                    keypoints: vec![
+                        NumberSequenceKeypoint {
+                            time: 0.0,
+                            value: 1.0,
+                            envelope: 0.0,
+                        },
+                        NumberSequenceKeypoint {
+                            time: 0.25,
+                            value: 2.0,
+                            envelope: 0.5,
+                        },
+                        NumberSequenceKeypoint {
+                            time: 1.0,
+                            value: 0.0,
+                            envelope: 0.0,
+                        },
+                    ],
+                },
+            }
+        );
+
+        let out_of_order = [
+            keypoint(0.0, 1.0, 0.0),
+            keypoint(0.75, 1.0, 0.0),
+            keypoint(0.5, 1.0, 0.0),
+            keypoint(1.0, 1.0, 0.0),
+        ];
+        assert_eq!(
+            resolve_number_sequence("ParticleEmitter", "Size", &out_of_order),
+            Err(NumberSequenceError::NotAscending {
+                previous: 0.75,
+                time: 0.5,
+            })
+        );
+
+        let no_start = [keypoint(0.5, 1.0, 0.0), keypoint(1.0, 1.0, 0.0)];
+        assert_eq!(
+            resolve_number_sequence("ParticleEmitter", "Size", &no_start),
+            Err(NumberSequenceError::FirstNotAtZero { time: 0.5 })
+        );
+
+        let no_end = [keypoint(0.0, 1.0, 0.0), keypoint(0.5, 1.0, 0.0)];
+        assert_eq!(
+            resolve_number_sequence("ParticleEmitter", "Size", &no_end),
+            Err(NumberSequenceError::LastNotAtOne { time: 0.5 })
+        );
+
+        assert_eq!(
+            resolve_number_sequence("ParticleEmitter", "Size", &[keypoint(0.0, 1.0, 0.0)]),
+            Err(NumberSequenceError::TooFewKeypoints)
+        );
+
+        assert!(matches!(
+            resolve_number_sequence("ParticleEmitter", "Name", &keypoints),
+            Err(NumberSequenceError::NotANumberSequence { .. })
+        ));
+    }
+}
@@ -16,15 +16,22 @@ use thiserror::Error;
 use crate::{
     change_processor::ChangeProcessor,
     message_queue::MessageQueue,
-    project::{Project, ProjectError},
+    project::{PathOverlap, Project, ProjectError, RootKind},
     session_id::SessionId,
     snapshot::{
-        apply_patch_set, compute_patch_set, AppliedPatchSet, InstanceContext,
-        InstancePropertiesWithMeta, PatchSet, RojoTree,
+        apply_patch_set, apply_transforms, compute_patch_set, compute_rescan_patch_set,
+        migrate_properties, warn_cross_subtree_refs, AppliedPatchSet, InstanceContext,
+        InstancePath, InstancePropertiesWithMeta, InstanceSnapshot, PatchSet, PropertyMigration,
+        RojoTree,
     },
     snapshot_middleware::{snapshot_from_vfs, SnapshotError},
+    trace,
 };
 
+/// How many patches the message queue keeps around for clients that are
+/// catching up from an older cursor.
+const MAX_RETAINED_MESSAGES: usize = 1000;
+
 /// Contains all of the state for a Rojo serve session.
 ///
 /// Nothing here is specific to any Rojo interface. Though the primary way to
@@ -58,6 +65,13 @@ pub struct ServeSession {
     /// path to a `.project.json` file.
     root_project: Project,
 
+    /// The path that the serve session was started from, which is snapshotted
+    /// again when the session is rescanned.
+    start_path: PathBuf,
+
+    /// See `ServeSessionOptions::property_migration`.
+    property_migration: Option<PropertyMigration>,
+
     /// A randomly generated ID for this serve session. It's used to ensure that
     /// a client doesn't begin connecting to a different server part way through
     /// an operation that needs to be atomic.
@@ -95,6 +109,16 @@ impl ServeSession {
     /// currently loaded from the filesystem directly instead of through the
     /// in-memory filesystem layer.
     pub fn new<P: AsRef<Path>>(vfs: Vfs, start_path: P) -> Result<Self, ServeSessionError> {
+        Self::with_options(vfs, start_path, ServeSessionOptions::default())
+    }
+
+    /// Start a new serve session like `ServeSession::new`, but with additional
+    /// options that change how the session reacts to changes.
+    pub fn with_options<P: AsRef<Path>>(
+        vfs: Vfs,
+        start_path: P,
+        options: ServeSessionOptions,
+    ) -> Result<Self, ServeSessionError> {
         let start_path = start_path.as_ref();
         let start_time = Instant::now();
 
@@ -109,12 +133,27 @@ impl ServeSession {
 
         log::debug!("Loading project file from {}", project_path.display());
 
-        let root_project = match vfs.read(&project_path).with_not_found()? {
-            Some(contents) => Project::load_from_slice(&contents, &project_path)?,
-            None => {
-                return Err(ServeSessionError::NoProjectFound {
-                    path: project_path.to_path_buf(),
-                });
+        let root_project = {
+            let _span = trace::span("project", || format!("load {}", project_path.display()));
+
+            match vfs.read(&project_path).with_not_found()? {
+                Some(contents) => {
+                    let overlay_path = Project::local_overlay_path(&project_path);
+                    let overlay_contents = vfs.read(&overlay_path).with_not_found()?;
+
+                    Project::load_with_overlay(
+                        &contents,
+                        overlay_contents
+                            .as_ref()
+                            .map(|contents| contents.as_slice()),
+                        &project_path,
+                    )?
+                }
+                None => {
+                    return Err(ServeSessionError::NoProjectFound {
+                        path: project_path.to_path_buf(),
+                    });
+                }
             }
         };
 
@@ -132,17 +171,35 @@ impl ServeSession {
         let instance_context = InstanceContext::default();
 
         log::trace!("Generating snapshot of instances from VFS");
-        let snapshot = snapshot_from_vfs(&instance_context, &vfs, &start_path)?
+        let mut snapshot = snapshot_from_vfs(&instance_context, &vfs, &start_path)?
             .expect("snapshot did not return an instance");
 
+        if let Some(root_kind) = root_project.root_kind {
+            if !root_kind.allows_root_class(&snapshot.class_name) {
+                return Err(ServeSessionError::WrongRootClass {
+                    root_kind,
+                    class_name: snapshot.class_name.into_owned(),
+                });
+            }
+        }
+
+        log::trace!("Applying project transforms");
+        apply_transforms(&mut snapshot);
+
+        if let Some(migration) = &options.property_migration {
+            migrate_properties(&mut snapshot, migration);
+        }
+
         log::trace!("Computing initial patch set");
         let patch_set = compute_patch_set(&snapshot, &tree, root_id);
 
         log::trace!("Applying initial patch set");
         apply_patch_set(&mut tree, patch_set);
 
+        warn_cross_subtree_refs(&tree);
+
         let session_id = SessionId::new();
-        let message_queue = MessageQueue::new();
+        let message_queue = MessageQueue::with_max_messages(MAX_RETAINED_MESSAGES);
 
         let tree = Arc::new(Mutex::new(tree));
         let message_queue = Arc::new(message_queue);
@@ -150,12 +207,15 @@ impl ServeSession {
 
         let (tree_mutation_sender, tree_mutation_receiver) = crossbeam_channel::unbounded();
 
+        let property_migration = options.property_migration.clone();
+
         log::trace!("Starting ChangeProcessor");
         let change_processor = ChangeProcessor::start(
             Arc::clone(&tree),
             Arc::clone(&vfs),
             Arc::clone(&message_queue),
             tree_mutation_receiver,
+            options,
         );
 
         Ok(Self {
@@ -163,6 +223,8 @@ impl ServeSession {
             start_time,
             session_id,
             root_project,
+            start_path: start_path.to_path_buf(),
+            property_migration,
             tree,
             message_queue,
             tree_mutation_sender,
@@ -210,6 +272,118 @@ impl ServeSession {
     pub fn serve_place_ids(&self) -> Option<&HashSet<u64>> {
         self.root_project.serve_place_ids.as_ref()
     }
+
+    pub fn root_kind(&self) -> Option<RootKind> {
+        self.root_project.root_kind
+    }
+
+    /// The pairs of nodes in the root project whose `$path` values overlap,
+    /// unless the project allows path aliasing.
+    pub fn path_overlaps(&self) -> Vec<PathOverlap> {
+        if self.root_project.allow_path_aliasing {
+            Vec::new()
+        } else {
+            self.root_project.find_path_overlaps()
+        }
+    }
+
+    /// The folder containing the root project file, which paths in the
+    /// project are relative to.
+    pub fn project_folder(&self) -> &Path {
+        self.root_project.folder_location()
+    }
+
+    /// Lists the instances that would be updated if the file or folder at the
+    /// given path changed, in the same way that the file watcher would find
+    /// them. Paths are sorted so that the order is stable.
+    pub fn instances_affected_by(&self, path: &Path) -> Vec<InstancePath> {
+        let tree = self.tree();
+
+        let mut paths: Vec<_> = tree
+            .ids_affected_by(path)
+            .into_iter()
+            .filter_map(|id| tree.instance_path(id))
+            .collect();
+
+        paths.sort_by(|a, b| a.names.cmp(&b.names));
+        paths
+    }
+
+    /// Snapshots the whole project again and patches the tree to match it,
+    /// notifying clients of any changes.
+    ///
+    /// Instances that aren't from the filesystem are kept if their parent
+    /// ignores unknown instances.
+    pub fn rescan(&self) -> Result<(), SnapshotError> {
+        let _span = trace::span("serve", || "rescan".to_owned());
+
+        log::trace!("Rescanning project at {}", self.start_path.display());
+
+        let snapshot = self.snapshot_project()?;
+
+        let applied_patch = {
+            let mut tree = self.tree.lock().unwrap();
+            let root_id = tree.get_root_id();
+
+            let patch_set = compute_rescan_patch_set(&snapshot, &tree, root_id);
+            apply_patch_set(&mut tree, patch_set)
+        };
+
+        self.message_queue.push_messages(&[applied_patch]);
+
+        Ok(())
+    }
+
+    /// Snapshots the whole project again and computes the patch that would
+    /// make the tree match it, like `rescan`, but doesn't apply it.
+    ///
+    /// This describes how the tree has drifted from the filesystem, like when
+    /// Studio changes properties that can't be written back to files.
+    pub fn drift(&self) -> Result<PatchSet, SnapshotError> {
+        let _span = trace::span("serve", || "drift".to_owned());
+
+        let snapshot = self.snapshot_project()?;
+
+        let tree = self.tree.lock().unwrap();
+        let root_id = tree.get_root_id();
+
+        Ok(compute_rescan_patch_set(&snapshot, &tree, root_id))
+    }
+
+    fn snapshot_project(&self) -> Result<InstanceSnapshot, SnapshotError> {
+        let mut snapshot =
+            snapshot_from_vfs(&InstanceContext::default(), &self.vfs, &self.start_path)?
+                .expect("snapshot did not return an instance");
+
+        apply_transforms(&mut snapshot);
+
+        if let Some(migration) = &self.property_migration {
+            migrate_properties(&mut snapshot, migration);
+        }
+
+        Ok(snapshot)
+    }
+}
+
+/// Options that can be passed to `ServeSession::with_options`.
+#[derive(Debug, Clone, Default)]
+pub struct ServeSessionOptions {
+    /// If set, changes from the filesystem will only be applied to instances
+    /// whose class is contained in this set. The initial sync is unaffected.
+    pub sync_classes: Option<HashSet<String>>,
+
+    /// If set, changes from Studio are logged as the file operations they
+    /// would cause, but the filesystem isn't touched.
+    pub reverse_dry_run: bool,
+
+    /// If set, changes from the filesystem that would change more instances
+    /// than this are not applied, which guards against things like a folder
+    /// being deleted by accident.
+    pub max_changes: Option<usize>,
+
+    /// If set, runs on every snapshot of the project before it's compared to
+    /// the tree, including snapshots made when files change.
+    pub property_migration: Option<PropertyMigration>,
 }
 
 #[derive(Debug, Error)]
@@ -221,6 +395,15 @@ pub enum ServeSessionError {
     )]
     NoProjectFound { path: PathBuf },
 
+    #[error(
+        "The project's rootKind is {root_kind}, but its root instance is a {class_name}. \
+        Place projects need a DataModel root, and model projects can't have one."
+    )]
+    WrongRootClass {
+        root_kind: RootKind,
+        class_name: String,
+    },
+
     #[error(transparent)]
     Io {
         #[from]
@@ -239,3 +422,198 @@ pub enum ServeSessionError {
         source: SnapshotError,
     },
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use maplit::hashmap;
+    use memofs::{InMemoryFs, VfsSnapshot};
+    use rbx_dom_weak::RbxValue;
+
+    fn affected_names(session: &ServeSession, path: &str) -> Vec<String> {
+        session
+            .instances_affected_by(Path::new(path))
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn instances_affected_by_files() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "affected",
+                        "tree": {
+                            "$path": "src"
+                        }
+                    }
+                "#),
+                "src" => VfsSnapshot::dir(hashmap! {
+                    "main.server.lua" => VfsSnapshot::file("print('hi')"),
+                    "Container" => VfsSnapshot::dir(hashmap! {
+                        "init.meta.json" => VfsSnapshot::file(r#"
+                            {
+                                "className": "Configuration"
+                            }
+                        "#),
+                        "child.lua" => VfsSnapshot::file("return nil"),
+                    }),
+                }),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        vfs.set_watch_enabled(false);
+        let session = ServeSession::new(vfs, "/foo").unwrap();
+
+        assert_eq!(
+            affected_names(&session, "/foo/src/main.server.lua"),
+            vec!["affected/main"]
+        );
+        assert_eq!(
+            affected_names(&session, "/foo/src/Container/init.meta.json"),
+            vec!["affected/Container"]
+        );
+
+        // New files are handled by their nearest ancestor with instances.
+        assert_eq!(
+            affected_names(&session, "/foo/src/Container/new.lua"),
+            vec!["affected/Container"]
+        );
+    }
+
+    #[test]
+    fn drift_from_studio_change() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "drift",
+                        "tree": {
+                            "$className": "Folder",
+                            "Greeting": {
+                                "$className": "StringValue",
+                                "$properties": {
+                                    "Value": "hello"
+                                }
+                            }
+                        }
+                    }
+                "#),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        vfs.set_watch_enabled(false);
+        let session = ServeSession::new(vfs, "/foo").unwrap();
+
+        assert_eq!(session.drift().unwrap(), PatchSet::new());
+
+        // Changes from Studio to properties other than Source are applied to
+        // the tree without being written to any file.
+        let greeting_id = {
+            let mut tree = session.tree();
+            let root_id = tree.get_root_id();
+            let greeting_id = tree.get_instance(root_id).unwrap().children()[0];
+
+            tree.get_instance_mut(greeting_id)
+                .unwrap()
+                .properties_mut()
+                .insert(
+                    "Value".to_owned(),
+                    RbxValue::String {
+                        value: "changed in Studio".to_owned(),
+                    },
+                );
+
+            greeting_id
+        };
+
+        let drift = session.drift().unwrap();
+
+        assert!(drift.removed_instances.is_empty());
+        assert!(drift.added_instances.is_empty());
+        assert_eq!(drift.updated_instances.len(), 1);
+        assert_eq!(drift.updated_instances[0].id, greeting_id);
+        assert_eq!(
+            drift.updated_instances[0].changed_properties,
+            hashmap! {
+                "Value".to_owned() => Some(RbxValue::String {
+                    value: "hello".to_owned(),
+                }),
+            }
+        );
+
+        // Computing the drift doesn't change the tree.
+        assert_eq!(session.drift().unwrap(), drift);
+    }
+
+    #[test]
+    fn patches_since_cursor() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "patches",
+                        "tree": {
+                            "$path": "src"
+                        }
+                    }
+                "#),
+                "src" => VfsSnapshot::dir(hashmap! {
+                    "main.lua" => VfsSnapshot::file("return 0"),
+                }),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        vfs.set_watch_enabled(false);
+        let session = ServeSession::new(vfs, "/foo").unwrap();
+
+        session
+            .vfs()
+            .write("/foo/src/main.lua", "return 1")
+            .unwrap();
+        session.rescan().unwrap();
+        session
+            .vfs()
+            .write("/foo/src/main.lua", "return 2")
+            .unwrap();
+        session.rescan().unwrap();
+
+        let (cursor, messages) = session.message_queue().messages_since(0).unwrap();
+        assert_eq!(cursor, 2);
+
+        let sources: Vec<_> = messages
+            .iter()
+            .map(|message| {
+                assert_eq!(message.updated.len(), 1);
+                message.updated[0].changed_properties["Source"].clone()
+            })
+            .collect();
+
+        assert_eq!(
+            sources,
+            vec![
+                Some(RbxValue::String {
+                    value: "return 1".to_owned()
+                }),
+                Some(RbxValue::String {
+                    value: "return 2".to_owned()
+                }),
+            ]
+        );
+    }
+}
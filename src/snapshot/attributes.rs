@@ -0,0 +1,348 @@
+//! Reads and writes the binary format that Roblox uses to store all of an
+//! instance's attributes in a single property.
+//!
+//! Rojo doesn't need to understand the values of attributes, only where each
+//! one starts and ends, so values are kept in their encoded form. This lets
+//! patches describe changes to individual attributes instead of replacing the
+//! whole property whenever any attribute changes.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryInto,
+};
+
+use rbx_dom_weak::RbxValue;
+use serde::{Deserialize, Serialize};
+
+/// The name of the property that attributes are serialized into.
+pub const ATTRIBUTES_PROPERTY: &str = "AttributesSerialize";
+
+/// A single attribute's value, still in its encoded form.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttributeValue {
+    pub type_id: u8,
+    pub data: Vec<u8>,
+}
+
+impl AttributeValue {
+    pub fn string(value: &str) -> Self {
+        let mut data = (value.len() as u32).to_le_bytes().to_vec();
+        data.extend_from_slice(value.as_bytes());
+
+        AttributeValue {
+            type_id: 0x02,
+            data,
+        }
+    }
+
+    pub fn bool(value: bool) -> Self {
+        AttributeValue {
+            type_id: 0x03,
+            data: vec![value as u8],
+        }
+    }
+
+    pub fn double(value: f64) -> Self {
+        AttributeValue {
+            type_id: 0x06,
+            data: value.to_le_bytes().to_vec(),
+        }
+    }
+
+    pub fn color3(value: [f32; 3]) -> Self {
+        AttributeValue {
+            type_id: 0x0F,
+            data: floats_to_bytes(&value),
+        }
+    }
+
+    pub fn vector3(value: [f32; 3]) -> Self {
+        AttributeValue {
+            type_id: 0x11,
+            data: floats_to_bytes(&value),
+        }
+    }
+}
+
+fn floats_to_bytes(values: &[f32]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| value.to_le_bytes().to_vec())
+        .collect()
+}
+
+/// All of the attributes on an instance, keyed by name. Sorted so that
+/// encoding the same attributes always produces the same bytes.
+pub type Attributes = BTreeMap<String, AttributeValue>;
+
+/// Changes to attributes, where `None` means the attribute was removed. This
+/// mirrors how `PatchUpdate` describes changed properties.
+pub type AttributeChanges = HashMap<String, Option<AttributeValue>>;
+
+/// Decodes a serialized attributes buffer. Returns `None` if the buffer is
+/// malformed or contains a type that we don't know the size of.
+///
+/// Instances without attributes usually have an empty buffer instead of one
+/// with a count of zero, so an empty buffer has no attributes.
+pub fn decode_attributes(buffer: &[u8]) -> Option<Attributes> {
+    let mut reader = Reader { buffer };
+    let mut attributes = Attributes::new();
+
+    if reader.buffer.is_empty() {
+        return Some(attributes);
+    }
+
+    let count = reader.read_u32()?;
+
+    for _ in 0..count {
+        let key_len = reader.read_u32()? as usize;
+        let key = String::from_utf8(reader.read_bytes(key_len)?.to_vec()).ok()?;
+
+        let type_id = reader.read_u8()?;
+        let value_len = value_len(type_id, reader.buffer)?;
+        let data = reader.read_bytes(value_len)?.to_vec();
+
+        attributes.insert(key, AttributeValue { type_id, data });
+    }
+
+    if reader.buffer.is_empty() {
+        Some(attributes)
+    } else {
+        None
+    }
+}
+
+pub fn encode_attributes(attributes: &Attributes) -> Vec<u8> {
+    let mut buffer = Vec::new();
+
+    buffer.extend_from_slice(&(attributes.len() as u32).to_le_bytes());
+
+    for (key, value) in attributes {
+        buffer.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        buffer.extend_from_slice(key.as_bytes());
+        buffer.push(value.type_id);
+        buffer.extend_from_slice(&value.data);
+    }
+
+    buffer
+}
+
+/// Compares two values of the attributes property attribute-by-attribute.
+///
+/// Returns `None` if the property isn't the attributes property or either
+/// value can't be decoded, in which case the property should be compared as a
+/// whole instead.
+pub fn diff_attributes(
+    property_name: &str,
+    new_value: &RbxValue,
+    old_value: &RbxValue,
+) -> Option<AttributeChanges> {
+    if property_name != ATTRIBUTES_PROPERTY {
+        return None;
+    }
+
+    let new_attributes = decode_value(new_value)?;
+    let old_attributes = decode_value(old_value)?;
+
+    let mut changes = AttributeChanges::new();
+
+    for (key, new) in &new_attributes {
+        if old_attributes.get(key) != Some(new) {
+            changes.insert(key.clone(), Some(new.clone()));
+        }
+    }
+
+    for key in old_attributes.keys() {
+        if !new_attributes.contains_key(key) {
+            changes.insert(key.clone(), None);
+        }
+    }
+
+    Some(changes)
+}
+
+/// Applies attribute changes to the current value of the attributes property,
+/// returning the new value of the property.
+pub fn apply_attribute_changes(current: Option<&RbxValue>, changes: AttributeChanges) -> RbxValue {
+    let mut attributes = match current.map(decode_value) {
+        Some(Some(attributes)) => attributes,
+        Some(None) => {
+            log::warn!(
+                "Could not decode existing {}, replacing it with the changed attributes",
+                ATTRIBUTES_PROPERTY
+            );
+            Attributes::new()
+        }
+        None => Attributes::new(),
+    };
+
+    for (key, change) in changes {
+        match change {
+            Some(value) => {
+                attributes.insert(key, value);
+            }
+            None => {
+                attributes.remove(&key);
+            }
+        }
+    }
+
+    RbxValue::BinaryString {
+        value: encode_attributes(&attributes),
+    }
+}
+
+fn decode_value(value: &RbxValue) -> Option<Attributes> {
+    match value {
+        RbxValue::BinaryString { value } => decode_attributes(value),
+        _ => None,
+    }
+}
+
+/// Tells how many bytes the value of an attribute with the given type takes up,
+/// given the buffer that starts with the value.
+fn value_len(type_id: u8, rest: &[u8]) -> Option<usize> {
+    let len = match type_id {
+        // String
+        0x02 => 4 + peek_u32(rest)? as usize,
+        // Bool
+        0x03 => 1,
+        // Float
+        0x05 => 4,
+        // Double
+        0x06 => 8,
+        // UDim
+        0x09 => 8,
+        // UDim2
+        0x0A => 16,
+        // BrickColor
+        0x0E => 4,
+        // Color3
+        0x0F => 12,
+        // Vector2
+        0x10 => 8,
+        // Vector3
+        0x11 => 12,
+        // CFrame: a position, then a rotation ID that is followed by a full
+        // rotation matrix if it's zero.
+        0x14 => match rest.get(12)? {
+            0 => 13 + 36,
+            _ => 13,
+        },
+        // NumberSequence: a count of keypoints with an envelope, time, and
+        // value each.
+        0x17 => 4 + peek_u32(rest)? as usize * 12,
+        // ColorSequence: a count of keypoints with an envelope, time, and
+        // color each.
+        0x19 => 4 + peek_u32(rest)? as usize * 20,
+        // NumberRange
+        0x1B => 8,
+        // Rect
+        0x1C => 16,
+        _ => return None,
+    };
+
+    Some(len)
+}
+
+fn peek_u32(buffer: &[u8]) -> Option<u32> {
+    let bytes = buffer.get(..4)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+struct Reader<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read_bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.buffer.len() < len {
+            return None;
+        }
+
+        let (bytes, rest) = self.buffer.split_at(len);
+        self.buffer = rest;
+        Some(bytes)
+    }
+
+    fn read_u8(&mut self) -> Option<u8> {
+        Some(self.read_bytes(1)?[0])
+    }
+
+    fn read_u32(&mut self) -> Option<u32> {
+        let value = peek_u32(self.buffer)?;
+        self.buffer = &self.buffer[4..];
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    use crate::log_capture;
+
+    pub fn string(value: &str) -> AttributeValue {
+        AttributeValue::string(value)
+    }
+
+    pub fn bool(value: bool) -> AttributeValue {
+        AttributeValue::bool(value)
+    }
+
+    pub fn double(value: f64) -> AttributeValue {
+        AttributeValue::double(value)
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut attributes = Attributes::new();
+        attributes.insert("Name".to_owned(), string("hello"));
+        attributes.insert("Enabled".to_owned(), bool(true));
+        attributes.insert("Speed".to_owned(), double(16.5));
+
+        let encoded = encode_attributes(&attributes);
+
+        assert_eq!(decode_attributes(&encoded), Some(attributes));
+    }
+
+    #[test]
+    fn empty_buffer_has_no_attributes() {
+        assert_eq!(decode_attributes(&[]), Some(Attributes::new()));
+
+        let empty = RbxValue::BinaryString { value: Vec::new() };
+        let mut changes = AttributeChanges::new();
+        changes.insert("Name".to_owned(), Some(string("hello")));
+
+        let (applied, logs) =
+            log_capture::capture(|| apply_attribute_changes(Some(&empty), changes));
+
+        let mut expected = Attributes::new();
+        expected.insert("Name".to_owned(), string("hello"));
+
+        assert_eq!(decode_value(&applied), Some(expected));
+        assert!(logs.is_empty());
+    }
+
+    #[test]
+    fn malformed_buffers() {
+        // A count that's cut off.
+        assert_eq!(decode_attributes(&[1, 0]), None);
+
+        // One attribute named "a" with an unknown type.
+        assert_eq!(
+            decode_attributes(&[1, 0, 0, 0, 1, 0, 0, 0, b'a', 0xFF]),
+            None
+        );
+
+        let mut attributes = Attributes::new();
+        attributes.insert("a".to_owned(), double(1.0));
+        let mut encoded = encode_attributes(&attributes);
+
+        encoded.push(0);
+        assert_eq!(decode_attributes(&encoded), None);
+
+        encoded.truncate(encoded.len() - 2);
+        assert_eq!(decode_attributes(&encoded), None);
+    }
+}
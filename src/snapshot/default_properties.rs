@@ -0,0 +1,130 @@
+//! Defines a way to remove properties that are set to their class's default
+//! value, which Studio leaves out of the files it saves.
+
+use std::{collections::HashMap, iter::once};
+
+use rbx_reflection::get_class_descriptor;
+
+use super::{apply_patch_set, AppliedPatchSet, PatchSet, PatchUpdate, RojoTree};
+
+/// Removes every property in the tree whose value is the default value for its
+/// class according to the reflection database. Classes and properties that the
+/// reflection database doesn't know a default for are left alone.
+///
+/// The properties are removed by applying a patch set, so the returned
+/// `AppliedPatchSet` remembers their values and can be inverted to put them
+/// back.
+pub fn strip_default_properties(tree: &mut RojoTree) -> AppliedPatchSet {
+    let root_id = tree.get_root_id();
+    let root = tree.get_instance(root_id).unwrap();
+    let mut updated_instances = Vec::new();
+
+    for instance in once(root).chain(tree.descendants(root_id)) {
+        let class = match get_class_descriptor(instance.class_name()) {
+            Some(class) => class,
+            None => continue,
+        };
+
+        let changed_properties: HashMap<_, _> = instance
+            .properties()
+            .iter()
+            .filter(|(name, value)| class.get_default_value(name) == Some(value))
+            .map(|(name, _)| (name.clone(), None))
+            .collect();
+
+        if !changed_properties.is_empty() {
+            updated_instances.push(PatchUpdate {
+                id: instance.id(),
+                changed_name: None,
+                changed_class_name: None,
+                changed_properties,
+                changed_attributes: Default::default(),
+                changed_ref_paths: Default::default(),
+                changed_metadata: None,
+            });
+        }
+    }
+
+    apply_patch_set(
+        tree,
+        PatchSet {
+            updated_instances,
+            ..Default::default()
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use maplit::hashmap;
+    use rbx_dom_weak::{RbxId, RbxInstanceProperties, RbxValue};
+
+    use super::super::{patch_invert::invert_applied_patch_set, InstancePropertiesWithMeta};
+
+    fn insert_part(tree: &mut RojoTree, name: &str, anchored: bool) -> RbxId {
+        let root_id = tree.get_root_id();
+
+        tree.insert_instance(
+            InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: name.to_owned(),
+                    class_name: "Part".to_owned(),
+                    properties: hashmap! {
+                        "Anchored".to_owned() => RbxValue::Bool { value: anchored },
+                    },
+                },
+                metadata: Default::default(),
+            },
+            root_id,
+        )
+    }
+
+    #[test]
+    fn default_anchored_is_stripped() {
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "Workspace".to_owned(),
+                class_name: "Workspace".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+
+        let loose_id = insert_part(&mut tree, "Loose", false);
+        let anchored_id = insert_part(&mut tree, "Anchored", true);
+
+        let anchored = |tree: &RojoTree, id| {
+            tree.get_instance(id)
+                .unwrap()
+                .properties()
+                .get("Anchored")
+                .cloned()
+        };
+
+        let applied_patch_set = strip_default_properties(&mut tree);
+
+        assert_eq!(anchored(&tree, loose_id), None);
+        assert_eq!(
+            anchored(&tree, anchored_id),
+            Some(RbxValue::Bool { value: true })
+        );
+
+        // Putting the defaults back restores the tree, and stripping again
+        // removes the same properties.
+        let inverse = invert_applied_patch_set(&applied_patch_set, &tree);
+        apply_patch_set(&mut tree, inverse);
+        assert_eq!(
+            anchored(&tree, loose_id),
+            Some(RbxValue::Bool { value: false })
+        );
+
+        let stripped_again = strip_default_properties(&mut tree);
+        assert_eq!(
+            stripped_again.updated.len(),
+            applied_patch_set.updated.len()
+        );
+        assert_eq!(anchored(&tree, loose_id), None);
+    }
+}
@@ -29,6 +29,15 @@ pub struct InstanceSnapshot {
     /// All other properties of the instance, weakly-typed.
     pub properties: HashMap<String, RbxValue>,
 
+    /// Ref properties that point at an instance by its path in the tree, like
+    /// `game.ReplicatedStorage.Shared.Config`, instead of by ID. They're
+    /// resolved when the snapshot is applied to a tree. If a property is in
+    /// both this and `properties`, the path wins.
+    ///
+    /// See `RojoTree::get_id_at_path` for how paths are followed.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub ref_paths: HashMap<String, String>,
+
     /// The children of the instance represented as more snapshots.
     ///
     /// Order is relevant for Roblox instances!
@@ -43,6 +52,7 @@ impl InstanceSnapshot {
             name: Cow::Borrowed("DEFAULT"),
             class_name: Cow::Borrowed("DEFAULT"),
             properties: HashMap::new(),
+            ref_paths: HashMap::new(),
             children: Vec::new(),
         }
     }
@@ -68,6 +78,13 @@ impl InstanceSnapshot {
         }
     }
 
+    pub fn ref_paths(self, ref_paths: impl Into<HashMap<String, String>>) -> Self {
+        Self {
+            ref_paths: ref_paths.into(),
+            ..self
+        }
+    }
+
     pub fn children(self, children: impl Into<Vec<Self>>) -> Self {
         Self {
             children: children.into(),
@@ -100,6 +117,7 @@ impl InstanceSnapshot {
             name: Cow::Owned(instance.name.clone()),
             class_name: Cow::Owned(instance.class_name.clone()),
             properties: instance.properties.clone(),
+            ref_paths: HashMap::new(),
             children,
         }
     }
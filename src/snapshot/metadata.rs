@@ -1,12 +1,22 @@
 use std::{
+    collections::HashMap,
     fmt,
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
 use serde::{Deserialize, Serialize};
 
-use crate::{glob::Glob, path_serializer, project::ProjectNode};
+use crate::{
+    glob::Glob,
+    path_serializer,
+    project::{
+        MissingPathBehavior, ProjectNode, PropertyComparison, PropertyTypeChangeBehavior,
+        ScriptKind, TransformRule, UnknownFileBehavior,
+    },
+    snapshot_middleware::DEFAULT_MIDDLEWARE_TIMEOUT,
+};
 
 /// Rojo-specific metadata that can be associated with an instance or a snapshot
 /// of an instance.
@@ -17,6 +27,18 @@ pub struct InstanceMetadata {
     /// manage.
     pub ignore_unknown_instances: bool,
 
+    /// Whether changes made to this instance and its descendants by clients,
+    /// like Roblox Studio, should be rejected instead of being written back to
+    /// the filesystem. Changes from the filesystem still apply.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub read_only: bool,
+
+    /// Properties of this instance that patches should never change once the
+    /// instance exists, sourced from the `ignoreProperties` field of a meta
+    /// file.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore_properties: Vec<String>,
+
     /// If a change occurs to this instance, the instigating source is what
     /// should be run through the snapshot functions to regenerate it.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -52,15 +74,26 @@ pub struct InstanceMetadata {
     /// that instance's instigating source is snapshotted directly, the same
     /// context will be passed into it.
     pub context: InstanceContext,
+
+    /// Arbitrary data attached to this instance by the user, sourced from the
+    /// `$meta` field of a meta file.
+    ///
+    /// Rojo doesn't use this data itself and doesn't include it in built
+    /// files. It's intended for tools that consume Rojo as a library.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub user_data: HashMap<String, serde_json::Value>,
 }
 
 impl InstanceMetadata {
     pub fn new() -> Self {
         Self {
             ignore_unknown_instances: false,
+            read_only: false,
+            ignore_properties: Vec::new(),
             instigating_source: None,
             relevant_paths: Vec::new(),
             context: InstanceContext::default(),
+            user_data: HashMap::new(),
         }
     }
 
@@ -71,6 +104,17 @@ impl InstanceMetadata {
         }
     }
 
+    pub fn read_only(self, read_only: bool) -> Self {
+        Self { read_only, ..self }
+    }
+
+    pub fn ignore_properties(self, ignore_properties: Vec<String>) -> Self {
+        Self {
+            ignore_properties,
+            ..self
+        }
+    }
+
     pub fn instigating_source(self, instigating_source: impl Into<InstigatingSource>) -> Self {
         Self {
             instigating_source: Some(instigating_source.into()),
@@ -91,6 +135,10 @@ impl InstanceMetadata {
             ..self
         }
     }
+
+    pub fn user_data(self, user_data: HashMap<String, serde_json::Value>) -> Self {
+        Self { user_data, ..self }
+    }
 }
 
 impl Default for InstanceMetadata {
@@ -103,9 +151,82 @@ impl Default for InstanceMetadata {
 pub struct InstanceContext {
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub path_ignore_rules: Arc<Vec<PathIgnoreRule>>,
+
+    /// What to do when a project node's `$path` refers to a path that doesn't
+    /// exist. Set by the nearest project file that specifies it.
+    #[serde(default, skip_serializing_if = "MissingPathBehavior::is_default")]
+    pub missing_path_behavior: MissingPathBehavior,
+
+    /// What to do when an update changes the type of a property. Set by the
+    /// nearest project file that specifies it.
+    #[serde(
+        default,
+        skip_serializing_if = "PropertyTypeChangeBehavior::is_default"
+    )]
+    pub property_type_change_behavior: PropertyTypeChangeBehavior,
+
+    /// What to do with files that no middleware knows how to handle. Set by
+    /// the nearest project file that specifies it.
+    #[serde(default, skip_serializing_if = "UnknownFileBehavior::is_default")]
+    pub unknown_file_behavior: UnknownFileBehavior,
+
+    /// Whether a leading UTF-8 byte order mark should be kept in the contents
+    /// of scripts. By default, Rojo strips it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keep_source_bom: bool,
+
+    /// The kind of script that Lua files without a `.server` or `.client`
+    /// suffix turn into. Set by the nearest project file that specifies it.
+    #[serde(default, skip_serializing_if = "ScriptKind::is_default")]
+    pub default_script_kind: ScriptKind,
+
+    /// The longest Source that a ModuleScript generated from a data file can
+    /// have before it's split into chunks. By default, they are never split.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_generated_source_size: Option<usize>,
+
+    /// How many levels of children a JSON model may have below its top-level
+    /// instance. By default, there's no limit beyond what the JSON parser
+    /// allows.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_model_depth: Option<usize>,
+
+    /// How long middleware may spend turning a single file into instances
+    /// before snapshotting it fails, or `None` for no limit. By default, this
+    /// is `DEFAULT_MIDDLEWARE_TIMEOUT`.
+    #[serde(skip, default = "default_middleware_timeout")]
+    pub middleware_timeout: Option<Duration>,
+
+    /// The chain of project files that are currently being snapshotted, from
+    /// the outermost project inwards. Used to detect projects that include
+    /// themselves.
+    #[serde(skip)]
+    pub project_stack: Arc<Vec<PathBuf>>,
+
+    /// The transform rules from every project enclosing this instance, from
+    /// the outermost project inwards. See `apply_transforms`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transform_rules: Arc<Vec<TransformRule>>,
+
+    /// How to compare specific string properties when computing patches,
+    /// keyed by `ClassName.PropertyName`. Modes from inner projects replace
+    /// ones from outer projects.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub property_comparisons: Arc<HashMap<String, PropertyComparison>>,
 }
 
 impl InstanceContext {
+    /// Returns whether the given project file is already being snapshotted,
+    /// which means that snapshotting it again would never finish.
+    pub fn is_project_in_stack(&self, project_path: &Path) -> bool {
+        self.project_stack.iter().any(|path| path == project_path)
+    }
+
+    /// Record that the given project file is being snapshotted.
+    pub fn push_project(&mut self, project_path: PathBuf) {
+        Arc::make_mut(&mut self.project_stack).push(project_path);
+    }
+
     /// Extend the list of ignore rules in the context with the given new rules.
     pub fn add_path_ignore_rules<I>(&mut self, new_rules: I)
     where
@@ -123,16 +244,73 @@ impl InstanceContext {
         let rules = Arc::make_mut(&mut self.path_ignore_rules);
         rules.extend(new_rules);
     }
+
+    /// Extend the list of transform rules in the context with the given new
+    /// rules. They take precedence over the rules that are already present.
+    pub fn add_transform_rules<I>(&mut self, new_rules: I)
+    where
+        I: IntoIterator<Item = TransformRule>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let new_rules = new_rules.into_iter();
+
+        if new_rules.len() == 0 {
+            return;
+        }
+
+        let rules = Arc::make_mut(&mut self.transform_rules);
+        rules.extend(new_rules);
+    }
+
+    /// Add property comparison modes to the context, replacing any that are
+    /// already present for the same properties.
+    pub fn add_property_comparisons<I>(&mut self, new_comparisons: I)
+    where
+        I: IntoIterator<Item = (String, PropertyComparison)>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let new_comparisons = new_comparisons.into_iter();
+
+        if new_comparisons.len() == 0 {
+            return;
+        }
+
+        let comparisons = Arc::make_mut(&mut self.property_comparisons);
+        comparisons.extend(new_comparisons);
+    }
+
+    /// Finds how the given property should be compared.
+    pub fn property_comparison(&self, class_name: &str, property: &str) -> PropertyComparison {
+        self.property_comparisons
+            .get(&format!("{}.{}", class_name, property))
+            .copied()
+            .unwrap_or_default()
+    }
 }
 
 impl Default for InstanceContext {
     fn default() -> Self {
         InstanceContext {
             path_ignore_rules: Arc::new(Vec::new()),
+            missing_path_behavior: MissingPathBehavior::default(),
+            property_type_change_behavior: PropertyTypeChangeBehavior::default(),
+            unknown_file_behavior: UnknownFileBehavior::default(),
+            keep_source_bom: false,
+            default_script_kind: ScriptKind::default(),
+            max_generated_source_size: None,
+            max_model_depth: None,
+            middleware_timeout: default_middleware_timeout(),
+            project_stack: Arc::new(Vec::new()),
+            transform_rules: Arc::new(Vec::new()),
+            property_comparisons: Arc::new(HashMap::new()),
         }
     }
 }
 
+fn default_middleware_timeout() -> Option<Duration> {
+    Some(DEFAULT_MIDDLEWARE_TIMEOUT)
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PathIgnoreRule {
     /// The path that this glob is relative to. Since ignore globs are defined
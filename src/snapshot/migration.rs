@@ -0,0 +1,196 @@
+//! Migrates properties that Roblox renamed or changed the type of, so that
+//! projects can keep working without changing every file that sets them.
+
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use rbx_dom_weak::RbxValue;
+
+use super::InstanceSnapshot;
+
+type MigrationFn = dyn Fn(&str, &str, RbxValue) -> Option<(String, RbxValue)> + Send + Sync;
+
+/// A function that changes a property, given the class name of the instance,
+/// the name of the property, and its value. It returns the name and value to
+/// use instead, or `None` to leave the property out.
+///
+/// `ServeSession` runs one on every snapshot if it's given one in its
+/// options. `rojo build` and `rojo serve` don't set one.
+#[derive(Clone)]
+pub struct PropertyMigration(Arc<MigrationFn>);
+
+impl PropertyMigration {
+    pub fn new<F>(migrate: F) -> Self
+    where
+        F: Fn(&str, &str, RbxValue) -> Option<(String, RbxValue)> + Send + Sync + 'static,
+    {
+        PropertyMigration(Arc::new(migrate))
+    }
+}
+
+impl fmt::Debug for PropertyMigration {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("PropertyMigration")
+    }
+}
+
+/// Runs `migration` on the properties of the given snapshot and all of its
+/// descendants.
+///
+/// This should happen before a patch is computed from the snapshot, like
+/// `apply_transforms`. That way the tree only ever holds migrated properties,
+/// and a file that hasn't changed doesn't produce a patch.
+///
+/// If more than one property ends up with the same name, a property that kept
+/// its name wins. Otherwise, the one whose original name sorts first wins, so
+/// the result doesn't depend on the order of the snapshot's properties.
+pub fn migrate_properties(snapshot: &mut InstanceSnapshot, migration: &PropertyMigration) {
+    let mut properties: Vec<(String, RbxValue)> = snapshot.properties.drain().collect();
+    properties.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut migrated = HashMap::with_capacity(properties.len());
+    let mut renamed = Vec::new();
+
+    for (key, value) in properties {
+        if let Some((new_key, new_value)) = (migration.0)(&snapshot.class_name, &key, value) {
+            if new_key == key {
+                migrated.insert(new_key, new_value);
+            } else {
+                renamed.push((new_key, new_value));
+            }
+        }
+    }
+
+    for (key, value) in renamed {
+        migrated.entry(key).or_insert(value);
+    }
+
+    snapshot.properties = migrated;
+
+    for child in &mut snapshot.children {
+        migrate_properties(child, migration);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use maplit::hashmap;
+    use rbx_dom_weak::{BrickColor, RbxInstanceProperties, RbxValueConversion, RbxValueType};
+
+    use crate::snapshot::{
+        apply_patch_set, compute_patch_set, InstancePropertiesWithMeta, RojoTree,
+    };
+
+    fn brick_color_to_color() -> PropertyMigration {
+        PropertyMigration::new(|_class_name, property, value| match property {
+            "BrickColor" => match value.try_convert_ref(RbxValueType::Color3) {
+                RbxValueConversion::Converted(color) => Some(("Color".to_owned(), color)),
+                _ => None,
+            },
+            "Obsolete" => None,
+            _ => Some((property.to_owned(), value)),
+        })
+    }
+
+    fn brick_color() -> RbxValue {
+        RbxValue::BrickColor {
+            value: BrickColor::BrightRed,
+        }
+    }
+
+    fn color() -> RbxValue {
+        RbxValue::Color3 {
+            value: [196.0 / 255.0, 40.0 / 255.0, 28.0 / 255.0],
+        }
+    }
+
+    fn root_with_part(properties: HashMap<String, RbxValue>) -> InstanceSnapshot {
+        InstanceSnapshot::new()
+            .name("Root")
+            .class_name("Folder")
+            .children(vec![InstanceSnapshot::new()
+                .name("Part")
+                .class_name("Part")
+                .properties(properties)])
+    }
+
+    #[test]
+    fn migrated_snapshots_patch_cleanly() {
+        let migration = brick_color_to_color();
+
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "Root".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+
+        let snapshot = || {
+            let mut snapshot = root_with_part(hashmap! {
+                "BrickColor".to_owned() => brick_color(),
+                "Anchored".to_owned() => RbxValue::Bool { value: true },
+                "Obsolete".to_owned() => RbxValue::Bool { value: true },
+            });
+            migrate_properties(&mut snapshot, &migration);
+            snapshot
+        };
+
+        let patch_set = compute_patch_set(&snapshot(), &tree, root_id);
+        apply_patch_set(&mut tree, patch_set);
+
+        let part_id = tree.get_instance(root_id).unwrap().children()[0];
+        assert_eq!(
+            tree.get_instance(part_id).unwrap().properties(),
+            &hashmap! {
+                "Color".to_owned() => color(),
+                "Anchored".to_owned() => RbxValue::Bool { value: true },
+            }
+        );
+
+        // Snapshotting the same file again doesn't change anything.
+        let patch_set = compute_patch_set(&snapshot(), &tree, root_id);
+        assert!(patch_set.added_instances.is_empty());
+        assert!(patch_set.removed_instances.is_empty());
+        assert!(patch_set.updated_instances.is_empty());
+
+        // Taking BrickColor out of the file takes out the migrated Color too.
+        let mut without_color = root_with_part(hashmap! {
+            "Anchored".to_owned() => RbxValue::Bool { value: true },
+        });
+        migrate_properties(&mut without_color, &migration);
+
+        let patch_set = compute_patch_set(&without_color, &tree, root_id);
+        apply_patch_set(&mut tree, patch_set);
+
+        assert_eq!(
+            tree.get_instance(part_id).unwrap().properties(),
+            &hashmap! {
+                "Anchored".to_owned() => RbxValue::Bool { value: true },
+            }
+        );
+    }
+
+    #[test]
+    fn properties_that_keep_their_name_win() {
+        let other_color = RbxValue::Color3 {
+            value: [0.0, 0.0, 1.0],
+        };
+
+        let mut snapshot = root_with_part(hashmap! {
+            "BrickColor".to_owned() => brick_color(),
+            "Color".to_owned() => other_color.clone(),
+        });
+        migrate_properties(&mut snapshot, &brick_color_to_color());
+
+        assert_eq!(
+            snapshot.children[0].properties,
+            hashmap! {
+                "Color".to_owned() => other_color,
+            }
+        );
+    }
+}
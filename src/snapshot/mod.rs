@@ -15,6 +15,9 @@
 //!
 //!    See `src/snapshot_middleware` for implementation.
 //!
+//!    Any `transforms` rules from project files are then applied to the
+//!    snapshot with `apply_transforms`.
+//!
 //! 2. Input snapshots are turned into `PatchSet` objects by Rojo's diffing
 //!    algorithm via `compute_patch_set`. This operation doesn't mutate the
 //!    instance tree, so work at this point can be thrown away.
@@ -48,18 +51,33 @@
 
 #![allow(dead_code)]
 
+mod attributes;
+mod default_properties;
 mod instance_snapshot;
 mod metadata;
+mod migration;
 mod patch;
 mod patch_apply;
 mod patch_compute;
+mod patch_filter;
+mod patch_invert;
+mod patch_preview;
+mod patch_split;
+mod ref_check;
+mod transform;
 mod tree;
 
+pub use attributes::{apply_attribute_changes, AttributeValue, ATTRIBUTES_PROPERTY};
+pub use default_properties::strip_default_properties;
 pub use instance_snapshot::InstanceSnapshot;
 pub use metadata::*;
+pub use migration::{migrate_properties, PropertyMigration};
 pub use patch::*;
 pub use patch_apply::apply_patch_set;
-pub use patch_compute::compute_patch_set;
+pub use patch_compute::{compute_patch_set, compute_rescan_patch_set};
+pub use patch_filter::filter_patch_set_by_class;
+pub use ref_check::warn_cross_subtree_refs;
+pub use transform::apply_transforms;
 pub use tree::*;
 
 #[cfg(test)]
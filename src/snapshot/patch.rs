@@ -1,11 +1,11 @@
 //! Defines the data structures used for describing instance patches.
 
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
 use rbx_dom_weak::{RbxId, RbxValue};
 use serde::{Deserialize, Serialize};
 
-use super::{InstanceMetadata, InstanceSnapshot};
+use super::{attributes::AttributeChanges, InstanceMetadata, InstanceSnapshot, RojoTree};
 
 /// A set of different kinds of patches that can be applied to an RbxTree.
 ///
@@ -27,6 +27,35 @@ impl<'a> PatchSet {
             updated_instances: Vec::new(),
         }
     }
+
+    /// Counts how many instances would change if this patch set were applied
+    /// to the given tree. Removing an instance also removes its descendants,
+    /// and adding an instance also adds its children, so those are counted
+    /// too.
+    pub fn changed_instance_count(&self, tree: &RojoTree) -> usize {
+        let removed: usize = self
+            .removed_instances
+            .iter()
+            .filter(|&&id| tree.get_instance(id).is_some())
+            .map(|&id| 1 + tree.descendants(id).count())
+            .sum();
+
+        let added: usize = self
+            .added_instances
+            .iter()
+            .map(|add| snapshot_instance_count(&add.instance))
+            .sum();
+
+        removed + added + self.updated_instances.len()
+    }
+}
+
+fn snapshot_instance_count(snapshot: &InstanceSnapshot) -> usize {
+    1 + snapshot
+        .children
+        .iter()
+        .map(snapshot_instance_count)
+        .sum::<usize>()
 }
 
 /// A patch containing an instance that was added to the tree.
@@ -47,10 +76,36 @@ pub struct PatchUpdate {
     /// then that property has been removed.
     pub changed_properties: HashMap<String, Option<RbxValue>>,
 
+    /// Contains changed attributes, which are compared one by one instead of
+    /// as part of `changed_properties`. If an attribute is assigned to `None`,
+    /// then that attribute has been removed.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub changed_attributes: AttributeChanges,
+
+    /// Ref properties that should point at whatever instance is at a path, from
+    /// `InstanceSnapshot::ref_paths`. These are resolved after the rest of the
+    /// patch is applied, since the path might lead into instances that the
+    /// patch adds.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub changed_ref_paths: HashMap<String, String>,
+
     /// Changed Rojo-specific metadata, if any of it changed.
     pub changed_metadata: Option<InstanceMetadata>,
 }
 
+impl PatchUpdate {
+    /// Tells whether this update only changes metadata, like when an
+    /// instance's file moved without its contents changing.
+    pub fn is_metadata_only(&self) -> bool {
+        self.changed_metadata.is_some()
+            && self.changed_name.is_none()
+            && self.changed_class_name.is_none()
+            && self.changed_properties.is_empty()
+            && self.changed_attributes.is_empty()
+            && self.changed_ref_paths.is_empty()
+    }
+}
+
 /// Applied patch sets have the same rough shape as PatchSet, but are
 /// descriptive of the operation that happened instead of prescribing what
 /// mutations to apply to the tree.
@@ -66,6 +121,23 @@ pub struct AppliedPatchSet {
     pub removed: Vec<RbxId>,
     pub added: Vec<RbxId>,
     pub updated: Vec<AppliedPatchUpdate>,
+
+    /// Parts of the patch that couldn't be applied. If this isn't empty, a
+    /// tree synchronized with these patches has likely drifted out of date.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<PatchApplyWarning>,
+
+    /// Ref properties on added instances that point to instances that aren't
+    /// in the tree.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unresolved_refs: Vec<UnresolvedRef>,
+
+    /// The instances in `removed` whose parents weren't removed, as they were
+    /// just before they were removed. This is only needed to undo the patch,
+    /// so it's left empty unless `PatchApplyOptions::record_removed_snapshots`
+    /// is set, and it isn't sent to clients.
+    #[serde(skip)]
+    pub removed_snapshots: Vec<PatchAdd>,
 }
 
 impl AppliedPatchSet {
@@ -74,6 +146,62 @@ impl AppliedPatchSet {
             removed: Vec::new(),
             added: Vec::new(),
             updated: Vec::new(),
+            warnings: Vec::new(),
+            unresolved_refs: Vec::new(),
+            removed_snapshots: Vec::new(),
+        }
+    }
+}
+
+/// Describes part of a patch that referred to an instance that didn't exist.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PatchApplyWarning {
+    pub id: RbxId,
+    pub operation: PatchOperation,
+}
+
+/// A Ref property that still pointed into snapshot space after a patch was
+/// applied, usually because two model files refer to each other, or that
+/// pointed at a path that no instance is at.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnresolvedRef {
+    pub id: RbxId,
+    pub property: String,
+    pub target: RefTarget,
+}
+
+/// What an unresolved Ref property was pointing at.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RefTarget {
+    Id(RbxId),
+
+    /// A path from `InstanceSnapshot::ref_paths`.
+    Path(String),
+}
+
+impl fmt::Display for RefTarget {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RefTarget::Id(id) => write!(formatter, "{}", id),
+            RefTarget::Path(path) => write!(formatter, "the path {}", path),
+        }
+    }
+}
+
+/// The kinds of changes in a patch that refer to an existing instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PatchOperation {
+    Remove,
+    Update,
+}
+
+impl PatchOperation {
+    pub fn verb(self) -> &'static str {
+        match self {
+            PatchOperation::Remove => "remove",
+            PatchOperation::Update => "update",
         }
     }
 }
@@ -87,6 +215,11 @@ pub struct AppliedPatchUpdate {
     pub changed_class_name: Option<String>,
     pub changed_properties: HashMap<String, Option<RbxValue>>,
     pub changed_metadata: Option<InstanceMetadata>,
+
+    /// What the changed parts of the instance were before the update. This is
+    /// only needed to undo the update, so it isn't sent to clients.
+    #[serde(skip)]
+    pub previous: PreviousValues,
 }
 
 impl AppliedPatchUpdate {
@@ -97,6 +230,27 @@ impl AppliedPatchUpdate {
             changed_class_name: None,
             changed_properties: HashMap::new(),
             changed_metadata: None,
+            previous: PreviousValues::default(),
         }
     }
+
+    /// Tells whether this update only changed metadata, in which case the
+    /// instance's name, class, and properties can be assumed to be the same.
+    pub fn is_metadata_only(&self) -> bool {
+        self.changed_metadata.is_some()
+            && self.changed_name.is_none()
+            && self.changed_class_name.is_none()
+            && self.changed_properties.is_empty()
+    }
+}
+
+/// The values that an `AppliedPatchUpdate` replaced. Each field is only set if
+/// the matching `changed_` field is. A property that didn't exist before the
+/// update is `None`.
+#[derive(Debug, Clone, Default)]
+pub struct PreviousValues {
+    pub name: Option<String>,
+    pub class_name: Option<String>,
+    pub properties: HashMap<String, Option<RbxValue>>,
+    pub metadata: Option<InstanceMetadata>,
 }
@@ -1,11 +1,21 @@
 //! Defines the algorithm for applying generated patches.
 
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    iter::once,
+};
 
 use rbx_dom_weak::{RbxId, RbxInstanceProperties, RbxValue};
+use thiserror::Error;
+
+use crate::{project::PropertyTypeChangeBehavior, trace};
 
 use super::{
-    patch::{AppliedPatchSet, AppliedPatchUpdate, PatchSet, PatchUpdate},
+    attributes::{apply_attribute_changes, ATTRIBUTES_PROPERTY},
+    patch::{
+        AppliedPatchSet, AppliedPatchUpdate, PatchAdd, PatchApplyWarning, PatchOperation, PatchSet,
+        PatchUpdate, RefTarget, UnresolvedRef,
+    },
     InstancePropertiesWithMeta, InstanceSnapshot, RojoTree,
 };
 
@@ -13,14 +23,117 @@ use super::{
 /// tree and returns an `AppliedPatchSet`, which can be used to keep another
 /// tree in sync with Rojo's.
 pub fn apply_patch_set(tree: &mut RojoTree, patch_set: PatchSet) -> AppliedPatchSet {
-    let mut context = PatchApplyContext::default();
+    apply_patch_set_with_options(tree, patch_set, PatchApplyOptions::default())
+}
+
+/// A part of a patch that refers to an instance that isn't in the tree.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("patch tried to {} instance {id}, but it is not in the tree", .operation.verb())]
+pub struct PatchApplyError {
+    pub id: RbxId,
+    pub operation: PatchOperation,
+}
+
+/// Like `apply_patch_set`, but fails instead of warning if the patch removes or
+/// updates an instance that isn't in the tree. The whole patch is checked
+/// before any of it is applied, so the tree is left alone when this fails.
+///
+/// Updates to instances that the same patch removes count as missing too,
+/// since they would be skipped otherwise.
+pub fn apply_patch_set_strict(
+    tree: &mut RojoTree,
+    patch_set: PatchSet,
+) -> Result<AppliedPatchSet, PatchApplyError> {
+    if let Some(error) = find_missing_instance(tree, &patch_set) {
+        return Err(error);
+    }
+
+    Ok(apply_patch_set(tree, patch_set))
+}
+
+/// Finds the first removal or update in the patch, in the order they'd be
+/// applied, that refers to an instance that won't be in the tree.
+fn find_missing_instance(tree: &RojoTree, patch_set: &PatchSet) -> Option<PatchApplyError> {
+    for &id in &patch_set.removed_instances {
+        if tree.get_instance(id).is_none() {
+            return Some(PatchApplyError {
+                id,
+                operation: PatchOperation::Remove,
+            });
+        }
+    }
+
+    let removed: HashSet<RbxId> = patch_set.removed_instances.iter().copied().collect();
+
+    for update in &patch_set.updated_instances {
+        if tree.get_instance(update.id).is_none() || is_removed(tree, update.id, &removed) {
+            return Some(PatchApplyError {
+                id: update.id,
+                operation: PatchOperation::Update,
+            });
+        }
+    }
+
+    None
+}
+
+/// Tells whether the instance or any of its ancestors is in `removed`.
+fn is_removed(tree: &RojoTree, id: RbxId, removed: &HashSet<RbxId>) -> bool {
+    let mut current = Some(id);
+
+    while let Some(current_id) = current {
+        if removed.contains(&current_id) {
+            return true;
+        }
+
+        current = tree
+            .get_instance(current_id)
+            .and_then(|instance| instance.parent());
+    }
+
+    false
+}
+
+/// Options that change how `apply_patch_set_with_options` handles patches
+/// that can't be applied cleanly, and what it records about them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PatchApplyOptions {
+    /// Set Ref properties on added instances that point to instances that
+    /// aren't in the tree to `None` instead of leaving them pointing nowhere.
+    /// Either way, they're listed in `AppliedPatchSet::unresolved_refs`.
+    pub clear_unresolved_refs: bool,
+
+    /// Fill in `AppliedPatchSet::removed_snapshots`, which
+    /// `invert_applied_patch_set` needs to add removed instances back. This
+    /// copies every removed subtree, so it's off unless the patch will be
+    /// undone.
+    pub record_removed_snapshots: bool,
+}
+
+/// Like `apply_patch_set`, but with control over how problems are handled.
+pub fn apply_patch_set_with_options(
+    tree: &mut RojoTree,
+    patch_set: PatchSet,
+    options: PatchApplyOptions,
+) -> AppliedPatchSet {
+    let _span = trace::span("patch", || "apply_patch_set".to_owned());
 
-    for removed_id in patch_set.removed_instances {
+    let mut context = PatchApplyContext {
+        clear_unresolved_refs: options.clear_unresolved_refs,
+        record_removed_snapshots: options.record_removed_snapshots,
+        ..Default::default()
+    };
+
+    for removed_id in top_level_removals(tree, patch_set.removed_instances) {
         apply_remove_instance(&mut context, tree, removed_id);
     }
 
     for add_patch in patch_set.added_instances {
-        apply_add_child(&mut context, tree, add_patch.parent_id, add_patch.instance);
+        if let Some(id) =
+            apply_add_child(&mut context, tree, add_patch.parent_id, add_patch.instance)
+        {
+            context.added_roots.push(id);
+        }
     }
 
     // Updates need to be applied after additions, which reduces the complexity
@@ -50,9 +163,10 @@ struct PatchApplyContext {
     ///    part of the snapshot that was put through the patch computation
     ///    function.
     ///
-    /// #2 should not occur in well-formed projects, but is indistinguishable
-    /// from #1 right now. It could happen if two model files try to reference
-    /// eachother.
+    /// #2 should not occur in well-formed projects. It could happen if two
+    /// model files try to reference eachother. Once every added instance is in
+    /// the tree, refs that don't point to an instance in the tree must be #2,
+    /// and are reported as unresolved.
     snapshot_id_to_instance_id: HashMap<RbxId, RbxId>,
 
     /// The properties of instances added by the current `PatchSet`.
@@ -68,7 +182,32 @@ struct PatchApplyContext {
     ///
     /// This doesn't affect updated instances, since they're always applied
     /// after we've added all the instances from the patch.
-    added_instance_properties: HashMap<RbxId, HashMap<String, RbxValue>>,
+    ///
+    /// Instances are kept in the order they were added so that finalizing a
+    /// patch always processes them in the same order.
+    added_instance_properties: Vec<(RbxId, HashMap<String, RbxValue>)>,
+
+    /// The `ref_paths` of instances added by the current `PatchSet`. Paths can
+    /// lead through other added instances, so they're also resolved once the
+    /// whole patch has been added to the tree.
+    added_instance_ref_paths: Vec<(RbxId, HashMap<String, String>)>,
+
+    /// The `changed_ref_paths` of instances updated by the current `PatchSet`,
+    /// resolved at the same time as `added_instance_ref_paths`.
+    updated_instance_ref_paths: Vec<(RbxId, HashMap<String, String>)>,
+
+    /// Instances that were added without their parent also being added.
+    added_roots: Vec<RbxId>,
+
+    /// Instances that were removed without their parent also being removed.
+    removed_roots: Vec<RemovedRoot>,
+
+    /// Whether unresolved refs should be cleared. See `PatchApplyOptions`.
+    clear_unresolved_refs: bool,
+
+    /// Whether removed instances should be snapshotted. See
+    /// `PatchApplyOptions`.
+    record_removed_snapshots: bool,
 
     /// The current applied patch result, describing changes made to the tree.
     applied_patch_set: AppliedPatchSet,
@@ -85,84 +224,541 @@ struct PatchApplyContext {
 /// where we build up a map of snapshot IDs to instance IDs as they're created,
 /// then apply properties all at once at the end.
 fn finalize_patch_application(context: PatchApplyContext, tree: &mut RojoTree) -> AppliedPatchSet {
-    for (id, properties) in context.added_instance_properties {
+    let PatchApplyContext {
+        snapshot_id_to_instance_id,
+        added_instance_properties,
+        added_instance_ref_paths,
+        updated_instance_ref_paths,
+        added_roots,
+        removed_roots,
+        clear_unresolved_refs,
+        record_removed_snapshots: _,
+        mut applied_patch_set,
+    } = context;
+
+    let replacements = find_replacements(tree, &removed_roots, &added_roots);
+
+    for (id, properties) in added_instance_properties {
+        let mut unresolved_refs = Vec::new();
+
+        let properties: Vec<(String, RbxValue)> = properties
+            .into_iter()
+            .map(|(key, property_value)| {
+                let target = match property_value {
+                    RbxValue::Ref {
+                        value: Some(target),
+                    } => target,
+                    _ => return (key, property_value),
+                };
+
+                // Snapshots added by an earlier patch, like another part of a
+                // split patch set, are only known to the tree.
+                if let Some(instance_id) = snapshot_id_to_instance_id
+                    .get(&target)
+                    .copied()
+                    .or_else(|| tree.get_id_for_snapshot_id(target))
+                    .or_else(|| replacements.get(&target).copied())
+                {
+                    let resolved = RbxValue::Ref {
+                        value: Some(instance_id),
+                    };
+                    return (key, resolved);
+                }
+
+                if tree.get_instance(target).is_some() {
+                    return (key, property_value);
+                }
+
+                unresolved_refs.push(UnresolvedRef {
+                    id,
+                    property: key.clone(),
+                    target: RefTarget::Id(target),
+                });
+
+                if clear_unresolved_refs {
+                    (key, RbxValue::Ref { value: None })
+                } else {
+                    (key, property_value)
+                }
+            })
+            .collect();
+
         // This should always succeed since instances marked as added in our
         // patch should be added without fail.
         let mut instance = tree
             .get_instance_mut(id)
             .expect("Invalid instance ID in deferred property map");
 
-        for (key, mut property_value) in properties {
-            if let RbxValue::Ref { value: Some(id) } = property_value {
-                if let Some(&instance_id) = context.snapshot_id_to_instance_id.get(&id) {
-                    property_value = RbxValue::Ref {
-                        value: Some(instance_id),
+        instance.properties_mut().extend(properties);
+
+        // Properties are stored in a HashMap, so we sort the refs from each
+        // instance to keep the list in a stable order.
+        unresolved_refs.sort_by(|a, b| a.property.cmp(&b.property));
+
+        for unresolved_ref in &unresolved_refs {
+            log::warn!(
+                "Ref property {} on instance {} points to {}, which is not in the tree",
+                unresolved_ref.property,
+                unresolved_ref.id,
+                unresolved_ref.target
+            );
+        }
+
+        applied_patch_set.unresolved_refs.extend(unresolved_refs);
+    }
+
+    for (id, ref_paths) in added_instance_ref_paths {
+        resolve_ref_paths(
+            tree,
+            id,
+            ref_paths,
+            clear_unresolved_refs,
+            &mut applied_patch_set,
+        );
+    }
+
+    // Unlike added instances, which are sent whole, updated instances have to
+    // report each property that changed.
+    for (id, ref_paths) in updated_instance_ref_paths {
+        let (changed_properties, previous_properties) = resolve_ref_paths(
+            tree,
+            id,
+            ref_paths,
+            clear_unresolved_refs,
+            &mut applied_patch_set,
+        );
+
+        record_property_changes(
+            &mut applied_patch_set,
+            id,
+            changed_properties,
+            previous_properties,
+        );
+    }
+
+    if !replacements.is_empty() {
+        redirect_refs(tree, &replacements, &mut applied_patch_set);
+    }
+
+    applied_patch_set
+}
+
+/// Points the given Ref properties of an instance at whatever instance is at
+/// their paths, reporting paths that don't lead anywhere as unresolved refs.
+///
+/// Returns the properties that changed, and what they were before.
+fn resolve_ref_paths(
+    tree: &mut RojoTree,
+    id: RbxId,
+    ref_paths: HashMap<String, String>,
+    clear_unresolved_refs: bool,
+    applied_patch_set: &mut AppliedPatchSet,
+) -> (
+    HashMap<String, Option<RbxValue>>,
+    HashMap<String, Option<RbxValue>>,
+) {
+    let mut changed_properties = HashMap::new();
+    let mut previous_properties = HashMap::new();
+
+    let mut ref_paths: Vec<_> = ref_paths.into_iter().collect();
+    ref_paths.sort();
+
+    for (property, path) in ref_paths {
+        let target = tree.get_id_at_path(&path);
+
+        if target.is_none() {
+            log::warn!(
+                "Ref property {} on instance {} points to {}, which is not in the tree",
+                property,
+                id,
+                path
+            );
+
+            applied_patch_set.unresolved_refs.push(UnresolvedRef {
+                id,
+                property: property.clone(),
+                target: RefTarget::Path(path),
+            });
+
+            if !clear_unresolved_refs {
+                continue;
+            }
+        }
+
+        let value = RbxValue::Ref { value: target };
+        let mut instance = tree.get_instance_mut(id).unwrap();
+        let previous = instance
+            .properties_mut()
+            .insert(property.clone(), value.clone());
+
+        if previous.as_ref() != Some(&value) {
+            changed_properties.insert(property.clone(), Some(value));
+            previous_properties.insert(property, previous);
+        }
+    }
+
+    (changed_properties, previous_properties)
+}
+
+/// Adds property changes made while finalizing a patch to the instance's entry
+/// in the applied patch set, so that trees kept in sync with this one make the
+/// same changes.
+fn record_property_changes(
+    applied_patch_set: &mut AppliedPatchSet,
+    id: RbxId,
+    changed_properties: HashMap<String, Option<RbxValue>>,
+    previous_properties: HashMap<String, Option<RbxValue>>,
+) {
+    if changed_properties.is_empty() {
+        return;
+    }
+
+    match applied_patch_set
+        .updated
+        .iter_mut()
+        .find(|update| update.id == id)
+    {
+        Some(update) => {
+            update.changed_properties.extend(changed_properties);
+
+            // If the update already changed this property, the value from
+            // before the update is the one to keep.
+            for (key, previous) in previous_properties {
+                update.previous.properties.entry(key).or_insert(previous);
+            }
+        }
+        None => {
+            let mut update = AppliedPatchUpdate::new(id);
+            update.changed_properties = changed_properties;
+            update.previous.properties = previous_properties;
+            applied_patch_set.updated.push(update);
+        }
+    }
+}
+
+/// What `find_replacements` needs to know about an instance that was removed
+/// without its parent.
+#[derive(Debug)]
+struct RemovedRoot {
+    id: RbxId,
+    parent_id: RbxId,
+    class_name: String,
+}
+
+/// Pairs up removed instances with the added instances that replace them, so
+/// that refs to a removed instance can follow it to its replacement.
+///
+/// Patch computation already keeps the IDs of instances that were only
+/// renamed, so this only matters when an instance was replaced and changed in
+/// other ways too. An instance is only replaced if it and exactly one added
+/// instance are the only ones with their parent and class name, since
+/// redirecting a ref to the wrong instance is worse than leaving it dangling.
+fn find_replacements(
+    tree: &RojoTree,
+    removed_roots: &[RemovedRoot],
+    added_roots: &[RbxId],
+) -> HashMap<RbxId, RbxId> {
+    let mut replacements = HashMap::new();
+
+    for removed in removed_roots {
+        let class_name = &removed.class_name;
+
+        let removed_count = removed_roots
+            .iter()
+            .filter(|other| other.parent_id == removed.parent_id && other.class_name == *class_name)
+            .count();
+
+        let mut candidates = added_roots.iter().filter(|&&added_id| {
+            tree.get_instance(added_id).map_or(false, |added| {
+                added.parent() == Some(removed.parent_id) && added.class_name() == class_name
+            })
+        });
+
+        if let (1, Some(&added_id), None) = (removed_count, candidates.next(), candidates.next()) {
+            replacements.insert(removed.id, added_id);
+        }
+    }
+
+    replacements
+}
+
+/// Points Ref properties anywhere in the tree that refer to a replaced instance
+/// at its replacement instead. These changes are reported as updates, so trees
+/// kept in sync with this one make the same change.
+fn redirect_refs(
+    tree: &mut RojoTree,
+    replacements: &HashMap<RbxId, RbxId>,
+    applied_patch_set: &mut AppliedPatchSet,
+) {
+    let root_id = tree.get_root_id();
+    let ids: Vec<RbxId> = once(root_id)
+        .chain(tree.descendants(root_id).map(|instance| instance.id()))
+        .collect();
+
+    for id in ids {
+        let mut instance = tree.get_instance_mut(id).unwrap();
+        let mut changed_properties = HashMap::new();
+        let mut previous_properties = HashMap::new();
+
+        for (key, value) in instance.properties_mut() {
+            if let RbxValue::Ref {
+                value: Some(target),
+            } = value
+            {
+                if let Some(&replacement_id) = replacements.get(target) {
+                    log::debug!(
+                        "Redirecting ref {} on instance {} from {} to {}",
+                        key,
+                        id,
+                        target,
+                        replacement_id
+                    );
+
+                    let previous = RbxValue::Ref {
+                        value: Some(*target),
                     };
+                    previous_properties.insert(key.clone(), Some(previous));
+
+                    *target = replacement_id;
+                    changed_properties.insert(key.clone(), Some(value.clone()));
                 }
             }
-
-            instance.properties_mut().insert(key, property_value);
         }
+
+        record_property_changes(
+            applied_patch_set,
+            id,
+            changed_properties,
+            previous_properties,
+        );
     }
+}
 
-    context.applied_patch_set
+/// Filters a list of instances to remove down to the ones that don't have an
+/// ancestor in the list, also removing duplicates.
+///
+/// Removing an instance removes all of its descendants, so removing them
+/// separately would either do nothing, if their ancestor was removed first, or
+/// make the result depend on the order of the list.
+fn top_level_removals(tree: &RojoTree, removed_ids: Vec<RbxId>) -> Vec<RbxId> {
+    let removed_set: HashSet<RbxId> = removed_ids.iter().copied().collect();
+    let mut seen = HashSet::new();
+
+    removed_ids
+        .into_iter()
+        .filter(|&id| {
+            let mut current = tree.get_instance(id).and_then(|instance| instance.parent());
+
+            while let Some(ancestor_id) = current {
+                if removed_set.contains(&ancestor_id) {
+                    return false;
+                }
+
+                current = tree
+                    .get_instance(ancestor_id)
+                    .and_then(|instance| instance.parent());
+            }
+
+            seen.insert(id)
+        })
+        .collect()
 }
 
+/// Removes an instance and all of its descendants. They're all listed in the
+/// applied patch, so that trees kept in sync with this one know to remove
+/// every one of them. If `PatchApplyOptions::record_removed_snapshots` is set,
+/// a snapshot of the removed instances is kept too, so that the patch can be
+/// undone.
 fn apply_remove_instance(context: &mut PatchApplyContext, tree: &mut RojoTree, removed_id: RbxId) {
+    let parent_id = tree
+        .get_instance(removed_id)
+        .and_then(|instance| instance.parent());
+
     match tree.remove_instance(removed_id) {
-        Some(_) => context.applied_patch_set.removed.push(removed_id),
+        Some(removed_tree) => {
+            if let Some(parent_id) = parent_id {
+                let removed_instance = removed_tree.get_instance(removed_id).unwrap();
+                context.removed_roots.push(RemovedRoot {
+                    id: removed_id,
+                    parent_id,
+                    class_name: removed_instance.class_name().to_owned(),
+                });
+
+                if context.record_removed_snapshots {
+                    context.applied_patch_set.removed_snapshots.push(PatchAdd {
+                        parent_id,
+                        instance: removed_tree.to_snapshot(removed_id).unwrap(),
+                    });
+                }
+            }
+
+            let removed = &mut context.applied_patch_set.removed;
+
+            removed.push(removed_id);
+            removed.extend(
+                removed_tree
+                    .descendants(removed_id)
+                    .map(|instance| instance.id()),
+            );
+        }
         None => {
             log::warn!(
                 "Patch misapplication: Tried to remove instance {} but it did not exist.",
                 removed_id
             );
+
+            context.applied_patch_set.warnings.push(PatchApplyWarning {
+                id: removed_id,
+                operation: PatchOperation::Remove,
+            });
         }
     }
 }
 
+/// Adds the snapshot to the tree, returning the ID of the new instance unless
+/// it was skipped.
+///
+/// Snapshots can be nested deeply enough that recursing into children would
+/// overflow the stack, so pending snapshots are kept on a stack instead.
+/// Children are pushed in reverse so that instances are still added in the
+/// same parent-before-child order as a depth-first walk.
 fn apply_add_child(
     context: &mut PatchApplyContext,
     tree: &mut RojoTree,
     parent_id: RbxId,
     snapshot: InstanceSnapshot,
-) {
-    let properties = InstancePropertiesWithMeta {
-        properties: RbxInstanceProperties {
-            name: snapshot.name.into_owned(),
-            class_name: snapshot.class_name.into_owned(),
+) -> Option<RbxId> {
+    let mut root_id = None;
+    let mut pending = vec![(parent_id, snapshot)];
 
-            // Property assignment is deferred until after we know about all
-            // instances in this patch. See `PatchApplyContext` for details.
-            properties: HashMap::new(),
-        },
-        metadata: snapshot.metadata,
-    };
+    while let Some((parent_id, snapshot)) = pending.pop() {
+        // If this snapshot was already added to the tree by an earlier patch,
+        // the same patch is being applied again. Adding it again would
+        // duplicate the instance, so we skip it instead.
+        if let Some(snapshot_id) = snapshot.snapshot_id {
+            if let Some(existing_id) = tree.get_id_for_snapshot_id(snapshot_id) {
+                log::debug!(
+                    "Snapshot {} was already added as instance {}, skipping it.",
+                    snapshot_id,
+                    existing_id
+                );
+
+                record_existing_snapshot_ids(context, tree, &snapshot);
+                continue;
+            }
+        }
+
+        let properties = InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: snapshot.name.into_owned(),
+                class_name: snapshot.class_name.into_owned(),
+
+                // Property assignment is deferred until after we know about
+                // all instances in this patch. See `PatchApplyContext` for
+                // details.
+                properties: HashMap::new(),
+            },
+            metadata: snapshot.metadata,
+        };
+
+        let id = tree.insert_instance(properties, parent_id);
 
-    let id = tree.insert_instance(properties, parent_id);
+        // The first snapshot off of the stack is the root, and nothing else is
+        // pushed if it's skipped.
+        root_id.get_or_insert(id);
 
-    context.applied_patch_set.added.push(id);
+        context.applied_patch_set.added.push(id);
 
-    context
-        .added_instance_properties
-        .insert(id, snapshot.properties);
+        // A ref path wins over a property with the same name, even if the path
+        // doesn't lead anywhere.
+        let mut properties = snapshot.properties;
+        for property in snapshot.ref_paths.keys() {
+            properties.remove(property);
+        }
+
+        context.added_instance_properties.push((id, properties));
+
+        if !snapshot.ref_paths.is_empty() {
+            context
+                .added_instance_ref_paths
+                .push((id, snapshot.ref_paths));
+        }
 
-    if let Some(snapshot_id) = snapshot.snapshot_id {
-        context.snapshot_id_to_instance_id.insert(snapshot_id, id);
+        if let Some(snapshot_id) = snapshot.snapshot_id {
+            context.snapshot_id_to_instance_id.insert(snapshot_id, id);
+            tree.insert_snapshot_id(snapshot_id, id);
+        }
+
+        pending.extend(
+            snapshot
+                .children
+                .into_iter()
+                .rev()
+                .map(|child_snapshot| (id, child_snapshot)),
+        );
     }
 
-    for child_snapshot in snapshot.children {
-        apply_add_child(context, tree, id, child_snapshot);
+    root_id
+}
+
+/// Fills in the snapshot ID mapping for a snapshot that was skipped because
+/// it was already applied, so that Ref properties pointing into it are still
+/// rewritten correctly.
+fn record_existing_snapshot_ids(
+    context: &mut PatchApplyContext,
+    tree: &RojoTree,
+    snapshot: &InstanceSnapshot,
+) {
+    let mut pending = vec![snapshot];
+
+    while let Some(snapshot) = pending.pop() {
+        if let Some(snapshot_id) = snapshot.snapshot_id {
+            if let Some(existing_id) = tree.get_id_for_snapshot_id(snapshot_id) {
+                context
+                    .snapshot_id_to_instance_id
+                    .insert(snapshot_id, existing_id);
+            }
+        }
+
+        pending.extend(&snapshot.children);
     }
 }
 
 fn apply_update_child(context: &mut PatchApplyContext, tree: &mut RojoTree, patch: PatchUpdate) {
     let mut applied_patch = AppliedPatchUpdate::new(patch.id);
+    let metadata_only = patch.is_metadata_only();
 
     if let Some(metadata) = patch.changed_metadata {
+        applied_patch.previous.metadata = tree.get_metadata(patch.id).cloned();
         tree.update_metadata(patch.id, metadata.clone());
         applied_patch.changed_metadata = Some(metadata);
     }
 
+    // Nothing else about the instance changes, so there's no need to look at
+    // its properties.
+    if metadata_only && tree.get_instance(patch.id).is_some() {
+        context.applied_patch_set.updated.push(applied_patch);
+        return;
+    }
+
+    let type_change_behavior = tree
+        .get_metadata(patch.id)
+        .map(|metadata| metadata.context.property_type_change_behavior)
+        .unwrap_or_default();
+
+    // Refs to snapshots that an earlier patch added, like another part of a
+    // split patch set, can only be found through the tree.
+    let mut changed_properties = patch.changed_properties;
+    for value in changed_properties.values_mut() {
+        if let Some(RbxValue::Ref { value: Some(id) }) = value {
+            if !context.snapshot_id_to_instance_id.contains_key(id) {
+                if let Some(instance_id) = tree.get_id_for_snapshot_id(*id) {
+                    *id = instance_id;
+                }
+            }
+        }
+    }
+
     let mut instance = match tree.get_instance_mut(patch.id) {
         Some(instance) => instance,
         None => {
@@ -170,21 +766,51 @@ fn apply_update_child(context: &mut PatchApplyContext, tree: &mut RojoTree, patc
                 "Patch misapplication: Instance {}, referred to by update patch, did not exist.",
                 patch.id
             );
+
+            context.applied_patch_set.warnings.push(PatchApplyWarning {
+                id: patch.id,
+                operation: PatchOperation::Update,
+            });
             return;
         }
     };
 
     if let Some(name) = patch.changed_name {
+        applied_patch.previous.name = Some(instance.name().to_owned());
         *instance.name_mut() = name.clone();
         applied_patch.changed_name = Some(name);
     }
 
     if let Some(class_name) = patch.changed_class_name {
+        applied_patch.previous.class_name = Some(instance.class_name().to_owned());
         *instance.class_name_mut() = class_name.clone();
         applied_patch.changed_class_name = Some(class_name);
     }
 
-    for (key, property_entry) in patch.changed_properties {
+    for (key, property_entry) in changed_properties {
+        let type_change = match (instance.properties().get(&key), &property_entry) {
+            (Some(old_value), Some(new_value)) => {
+                describe_type_change(patch.id, &key, old_value, new_value)
+            }
+            _ => None,
+        };
+
+        if let Some(message) = type_change {
+            match type_change_behavior {
+                PropertyTypeChangeBehavior::Allow => {}
+                PropertyTypeChangeBehavior::Warn => log::warn!("{}", message),
+                PropertyTypeChangeBehavior::Reject => {
+                    log::error!("{}, keeping its old value", message);
+                    continue;
+                }
+            }
+        }
+
+        applied_patch
+            .previous
+            .properties
+            .insert(key.clone(), instance.properties().get(&key).cloned());
+
         match property_entry {
             // Ref values need to be potentially rewritten from snapshot IDs to
             // instance IDs if they referred to an instance that was created as
@@ -218,9 +844,56 @@ fn apply_update_child(context: &mut PatchApplyContext, tree: &mut RojoTree, patc
         applied_patch.changed_properties.insert(key, property_entry);
     }
 
+    // Attributes are patched individually, but the tree and anything syncing
+    // from it only know about the property that they're all stored in.
+    if !patch.changed_attributes.is_empty() {
+        applied_patch.previous.properties.insert(
+            ATTRIBUTES_PROPERTY.to_owned(),
+            instance.properties().get(ATTRIBUTES_PROPERTY).cloned(),
+        );
+
+        let value = apply_attribute_changes(
+            instance.properties().get(ATTRIBUTES_PROPERTY),
+            patch.changed_attributes,
+        );
+
+        instance
+            .properties_mut()
+            .insert(ATTRIBUTES_PROPERTY.to_owned(), value.clone());
+        applied_patch
+            .changed_properties
+            .insert(ATTRIBUTES_PROPERTY.to_owned(), Some(value));
+    }
+
+    if !patch.changed_ref_paths.is_empty() {
+        context
+            .updated_instance_ref_paths
+            .push((patch.id, patch.changed_ref_paths));
+    }
+
     context.applied_patch_set.updated.push(applied_patch)
 }
 
+/// Describes how a property's type changed, if it did.
+fn describe_type_change(
+    id: RbxId,
+    key: &str,
+    old_value: &RbxValue,
+    new_value: &RbxValue,
+) -> Option<String> {
+    let old_type = old_value.get_type();
+    let new_type = new_value.get_type();
+
+    if old_type == new_type {
+        None
+    } else {
+        Some(format!(
+            "Property {} of instance {} changed type from {:?} to {:?}",
+            key, id, old_type, new_type
+        ))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -230,11 +903,18 @@ mod test {
     use maplit::hashmap;
     use rbx_dom_weak::RbxValue;
 
-    use super::super::PatchAdd;
+    use super::super::{
+        attributes::{
+            decode_attributes, encode_attributes,
+            test::{double, string},
+            AttributeChanges, Attributes,
+        },
+        InstanceMetadata,
+    };
 
     #[test]
     fn add_from_empty() {
-        let _ = env_logger::try_init();
+        crate::log_capture::init();
 
         let mut tree = RojoTree::new(InstancePropertiesWithMeta {
             properties: RbxInstanceProperties {
@@ -255,6 +935,7 @@ mod test {
             properties: hashmap! {
                 "Baz".to_owned() => RbxValue::Int32 { value: 5 },
             },
+            ref_paths: Default::default(),
             children: Vec::new(),
         };
 
@@ -278,9 +959,265 @@ mod test {
         assert!(child_instance.children().is_empty());
     }
 
+    #[test]
+    fn refs_between_added_instances() {
+        crate::log_capture::init();
+
+        let snapshot_ids = [RbxId::new(), RbxId::new(), RbxId::new()];
+
+        // Each instance refers to the next, and the last refers to the first.
+        let snapshots: Vec<InstanceSnapshot> = (0..3)
+            .map(|index| InstanceSnapshot {
+                snapshot_id: Some(snapshot_ids[index]),
+                metadata: Default::default(),
+                name: Cow::Owned(format!("Value{}", index)),
+                class_name: Cow::Borrowed("ObjectValue"),
+                properties: hashmap! {
+                    "Value".to_owned() => RbxValue::Ref {
+                        value: Some(snapshot_ids[(index + 1) % 3]),
+                    },
+                },
+                ref_paths: Default::default(),
+                children: Vec::new(),
+            })
+            .collect();
+
+        for rotation in 0..3 {
+            let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: "Folder".to_owned(),
+                    class_name: "Folder".to_owned(),
+                    properties: HashMap::new(),
+                },
+                metadata: Default::default(),
+            });
+
+            let root_id = tree.get_root_id();
+
+            let mut added = snapshots.clone();
+            added.rotate_left(rotation);
+
+            let patch_set = PatchSet {
+                added_instances: added
+                    .into_iter()
+                    .map(|instance| PatchAdd {
+                        parent_id: root_id,
+                        instance,
+                    })
+                    .collect(),
+                ..Default::default()
+            };
+
+            apply_patch_set(&mut tree, patch_set);
+
+            let instance_ids: Vec<RbxId> = snapshot_ids
+                .iter()
+                .map(|snapshot_id| tree.get_id_for_snapshot_id(*snapshot_id).unwrap())
+                .collect();
+
+            for index in 0..3 {
+                let instance = tree.get_instance(instance_ids[index]).unwrap();
+
+                assert_eq!(instance.name(), format!("Value{}", index));
+                assert_eq!(
+                    instance.properties().get("Value"),
+                    Some(&RbxValue::Ref {
+                        value: Some(instance_ids[(index + 1) % 3]),
+                    })
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unresolved_refs_are_reported() {
+        crate::log_capture::init();
+
+        let missing_id = RbxId::new();
+
+        for &clear_unresolved_refs in &[false, true] {
+            let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: "Folder".to_owned(),
+                    class_name: "Folder".to_owned(),
+                    properties: HashMap::new(),
+                },
+                metadata: Default::default(),
+            });
+
+            let root_id = tree.get_root_id();
+
+            let snapshot = InstanceSnapshot {
+                snapshot_id: None,
+                metadata: Default::default(),
+                name: Cow::Borrowed("Value"),
+                class_name: Cow::Borrowed("ObjectValue"),
+                properties: hashmap! {
+                    "Value".to_owned() => RbxValue::Ref {
+                        value: Some(missing_id),
+                    },
+                    "Existing".to_owned() => RbxValue::Ref {
+                        value: Some(root_id),
+                    },
+                },
+                ref_paths: Default::default(),
+                children: Vec::new(),
+            };
+
+            let patch_set = PatchSet {
+                added_instances: vec![PatchAdd {
+                    parent_id: root_id,
+                    instance: snapshot,
+                }],
+                ..Default::default()
+            };
+
+            let options = PatchApplyOptions {
+                clear_unresolved_refs,
+                ..Default::default()
+            };
+            let applied = apply_patch_set_with_options(&mut tree, patch_set, options);
+
+            let child_id = applied.added[0];
+            assert_eq!(
+                applied.unresolved_refs,
+                vec![UnresolvedRef {
+                    id: child_id,
+                    property: "Value".to_owned(),
+                    target: RefTarget::Id(missing_id),
+                }]
+            );
+
+            let expected = if clear_unresolved_refs {
+                None
+            } else {
+                Some(missing_id)
+            };
+
+            let child = tree.get_instance(child_id).unwrap();
+            assert_eq!(
+                child.properties().get("Value"),
+                Some(&RbxValue::Ref { value: expected })
+            );
+            assert_eq!(
+                child.properties().get("Existing"),
+                Some(&RbxValue::Ref {
+                    value: Some(root_id)
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn refs_by_path() {
+        crate::log_capture::init();
+
+        let (mut tree, child_id) = folder_tree();
+        let root_id = tree.get_root_id();
+
+        // Paths can lead to instances already in the tree, to instances added
+        // by the same patch, or nowhere at all.
+        let pointer = InstanceSnapshot::new()
+            .name("Pointer")
+            .class_name("ObjectValue")
+            .ref_paths(hashmap! {
+                "Value".to_owned() => "game.Child".to_owned(),
+                "Sibling".to_owned() => "Folder.Added.Target".to_owned(),
+                "Dangling".to_owned() => "game.Child.Missing".to_owned(),
+            });
+
+        let added = InstanceSnapshot::new()
+            .name("Added")
+            .class_name("Folder")
+            .children(vec![
+                pointer,
+                InstanceSnapshot::new().name("Target").class_name("Folder"),
+            ]);
+
+        let patch_set = PatchSet {
+            added_instances: vec![PatchAdd {
+                parent_id: root_id,
+                instance: added,
+            }],
+            ..Default::default()
+        };
+
+        let applied = apply_patch_set(&mut tree, patch_set);
+
+        let pointer_id = tree.get_id_at_path("game.Added.Pointer").unwrap();
+        let target_id = tree.get_id_at_path("game.Added.Target").unwrap();
+
+        assert_eq!(
+            applied.unresolved_refs,
+            vec![UnresolvedRef {
+                id: pointer_id,
+                property: "Dangling".to_owned(),
+                target: RefTarget::Path("game.Child.Missing".to_owned()),
+            }]
+        );
+
+        let pointer = tree.get_instance(pointer_id).unwrap();
+        assert_eq!(
+            pointer.properties().get("Value"),
+            Some(&RbxValue::Ref {
+                value: Some(child_id)
+            })
+        );
+        assert_eq!(
+            pointer.properties().get("Sibling"),
+            Some(&RbxValue::Ref {
+                value: Some(target_id)
+            })
+        );
+        assert_eq!(pointer.properties().get("Dangling"), None);
+    }
+
+    #[test]
+    fn add_twice_is_idempotent() {
+        crate::log_capture::init();
+
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "Folder".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+
+        let root_id = tree.get_root_id();
+
+        let snapshot = InstanceSnapshot {
+            snapshot_id: Some(RbxId::new()),
+            metadata: Default::default(),
+            name: Cow::Borrowed("Foo"),
+            class_name: Cow::Borrowed("Bar"),
+            properties: HashMap::new(),
+            ref_paths: Default::default(),
+            children: Vec::new(),
+        };
+
+        let patch_set = PatchSet {
+            added_instances: vec![PatchAdd {
+                parent_id: root_id,
+                instance: snapshot,
+            }],
+            ..Default::default()
+        };
+
+        let first = apply_patch_set(&mut tree, patch_set.clone());
+        let second = apply_patch_set(&mut tree, patch_set);
+
+        assert_eq!(first.added.len(), 1);
+        assert!(second.added.is_empty());
+
+        let root_instance = tree.get_instance(root_id).unwrap();
+        assert_eq!(root_instance.children().len(), 1);
+    }
+
     #[test]
     fn update_existing() {
-        let _ = env_logger::try_init();
+        crate::log_capture::init();
 
         let mut tree = RojoTree::new(InstancePropertiesWithMeta {
             properties: RbxInstanceProperties {
@@ -311,6 +1248,8 @@ mod test {
                 // Baz has been added
                 "Baz".to_owned() => Some(RbxValue::Int32 { value: 10 }),
             },
+            changed_attributes: Default::default(),
+            changed_ref_paths: Default::default(),
             changed_metadata: None,
         };
 
@@ -332,4 +1271,549 @@ mod test {
         assert_eq!(root_instance.class_name(), "NewClassName");
         assert_eq!(root_instance.properties(), &expected_properties);
     }
+
+    #[test]
+    fn remove_parent_and_child_in_any_order() {
+        crate::log_capture::init();
+
+        let build_tree = || {
+            let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: "Root".to_owned(),
+                    class_name: "Folder".to_owned(),
+                    properties: HashMap::new(),
+                },
+                metadata: Default::default(),
+            });
+            let root_id = tree.get_root_id();
+
+            let folder = |name: &str| InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: name.to_owned(),
+                    class_name: "Folder".to_owned(),
+                    properties: HashMap::new(),
+                },
+                metadata: Default::default(),
+            };
+
+            let parent_id = tree.insert_instance(folder("Parent"), root_id);
+            let child_id = tree.insert_instance(folder("Child"), parent_id);
+            let sibling_id = tree.insert_instance(folder("Sibling"), root_id);
+
+            (tree, parent_id, child_id, sibling_id)
+        };
+
+        let (tree, parent_id, child_id, sibling_id) = build_tree();
+        assert_eq!(
+            top_level_removals(&tree, vec![child_id, parent_id, sibling_id, parent_id]),
+            vec![parent_id, sibling_id]
+        );
+
+        for &child_first in &[true, false] {
+            let (mut tree, parent_id, child_id, _) = build_tree();
+
+            let removed_instances = if child_first {
+                vec![child_id, parent_id]
+            } else {
+                vec![parent_id, child_id]
+            };
+
+            let patch_set = PatchSet {
+                removed_instances,
+                ..Default::default()
+            };
+
+            let applied_patch_set = apply_patch_set(&mut tree, patch_set);
+
+            assert_eq!(applied_patch_set.removed, vec![parent_id, child_id]);
+            assert!(applied_patch_set.warnings.is_empty());
+            assert!(tree.get_instance(parent_id).is_none());
+            assert!(tree.get_instance(child_id).is_none());
+
+            // Removing an instance that's already gone is reported.
+            let patch_set = PatchSet {
+                removed_instances: vec![parent_id],
+                ..Default::default()
+            };
+
+            let applied_patch_set = apply_patch_set(&mut tree, patch_set);
+
+            assert!(applied_patch_set.removed.is_empty());
+            assert_eq!(
+                applied_patch_set.warnings,
+                vec![PatchApplyWarning {
+                    id: parent_id,
+                    operation: PatchOperation::Remove,
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn remove_reports_descendants() {
+        crate::log_capture::init();
+
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "Root".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+
+        let folder = |name: &str| InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: name.to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        };
+
+        let parent_id = tree.insert_instance(folder("Parent"), root_id);
+        let first_id = tree.insert_instance(folder("First"), parent_id);
+        let second_id = tree.insert_instance(folder("Second"), parent_id);
+
+        let patch_set = PatchSet {
+            removed_instances: vec![parent_id],
+            ..Default::default()
+        };
+
+        let applied_patch_set = apply_patch_set(&mut tree, patch_set);
+
+        // Snapshots are only kept for patches that will be undone.
+        assert!(applied_patch_set.removed_snapshots.is_empty());
+
+        let removed = applied_patch_set.removed;
+        assert_eq!(removed.len(), 3);
+        assert_eq!(removed[0], parent_id);
+
+        let removed: HashSet<RbxId> = removed.into_iter().collect();
+        let expected: HashSet<RbxId> = vec![parent_id, first_id, second_id].into_iter().collect();
+        assert_eq!(removed, expected);
+        assert_eq!(tree.get_instance(root_id).unwrap().children(), &[]);
+    }
+
+    #[test]
+    fn property_type_changes() {
+        crate::log_capture::init();
+
+        let old_value = RbxValue::Int32 { value: 5 };
+        let new_value = RbxValue::String {
+            value: "five".to_owned(),
+        };
+
+        let update = |behavior: PropertyTypeChangeBehavior| {
+            let mut metadata = InstanceMetadata::new();
+            metadata.context.property_type_change_behavior = behavior;
+
+            let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: "Value".to_owned(),
+                    class_name: "Folder".to_owned(),
+                    properties: hashmap! {
+                        "Value".to_owned() => old_value.clone(),
+                    },
+                },
+                metadata,
+            });
+            let root_id = tree.get_root_id();
+
+            let patch_set = PatchSet {
+                updated_instances: vec![PatchUpdate {
+                    id: root_id,
+                    changed_name: None,
+                    changed_class_name: None,
+                    changed_properties: hashmap! {
+                        "Value".to_owned() => Some(new_value.clone()),
+                    },
+                    changed_attributes: Default::default(),
+                    changed_ref_paths: Default::default(),
+                    changed_metadata: None,
+                }],
+                ..Default::default()
+            };
+
+            apply_patch_set(&mut tree, patch_set);
+
+            let value = tree.get_instance(root_id).unwrap().properties()["Value"].clone();
+            value
+        };
+
+        let id = RbxId::new();
+        let message = describe_type_change(id, "Value", &old_value, &new_value).unwrap();
+        assert!(message.contains("from Int32 to String"), "{}", message);
+        assert_eq!(
+            describe_type_change(id, "Value", &old_value, &RbxValue::Int32 { value: 6 }),
+            None
+        );
+
+        assert_eq!(update(PropertyTypeChangeBehavior::Allow), new_value);
+        assert_eq!(update(PropertyTypeChangeBehavior::Warn), new_value);
+        assert_eq!(update(PropertyTypeChangeBehavior::Reject), old_value);
+    }
+
+    fn attributes_of(tree: &RojoTree, id: RbxId) -> Option<Attributes> {
+        match tree
+            .get_instance(id)?
+            .properties()
+            .get(ATTRIBUTES_PROPERTY)?
+        {
+            RbxValue::BinaryString { value } => decode_attributes(value),
+            _ => None,
+        }
+    }
+
+    fn attribute_update(id: RbxId, changed_attributes: AttributeChanges) -> PatchSet {
+        PatchSet {
+            removed_instances: Vec::new(),
+            added_instances: Vec::new(),
+            updated_instances: vec![PatchUpdate {
+                id,
+                changed_name: None,
+                changed_class_name: None,
+                changed_properties: HashMap::new(),
+                changed_attributes,
+                changed_ref_paths: Default::default(),
+                changed_metadata: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn attribute_changes_merge_into_existing() {
+        let mut existing = Attributes::new();
+        existing.insert("Title".to_owned(), string("Hello"));
+        existing.insert("Speed".to_owned(), double(16.0));
+
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "Folder".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: hashmap! {
+                    ATTRIBUTES_PROPERTY.to_owned() => RbxValue::BinaryString {
+                        value: encode_attributes(&existing),
+                    },
+                },
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+
+        apply_patch_set(
+            &mut tree,
+            attribute_update(
+                root_id,
+                hashmap! {
+                    "Speed".to_owned() => Some(double(20.0)),
+                    "Title".to_owned() => None,
+                },
+            ),
+        );
+
+        let mut expected = Attributes::new();
+        expected.insert("Speed".to_owned(), double(20.0));
+
+        assert_eq!(attributes_of(&tree, root_id), Some(expected));
+    }
+
+    #[test]
+    fn attribute_changes_merge_when_absent() {
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "Folder".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+
+        let applied = apply_patch_set(
+            &mut tree,
+            attribute_update(
+                root_id,
+                hashmap! {
+                    "Title".to_owned() => Some(string("Hello")),
+                },
+            ),
+        );
+
+        let mut expected = Attributes::new();
+        expected.insert("Title".to_owned(), string("Hello"));
+
+        assert_eq!(attributes_of(&tree, root_id), Some(expected));
+        assert!(applied.updated[0]
+            .changed_properties
+            .contains_key(ATTRIBUTES_PROPERTY));
+    }
+
+    fn folder_tree() -> (RojoTree, RbxId) {
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "Folder".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+
+        let child_id = tree.insert_instance(
+            InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: "Child".to_owned(),
+                    class_name: "Folder".to_owned(),
+                    properties: HashMap::new(),
+                },
+                metadata: Default::default(),
+            },
+            root_id,
+        );
+
+        (tree, child_id)
+    }
+
+    fn rename(id: RbxId) -> PatchUpdate {
+        PatchUpdate {
+            id,
+            changed_name: Some("Renamed".to_owned()),
+            changed_class_name: None,
+            changed_properties: HashMap::new(),
+            changed_attributes: Default::default(),
+            changed_ref_paths: Default::default(),
+            changed_metadata: None,
+        }
+    }
+
+    #[test]
+    fn metadata_only_update() {
+        let (mut tree, child_id) = folder_tree();
+
+        let metadata = InstanceMetadata::new().relevant_paths(vec!["/moved".into()]);
+        let patch = PatchUpdate {
+            id: child_id,
+            changed_name: None,
+            changed_class_name: None,
+            changed_properties: HashMap::new(),
+            changed_attributes: Default::default(),
+            changed_ref_paths: Default::default(),
+            changed_metadata: Some(metadata.clone()),
+        };
+
+        assert!(patch.is_metadata_only());
+        assert!(!rename(child_id).is_metadata_only());
+
+        let applied = apply_patch_set(
+            &mut tree,
+            PatchSet {
+                updated_instances: vec![patch],
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(applied.updated.len(), 1);
+        assert!(applied.updated[0].is_metadata_only());
+        assert!(applied.updated[0].changed_properties.is_empty());
+        assert_eq!(tree.get_metadata(child_id), Some(&metadata));
+    }
+
+    #[test]
+    fn strict_missing_remove() {
+        let (mut tree, child_id) = folder_tree();
+        let missing_id = RbxId::new();
+
+        let patch_set = PatchSet {
+            removed_instances: vec![child_id, missing_id],
+            added_instances: Vec::new(),
+            updated_instances: Vec::new(),
+        };
+
+        assert_eq!(
+            apply_patch_set_strict(&mut tree, patch_set).unwrap_err(),
+            PatchApplyError {
+                id: missing_id,
+                operation: PatchOperation::Remove,
+            }
+        );
+
+        // Nothing is applied if any part of the patch is missing.
+        assert!(tree.get_instance(child_id).is_some());
+    }
+
+    #[test]
+    fn strict_missing_update() {
+        let (mut tree, child_id) = folder_tree();
+        let missing_id = RbxId::new();
+
+        let patch_set = PatchSet {
+            removed_instances: Vec::new(),
+            added_instances: Vec::new(),
+            updated_instances: vec![rename(child_id), rename(missing_id)],
+        };
+
+        let error = apply_patch_set_strict(&mut tree, patch_set).unwrap_err();
+        assert_eq!(
+            error,
+            PatchApplyError {
+                id: missing_id,
+                operation: PatchOperation::Update,
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "patch tried to update instance {}, but it is not in the tree",
+                missing_id
+            )
+        );
+        assert_eq!(tree.get_instance(child_id).unwrap().name(), "Child");
+
+        // Updating an instance that the same patch removes would be skipped,
+        // so it's an error too.
+        let patch_set = PatchSet {
+            removed_instances: vec![child_id],
+            added_instances: Vec::new(),
+            updated_instances: vec![rename(child_id)],
+        };
+
+        assert_eq!(
+            apply_patch_set_strict(&mut tree, patch_set)
+                .unwrap_err()
+                .operation,
+            PatchOperation::Update
+        );
+
+        let applied = apply_patch_set_strict(
+            &mut tree,
+            PatchSet {
+                updated_instances: vec![rename(child_id)],
+                ..PatchSet::new()
+            },
+        )
+        .unwrap();
+        assert!(applied.warnings.is_empty());
+        assert_eq!(tree.get_instance(child_id).unwrap().name(), "Renamed");
+    }
+
+    #[test]
+    fn refs_follow_replaced_instance() {
+        crate::log_capture::init();
+
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "Root".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+
+        let old_id = tree.insert_instance(
+            InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: "Old".to_owned(),
+                    class_name: "ModuleScript".to_owned(),
+                    properties: HashMap::new(),
+                },
+                metadata: Default::default(),
+            },
+            root_id,
+        );
+
+        let pointer_id = tree.insert_instance(
+            InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: "Pointer".to_owned(),
+                    class_name: "ObjectValue".to_owned(),
+                    properties: hashmap! {
+                        "Value".to_owned() => RbxValue::Ref { value: Some(old_id) },
+                    },
+                },
+                metadata: Default::default(),
+            },
+            root_id,
+        );
+
+        let patch_set = PatchSet {
+            removed_instances: vec![old_id],
+            added_instances: vec![PatchAdd {
+                parent_id: root_id,
+                instance: InstanceSnapshot::new()
+                    .name("New")
+                    .class_name("ModuleScript")
+                    .properties(hashmap! {
+                        "Source".to_owned() => RbxValue::String { value: "return 2".to_owned() },
+                    }),
+            }],
+            updated_instances: Vec::new(),
+        };
+
+        let applied_patch_set = apply_patch_set(&mut tree, patch_set);
+        let new_id = applied_patch_set.added[0];
+        let new_ref = RbxValue::Ref {
+            value: Some(new_id),
+        };
+
+        assert_eq!(
+            tree.get_instance(pointer_id).unwrap().properties()["Value"],
+            new_ref
+        );
+
+        assert_eq!(applied_patch_set.updated.len(), 1);
+        assert_eq!(applied_patch_set.updated[0].id, pointer_id);
+        assert_eq!(
+            applied_patch_set.updated[0].changed_properties,
+            hashmap! {
+                "Value".to_owned() => Some(new_ref),
+            }
+        );
+    }
+
+    #[test]
+    fn deeply_nested_add() {
+        const DEPTH: usize = 10_000;
+
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "Root".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+
+        let mut snapshot = InstanceSnapshot::new().name("Leaf").class_name("Folder");
+        for _ in 0..DEPTH {
+            snapshot = InstanceSnapshot::new()
+                .name("Nested")
+                .class_name("Folder")
+                .children(vec![snapshot]);
+        }
+
+        let patch_set = PatchSet {
+            added_instances: vec![PatchAdd {
+                parent_id: root_id,
+                instance: snapshot,
+            }],
+            ..Default::default()
+        };
+
+        let applied_patch_set = apply_patch_set(&mut tree, patch_set);
+        let added = &applied_patch_set.added;
+        assert_eq!(added.len(), DEPTH + 1);
+
+        // Every instance is listed right after its parent.
+        let parent_of = |id| tree.get_instance(id).unwrap().parent();
+        assert_eq!(parent_of(added[0]), Some(root_id));
+        for pair in added.windows(2) {
+            assert_eq!(parent_of(pair[1]), Some(pair[0]));
+        }
+
+        assert_eq!(tree.get_instance(added[DEPTH]).unwrap().name(), "Leaf");
+    }
 }
@@ -5,16 +5,51 @@ use std::collections::{HashMap, HashSet};
 
 use rbx_dom_weak::{RbxId, RbxValue};
 
+use crate::trace;
+
 use super::{
+    attributes::diff_attributes,
     patch::{PatchAdd, PatchSet, PatchUpdate},
     InstanceSnapshot, InstanceWithMeta, RojoTree,
 };
 
 pub fn compute_patch_set(snapshot: &InstanceSnapshot, tree: &RojoTree, id: RbxId) -> PatchSet {
+    let _span = trace::span("patch", || "compute_patch_set".to_owned());
+
+    compute_patch_set_with_context(ComputePatchContext::default(), snapshot, tree, id)
+}
+
+/// Computes a patch set like `compute_patch_set`, but for resyncing a tree
+/// that may contain instances that were never on the filesystem.
+///
+/// Children of instances that ignore unknown instances aren't removed if they
+/// don't come from a file, so a full resync keeps instances that are managed
+/// outside of Rojo.
+pub fn compute_rescan_patch_set(
+    snapshot: &InstanceSnapshot,
+    tree: &RojoTree,
+    id: RbxId,
+) -> PatchSet {
+    let _span = trace::span("patch", || "compute_rescan_patch_set".to_owned());
+
+    let context = ComputePatchContext {
+        preserve_unknown_instances: true,
+        ..ComputePatchContext::default()
+    };
+
+    compute_patch_set_with_context(context, snapshot, tree, id)
+}
+
+fn compute_patch_set_with_context(
+    mut context: ComputePatchContext,
+    snapshot: &InstanceSnapshot,
+    tree: &RojoTree,
+    id: RbxId,
+) -> PatchSet {
     let mut patch_set = PatchSet::new();
-    let mut context = ComputePatchContext::default();
 
     compute_patch_set_internal(&mut context, snapshot, tree, id, &mut patch_set);
+    recheck_removed_ref_targets(&context, tree, &mut patch_set);
 
     // Rewrite Ref properties to refer to instance IDs instead of snapshot IDs
     // for all of the IDs that we know about so far.
@@ -27,6 +62,64 @@ pub fn compute_patch_set(snapshot: &InstanceSnapshot, tree: &RojoTree, id: RbxId
 #[derive(Default)]
 struct ComputePatchContext {
     snapshot_id_to_instance_id: HashMap<RbxId, RbxId>,
+
+    /// Whether unmatched children that aren't backed by a file should be kept
+    /// when their parent ignores unknown instances.
+    preserve_unknown_instances: bool,
+
+    /// Refs by path that already point at the instance their path leads to:
+    /// the instance, property, path, and target. They're left out of the patch
+    /// unless the patch removes the target.
+    unchanged_ref_paths: Vec<(RbxId, String, String, RbxId)>,
+}
+
+/// Adds the refs by path whose targets this patch removes back into the patch,
+/// so that they're resolved again once the patch is applied. Otherwise they'd
+/// be left pointing at an instance that isn't in the tree anymore.
+fn recheck_removed_ref_targets(
+    context: &ComputePatchContext,
+    tree: &RojoTree,
+    patch_set: &mut PatchSet,
+) {
+    if context.unchanged_ref_paths.is_empty() || patch_set.removed_instances.is_empty() {
+        return;
+    }
+
+    let mut removed = HashSet::new();
+    for &id in &patch_set.removed_instances {
+        removed.insert(id);
+        removed.extend(tree.descendants(id).map(|instance| instance.id()));
+    }
+
+    for (id, property, path, target) in &context.unchanged_ref_paths {
+        if !removed.contains(target) {
+            continue;
+        }
+
+        let index = match patch_set
+            .updated_instances
+            .iter()
+            .position(|update| update.id == *id)
+        {
+            Some(index) => index,
+            None => {
+                patch_set.updated_instances.push(PatchUpdate {
+                    id: *id,
+                    changed_name: None,
+                    changed_class_name: None,
+                    changed_properties: HashMap::new(),
+                    changed_attributes: HashMap::new(),
+                    changed_ref_paths: HashMap::new(),
+                    changed_metadata: None,
+                });
+                patch_set.updated_instances.len() - 1
+            }
+        };
+
+        patch_set.updated_instances[index]
+            .changed_ref_paths
+            .insert(property.clone(), path.clone());
+    }
 }
 
 fn rewrite_refs_in_updates(context: &ComputePatchContext, updates: &mut [PatchUpdate]) {
@@ -80,17 +173,39 @@ fn compute_patch_set_internal(
         .get_instance(id)
         .expect("Instance did not exist in tree");
 
-    compute_property_patches(snapshot, &instance, patch_set);
+    compute_property_patches(context, snapshot, &instance, tree, patch_set);
     compute_children_patches(context, snapshot, tree, id, patch_set);
 }
 
+/// Tells whether two different values of a property should be treated as the
+/// same because the project set a looser comparison for that property.
+fn equal_by_comparison(
+    snapshot: &InstanceSnapshot,
+    property: &str,
+    snapshot_value: &RbxValue,
+    instance_value: &RbxValue,
+) -> bool {
+    match (snapshot_value, instance_value) {
+        (RbxValue::String { value: a }, RbxValue::String { value: b }) => snapshot
+            .metadata
+            .context
+            .property_comparison(&snapshot.class_name, property)
+            .matches(a, b),
+        _ => false,
+    }
+}
+
 fn compute_property_patches(
+    context: &mut ComputePatchContext,
     snapshot: &InstanceSnapshot,
     instance: &InstanceWithMeta,
+    tree: &RojoTree,
     patch_set: &mut PatchSet,
 ) {
     let mut visited_properties = HashSet::new();
     let mut changed_properties = HashMap::new();
+    let mut changed_attributes = HashMap::new();
+    let mut changed_ref_paths = HashMap::new();
 
     let changed_name = if snapshot.name == instance.name() {
         None
@@ -110,13 +225,59 @@ fn compute_property_patches(
         Some(snapshot.metadata.clone())
     };
 
-    for (name, snapshot_value) in &snapshot.properties {
+    // Ignored properties are marked as visited up front so that they're
+    // neither updated nor removed.
+    for name in &snapshot.metadata.ignore_properties {
         visited_properties.insert(name.as_str());
+    }
+
+    // Refs by path are resolved once the patch is applied, since the path
+    // might lead into instances that this patch adds. Refs that already point
+    // where their path leads in the tree as it is now are left alone.
+    //
+    // They're visited before other properties so that a path wins over a
+    // property with the same name, like it does when instances are added.
+    for (name, path) in &snapshot.ref_paths {
+        if !visited_properties.insert(name.as_str()) {
+            continue;
+        }
+
+        match tree.get_id_at_path(path) {
+            Some(target)
+                if instance.properties().get(name)
+                    == Some(&RbxValue::Ref {
+                        value: Some(target),
+                    }) =>
+            {
+                context.unchanged_ref_paths.push((
+                    instance.id(),
+                    name.clone(),
+                    path.clone(),
+                    target,
+                ));
+            }
+            _ => {
+                changed_ref_paths.insert(name.clone(), path.clone());
+            }
+        }
+    }
+
+    for (name, snapshot_value) in &snapshot.properties {
+        if !visited_properties.insert(name.as_str()) {
+            continue;
+        }
 
         match instance.properties().get(name) {
             Some(instance_value) => {
-                if snapshot_value != instance_value {
-                    changed_properties.insert(name.clone(), Some(snapshot_value.clone()));
+                if snapshot_value != instance_value
+                    && !equal_by_comparison(snapshot, name, snapshot_value, instance_value)
+                {
+                    match diff_attributes(name, snapshot_value, instance_value) {
+                        Some(attribute_changes) => changed_attributes = attribute_changes,
+                        None => {
+                            changed_properties.insert(name.clone(), Some(snapshot_value.clone()));
+                        }
+                    }
                 }
             }
             None => {
@@ -134,6 +295,8 @@ fn compute_property_patches(
     }
 
     if changed_properties.is_empty()
+        && changed_attributes.is_empty()
+        && changed_ref_paths.is_empty()
         && changed_name.is_none()
         && changed_class_name.is_none()
         && changed_metadata.is_none()
@@ -146,6 +309,8 @@ fn compute_property_patches(
         changed_name,
         changed_class_name,
         changed_properties,
+        changed_attributes,
+        changed_ref_paths,
         changed_metadata,
     });
 }
@@ -165,37 +330,48 @@ fn compute_children_patches(
 
     let mut paired_instances = vec![false; instance_children.len()];
 
-    for snapshot_child in snapshot.children.iter() {
-        let matching_instance =
-            instance_children
-                .iter()
-                .enumerate()
-                .find(|(instance_index, instance_child_id)| {
-                    if paired_instances[*instance_index] {
-                        return false;
-                    }
-
-                    let instance_child = tree
-                        .get_instance(**instance_child_id)
-                        .expect("Instance did not exist in tree");
-
-                    if snapshot_child.name == instance_child.name()
+    let mut matching_instances: Vec<Option<RbxId>> = snapshot
+        .children
+        .iter()
+        .map(|snapshot_child| {
+            find_unpaired_child(
+                tree,
+                instance_children,
+                &mut paired_instances,
+                |instance_child| {
+                    snapshot_child.name == instance_child.name()
                         && snapshot_child.class_name == instance_child.class_name()
-                    {
-                        paired_instances[*instance_index] = true;
-                        return true;
-                    }
+                },
+            )
+        })
+        .collect();
 
-                    false
-                });
+    // A child that didn't match any instance by name might be one that was
+    // renamed, like when its file is renamed. Updating the existing instance
+    // instead of replacing it keeps its ID, so refs to it and anything
+    // tracking it in Studio keep working.
+    for (snapshot_child, matching_instance) in snapshot.children.iter().zip(&mut matching_instances)
+    {
+        if matching_instance.is_some() {
+            continue;
+        }
+
+        *matching_instance = find_unpaired_child(
+            tree,
+            instance_children,
+            &mut paired_instances,
+            |instance_child| is_renamed(snapshot_child, instance_child),
+        );
+    }
 
+    for (snapshot_child, matching_instance) in snapshot.children.iter().zip(matching_instances) {
         match matching_instance {
-            Some((_, instance_child_id)) => {
+            Some(instance_child_id) => {
                 compute_patch_set_internal(
                     context,
                     snapshot_child,
                     tree,
-                    *instance_child_id,
+                    instance_child_id,
                     patch_set,
                 );
             }
@@ -213,20 +389,80 @@ fn compute_children_patches(
             continue;
         }
 
+        if context.preserve_unknown_instances
+            && snapshot.metadata.ignore_unknown_instances
+            && !is_from_filesystem(tree, *instance_child_id)
+        {
+            continue;
+        }
+
         patch_set.removed_instances.push(*instance_child_id);
     }
 }
 
+/// Finds the first child that hasn't been paired with a snapshot yet and
+/// matches the predicate, then marks it as paired.
+fn find_unpaired_child(
+    tree: &RojoTree,
+    instance_children: &[RbxId],
+    paired_instances: &mut [bool],
+    predicate: impl Fn(&InstanceWithMeta) -> bool,
+) -> Option<RbxId> {
+    let index = instance_children
+        .iter()
+        .zip(paired_instances.iter())
+        .position(|(instance_child_id, &paired)| {
+            if paired {
+                return false;
+            }
+
+            let instance_child = tree
+                .get_instance(*instance_child_id)
+                .expect("Instance did not exist in tree");
+
+            predicate(&instance_child)
+        })?;
+
+    paired_instances[index] = true;
+    Some(instance_children[index])
+}
+
+/// Tells whether a snapshot is the same as an instance apart from its name,
+/// which means the instance was most likely renamed.
+fn is_renamed(snapshot: &InstanceSnapshot, instance: &InstanceWithMeta) -> bool {
+    snapshot.class_name == instance.class_name()
+        && &snapshot.properties == instance.properties()
+        && snapshot.children.len() == instance.children().len()
+}
+
+/// Tells whether the given instance was created from a file, as opposed to
+/// being added to the tree by something other than a snapshot.
+fn is_from_filesystem(tree: &RojoTree, id: RbxId) -> bool {
+    tree.get_metadata(id)
+        .map(|metadata| metadata.instigating_source.is_some())
+        .unwrap_or(false)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
-    use std::borrow::Cow;
+    use std::{borrow::Cow, path::Path};
 
     use maplit::hashmap;
     use rbx_dom_weak::RbxInstanceProperties;
 
-    use super::super::InstancePropertiesWithMeta;
+    use super::super::{
+        apply_patch_set,
+        attributes::{
+            decode_attributes, encode_attributes,
+            test::{bool, double, string},
+            Attributes, ATTRIBUTES_PROPERTY,
+        },
+        patch::{RefTarget, UnresolvedRef},
+        InstanceContext, InstanceMetadata, InstancePropertiesWithMeta,
+    };
+    use crate::project::PropertyComparison;
 
     /// This test makes sure that rewriting refs in instance update patches to
     /// instances that already exists works. We should be able to correlate the
@@ -259,6 +495,7 @@ mod test {
             metadata: Default::default(),
             name: Cow::Borrowed("foo"),
             class_name: Cow::Borrowed("foo"),
+            ref_paths: Default::default(),
             children: Vec::new(),
         };
 
@@ -274,6 +511,8 @@ mod test {
                         value: Some(root_id),
                     }),
                 },
+                changed_attributes: Default::default(),
+                changed_ref_paths: Default::default(),
                 changed_metadata: None,
             }],
             added_instances: Vec::new(),
@@ -303,6 +542,7 @@ mod test {
         let snapshot_id = RbxId::new();
         let snapshot = InstanceSnapshot {
             snapshot_id: Some(snapshot_id),
+            ref_paths: Default::default(),
             children: vec![InstanceSnapshot {
                 properties: hashmap! {
                     "Self".to_owned() => RbxValue::Ref {
@@ -314,6 +554,7 @@ mod test {
                 metadata: Default::default(),
                 name: Cow::Borrowed("child"),
                 class_name: Cow::Borrowed("child"),
+                ref_paths: Default::default(),
                 children: Vec::new(),
             }],
 
@@ -338,6 +579,7 @@ mod test {
                     },
                     name: Cow::Borrowed("child"),
                     class_name: Cow::Borrowed("child"),
+                    ref_paths: Default::default(),
                     children: Vec::new(),
                 },
             }],
@@ -347,4 +589,497 @@ mod test {
 
         assert_eq!(patch_set, expected_patch_set);
     }
+
+    /// Rescanning a tree shouldn't remove instances that only exist in Studio
+    /// if their parent ignores unknown instances, but should still remove
+    /// instances whose file is gone.
+    #[test]
+    fn rescan_preserves_unknown_children() {
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "container".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: InstanceMetadata::new().ignore_unknown_instances(true),
+        });
+
+        let root_id = tree.get_root_id();
+
+        let studio_only_id = tree.insert_instance(
+            InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: "StudioOnly".to_owned(),
+                    class_name: "Part".to_owned(),
+                    properties: HashMap::new(),
+                },
+                metadata: Default::default(),
+            },
+            root_id,
+        );
+
+        let deleted_file_id = tree.insert_instance(
+            InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: "Deleted".to_owned(),
+                    class_name: "ModuleScript".to_owned(),
+                    properties: HashMap::new(),
+                },
+                metadata: InstanceMetadata::new().instigating_source(Path::new("/Deleted.lua")),
+            },
+            root_id,
+        );
+
+        let snapshot = InstanceSnapshot::new()
+            .name("container")
+            .class_name("Folder")
+            .metadata(InstanceMetadata::new().ignore_unknown_instances(true));
+
+        let patch_set = compute_rescan_patch_set(&snapshot, &tree, root_id);
+        assert_eq!(patch_set.removed_instances, vec![deleted_file_id]);
+
+        let patch_set = compute_patch_set(&snapshot, &tree, root_id);
+        assert_eq!(
+            patch_set.removed_instances,
+            vec![studio_only_id, deleted_file_id]
+        );
+    }
+
+    /// Properties listed in an instance's ignore_properties shouldn't be
+    /// patched, while the same property on other instances should be.
+    #[test]
+    fn ignored_properties_are_not_patched() {
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "root".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+
+        let root_id = tree.get_root_id();
+        let ignored_metadata = InstanceMetadata::new().ignore_properties(vec!["Value".to_owned()]);
+
+        let old_value = hashmap! {
+            "Value".to_owned() => RbxValue::Float64 { value: 1.0 },
+        };
+        let new_value = hashmap! {
+            "Value".to_owned() => RbxValue::Float64 { value: 2.0 },
+        };
+
+        tree.insert_instance(
+            InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: "Ignored".to_owned(),
+                    class_name: "NumberValue".to_owned(),
+                    properties: old_value.clone(),
+                },
+                metadata: ignored_metadata.clone(),
+            },
+            root_id,
+        );
+
+        let synced_id = tree.insert_instance(
+            InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: "Synced".to_owned(),
+                    class_name: "NumberValue".to_owned(),
+                    properties: old_value,
+                },
+                metadata: Default::default(),
+            },
+            root_id,
+        );
+
+        let snapshot = InstanceSnapshot::new()
+            .name("root")
+            .class_name("Folder")
+            .children(vec![
+                InstanceSnapshot::new()
+                    .name("Ignored")
+                    .class_name("NumberValue")
+                    .properties(new_value.clone())
+                    .metadata(ignored_metadata),
+                InstanceSnapshot::new()
+                    .name("Synced")
+                    .class_name("NumberValue")
+                    .properties(new_value),
+            ]);
+
+        let patch_set = compute_patch_set(&snapshot, &tree, root_id);
+
+        let expected_patch_set = PatchSet {
+            updated_instances: vec![PatchUpdate {
+                id: synced_id,
+                changed_name: None,
+                changed_class_name: None,
+                changed_properties: hashmap! {
+                    "Value".to_owned() => Some(RbxValue::Float64 { value: 2.0 }),
+                },
+                changed_attributes: Default::default(),
+                changed_ref_paths: Default::default(),
+                changed_metadata: None,
+            }],
+            added_instances: Vec::new(),
+            removed_instances: Vec::new(),
+        };
+
+        assert_eq!(patch_set, expected_patch_set);
+    }
+
+    #[test]
+    fn only_changed_attributes_are_patched() {
+        fn attributes_property(attributes: &Attributes) -> HashMap<String, RbxValue> {
+            hashmap! {
+                ATTRIBUTES_PROPERTY.to_owned() => RbxValue::BinaryString {
+                    value: encode_attributes(attributes),
+                },
+            }
+        }
+
+        let mut old_attributes = Attributes::new();
+        old_attributes.insert("Title".to_owned(), string("Hello"));
+        old_attributes.insert("Enabled".to_owned(), bool(true));
+        old_attributes.insert("Speed".to_owned(), double(16.0));
+
+        let mut new_attributes = old_attributes.clone();
+        new_attributes.insert("Speed".to_owned(), double(20.0));
+
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "root".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: attributes_property(&old_attributes),
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+
+        let snapshot = InstanceSnapshot::new()
+            .name("root")
+            .class_name("Folder")
+            .properties(attributes_property(&new_attributes));
+
+        let patch_set = compute_patch_set(&snapshot, &tree, root_id);
+
+        let expected_patch_set = PatchSet {
+            updated_instances: vec![PatchUpdate {
+                id: root_id,
+                changed_name: None,
+                changed_class_name: None,
+                changed_properties: HashMap::new(),
+                changed_attributes: hashmap! {
+                    "Speed".to_owned() => Some(double(20.0)),
+                },
+                changed_ref_paths: Default::default(),
+                changed_metadata: None,
+            }],
+            added_instances: Vec::new(),
+            removed_instances: Vec::new(),
+        };
+
+        assert_eq!(patch_set, expected_patch_set);
+
+        let applied_patch_set = apply_patch_set(&mut tree, patch_set);
+        assert_eq!(applied_patch_set.updated.len(), 1);
+
+        let root = tree.get_instance(root_id).unwrap();
+        match root.properties().get(ATTRIBUTES_PROPERTY) {
+            Some(RbxValue::BinaryString { value }) => {
+                assert_eq!(decode_attributes(value), Some(new_attributes));
+            }
+            other => panic!("unexpected attributes value: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn configured_comparison_ignores_case() {
+        let mut context = InstanceContext::default();
+        context.add_property_comparisons(vec![(
+            "StringValue.Value".to_owned(),
+            PropertyComparison::CaseInsensitive,
+        )]);
+        let metadata = InstanceMetadata::new().context(&context);
+
+        let string = |value: &str| RbxValue::String {
+            value: value.to_owned(),
+        };
+
+        let tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "root".to_owned(),
+                class_name: "StringValue".to_owned(),
+                properties: hashmap! {
+                    "Value".to_owned() => string("hello"),
+                    "Tags".to_owned() => string("hello"),
+                },
+            },
+            metadata: metadata.clone(),
+        });
+        let root_id = tree.get_root_id();
+
+        let snapshot = InstanceSnapshot::new()
+            .name("root")
+            .class_name("StringValue")
+            .metadata(metadata)
+            .properties(hashmap! {
+                "Value".to_owned() => string("HELLO"),
+                "Tags".to_owned() => string("HELLO"),
+            });
+
+        let patch_set = compute_patch_set(&snapshot, &tree, root_id);
+
+        assert_eq!(patch_set.updated_instances.len(), 1);
+        assert_eq!(
+            patch_set.updated_instances[0].changed_properties,
+            hashmap! {
+                "Tags".to_owned() => Some(string("HELLO")),
+            }
+        );
+    }
+
+    /// Renaming a file shouldn't replace its instance, since that would give it
+    /// a new ID and break refs to it.
+    #[test]
+    fn renamed_instance_is_updated() {
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "root".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+
+        let root_id = tree.get_root_id();
+        let source = hashmap! {
+            "Source".to_owned() => RbxValue::String { value: "return 1".to_owned() },
+        };
+
+        let old_id = tree.insert_instance(
+            InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: "Old".to_owned(),
+                    class_name: "ModuleScript".to_owned(),
+                    properties: source.clone(),
+                },
+                metadata: Default::default(),
+            },
+            root_id,
+        );
+
+        let snapshot = InstanceSnapshot::new()
+            .name("root")
+            .class_name("Folder")
+            .children(vec![
+                InstanceSnapshot::new()
+                    .name("New")
+                    .class_name("ModuleScript")
+                    .properties(source),
+                InstanceSnapshot::new()
+                    .name("Other")
+                    .class_name("ModuleScript")
+                    .properties(hashmap! {
+                        "Source".to_owned() => RbxValue::String { value: "return 2".to_owned() },
+                    }),
+            ]);
+
+        let patch_set = compute_patch_set(&snapshot, &tree, root_id);
+
+        assert!(patch_set.removed_instances.is_empty());
+        assert_eq!(patch_set.added_instances.len(), 1);
+        assert_eq!(patch_set.added_instances[0].instance.name, "Other");
+        assert_eq!(
+            patch_set.updated_instances,
+            vec![PatchUpdate {
+                id: old_id,
+                changed_name: Some("New".to_owned()),
+                changed_class_name: None,
+                changed_properties: HashMap::new(),
+                changed_attributes: Default::default(),
+                changed_ref_paths: Default::default(),
+                changed_metadata: None,
+            }]
+        );
+    }
+
+    fn insert_child(tree: &mut RojoTree, parent_id: RbxId, name: &str, class_name: &str) -> RbxId {
+        tree.insert_instance(
+            InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: name.to_owned(),
+                    class_name: class_name.to_owned(),
+                    properties: HashMap::new(),
+                },
+                metadata: Default::default(),
+            },
+            parent_id,
+        )
+    }
+
+    fn pointer_snapshot() -> InstanceSnapshot {
+        InstanceSnapshot::new()
+            .name("Pointer")
+            .class_name("ObjectValue")
+            .ref_paths(hashmap! {
+                "Value".to_owned() => "game.Target".to_owned(),
+            })
+    }
+
+    /// Refs by path on existing instances should be able to point at instances
+    /// that the same patch adds.
+    #[test]
+    fn ref_path_into_added_instance() {
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "root".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+
+        let root_id = tree.get_root_id();
+        let pointer_id = insert_child(&mut tree, root_id, "Pointer", "ObjectValue");
+
+        let snapshot = InstanceSnapshot::new()
+            .name("root")
+            .class_name("Folder")
+            .children(vec![
+                pointer_snapshot(),
+                InstanceSnapshot::new().name("Target").class_name("Folder"),
+            ]);
+
+        let patch_set = compute_patch_set(&snapshot, &tree, root_id);
+        let applied = apply_patch_set(&mut tree, patch_set);
+
+        let target_id = tree.get_id_at_path("game.Target").unwrap();
+
+        assert!(applied.unresolved_refs.is_empty());
+        assert_eq!(
+            tree.get_instance(pointer_id)
+                .unwrap()
+                .properties()
+                .get("Value"),
+            Some(&RbxValue::Ref {
+                value: Some(target_id)
+            })
+        );
+
+        let update = applied
+            .updated
+            .iter()
+            .find(|update| update.id == pointer_id)
+            .unwrap();
+        assert_eq!(
+            update.changed_properties.get("Value"),
+            Some(&Some(RbxValue::Ref {
+                value: Some(target_id)
+            }))
+        );
+    }
+
+    /// A ref path should win over a property with the same name, both when the
+    /// instance is added and when it's compared to the tree afterwards.
+    #[test]
+    fn ref_path_wins_over_property() {
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "root".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+
+        let snapshot = InstanceSnapshot::new()
+            .name("root")
+            .class_name("Folder")
+            .children(vec![
+                pointer_snapshot().properties(hashmap! {
+                    "Value".to_owned() => RbxValue::Ref { value: None },
+                }),
+                InstanceSnapshot::new().name("Target").class_name("Folder"),
+            ]);
+
+        let patch_set = compute_patch_set(&snapshot, &tree, root_id);
+        apply_patch_set(&mut tree, patch_set);
+
+        let pointer_id = tree.get_id_at_path("game.Pointer").unwrap();
+        let target_id = tree.get_id_at_path("game.Target").unwrap();
+        assert_eq!(
+            tree.get_instance(pointer_id)
+                .unwrap()
+                .properties()
+                .get("Value"),
+            Some(&RbxValue::Ref {
+                value: Some(target_id)
+            })
+        );
+
+        let patch_set = compute_patch_set(&snapshot, &tree, root_id);
+        assert!(patch_set.added_instances.is_empty());
+        assert!(patch_set.removed_instances.is_empty());
+        assert!(patch_set.updated_instances.is_empty());
+    }
+
+    /// A ref by path that already points at its target shouldn't be patched,
+    /// unless the patch removes the target.
+    #[test]
+    fn ref_path_to_removed_instance() {
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "root".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+
+        let root_id = tree.get_root_id();
+        let pointer_id = insert_child(&mut tree, root_id, "Pointer", "ObjectValue");
+        let target_id = insert_child(&mut tree, root_id, "Target", "Folder");
+
+        tree.get_instance_mut(pointer_id)
+            .unwrap()
+            .properties_mut()
+            .insert(
+                "Value".to_owned(),
+                RbxValue::Ref {
+                    value: Some(target_id),
+                },
+            );
+
+        let unchanged = InstanceSnapshot::new()
+            .name("root")
+            .class_name("Folder")
+            .children(vec![
+                pointer_snapshot(),
+                InstanceSnapshot::new().name("Target").class_name("Folder"),
+            ]);
+
+        assert_eq!(
+            compute_patch_set(&unchanged, &tree, root_id),
+            PatchSet::new()
+        );
+
+        let snapshot = InstanceSnapshot::new()
+            .name("root")
+            .class_name("Folder")
+            .children(vec![pointer_snapshot()]);
+
+        let patch_set = compute_patch_set(&snapshot, &tree, root_id);
+        let applied = apply_patch_set(&mut tree, patch_set);
+
+        assert_eq!(
+            applied.unresolved_refs,
+            vec![UnresolvedRef {
+                id: pointer_id,
+                property: "Value".to_owned(),
+                target: RefTarget::Path("game.Target".to_owned()),
+            }]
+        );
+    }
 }
@@ -0,0 +1,194 @@
+//! Defines a way to narrow down a patch set so that it only affects instances
+//! of a given set of classes.
+
+use std::collections::HashSet;
+
+use rbx_dom_weak::RbxId;
+
+use super::{InstanceSnapshot, PatchSet, RojoTree};
+
+/// Removes all changes from the given `PatchSet` that do not affect instances
+/// with a class name in `class_names`.
+///
+/// Additions and removals are kept if the instance being added or removed, or
+/// any of its descendants, has a matching class. Otherwise, it wouldn't be
+/// possible to sync a script inside of a newly-created folder.
+///
+/// Updates are kept if the instance has a matching class either before or after
+/// the update is applied.
+pub fn filter_patch_set_by_class(
+    patch_set: &mut PatchSet,
+    tree: &RojoTree,
+    class_names: &HashSet<String>,
+) {
+    patch_set
+        .removed_instances
+        .retain(|&id| tree_contains_class(tree, id, class_names));
+
+    patch_set
+        .added_instances
+        .retain(|add| snapshot_contains_class(&add.instance, class_names));
+
+    patch_set.updated_instances.retain(|update| {
+        if let Some(class_name) = &update.changed_class_name {
+            if class_names.contains(class_name) {
+                return true;
+            }
+        }
+
+        match tree.get_instance(update.id) {
+            Some(instance) => class_names.contains(instance.class_name()),
+            None => false,
+        }
+    });
+}
+
+fn tree_contains_class(tree: &RojoTree, id: RbxId, class_names: &HashSet<String>) -> bool {
+    let instance = match tree.get_instance(id) {
+        Some(instance) => instance,
+        None => return false,
+    };
+
+    if class_names.contains(instance.class_name()) {
+        return true;
+    }
+
+    tree.descendants(id)
+        .any(|descendant| class_names.contains(descendant.class_name()))
+}
+
+fn snapshot_contains_class(snapshot: &InstanceSnapshot, class_names: &HashSet<String>) -> bool {
+    class_names.contains(snapshot.class_name.as_ref())
+        || snapshot
+            .children
+            .iter()
+            .any(|child| snapshot_contains_class(child, class_names))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use maplit::hashmap;
+    use rbx_dom_weak::{RbxInstanceProperties, RbxValue};
+
+    use super::super::{InstancePropertiesWithMeta, PatchAdd, PatchUpdate};
+
+    fn script_classes() -> HashSet<String> {
+        ["Script", "LocalScript", "ModuleScript"]
+            .iter()
+            .map(|name| (*name).to_owned())
+            .collect()
+    }
+
+    fn insert(tree: &mut RojoTree, parent_id: RbxId, name: &str, class_name: &str) -> RbxId {
+        tree.insert_instance(
+            InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: name.to_owned(),
+                    class_name: class_name.to_owned(),
+                    properties: HashMap::new(),
+                },
+                metadata: Default::default(),
+            },
+            parent_id,
+        )
+    }
+
+    #[test]
+    fn part_change_ignored_script_change_kept() {
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "Root".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+
+        let root_id = tree.get_root_id();
+        let part_id = insert(&mut tree, root_id, "Part", "Part");
+        let script_id = insert(&mut tree, root_id, "Script", "Script");
+
+        let mut patch_set = PatchSet {
+            updated_instances: vec![
+                PatchUpdate {
+                    id: part_id,
+                    changed_name: None,
+                    changed_class_name: None,
+                    changed_properties: hashmap! {
+                        "Transparency".to_owned() => Some(RbxValue::Float32 { value: 0.5 }),
+                    },
+                    changed_attributes: Default::default(),
+                    changed_ref_paths: Default::default(),
+                    changed_metadata: None,
+                },
+                PatchUpdate {
+                    id: script_id,
+                    changed_name: None,
+                    changed_class_name: None,
+                    changed_properties: hashmap! {
+                        "Source".to_owned() => Some(RbxValue::String {
+                            value: "print('hi')".to_owned(),
+                        }),
+                    },
+                    changed_attributes: Default::default(),
+                    changed_ref_paths: Default::default(),
+                    changed_metadata: None,
+                },
+            ],
+            ..Default::default()
+        };
+
+        filter_patch_set_by_class(&mut patch_set, &tree, &script_classes());
+
+        assert_eq!(patch_set.updated_instances.len(), 1);
+        assert_eq!(patch_set.updated_instances[0].id, script_id);
+    }
+
+    #[test]
+    fn keep_additions_and_removals_containing_scripts() {
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "Root".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+
+        let root_id = tree.get_root_id();
+        let model_id = insert(&mut tree, root_id, "Model", "Model");
+        insert(&mut tree, model_id, "Part", "Part");
+        let folder_id = insert(&mut tree, root_id, "Folder", "Folder");
+        insert(&mut tree, folder_id, "Module", "ModuleScript");
+
+        let mut patch_set = PatchSet {
+            removed_instances: vec![model_id, folder_id],
+            added_instances: vec![
+                PatchAdd {
+                    parent_id: root_id,
+                    instance: InstanceSnapshot::new().name("Part").class_name("Part"),
+                },
+                PatchAdd {
+                    parent_id: root_id,
+                    instance: InstanceSnapshot::new()
+                        .name("Folder")
+                        .class_name("Folder")
+                        .children(vec![InstanceSnapshot::new()
+                            .name("Client")
+                            .class_name("LocalScript")]),
+                },
+            ],
+            ..Default::default()
+        };
+
+        filter_patch_set_by_class(&mut patch_set, &tree, &script_classes());
+
+        assert_eq!(patch_set.removed_instances, vec![folder_id]);
+        assert_eq!(patch_set.added_instances.len(), 1);
+        assert_eq!(patch_set.added_instances[0].instance.name, "Folder");
+    }
+}
@@ -0,0 +1,173 @@
+//! Defines a way to undo a patch set after it's been applied.
+
+use super::{AppliedPatchSet, PatchSet, PatchUpdate, RojoTree};
+
+/// Computes a patch set that undoes the given applied patch set, if it's
+/// applied to the tree that the applied patch set was applied to.
+///
+/// The patch set must have been applied with
+/// `PatchApplyOptions::record_removed_snapshots` set, or instances that it
+/// removed won't be added back.
+///
+/// Instances that the patch removed are added back from snapshots of them, so
+/// they get new IDs and are added after their siblings. Refs between the
+/// instances that are added back will point to each other again, but refs to
+/// them from the rest of the tree won't.
+pub fn invert_applied_patch_set(applied_patch_set: &AppliedPatchSet, tree: &RojoTree) -> PatchSet {
+    let removed_instances = applied_patch_set
+        .added
+        .iter()
+        .copied()
+        .filter(|&id| tree.get_instance(id).is_some())
+        .collect();
+
+    // Instances are added back in the opposite order that they were removed,
+    // so that siblings end up in the order they were in before.
+    let added_instances = applied_patch_set
+        .removed_snapshots
+        .iter()
+        .rev()
+        .cloned()
+        .collect();
+
+    // Updates are undone in the opposite order too, since more than one update
+    // could have changed the same property.
+    let updated_instances = applied_patch_set
+        .updated
+        .iter()
+        .rev()
+        .map(|update| PatchUpdate {
+            id: update.id,
+            changed_name: update.previous.name.clone(),
+            changed_class_name: update.previous.class_name.clone(),
+            changed_properties: update.previous.properties.clone(),
+            changed_attributes: Default::default(),
+            changed_ref_paths: Default::default(),
+            changed_metadata: update.previous.metadata.clone(),
+        })
+        .collect();
+
+    PatchSet {
+        removed_instances,
+        added_instances,
+        updated_instances,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{collections::HashMap, path::Path};
+
+    use maplit::hashmap;
+    use rbx_dom_weak::{RbxId, RbxInstanceProperties, RbxValue};
+
+    use super::super::{
+        apply_patch_set,
+        patch_apply::{apply_patch_set_with_options, PatchApplyOptions},
+        InstanceMetadata, InstancePropertiesWithMeta, InstanceSnapshot, PatchAdd,
+    };
+
+    fn string_value(value: &str) -> HashMap<String, RbxValue> {
+        hashmap! {
+            "Value".to_owned() => RbxValue::String {
+                value: value.to_owned(),
+            },
+        }
+    }
+
+    fn insert(
+        tree: &mut RojoTree,
+        parent_id: RbxId,
+        name: &str,
+        class_name: &str,
+        properties: HashMap<String, RbxValue>,
+    ) -> RbxId {
+        tree.insert_instance(
+            InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: name.to_owned(),
+                    class_name: class_name.to_owned(),
+                    properties,
+                },
+                metadata: InstanceMetadata::new().instigating_source(Path::new(name)),
+            },
+            parent_id,
+        )
+    }
+
+    /// Snapshots the whole tree without instance IDs, which change when
+    /// removed instances are added back.
+    fn tree_contents(tree: &RojoTree) -> InstanceSnapshot {
+        fn strip_ids(mut snapshot: InstanceSnapshot) -> InstanceSnapshot {
+            snapshot.snapshot_id = None;
+            snapshot.children = snapshot.children.into_iter().map(strip_ids).collect();
+            snapshot
+        }
+
+        strip_ids(tree.to_snapshot(tree.get_root_id()).unwrap())
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "Root".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+
+        let kept_id = insert(&mut tree, root_id, "Kept", "StringValue", string_value("a"));
+        let removed_id = insert(&mut tree, root_id, "Removed", "Folder", HashMap::new());
+        insert(
+            &mut tree,
+            removed_id,
+            "Child",
+            "StringValue",
+            string_value("b"),
+        );
+
+        let original = tree_contents(&tree);
+
+        let patch_set = PatchSet {
+            removed_instances: vec![removed_id],
+            added_instances: vec![PatchAdd {
+                parent_id: root_id,
+                instance: InstanceSnapshot::new()
+                    .name("Added")
+                    .class_name("StringValue")
+                    .properties(string_value("c")),
+            }],
+            updated_instances: vec![PatchUpdate {
+                id: kept_id,
+                changed_name: Some("Renamed".to_owned()),
+                changed_class_name: None,
+                changed_properties: hashmap! {
+                    "Value".to_owned() => Some(RbxValue::String {
+                        value: "changed".to_owned(),
+                    }),
+                    "Extra".to_owned() => Some(RbxValue::Bool { value: true }),
+                },
+                changed_attributes: Default::default(),
+                changed_ref_paths: Default::default(),
+                changed_metadata: Some(InstanceMetadata::new()),
+            }],
+        };
+
+        let options = PatchApplyOptions {
+            record_removed_snapshots: true,
+            ..Default::default()
+        };
+        let applied_patch_set = apply_patch_set_with_options(&mut tree, patch_set, options);
+        assert_ne!(tree_contents(&tree), original);
+
+        let inverse = invert_applied_patch_set(&applied_patch_set, &tree);
+        apply_patch_set(&mut tree, inverse);
+
+        assert_eq!(tree_contents(&tree), original);
+    }
+}
@@ -0,0 +1,304 @@
+//! Defines a way to find out what applying a patch would do to a tree without
+//! changing the tree.
+
+use std::collections::HashMap;
+
+use rbx_dom_weak::{RbxId, RbxValue};
+
+use crate::trace;
+
+use super::{
+    patch::{AppliedPatchSet, PatchSet, RefTarget},
+    patch_apply::apply_patch_set,
+    RojoTree,
+};
+
+/// Describes the changes that `apply_patch_set` would make if it were given
+/// the same tree and patch set, without changing the tree.
+///
+/// The patch is applied to a copy of the tree, so the preview always matches
+/// what applying it would report. Instances that haven't been added yet don't
+/// have IDs, so each added instance is listed by its snapshot ID instead, or
+/// by a new placeholder ID if it doesn't have one. These won't match the IDs
+/// the instances are given when the patch is actually applied.
+pub fn preview_patch_set(tree: &RojoTree, patch_set: &PatchSet) -> AppliedPatchSet {
+    let _span = trace::span("patch", || "preview_patch_set".to_owned());
+
+    let mut preview_tree = tree.clone();
+    let mut applied_patch_set = apply_patch_set(&mut preview_tree, patch_set.clone());
+
+    let placeholders: HashMap<RbxId, RbxId> = applied_patch_set
+        .added
+        .iter()
+        .map(|&id| {
+            let placeholder = preview_tree.get_snapshot_id(id).unwrap_or_else(RbxId::new);
+            (id, placeholder)
+        })
+        .collect();
+
+    replace_ids(&mut applied_patch_set, &placeholders);
+
+    applied_patch_set
+}
+
+/// Replaces every ID in the applied patch set that's a key of `ids`, including
+/// the targets of Ref properties, with its value.
+fn replace_ids(applied_patch_set: &mut AppliedPatchSet, ids: &HashMap<RbxId, RbxId>) {
+    let replace = |id: &mut RbxId| {
+        if let Some(&replacement) = ids.get(id) {
+            *id = replacement;
+        }
+    };
+
+    let replace_in_value = |value: &mut Option<RbxValue>| {
+        if let Some(RbxValue::Ref { value: Some(id) }) = value {
+            replace(id);
+        }
+    };
+
+    applied_patch_set.added.iter_mut().for_each(replace);
+
+    for update in &mut applied_patch_set.updated {
+        replace(&mut update.id);
+        update
+            .changed_properties
+            .values_mut()
+            .for_each(replace_in_value);
+        update
+            .previous
+            .properties
+            .values_mut()
+            .for_each(replace_in_value);
+    }
+
+    for unresolved_ref in &mut applied_patch_set.unresolved_refs {
+        replace(&mut unresolved_ref.id);
+
+        if let RefTarget::Id(id) = &mut unresolved_ref.target {
+            replace(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::borrow::Cow;
+
+    use maplit::hashmap;
+    use rbx_dom_weak::RbxInstanceProperties;
+
+    use super::super::{InstancePropertiesWithMeta, InstanceSnapshot, PatchAdd, PatchUpdate};
+
+    fn insert(tree: &mut RojoTree, parent_id: RbxId, name: &str) -> RbxId {
+        tree.insert_instance(
+            InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: name.to_owned(),
+                    class_name: "IntValue".to_owned(),
+                    properties: hashmap! {
+                        "Value".to_owned() => RbxValue::Int32 { value: 1 },
+                    },
+                },
+                metadata: Default::default(),
+            },
+            parent_id,
+        )
+    }
+
+    #[test]
+    fn preview_matches_apply() {
+        crate::log_capture::init();
+
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "Folder".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+
+        let kept_id = insert(&mut tree, root_id, "Kept");
+        let parent_id = insert(&mut tree, root_id, "Parent");
+        let child_id = insert(&mut tree, parent_id, "Child");
+        let missing_id = RbxId::new();
+
+        let renamed = PatchUpdate {
+            id: kept_id,
+            changed_name: Some("Renamed".to_owned()),
+            changed_class_name: None,
+            changed_properties: hashmap! {
+                "Value".to_owned() => Some(RbxValue::Int32 { value: 2 }),
+            },
+            changed_attributes: HashMap::new(),
+            changed_ref_paths: hashmap! {
+                "Target".to_owned() => "Folder.New.NewChild".to_owned(),
+                "Missing".to_owned() => "Folder.Parent".to_owned(),
+            },
+            changed_metadata: None,
+        };
+
+        let removed_child = PatchUpdate {
+            id: child_id,
+            changed_name: Some("Gone".to_owned()),
+            changed_class_name: None,
+            changed_properties: HashMap::new(),
+            changed_attributes: HashMap::new(),
+            changed_ref_paths: Default::default(),
+            changed_metadata: None,
+        };
+
+        let snapshot = InstanceSnapshot {
+            snapshot_id: Some(RbxId::new()),
+            metadata: Default::default(),
+            name: Cow::Borrowed("New"),
+            class_name: Cow::Borrowed("Folder"),
+            properties: HashMap::new(),
+            ref_paths: Default::default(),
+            children: vec![InstanceSnapshot {
+                snapshot_id: None,
+                metadata: Default::default(),
+                name: Cow::Borrowed("NewChild"),
+                class_name: Cow::Borrowed("Folder"),
+                properties: HashMap::new(),
+                ref_paths: Default::default(),
+                children: Vec::new(),
+            }],
+        };
+
+        let patch_set = PatchSet {
+            removed_instances: vec![child_id, parent_id, missing_id],
+            added_instances: vec![PatchAdd {
+                parent_id: root_id,
+                instance: snapshot,
+            }],
+            updated_instances: vec![renamed, removed_child],
+        };
+
+        let preview = preview_patch_set(&tree, &patch_set);
+
+        // Previewing doesn't touch the tree.
+        assert_eq!(tree.get_instance(kept_id).unwrap().name(), "Kept");
+        assert!(tree.get_instance(child_id).is_some());
+
+        let mut applied_tree = tree.clone();
+        let mut applied = apply_patch_set(&mut applied_tree, patch_set);
+
+        assert_eq!(preview.removed, vec![parent_id, child_id]);
+        assert_eq!(preview.warnings.len(), 2);
+        assert_eq!(preview.unresolved_refs.len(), 1);
+        assert_eq!(preview.added.len(), 2);
+
+        // Other than the IDs of added instances, the preview should be exactly
+        // what applying the patch reported.
+        let added_ids = applied
+            .added
+            .iter()
+            .copied()
+            .zip(preview.added.iter().copied())
+            .collect();
+        replace_ids(&mut applied, &added_ids);
+
+        assert_eq!(
+            serde_json::to_value(&preview).unwrap(),
+            serde_json::to_value(&applied).unwrap()
+        );
+    }
+
+    #[test]
+    fn preview_skips_already_applied_additions() {
+        crate::log_capture::init();
+
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "Folder".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+
+        let patch_set = PatchSet {
+            added_instances: vec![PatchAdd {
+                parent_id: root_id,
+                instance: InstanceSnapshot {
+                    snapshot_id: Some(RbxId::new()),
+                    metadata: Default::default(),
+                    name: Cow::Borrowed("Foo"),
+                    class_name: Cow::Borrowed("Folder"),
+                    properties: HashMap::new(),
+                    ref_paths: Default::default(),
+                    children: Vec::new(),
+                },
+            }],
+            ..Default::default()
+        };
+
+        assert_eq!(preview_patch_set(&tree, &patch_set).added.len(), 1);
+
+        apply_patch_set(&mut tree, patch_set.clone());
+
+        assert!(preview_patch_set(&tree, &patch_set).added.is_empty());
+    }
+
+    #[test]
+    fn preview_redirects_refs_to_replacements() {
+        crate::log_capture::init();
+
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "Root".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+
+        let old_id = insert(&mut tree, root_id, "Old");
+        let pointer_id = tree.insert_instance(
+            InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: "Pointer".to_owned(),
+                    class_name: "ObjectValue".to_owned(),
+                    properties: hashmap! {
+                        "Value".to_owned() => RbxValue::Ref { value: Some(old_id) },
+                    },
+                },
+                metadata: Default::default(),
+            },
+            root_id,
+        );
+
+        let patch_set = PatchSet {
+            removed_instances: vec![old_id],
+            added_instances: vec![PatchAdd {
+                parent_id: root_id,
+                instance: InstanceSnapshot::new().name("New").class_name("IntValue"),
+            }],
+            updated_instances: Vec::new(),
+        };
+
+        let preview = preview_patch_set(&tree, &patch_set);
+
+        assert_eq!(
+            tree.get_instance(pointer_id).unwrap().properties()["Value"],
+            RbxValue::Ref {
+                value: Some(old_id)
+            }
+        );
+
+        assert_eq!(preview.updated.len(), 1);
+        assert_eq!(preview.updated[0].id, pointer_id);
+        assert_eq!(
+            preview.updated[0].changed_properties,
+            hashmap! {
+                "Value".to_owned() => Some(RbxValue::Ref { value: Some(preview.added[0]) }),
+            }
+        );
+    }
+}
@@ -0,0 +1,366 @@
+//! Defines a way to split one large patch set into smaller ones that each only
+//! touch one subtree, so that they can be applied one after another.
+
+use std::collections::HashMap;
+
+use rbx_dom_weak::{RbxId, RbxValue};
+
+use super::{InstanceSnapshot, PatchSet, RojoTree};
+
+/// The result of `split_patch_by_subtree`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SplitPatchSet {
+    /// One patch set for each subtree root, in the same order as the roots.
+    pub patch_sets: Vec<PatchSet>,
+
+    /// Changes to instances that aren't inside of any of the roots.
+    pub remainder: PatchSet,
+
+    /// Refs that point from one patch set to an instance in another. These
+    /// only resolve if the patch set they point into is applied first, in
+    /// which case applying finds the instance through the tree's snapshot IDs.
+    pub cross_refs: Vec<CrossPartitionRef>,
+}
+
+/// A Ref property in one part of a split patch set whose target is affected by
+/// a different part. Parts are identified by their index in
+/// `SplitPatchSet::patch_sets`, where `None` means the remainder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrossPartitionRef {
+    /// The instance that the ref is set on: its ID in the tree if it's being
+    /// updated, or its snapshot ID if it's being added.
+    pub owner: Option<RbxId>,
+    pub property: String,
+    pub target: RbxId,
+    pub from: Option<usize>,
+    pub to: Option<usize>,
+}
+
+/// Splits up a patch set so that every change is in the patch set of the
+/// closest root that contains it. Roots are IDs of instances in the tree.
+///
+/// Additions belong to the subtree that their parent is in, so a patch set
+/// never adds an instance under one that another patch set adds.
+pub fn split_patch_by_subtree(
+    patch_set: PatchSet,
+    tree: &RojoTree,
+    roots: &[RbxId],
+) -> SplitPatchSet {
+    let partition_of = |id: RbxId| find_partition(tree, id, roots);
+
+    let mut split = SplitPatchSet {
+        patch_sets: vec![PatchSet::new(); roots.len()],
+        ..Default::default()
+    };
+
+    // Added instances can be the target of refs, so we need to know where they
+    // went before we can look at any refs.
+    let mut added_partitions = HashMap::new();
+    for add in &patch_set.added_instances {
+        collect_snapshot_ids(
+            &add.instance,
+            partition_of(add.parent_id),
+            &mut added_partitions,
+        );
+    }
+
+    let target_partition = |target: RbxId| match added_partitions.get(&target) {
+        Some(&partition) => Some(partition),
+        None => tree.get_instance(target).map(|_| partition_of(target)),
+    };
+
+    for id in patch_set.removed_instances {
+        split
+            .partition_mut(partition_of(id))
+            .removed_instances
+            .push(id);
+    }
+
+    for update in patch_set.updated_instances {
+        let partition = partition_of(update.id);
+
+        for (property, value) in &update.changed_properties {
+            if let Some(RbxValue::Ref {
+                value: Some(target),
+            }) = value
+            {
+                check_ref(
+                    Some(update.id),
+                    property,
+                    *target,
+                    partition,
+                    &target_partition,
+                    &mut split.cross_refs,
+                );
+            }
+        }
+
+        split
+            .partition_mut(partition)
+            .updated_instances
+            .push(update);
+    }
+
+    for add in patch_set.added_instances {
+        let partition = partition_of(add.parent_id);
+
+        check_snapshot_refs(
+            &add.instance,
+            partition,
+            &target_partition,
+            &mut split.cross_refs,
+        );
+
+        split.partition_mut(partition).added_instances.push(add);
+    }
+
+    split
+}
+
+impl SplitPatchSet {
+    fn partition_mut(&mut self, partition: Option<usize>) -> &mut PatchSet {
+        match partition {
+            Some(index) => &mut self.patch_sets[index],
+            None => &mut self.remainder,
+        }
+    }
+}
+
+/// Finds the index of the closest root that is the given instance or one of its
+/// ancestors.
+fn find_partition(tree: &RojoTree, id: RbxId, roots: &[RbxId]) -> Option<usize> {
+    let mut current = Some(id);
+
+    while let Some(current_id) = current {
+        if let Some(index) = roots.iter().position(|&root| root == current_id) {
+            return Some(index);
+        }
+
+        current = tree
+            .get_instance(current_id)
+            .and_then(|instance| instance.parent());
+    }
+
+    None
+}
+
+fn collect_snapshot_ids(
+    snapshot: &InstanceSnapshot,
+    partition: Option<usize>,
+    partitions: &mut HashMap<RbxId, Option<usize>>,
+) {
+    if let Some(snapshot_id) = snapshot.snapshot_id {
+        partitions.insert(snapshot_id, partition);
+    }
+
+    for child in &snapshot.children {
+        collect_snapshot_ids(child, partition, partitions);
+    }
+}
+
+fn check_snapshot_refs(
+    snapshot: &InstanceSnapshot,
+    partition: Option<usize>,
+    target_partition: &impl Fn(RbxId) -> Option<Option<usize>>,
+    cross_refs: &mut Vec<CrossPartitionRef>,
+) {
+    for (property, value) in &snapshot.properties {
+        if let RbxValue::Ref {
+            value: Some(target),
+        } = value
+        {
+            check_ref(
+                snapshot.snapshot_id,
+                property,
+                *target,
+                partition,
+                target_partition,
+                cross_refs,
+            );
+        }
+    }
+
+    for child in &snapshot.children {
+        check_snapshot_refs(child, partition, target_partition, cross_refs);
+    }
+}
+
+/// Records the ref if its target is in a different partition. Targets that
+/// aren't in the tree or being added are left alone.
+fn check_ref(
+    owner: Option<RbxId>,
+    property: &str,
+    target: RbxId,
+    from: Option<usize>,
+    target_partition: &impl Fn(RbxId) -> Option<Option<usize>>,
+    cross_refs: &mut Vec<CrossPartitionRef>,
+) {
+    if let Some(to) = target_partition(target) {
+        if to != from {
+            cross_refs.push(CrossPartitionRef {
+                owner,
+                property: property.to_owned(),
+                target,
+                from,
+                to,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use maplit::hashmap;
+    use rbx_dom_weak::RbxInstanceProperties;
+
+    use super::super::{apply_patch_set, InstancePropertiesWithMeta, PatchAdd, PatchUpdate};
+
+    fn insert(tree: &mut RojoTree, parent_id: RbxId, name: &str, class_name: &str) -> RbxId {
+        tree.insert_instance(
+            InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: name.to_owned(),
+                    class_name: class_name.to_owned(),
+                    properties: HashMap::new(),
+                },
+                metadata: Default::default(),
+            },
+            parent_id,
+        )
+    }
+
+    fn ref_to(id: RbxId) -> RbxValue {
+        RbxValue::Ref { value: Some(id) }
+    }
+
+    #[test]
+    fn split_between_services() {
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "game".to_owned(),
+                class_name: "DataModel".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+
+        let storage_id = insert(&mut tree, root_id, "ReplicatedStorage", "ReplicatedStorage");
+        let workspace_id = insert(&mut tree, root_id, "Workspace", "Workspace");
+        let old_id = insert(&mut tree, storage_id, "Old", "Folder");
+        let pointer_id = insert(&mut tree, workspace_id, "Pointer", "ObjectValue");
+
+        let added_id = RbxId::new();
+        let local_id = RbxId::new();
+
+        let mut local = InstanceSnapshot::new()
+            .name("Local")
+            .class_name("ObjectValue")
+            .properties(hashmap! {
+                "Value".to_owned() => ref_to(added_id),
+            });
+        local.snapshot_id = Some(local_id);
+
+        let mut added = InstanceSnapshot::new()
+            .name("New")
+            .class_name("Folder")
+            .children(vec![local]);
+        added.snapshot_id = Some(added_id);
+
+        let remote_id = RbxId::new();
+        let mut remote = InstanceSnapshot::new()
+            .name("Remote")
+            .class_name("ObjectValue")
+            .properties(hashmap! {
+                "Value".to_owned() => ref_to(added_id),
+            });
+        remote.snapshot_id = Some(remote_id);
+
+        let patch_set = PatchSet {
+            removed_instances: vec![old_id],
+            added_instances: vec![
+                PatchAdd {
+                    parent_id: storage_id,
+                    instance: added,
+                },
+                PatchAdd {
+                    parent_id: workspace_id,
+                    instance: remote,
+                },
+            ],
+            updated_instances: vec![PatchUpdate {
+                id: pointer_id,
+                changed_name: None,
+                changed_class_name: None,
+                changed_properties: hashmap! {
+                    "Value".to_owned() => Some(ref_to(added_id)),
+                },
+                changed_attributes: Default::default(),
+                changed_ref_paths: Default::default(),
+                changed_metadata: None,
+            }],
+        };
+
+        let split = split_patch_by_subtree(patch_set, &tree, &[storage_id, workspace_id]);
+
+        assert_eq!(split.patch_sets.len(), 2);
+        assert_eq!(split.remainder, PatchSet::new());
+
+        let storage = &split.patch_sets[0];
+        assert_eq!(storage.removed_instances, vec![old_id]);
+        assert_eq!(storage.added_instances.len(), 1);
+        assert!(storage.updated_instances.is_empty());
+
+        let workspace = &split.patch_sets[1];
+        assert!(workspace.removed_instances.is_empty());
+        assert_eq!(workspace.added_instances.len(), 1);
+        assert_eq!(workspace.updated_instances.len(), 1);
+
+        // The ref between the two added instances stays inside of the storage
+        // patch set, so only the workspace's refs are reported.
+        assert_eq!(
+            split.cross_refs,
+            vec![
+                CrossPartitionRef {
+                    owner: Some(pointer_id),
+                    property: "Value".to_owned(),
+                    target: added_id,
+                    from: Some(1),
+                    to: Some(0),
+                },
+                CrossPartitionRef {
+                    owner: Some(remote_id),
+                    property: "Value".to_owned(),
+                    target: added_id,
+                    from: Some(1),
+                    to: Some(0),
+                },
+            ]
+        );
+
+        let applied = apply_patch_set(&mut tree, split.patch_sets[0].clone());
+        assert!(applied.unresolved_refs.is_empty());
+
+        let applied = apply_patch_set(&mut tree, split.patch_sets[1].clone());
+        assert!(applied.unresolved_refs.is_empty());
+
+        let new_id = tree.get_id_for_snapshot_id(added_id).unwrap();
+        let local_id = tree.get_id_for_snapshot_id(local_id).unwrap();
+        let remote_id = tree.get_id_for_snapshot_id(remote_id).unwrap();
+
+        assert_eq!(
+            tree.get_instance(pointer_id).unwrap().properties()["Value"],
+            ref_to(new_id)
+        );
+        assert_eq!(
+            tree.get_instance(local_id).unwrap().properties()["Value"],
+            ref_to(new_id)
+        );
+        assert_eq!(
+            tree.get_instance(remote_id).unwrap().properties()["Value"],
+            ref_to(new_id)
+        );
+    }
+}
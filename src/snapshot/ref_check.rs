@@ -0,0 +1,214 @@
+//! Finds Ref properties that point from one `$path`-backed subtree of a
+//! project into another. Those subtrees can be built or synced on their own,
+//! which would leave refs like this dangling.
+
+use std::{collections::HashMap, fmt};
+
+use rbx_dom_weak::{RbxId, RbxValue};
+
+use crate::trace;
+
+use super::{InstigatingSource, RojoTree};
+
+/// A Ref property whose target is in a different subtree than the instance
+/// that it's defined on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrossSubtreeRef {
+    pub owner_path: String,
+    pub property: String,
+    pub target_path: String,
+}
+
+impl fmt::Display for CrossSubtreeRef {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{}.{} refers to {}, which comes from a different $path",
+            self.owner_path, self.property, self.target_path
+        )
+    }
+}
+
+/// Finds every Ref property in the tree that crosses from one `$path` subtree
+/// into another. Refs to instances that aren't in the tree are ignored.
+pub fn find_cross_subtree_refs(tree: &RojoTree) -> Vec<CrossSubtreeRef> {
+    let _span = trace::span("patch", || "find_cross_subtree_refs".to_owned());
+
+    // The closest ancestor of each instance, or the instance itself, that was
+    // created from a project node with a `$path`.
+    let mut subtrees = HashMap::new();
+    collect_subtrees(tree, tree.get_root_id(), None, &mut subtrees);
+
+    let mut cross_refs = Vec::new();
+
+    for (&id, subtree) in &subtrees {
+        let instance = tree.get_instance(id).unwrap();
+
+        for (property, value) in instance.properties() {
+            let target_id = match value {
+                RbxValue::Ref {
+                    value: Some(target_id),
+                } => target_id,
+                _ => continue,
+            };
+
+            if let Some(target_subtree) = subtrees.get(target_id) {
+                if target_subtree != subtree {
+                    cross_refs.push(CrossSubtreeRef {
+                        owner_path: tree.instance_path(id).unwrap().to_string(),
+                        property: property.clone(),
+                        target_path: tree.instance_path(*target_id).unwrap().to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    // HashMap iteration order isn't stable, so we sort to give consistent
+    // output.
+    cross_refs.sort_by(|a, b| (&a.owner_path, &a.property).cmp(&(&b.owner_path, &b.property)));
+
+    cross_refs
+}
+
+/// Logs a warning for every Ref property in the tree that crosses from one
+/// `$path` subtree into another.
+pub fn warn_cross_subtree_refs(tree: &RojoTree) {
+    for cross_ref in find_cross_subtree_refs(tree) {
+        log::warn!("{}", cross_ref);
+    }
+}
+
+fn collect_subtrees(
+    tree: &RojoTree,
+    id: RbxId,
+    parent_subtree: Option<RbxId>,
+    subtrees: &mut HashMap<RbxId, Option<RbxId>>,
+) {
+    let instance = tree.get_instance(id).unwrap();
+
+    let subtree = match &instance.metadata().instigating_source {
+        Some(InstigatingSource::ProjectNode(_, _, node, _)) if node.path.is_some() => Some(id),
+        _ => parent_subtree,
+    };
+
+    for &child_id in instance.children() {
+        collect_subtrees(tree, child_id, subtree, subtrees);
+    }
+
+    subtrees.insert(id, subtree);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    use maplit::hashmap;
+    use rbx_dom_weak::RbxInstanceProperties;
+
+    use crate::{
+        project::ProjectNode,
+        snapshot::{InstanceMetadata, InstancePropertiesWithMeta},
+    };
+
+    fn insert(
+        tree: &mut RojoTree,
+        parent_id: RbxId,
+        name: &str,
+        properties: HashMap<String, RbxValue>,
+        metadata: InstanceMetadata,
+    ) -> RbxId {
+        tree.insert_instance(
+            InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: name.to_owned(),
+                    class_name: "ObjectValue".to_owned(),
+                    properties,
+                },
+                metadata,
+            },
+            parent_id,
+        )
+    }
+
+    fn path_node(name: &str) -> InstanceMetadata {
+        let node = ProjectNode {
+            path: Some(PathBuf::from(name)),
+            ..ProjectNode::default()
+        };
+
+        InstanceMetadata::new().instigating_source(InstigatingSource::ProjectNode(
+            PathBuf::from("/foo/default.project.json"),
+            name.to_owned(),
+            node,
+            None,
+        ))
+    }
+
+    fn ref_to(id: RbxId) -> HashMap<String, RbxValue> {
+        hashmap! {
+            "Value".to_owned() => RbxValue::Ref { value: Some(id) },
+        }
+    }
+
+    #[test]
+    fn cross_and_intra_subtree_refs() {
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "root".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+
+        let first_id = insert(
+            &mut tree,
+            root_id,
+            "First",
+            HashMap::new(),
+            path_node("first"),
+        );
+        let second_id = insert(
+            &mut tree,
+            root_id,
+            "Second",
+            HashMap::new(),
+            path_node("second"),
+        );
+
+        let target_id = insert(
+            &mut tree,
+            first_id,
+            "Target",
+            HashMap::new(),
+            Default::default(),
+        );
+        insert(
+            &mut tree,
+            first_id,
+            "Near",
+            ref_to(target_id),
+            Default::default(),
+        );
+        insert(
+            &mut tree,
+            second_id,
+            "Far",
+            ref_to(target_id),
+            Default::default(),
+        );
+
+        assert_eq!(
+            find_cross_subtree_refs(&tree),
+            vec![CrossSubtreeRef {
+                owner_path: "root/Second/Far".to_owned(),
+                property: "Value".to_owned(),
+                target_path: "root/First/Target".to_owned(),
+            }]
+        );
+    }
+}
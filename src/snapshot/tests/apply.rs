@@ -22,6 +22,8 @@ fn set_name_and_class_name() {
             changed_name: Some("Hello, world!".to_owned()),
             changed_class_name: Some("Folder".to_owned()),
             changed_properties: Default::default(),
+            changed_attributes: Default::default(),
+            changed_ref_paths: Default::default(),
             changed_metadata: None,
         }],
         ..Default::default()
@@ -53,6 +55,8 @@ fn add_property() {
                     value: "Value of Foo".to_owned(),
                 }),
             },
+            changed_attributes: Default::default(),
+            changed_ref_paths: Default::default(),
             changed_metadata: None,
         }],
         ..Default::default()
@@ -97,6 +101,8 @@ fn remove_property() {
             changed_properties: hashmap! {
                 "Foo".to_owned() => None,
             },
+            changed_attributes: Default::default(),
+            changed_ref_paths: Default::default(),
             changed_metadata: None,
         }],
         ..Default::default()
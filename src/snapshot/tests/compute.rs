@@ -21,6 +21,7 @@ fn set_name_and_class_name() {
         name: Cow::Borrowed("Some Folder"),
         class_name: Cow::Borrowed("Folder"),
         properties: Default::default(),
+        ref_paths: Default::default(),
         children: Vec::new(),
     };
 
@@ -47,6 +48,7 @@ fn set_property() {
                 value: "Hello, world!".to_owned(),
             },
         },
+        ref_paths: Default::default(),
         children: Vec::new(),
     };
 
@@ -80,6 +82,7 @@ fn remove_property() {
         name: Cow::Borrowed("ROOT"),
         class_name: Cow::Borrowed("ROOT"),
         properties: Default::default(),
+        ref_paths: Default::default(),
         children: Vec::new(),
     };
 
@@ -102,12 +105,14 @@ fn add_child() {
         name: Cow::Borrowed("ROOT"),
         class_name: Cow::Borrowed("ROOT"),
         properties: Default::default(),
+        ref_paths: Default::default(),
         children: vec![InstanceSnapshot {
             snapshot_id: None,
             metadata: Default::default(),
             name: Cow::Borrowed("New"),
             class_name: Cow::Borrowed("Folder"),
             properties: Default::default(),
+            ref_paths: Default::default(),
             children: Vec::new(),
         }],
     };
@@ -148,6 +153,7 @@ fn remove_child() {
         name: Cow::Borrowed("ROOT"),
         class_name: Cow::Borrowed("ROOT"),
         properties: Default::default(),
+        ref_paths: Default::default(),
         children: Vec::new(),
     };
 
@@ -0,0 +1,160 @@
+//! Implements the `transforms` field of project files, which sets properties
+//! on every instance of a class after a project has been snapshotted.
+
+use std::sync::Arc;
+
+use crate::{
+    project::TransformRule, reflection_cache::is_subclass_of, resolution::resolve_value, trace,
+};
+
+use super::InstanceSnapshot;
+
+/// Applies the transform rules attached to the given snapshot and all of its
+/// descendants via their `InstanceContext`.
+///
+/// Snapshots that aren't given a context by their middleware, like instances
+/// inside of model files, use the rules of their closest ancestor that has
+/// some.
+pub fn apply_transforms(snapshot: &mut InstanceSnapshot) {
+    let _span = trace::span("snapshot", || format!("apply_transforms {}", snapshot.name));
+
+    apply_transforms_inner(snapshot, &[]);
+}
+
+fn apply_transforms_inner(snapshot: &mut InstanceSnapshot, inherited_rules: &[TransformRule]) {
+    // We clone the Arc so that the rules can be read while the snapshot is
+    // being mutated.
+    let own_rules = Arc::clone(&snapshot.metadata.context.transform_rules);
+    let rules = if own_rules.is_empty() {
+        inherited_rules
+    } else {
+        own_rules.as_slice()
+    };
+
+    for rule in rules {
+        if !rule_matches(rule, &snapshot.class_name) {
+            continue;
+        }
+
+        for (key, value) in &rule.set {
+            match resolve_value(&snapshot.class_name, key, value) {
+                Ok(resolved) => {
+                    snapshot.properties.insert(key.clone(), resolved);
+                }
+                Err(err) => {
+                    log::warn!(
+                        "Could not apply transform for {}.{} on {}: {}",
+                        rule.class_match,
+                        key,
+                        snapshot.name,
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    for child in &mut snapshot.children {
+        apply_transforms_inner(child, rules);
+    }
+}
+
+fn rule_matches(rule: &TransformRule, class_name: &str) -> bool {
+    if rule.include_subclasses {
+        is_subclass_of(class_name, &rule.class_match)
+    } else {
+        rule.class_match == class_name
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use maplit::hashmap;
+    use rbx_dom_weak::{RbxValue, UnresolvedRbxValue};
+
+    use crate::snapshot::{InstanceContext, InstanceMetadata};
+
+    fn transformed_project(rules: Vec<TransformRule>) -> InstanceSnapshot {
+        let context = InstanceContext {
+            transform_rules: Arc::new(rules),
+            ..InstanceContext::default()
+        };
+
+        let children: Vec<_> = vec![
+            InstanceSnapshot::new().name("Floor").class_name("Part"),
+            InstanceSnapshot::new()
+                .name("Spawn")
+                .class_name("SpawnLocation"),
+            InstanceSnapshot::new()
+                .name("Model")
+                .class_name("Model")
+                .children(vec![InstanceSnapshot::new()
+                    .name("Wall")
+                    .class_name("Part")]),
+        ];
+
+        let mut snapshot = InstanceSnapshot::new()
+            .name("Workspace")
+            .class_name("Folder")
+            .children(children)
+            .metadata(InstanceMetadata::new().context(&context));
+
+        apply_transforms(&mut snapshot);
+        snapshot
+    }
+
+    fn cast_shadow_rule(value: bool) -> TransformRule {
+        TransformRule {
+            class_match: "Part".to_owned(),
+            include_subclasses: false,
+            set: hashmap! {
+                "CastShadow".to_owned() => UnresolvedRbxValue::from(RbxValue::Bool { value }),
+            },
+        }
+    }
+
+    fn cast_shadow(snapshot: &InstanceSnapshot) -> Option<&RbxValue> {
+        snapshot.properties.get("CastShadow")
+    }
+
+    #[test]
+    fn exact_class_match() {
+        let snapshot = transformed_project(vec![cast_shadow_rule(false)]);
+        let disabled = RbxValue::Bool { value: false };
+
+        assert_eq!(cast_shadow(&snapshot), None);
+        assert_eq!(cast_shadow(&snapshot.children[0]), Some(&disabled));
+        assert_eq!(cast_shadow(&snapshot.children[1]), None);
+        assert_eq!(cast_shadow(&snapshot.children[2]), None);
+        assert_eq!(
+            cast_shadow(&snapshot.children[2].children[0]),
+            Some(&disabled)
+        );
+    }
+
+    #[test]
+    fn subclass_match() {
+        let mut rule = cast_shadow_rule(false);
+        rule.include_subclasses = true;
+
+        let snapshot = transformed_project(vec![rule]);
+
+        assert_eq!(
+            cast_shadow(&snapshot.children[1]),
+            Some(&RbxValue::Bool { value: false })
+        );
+        assert_eq!(cast_shadow(&snapshot.children[2]), None);
+    }
+
+    #[test]
+    fn later_rules_override_earlier() {
+        let snapshot = transformed_project(vec![cast_shadow_rule(false), cast_shadow_rule(true)]);
+
+        assert_eq!(
+            cast_shadow(&snapshot.children[0]),
+            Some(&RbxValue::Bool { value: true })
+        );
+    }
+}
@@ -1,5 +1,9 @@
 use std::{
+    borrow::Cow,
+    cell::RefCell,
     collections::HashMap,
+    fmt,
+    hash::Hasher,
     path::{Path, PathBuf},
 };
 
@@ -7,13 +11,47 @@ use rbx_dom_weak::{Descendants, RbxId, RbxInstance, RbxInstanceProperties, RbxTr
 
 use crate::multimap::MultiMap;
 
-use super::InstanceMetadata;
+use super::{InstanceMetadata, InstanceSnapshot};
+
+/// The location of an instance in a `RojoTree`, made up of the names of the
+/// instance and each of its ancestors, starting from the root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstancePath {
+    pub id: RbxId,
+    pub names: Vec<String>,
+}
+
+impl InstancePath {
+    /// Finds the path of the given instance in a plain `RbxTree`, for code that
+    /// works on a tree after it's been taken out of a `RojoTree`.
+    pub fn in_tree(tree: &RbxTree, id: RbxId) -> Option<Self> {
+        let mut names = Vec::new();
+        let mut current = Some(tree.get_instance(id)?);
+
+        while let Some(instance) = current {
+            names.push(instance.name.clone());
+            current = instance
+                .get_parent_id()
+                .and_then(|parent_id| tree.get_instance(parent_id));
+        }
+
+        names.reverse();
+
+        Some(InstancePath { id, names })
+    }
+}
+
+impl fmt::Display for InstancePath {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.names.join("/"))
+    }
+}
 
 /// An expanded variant of rbx_dom_weak's `RbxTree` that tracks additional
 /// metadata per instance that's Rojo-specific.
 ///
 /// This tree is also optimized for doing fast incremental updates and patches.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RojoTree {
     /// Contains the instances without their Rojo-specific metadata.
     inner: RbxTree,
@@ -30,6 +68,22 @@ pub struct RojoTree {
     /// appearing multiple times in the same Rojo project. This is sometimes
     /// called "path aliasing" in various Rojo documentation.
     path_to_ids: MultiMap<PathBuf, RbxId>,
+
+    /// A map from the snapshot IDs of instances that were added to the tree
+    /// via a patch to the IDs they were given in the tree.
+    ///
+    /// This is kept across patch applications so that applying the same patch
+    /// twice doesn't create duplicate instances. `instance_to_snapshot_id` is
+    /// the reverse mapping, used to clean this map up when instances are
+    /// removed.
+    snapshot_id_to_instance_id: HashMap<RbxId, RbxId>,
+    instance_to_snapshot_id: HashMap<RbxId, RbxId>,
+
+    /// Cached results of `subtree_hash`. Whenever an instance is changed, its
+    /// entry and the entries of all of its ancestors are removed.
+    ///
+    /// If an instance has an entry in this map, all of its descendants do too.
+    subtree_hashes: RefCell<HashMap<RbxId, u64>>,
 }
 
 impl RojoTree {
@@ -38,6 +92,9 @@ impl RojoTree {
             inner: RbxTree::new(root.properties),
             metadata_map: HashMap::new(),
             path_to_ids: MultiMap::new(),
+            snapshot_id_to_instance_id: HashMap::new(),
+            instance_to_snapshot_id: HashMap::new(),
+            subtree_hashes: RefCell::new(HashMap::new()),
         };
 
         tree.insert_metadata(tree.inner.get_root_id(), root.metadata);
@@ -63,6 +120,8 @@ impl RojoTree {
     }
 
     pub fn get_instance_mut(&mut self, id: RbxId) -> Option<InstanceWithMetaMut> {
+        self.invalidate_subtree_hash(id);
+
         if let Some(instance) = self.inner.get_instance_mut(id) {
             let metadata = self.metadata_map.get_mut(&id).unwrap();
 
@@ -77,25 +136,37 @@ impl RojoTree {
         properties: InstancePropertiesWithMeta,
         parent_id: RbxId,
     ) -> RbxId {
+        self.invalidate_subtree_hash(parent_id);
+
         let id = self.inner.insert_instance(properties.properties, parent_id);
         self.insert_metadata(id, properties.metadata);
         id
     }
 
     pub fn remove_instance(&mut self, id: RbxId) -> Option<RojoTree> {
+        // RbxTree panics when asked to remove an instance that doesn't exist.
+        self.inner.get_instance(id)?;
+
+        self.invalidate_subtree_hash(id);
+
         if let Some(inner) = self.inner.remove_instance(id) {
             let mut metadata_map = HashMap::new();
             let mut path_to_ids = MultiMap::new();
-
             self.move_metadata(id, &mut metadata_map, &mut path_to_ids);
+            self.forget_snapshot_id(id);
             for instance in inner.descendants(id) {
                 self.move_metadata(instance.get_id(), &mut metadata_map, &mut path_to_ids);
+                self.forget_snapshot_id(instance.get_id());
+                self.subtree_hashes.get_mut().remove(&instance.get_id());
             }
 
             Some(RojoTree {
                 inner,
                 metadata_map,
                 path_to_ids,
+                snapshot_id_to_instance_id: HashMap::new(),
+                instance_to_snapshot_id: HashMap::new(),
+                subtree_hashes: RefCell::new(HashMap::new()),
             })
         } else {
             None
@@ -142,10 +213,190 @@ impl RojoTree {
         self.path_to_ids.get(path)
     }
 
+    /// Finds the instances that need to be snapshotted again when the given
+    /// path changes. These are the instances associated with the path itself
+    /// or, if there aren't any, with its nearest ancestor that has some.
+    ///
+    /// Walking up to an ancestor makes sure that we handle additions
+    /// correctly, especially if a large tree of files is created all at once.
+    pub fn ids_affected_by(&self, path: &Path) -> Vec<RbxId> {
+        let mut current_path = path;
+
+        loop {
+            let ids = self.get_ids_at_path(current_path);
+
+            log::trace!("Path {} affects IDs {:?}", current_path.display(), ids);
+
+            if !ids.is_empty() {
+                return ids.to_vec();
+            }
+
+            log::trace!("Trying parent path...");
+            match current_path.parent() {
+                Some(parent) => current_path = parent,
+                None => return Vec::new(),
+            }
+        }
+    }
+
+    /// Describes where the given instance is in the tree by its name and the
+    /// names of its ancestors.
+    pub fn instance_path(&self, id: RbxId) -> Option<InstancePath> {
+        InstancePath::in_tree(&self.inner, id)
+    }
+
+    /// Finds an instance from the names of it and its ancestors separated by
+    /// dots, like `game.ReplicatedStorage.Shared.Config`. The first name is
+    /// the root, which can also be called `game`. If siblings share a name,
+    /// the first one is followed.
+    ///
+    /// Names that contain dots need them escaped with a backslash, like
+    /// `game.Workspace.Map\.v2`. A backslash in a name is written as `\\`.
+    pub fn get_id_at_path(&self, path: &str) -> Option<RbxId> {
+        let names = split_instance_path(path);
+        let mut names = names.iter();
+        let mut current = self.get_instance(self.get_root_id())?;
+
+        let root_name = names.next()?;
+        if root_name != "game" && root_name != current.name() {
+            return None;
+        }
+
+        for name in names {
+            current = current
+                .children()
+                .iter()
+                .filter_map(|&child_id| self.get_instance(child_id))
+                .find(|child| child.name() == name)?;
+        }
+
+        Some(current.id())
+    }
+
+    /// Describes the given instance and its descendants as a snapshot, which
+    /// would recreate them if it were added to a tree. Each snapshot ID is the
+    /// ID of the instance it came from.
+    pub fn to_snapshot(&self, id: RbxId) -> Option<InstanceSnapshot> {
+        let instance = self.get_instance(id)?;
+
+        let children = instance
+            .children()
+            .iter()
+            .filter_map(|&child_id| self.to_snapshot(child_id))
+            .collect();
+
+        Some(InstanceSnapshot {
+            snapshot_id: Some(id),
+            metadata: instance.metadata().clone(),
+            name: Cow::Owned(instance.name().to_owned()),
+            class_name: Cow::Owned(instance.class_name().to_owned()),
+            properties: instance.properties().clone(),
+            ref_paths: Default::default(),
+            children,
+        })
+    }
+
     pub fn get_metadata(&self, id: RbxId) -> Option<&InstanceMetadata> {
         self.metadata_map.get(&id)
     }
 
+    /// Returns the ID of the instance that was created from the snapshot with
+    /// the given snapshot ID, if it's still in the tree.
+    pub fn get_id_for_snapshot_id(&self, snapshot_id: RbxId) -> Option<RbxId> {
+        self.snapshot_id_to_instance_id.get(&snapshot_id).copied()
+    }
+
+    /// Returns the snapshot ID of the snapshot that the instance was created
+    /// from, if it had one.
+    pub fn get_snapshot_id(&self, id: RbxId) -> Option<RbxId> {
+        self.instance_to_snapshot_id.get(&id).copied()
+    }
+
+    /// Records that the instance with the given ID was created from the
+    /// snapshot with the given snapshot ID.
+    pub fn insert_snapshot_id(&mut self, snapshot_id: RbxId, id: RbxId) {
+        self.snapshot_id_to_instance_id.insert(snapshot_id, id);
+        self.instance_to_snapshot_id.insert(id, snapshot_id);
+    }
+
+    /// Computes a hash of the given instance and all of its descendants from
+    /// their names, classes, properties, and the order of their children.
+    /// Instances that compare equal always have equal hashes.
+    ///
+    /// Ref properties are hashed by the ID of the instance they point to, so
+    /// hashes of instances with refs are only comparable within one tree. That
+    /// keeps the cache correct when the target of a ref is moved or renamed.
+    ///
+    /// Hashes are cached until the instance or one of its descendants changes.
+    ///
+    /// Panics if the instance isn't in the tree.
+    pub fn subtree_hash(&self, id: RbxId) -> u64 {
+        if let Some(&hash) = self.subtree_hashes.borrow().get(&id) {
+            return hash;
+        }
+
+        let instance = self
+            .inner
+            .get_instance(id)
+            .expect("instance passed to subtree_hash was not in the tree");
+
+        let mut hasher = StableHasher::new();
+        hash_instance_contents(&self.inner, instance, RefHashing::ById, &mut hasher);
+
+        for &child_id in instance.get_children_ids() {
+            hasher.write_u64(self.subtree_hash(child_id));
+        }
+
+        let hash = hasher.finish();
+        self.subtree_hashes.borrow_mut().insert(id, hash);
+        hash
+    }
+
+    /// Like `subtree_hash`, but only covers the instance itself, so changes to
+    /// its descendants don't change it. These hashes aren't cached.
+    ///
+    /// Ref properties are hashed by the path of the instance they point to
+    /// instead of its ID, since IDs are different every time Rojo runs. This
+    /// makes these hashes stable between runs, which build manifests rely on.
+    ///
+    /// Panics if the instance isn't in the tree.
+    pub fn instance_hash(&self, id: RbxId) -> u64 {
+        let instance = self
+            .inner
+            .get_instance(id)
+            .expect("instance passed to instance_hash was not in the tree");
+
+        let mut hasher = StableHasher::new();
+        hash_instance_contents(&self.inner, instance, RefHashing::ByPath, &mut hasher);
+        hasher.finish()
+    }
+
+    /// Forgets the cached subtree hashes of the given instance and all of its
+    /// ancestors.
+    fn invalidate_subtree_hash(&mut self, id: RbxId) {
+        let subtree_hashes = self.subtree_hashes.get_mut();
+        let mut current_id = Some(id);
+
+        while let Some(id) = current_id {
+            // Ancestors of an instance without a cached hash can't have one
+            // either, so we can stop early.
+            if subtree_hashes.remove(&id).is_none() {
+                break;
+            }
+
+            current_id = self
+                .inner
+                .get_instance(id)
+                .and_then(|instance| instance.get_parent_id());
+        }
+    }
+
+    fn forget_snapshot_id(&mut self, id: RbxId) {
+        if let Some(snapshot_id) = self.instance_to_snapshot_id.remove(&id) {
+            self.snapshot_id_to_instance_id.remove(&snapshot_id);
+        }
+    }
+
     fn insert_metadata(&mut self, id: RbxId, metadata: InstanceMetadata) {
         for path in &metadata.relevant_paths {
             self.path_to_ids.insert(path.clone(), id);
@@ -173,6 +424,120 @@ impl RojoTree {
     }
 }
 
+/// Hashes the name, class, and properties of an instance.
+/// Splits a path for `RojoTree::get_id_at_path` into names on every dot that
+/// isn't escaped with a backslash.
+fn split_instance_path(path: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut current = String::new();
+    let mut chars = path.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped) => current.push(escaped),
+                None => current.push('\\'),
+            },
+            '.' => names.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+
+    names.push(current);
+    names
+}
+
+/// How `hash_instance_contents` hashes Ref properties.
+#[derive(Clone, Copy, PartialEq)]
+enum RefHashing {
+    /// By the target's ID, which only means something within one tree.
+    ById,
+
+    /// By the target's path, which is the same for equal trees.
+    ByPath,
+}
+
+fn hash_instance_contents(
+    tree: &RbxTree,
+    instance: &RbxInstance,
+    ref_hashing: RefHashing,
+    hasher: &mut StableHasher,
+) {
+    hasher.write(instance.class_name.as_bytes());
+    hasher.write_u8(0);
+    hasher.write(instance.name.as_bytes());
+    hasher.write_u8(0);
+
+    let mut properties: Vec<_> = instance.properties.iter().collect();
+    properties.sort_by_key(|(key, _)| key.as_str());
+
+    for (key, value) in properties {
+        hasher.write(key.as_bytes());
+        hasher.write_u8(0);
+
+        if let RbxValue::Ref {
+            value: Some(target),
+        } = value
+        {
+            if ref_hashing == RefHashing::ByPath {
+                hash_ref_target(tree, *target, hasher);
+                continue;
+            }
+        }
+
+        // RbxValue doesn't implement Hash, but its serialized form is stable
+        // and unique for each value.
+        let serialized = serde_json::to_vec(value).expect("RbxValue could not be serialized");
+        hasher.write(&serialized);
+        hasher.write_u8(0);
+    }
+}
+
+/// Hashes a ref by the names of its target and the target's ancestors. Refs to
+/// instances outside of the tree all hash the same.
+fn hash_ref_target(tree: &RbxTree, target: RbxId, hasher: &mut StableHasher) {
+    // Serialized values start with `{`, so this can't collide with them.
+    hasher.write(b"ref");
+    hasher.write_u8(0);
+
+    if let Some(path) = InstancePath::in_tree(tree, target) {
+        for name in &path.names {
+            hasher.write(name.as_bytes());
+            hasher.write_u8(0);
+        }
+    }
+
+    hasher.write_u8(0);
+}
+
+/// A 64-bit FNV-1a hasher. Unlike std's `DefaultHasher`, its output is
+/// guaranteed to be the same across Rust versions and platforms, which makes it
+/// suitable for hashes that are sent to clients.
+struct StableHasher(u64);
+
+impl StableHasher {
+    fn new() -> Self {
+        StableHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
 pub struct RojoDescendants<'a> {
     inner: Descendants<'a>,
     tree: &'a RojoTree,
@@ -297,3 +662,216 @@ impl InstanceWithMetaMut<'_> {
         &self.metadata
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use maplit::hashmap;
+    use rbx_dom_weak::RbxInstanceProperties;
+
+    use crate::snapshot::{
+        apply_patch_set, compute_patch_set, InstanceSnapshot, PatchSet, PatchUpdate,
+    };
+
+    fn new_tree() -> RojoTree {
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "ROOT".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+
+        let module = |name: &str| {
+            InstanceSnapshot::new()
+                .name(name.to_owned())
+                .class_name("ModuleScript")
+                .properties(hashmap! {
+                    "Source".to_owned() => RbxValue::String {
+                        value: "return nil".to_owned(),
+                    },
+                })
+        };
+
+        let snapshot = InstanceSnapshot::new()
+            .name("ROOT")
+            .class_name("Folder")
+            .children(vec![
+                InstanceSnapshot::new()
+                    .name("Shared")
+                    .class_name("Folder")
+                    .children(vec![module("Util")]),
+                module("Main"),
+            ]);
+
+        let root_id = tree.get_root_id();
+        let patch_set = compute_patch_set(&snapshot, &tree, root_id);
+        apply_patch_set(&mut tree, patch_set);
+
+        tree
+    }
+
+    fn child_named(tree: &RojoTree, parent: RbxId, name: &str) -> RbxId {
+        let parent = tree.get_instance(parent).unwrap();
+
+        parent
+            .children()
+            .iter()
+            .copied()
+            .find(|&id| tree.get_instance(id).unwrap().name() == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn ref_hashes_match_between_trees() {
+        let point_main_at_util = |tree: &mut RojoTree| {
+            let root_id = tree.get_root_id();
+            let util_id = child_named(tree, child_named(tree, root_id, "Shared"), "Util");
+            let main_id = child_named(tree, root_id, "Main");
+
+            tree.get_instance_mut(main_id)
+                .unwrap()
+                .properties_mut()
+                .insert(
+                    "PrimaryPart".to_owned(),
+                    RbxValue::Ref {
+                        value: Some(util_id),
+                    },
+                );
+
+            main_id
+        };
+
+        let mut tree = new_tree();
+        let mut other_tree = new_tree();
+        let main_id = point_main_at_util(&mut tree);
+        let other_main_id = point_main_at_util(&mut other_tree);
+
+        assert_eq!(
+            tree.instance_hash(main_id),
+            other_tree.instance_hash(other_main_id)
+        );
+    }
+
+    #[test]
+    fn renaming_ref_target_keeps_cached_hashes_correct() {
+        let mut tree = new_tree();
+
+        let root_id = tree.get_root_id();
+        let shared_id = child_named(&tree, root_id, "Shared");
+        let util_id = child_named(&tree, shared_id, "Util");
+        let main_id = child_named(&tree, root_id, "Main");
+
+        tree.get_instance_mut(main_id)
+            .unwrap()
+            .properties_mut()
+            .insert(
+                "PrimaryPart".to_owned(),
+                RbxValue::Ref {
+                    value: Some(util_id),
+                },
+            );
+
+        let old_subtree = tree.subtree_hash(main_id);
+        let old_instance = tree.instance_hash(main_id);
+
+        let patch_set = PatchSet {
+            updated_instances: vec![PatchUpdate {
+                id: util_id,
+                changed_name: Some("Helpers".to_owned()),
+                changed_class_name: None,
+                changed_properties: Default::default(),
+                changed_attributes: Default::default(),
+                changed_ref_paths: Default::default(),
+                changed_metadata: None,
+            }],
+            ..Default::default()
+        };
+        apply_patch_set(&mut tree, patch_set);
+
+        // Main still points at the same instance, so its cached subtree hash
+        // is still right. Its path-based hash follows the new name.
+        assert_eq!(tree.subtree_hash(main_id), old_subtree);
+        tree.subtree_hashes.borrow_mut().clear();
+        assert_eq!(tree.subtree_hash(main_id), old_subtree);
+
+        assert_ne!(tree.instance_hash(main_id), old_instance);
+    }
+
+    #[test]
+    fn paths_with_escaped_dots() {
+        let mut tree = new_tree();
+        let root_id = tree.get_root_id();
+        let shared_id = child_named(&tree, root_id, "Shared");
+
+        let dotted_id = tree.insert_instance(
+            InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: "Map.v2".to_owned(),
+                    class_name: "Folder".to_owned(),
+                    properties: HashMap::new(),
+                },
+                metadata: Default::default(),
+            },
+            shared_id,
+        );
+
+        assert_eq!(tree.get_id_at_path(r"game.Shared.Map\.v2"), Some(dotted_id));
+        assert_eq!(tree.get_id_at_path("game.Shared.Map.v2"), None);
+        assert_eq!(
+            tree.get_id_at_path("ROOT.Shared.Util"),
+            Some(child_named(&tree, shared_id, "Util"))
+        );
+
+        assert_eq!(
+            split_instance_path(r"a\\b.c\"),
+            vec![r"a\b".to_owned(), r"c\".to_owned()]
+        );
+    }
+
+    #[test]
+    fn subtree_hash_changes_up_to_root() {
+        let mut tree = new_tree();
+        let other_tree = new_tree();
+
+        let root_id = tree.get_root_id();
+        let shared_id = child_named(&tree, root_id, "Shared");
+        let util_id = child_named(&tree, shared_id, "Util");
+        let main_id = child_named(&tree, root_id, "Main");
+
+        assert_eq!(
+            tree.subtree_hash(root_id),
+            other_tree.subtree_hash(other_tree.get_root_id())
+        );
+
+        let old_root = tree.subtree_hash(root_id);
+        let old_shared = tree.subtree_hash(shared_id);
+        let old_util = tree.subtree_hash(util_id);
+        let old_main = tree.subtree_hash(main_id);
+
+        let patch_set = PatchSet {
+            updated_instances: vec![PatchUpdate {
+                id: util_id,
+                changed_name: None,
+                changed_class_name: None,
+                changed_properties: hashmap! {
+                    "Source".to_owned() => Some(RbxValue::String {
+                        value: "return {}".to_owned(),
+                    }),
+                },
+                changed_attributes: Default::default(),
+                changed_ref_paths: Default::default(),
+                changed_metadata: None,
+            }],
+            ..Default::default()
+        };
+        apply_patch_set(&mut tree, patch_set);
+
+        assert_ne!(tree.subtree_hash(util_id), old_util);
+        assert_ne!(tree.subtree_hash(shared_id), old_shared);
+        assert_ne!(tree.subtree_hash(root_id), old_root);
+        assert_eq!(tree.subtree_hash(main_id), old_main);
+    }
+}
@@ -0,0 +1,305 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use maplit::hashmap;
+use memofs::Vfs;
+use rbx_dom_weak::{AmbiguousRbxValue, RbxValue, UnresolvedRbxValue};
+use rbx_reflection::try_resolve_value;
+use serde::Deserialize;
+
+use crate::{
+    snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot},
+    trace,
+};
+
+use super::{error::SnapshotError, middleware::SnapshotInstanceResult};
+
+/// Turns a `.anim.json` file, describing an animation as a list of keyframes,
+/// into a KeyframeSequence with Keyframe and Pose descendants.
+pub fn snapshot_anim_json(
+    context: &InstanceContext,
+    vfs: &Vfs,
+    path: &Path,
+    instance_name: &str,
+) -> SnapshotInstanceResult {
+    let _span = trace::span("snapshot", || {
+        format!("snapshot_anim_json {}", path.display())
+    });
+
+    let contents = vfs.read(path)?;
+    let animation: Animation = serde_json::from_slice(&contents)
+        .map_err(|source| SnapshotError::malformed_animation_json(source, path))?;
+
+    let mut properties = HashMap::new();
+    properties.insert(
+        "Loop".to_owned(),
+        RbxValue::Bool {
+            value: animation.looped,
+        },
+    );
+
+    if let Some(priority) = animation.priority {
+        let unresolved = UnresolvedRbxValue::Ambiguous(AmbiguousRbxValue::String(priority.clone()));
+        let value = try_resolve_value("KeyframeSequence", "Priority", &unresolved)
+            .map_err(|_| SnapshotError::invalid_animation_priority(priority, path))?;
+
+        properties.insert("Priority".to_owned(), value);
+    }
+
+    let children: Vec<_> = animation
+        .keyframes
+        .into_iter()
+        .map(Keyframe::into_snapshot)
+        .collect();
+
+    let snapshot = InstanceSnapshot::new()
+        .name(instance_name)
+        .class_name("KeyframeSequence")
+        .properties(properties)
+        .children(children)
+        .metadata(
+            InstanceMetadata::new()
+                .instigating_source(path)
+                .relevant_paths(vec![path.to_path_buf()])
+                .context(context),
+        );
+
+    Ok(Some(snapshot))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct Animation {
+    #[serde(default, rename = "loop")]
+    looped: bool,
+
+    /// The name of an AnimationPriority enum item, like `Action`.
+    #[serde(default)]
+    priority: Option<String>,
+
+    keyframes: Vec<Keyframe>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct Keyframe {
+    #[serde(default = "default_keyframe_name")]
+    name: String,
+
+    /// The time of this keyframe, in seconds from the start of the animation.
+    time: f32,
+
+    /// The poses of the root parts of the rig, keyed by part name.
+    #[serde(default)]
+    poses: BTreeMap<String, Pose>,
+}
+
+impl Keyframe {
+    fn into_snapshot(self) -> InstanceSnapshot {
+        let children: Vec<_> = self
+            .poses
+            .into_iter()
+            .map(|(name, pose)| pose.into_snapshot(name))
+            .collect();
+
+        InstanceSnapshot::new()
+            .name(self.name)
+            .class_name("Keyframe")
+            .properties(hashmap! {
+                "Time".to_owned() => RbxValue::Float32 { value: self.time },
+            })
+            .children(children)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct Pose {
+    /// The pose's transform relative to its joint, given as a position and
+    /// the nine components of a rotation matrix in row-major order.
+    #[serde(default = "identity_cframe")]
+    cframe: [f32; 12],
+
+    #[serde(default = "default_pose_weight")]
+    weight: f32,
+
+    /// Poses of the parts attached to this one, keyed by part name.
+    #[serde(default)]
+    children: BTreeMap<String, Pose>,
+}
+
+impl Pose {
+    fn into_snapshot(self, name: String) -> InstanceSnapshot {
+        let children: Vec<_> = self
+            .children
+            .into_iter()
+            .map(|(name, pose)| pose.into_snapshot(name))
+            .collect();
+
+        InstanceSnapshot::new()
+            .name(name)
+            .class_name("Pose")
+            .properties(hashmap! {
+                "CFrame".to_owned() => RbxValue::CFrame { value: self.cframe },
+                "Weight".to_owned() => RbxValue::Float32 { value: self.weight },
+            })
+            .children(children)
+    }
+}
+
+fn default_keyframe_name() -> String {
+    "Keyframe".to_owned()
+}
+
+fn default_pose_weight() -> f32 {
+    1.0
+}
+
+fn identity_cframe() -> [f32; 12] {
+    [
+        0.0, 0.0, 0.0, //
+        1.0, 0.0, 0.0, //
+        0.0, 1.0, 0.0, //
+        0.0, 0.0, 1.0,
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use memofs::{InMemoryFs, VfsSnapshot};
+
+    fn snapshot_from_source(source: &str) -> SnapshotInstanceResult {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/wave.anim.json", VfsSnapshot::file(source))
+            .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        snapshot_anim_json(
+            &InstanceContext::default(),
+            &vfs,
+            Path::new("/wave.anim.json"),
+            "wave",
+        )
+    }
+
+    #[test]
+    fn minimal_animation() {
+        let snapshot = snapshot_from_source(
+            r#"
+                {
+                    "loop": true,
+                    "priority": "Action",
+                    "keyframes": [
+                        {
+                            "time": 0,
+                            "poses": {
+                                "HumanoidRootPart": {
+                                    "children": {
+                                        "LowerTorso": {
+                                            "cframe": [0, 1, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1]
+                                        }
+                                    }
+                                }
+                            }
+                        },
+                        {
+                            "name": "End",
+                            "time": 0.5
+                        }
+                    ]
+                }
+            "#,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(snapshot.name, "wave");
+        assert_eq!(snapshot.class_name, "KeyframeSequence");
+        assert_eq!(
+            snapshot.properties.get("Loop"),
+            Some(&RbxValue::Bool { value: true })
+        );
+        assert_eq!(
+            snapshot.properties.get("Priority"),
+            Some(&RbxValue::Enum { value: 2 })
+        );
+
+        assert_eq!(snapshot.children.len(), 2);
+
+        let first = &snapshot.children[0];
+        assert_eq!(first.name, "Keyframe");
+        assert_eq!(first.class_name, "Keyframe");
+        assert_eq!(
+            first.properties.get("Time"),
+            Some(&RbxValue::Float32 { value: 0.0 })
+        );
+
+        let root_pose = &first.children[0];
+        assert_eq!(root_pose.name, "HumanoidRootPart");
+        assert_eq!(root_pose.class_name, "Pose");
+        assert_eq!(
+            root_pose.properties.get("CFrame"),
+            Some(&RbxValue::CFrame {
+                value: identity_cframe()
+            })
+        );
+
+        let torso_pose = &root_pose.children[0];
+        assert_eq!(torso_pose.name, "LowerTorso");
+        assert_eq!(
+            torso_pose.properties.get("CFrame"),
+            Some(&RbxValue::CFrame {
+                value: [0.0, 1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]
+            })
+        );
+        assert_eq!(
+            torso_pose.properties.get("Weight"),
+            Some(&RbxValue::Float32 { value: 1.0 })
+        );
+
+        let last = &snapshot.children[1];
+        assert_eq!(last.name, "End");
+        assert!(last.children.is_empty());
+    }
+
+    #[test]
+    fn invalid_priority() {
+        let err = snapshot_from_source(
+            r#"
+                {
+                    "priority": "Dancing",
+                    "keyframes": []
+                }
+            "#,
+        )
+        .unwrap_err();
+
+        assert!(err.to_string().contains("Dancing"));
+    }
+
+    #[test]
+    fn malformed_pose() {
+        let err = snapshot_from_source(
+            r#"
+                {
+                    "keyframes": [
+                        {
+                            "time": 0,
+                            "poses": {
+                                "Head": { "cframe": [1, 2, 3] }
+                            }
+                        }
+                    ]
+                }
+            "#,
+        )
+        .unwrap_err();
+
+        assert!(err.to_string().contains("/wave.anim.json"));
+    }
+}
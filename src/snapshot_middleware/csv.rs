@@ -1,11 +1,14 @@
-use std::{collections::BTreeMap, path::Path};
+use std::path::Path;
 
 use maplit::hashmap;
 use memofs::{IoResultExt, Vfs};
 use rbx_dom_weak::RbxValue;
-use serde::Serialize;
+use serde::{ser::SerializeMap, Serialize, Serializer};
 
-use crate::snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot};
+use crate::{
+    snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot},
+    trace,
+};
 
 use super::{
     error::SnapshotError, meta_file::AdjacentMetadata, middleware::SnapshotInstanceResult,
@@ -17,6 +20,8 @@ pub fn snapshot_csv(
     path: &Path,
     instance_name: &str,
 ) -> SnapshotInstanceResult {
+    let _span = trace::span("snapshot", || format!("snapshot_csv {}", path.display()));
+
     let meta_path = path.with_file_name(format!("{}.meta.json", instance_name));
     let contents = vfs.read(path)?;
 
@@ -39,7 +44,7 @@ pub fn snapshot_csv(
 
     if let Some(meta_contents) = vfs.read(&meta_path).with_not_found()? {
         let mut metadata = AdjacentMetadata::from_slice(&meta_contents, &meta_path)?;
-        metadata.apply_all(&mut snapshot);
+        metadata.apply_all(vfs, &mut snapshot)?;
     }
 
     Ok(Some(snapshot))
@@ -64,8 +69,31 @@ struct LocalizationEntry<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
     source: Option<&'a str>,
 
-    // We use a BTreeMap here to get deterministic output order.
-    values: BTreeMap<&'a str, &'a str>,
+    values: LocaleValues<'a>,
+}
+
+/// The translations in one row, in the same order as the columns they came
+/// from, so that the order in the file is the order in `Contents`.
+#[derive(Debug, Default)]
+struct LocaleValues<'a>(Vec<(&'a str, &'a str)>);
+
+impl Serialize for LocaleValues<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+
+        for (locale, value) in &self.0 {
+            map.serialize_entry(locale, value)?;
+        }
+
+        map.end()
+    }
+}
+
+/// Tells whether the first column of a table is for comments, which is the case
+/// if its header is empty or starts with `#`. Comments aren't part of the
+/// table's contents.
+fn is_comment_column(header: &str) -> bool {
+    header.is_empty() || header.starts_with('#')
 }
 
 /// Normally, we'd be able to let the csv crate construct our struct for us.
@@ -81,6 +109,10 @@ fn convert_localization_csv(contents: &[u8]) -> Result<String, csv::Error> {
     let mut reader = csv::Reader::from_reader(contents);
 
     let headers = reader.headers()?.clone();
+    let skip_columns = match headers.get(0) {
+        Some(header) if is_comment_column(header) => 1,
+        _ => 0,
+    };
 
     let mut records = Vec::new();
 
@@ -93,19 +125,22 @@ fn convert_localization_csv(contents: &[u8]) -> Result<String, csv::Error> {
     for record in &records {
         let mut entry = LocalizationEntry::default();
 
-        for (header, value) in headers.iter().zip(record.into_iter()) {
-            if header.is_empty() || value.is_empty() {
+        let columns = headers.iter().zip(record.into_iter()).skip(skip_columns);
+
+        for (header, value) in columns {
+            if header.is_empty() {
                 continue;
             }
 
+            // Empty translations are kept so that every row has the same
+            // locales, but the other columns are left out when they're empty.
             match header {
+                "Key" | "Source" | "Context" | "Example" if value.is_empty() => {}
                 "Key" => entry.key = Some(value),
                 "Source" => entry.source = Some(value),
                 "Context" => entry.context = Some(value),
                 "Example" => entry.example = Some(value),
-                _ => {
-                    entry.values.insert(header, value);
-                }
+                _ => entry.values.0.push((header, value)),
             }
         }
 
@@ -127,6 +162,9 @@ mod test {
     use super::*;
 
     use memofs::{InMemoryFs, VfsSnapshot};
+    use rbx_dom_weak::RbxInstanceProperties;
+
+    use crate::snapshot::{compute_patch_set, InstancePropertiesWithMeta, RojoTree};
 
     #[test]
     fn csv_from_vfs() {
@@ -186,4 +224,87 @@ Ack,Ack!,,An exclamation of despair,¡Ay!"#,
 
         insta::assert_yaml_snapshot!(instance_snapshot);
     }
+
+    fn contents(csv: &str) -> String {
+        convert_localization_csv(csv.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn columns_keep_their_order() {
+        assert_eq!(
+            contents("Key,Source,fr,es,de\nHello,Hello,Bonjour,Hola,Hallo"),
+            r#"[{"key":"Hello","source":"Hello","values":{"fr":"Bonjour","es":"Hola","de":"Hallo"}}]"#
+        );
+
+        assert_eq!(
+            contents("de,Source,es,Key\nHallo,Hello,Hola,Hello"),
+            r#"[{"key":"Hello","source":"Hello","values":{"de":"Hallo","es":"Hola"}}]"#
+        );
+    }
+
+    #[test]
+    fn empty_translations_are_kept() {
+        assert_eq!(
+            contents("Key,Source,Context,es,fr\nHello,Hello,,,Bonjour"),
+            r#"[{"key":"Hello","source":"Hello","values":{"es":"","fr":"Bonjour"}}]"#
+        );
+    }
+
+    #[test]
+    fn comment_column_is_skipped() {
+        let expected = r#"[{"key":"Hello","source":"Hello","values":{"es":"Hola"}}]"#;
+
+        assert_eq!(
+            contents("# Notes,Key,Source,es\nShown on the title screen,Hello,Hello,Hola"),
+            expected
+        );
+        assert_eq!(
+            contents(",Key,Source,es\nShown on the title screen,Hello,Hello,Hola"),
+            expected
+        );
+    }
+
+    #[test]
+    fn changed_cell_updates_contents() {
+        let snapshot_file = |contents: &str| {
+            let mut imfs = InMemoryFs::new();
+            imfs.load_snapshot("/foo.csv", VfsSnapshot::file(contents))
+                .unwrap();
+
+            let vfs = Vfs::new(imfs);
+
+            snapshot_csv(
+                &InstanceContext::default(),
+                &vfs,
+                Path::new("/foo.csv"),
+                "foo",
+            )
+            .unwrap()
+            .unwrap()
+        };
+
+        let before = snapshot_file("Key,Source,es\nHello,Hello,Hola\nBye,Bye,Adios");
+        let after = snapshot_file("Key,Source,es\nHello,Hello,Hola\nBye,Bye,Adiós");
+
+        let tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: before.name.clone().into_owned(),
+                class_name: before.class_name.clone().into_owned(),
+                properties: before.properties.clone(),
+            },
+            metadata: before.metadata.clone(),
+        });
+
+        let patch_set = compute_patch_set(&after, &tree, tree.get_root_id());
+
+        assert!(patch_set.added_instances.is_empty());
+        assert!(patch_set.removed_instances.is_empty());
+        assert_eq!(patch_set.updated_instances.len(), 1);
+        assert_eq!(
+            patch_set.updated_instances[0].changed_properties,
+            hashmap! {
+                "Contents".to_owned() => after.properties.get("Contents").cloned(),
+            }
+        );
+    }
 }
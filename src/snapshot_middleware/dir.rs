@@ -1,15 +1,62 @@
-use std::path::Path;
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
 
 use memofs::{DirEntry, IoResultExt, Vfs};
+use rayon::prelude::*;
+use rbx_dom_weak::RbxValue;
 
-use crate::snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot};
+use crate::{
+    reflection_cache::is_subclass_of,
+    snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot},
+    trace,
+};
 
 use super::{
-    error::SnapshotError, meta_file::DirectoryMetadata, middleware::SnapshotInstanceResult,
-    snapshot_from_vfs,
+    error::SnapshotError, lua::init_script_paths, meta_file::DirectoryMetadata,
+    middleware::SnapshotInstanceResult, snapshot_from_vfs,
 };
 
+/// The name of files that set default properties for the directory they're in
+/// and every directory below it.
+const DEFAULTS_META_NAME: &str = "defaults.meta.json";
+
 pub fn snapshot_dir(context: &InstanceContext, vfs: &Vfs, path: &Path) -> SnapshotInstanceResult {
+    let _span = trace::span("snapshot", || format!("snapshot_dir {}", path.display()));
+
+    let mut snapshot = snapshot_dir_without_meta(context, vfs, path)?;
+
+    let meta_path = path.join("init.meta.json");
+    let mut metadata = match vfs.read(&meta_path).with_not_found()? {
+        Some(meta_contents) => DirectoryMetadata::from_slice(&meta_contents, &meta_path)?,
+        None => DirectoryMetadata::empty(&meta_path),
+    };
+
+    for defaults_path in defaults_meta_paths(context, path) {
+        if let Some(contents) = vfs.read(&defaults_path).with_not_found()? {
+            metadata.add_defaults(DirectoryMetadata::from_slice(&contents, &defaults_path)?);
+        }
+    }
+
+    metadata.apply_all(vfs, &mut snapshot)?;
+
+    if let Some(problem) = handle_problem(&snapshot) {
+        log::warn!("{} {}", path.display(), problem);
+    }
+
+    Ok(Some(snapshot))
+}
+
+/// Snapshots a directory and its children as a Folder, without applying its
+/// init.meta.json or any defaults files. Init scripts use this, since the
+/// init.meta.json next to them applies to the script instead.
+pub fn snapshot_dir_without_meta(
+    context: &InstanceContext,
+    vfs: &Vfs,
+    path: &Path,
+) -> Result<InstanceSnapshot, SnapshotError> {
     let passes_filter_rules = |child: &DirEntry| {
         context
             .path_ignore_rules
@@ -17,20 +64,54 @@ pub fn snapshot_dir(context: &InstanceContext, vfs: &Vfs, path: &Path) -> Snapsh
             .all(|rule| rule.passes(child.path()))
     };
 
-    let mut snapshot_children = Vec::new();
-
+    let mut entries = Vec::new();
     for entry in vfs.read_dir(path)? {
         let entry = entry?;
 
-        if !passes_filter_rules(&entry) {
-            continue;
+        if passes_filter_rules(&entry) {
+            entries.push(entry);
         }
+    }
+
+    // Siblings don't depend on each other, so they're snapshotted on rayon's
+    // thread pool. Collecting keeps the results in the same order as the
+    // entries, and errors are returned in that order too, so the result is the
+    // same as snapshotting them one at a time.
+    let results: Vec<_> = entries
+        .par_iter()
+        .map(|entry| snapshot_from_vfs(context, vfs, entry.path()))
+        .collect();
+
+    let mut snapshot_children = Vec::new();
+    let mut child_paths: HashMap<String, Vec<PathBuf>> = HashMap::new();
+
+    for (entry, result) in entries.iter().zip(results) {
+        if let Some(child_snapshot) = result? {
+            child_paths
+                .entry(child_snapshot.name.to_string())
+                .or_default()
+                .push(entry.path().to_path_buf());
 
-        if let Some(child_snapshot) = snapshot_from_vfs(context, vfs, entry.path())? {
             snapshot_children.push(child_snapshot);
         }
     }
 
+    // Files like `Foo.lua` and `Foo.model.json` both turn into an instance
+    // named Foo. Keeping both would make it unclear which one a change in the
+    // tree came from, so we report them instead.
+    let mut collisions: Vec<_> = child_paths
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .collect();
+
+    if !collisions.is_empty() {
+        collisions.sort();
+        let (name, mut paths) = collisions.swap_remove(0);
+        paths.sort();
+
+        return Err(SnapshotError::name_collision(name, paths));
+    }
+
     let instance_name = path
         .file_name()
         .expect("Could not extract file name")
@@ -38,20 +119,14 @@ pub fn snapshot_dir(context: &InstanceContext, vfs: &Vfs, path: &Path) -> Snapsh
         .ok_or_else(|| SnapshotError::file_name_bad_unicode(path))?
         .to_string();
 
-    let meta_path = path.join("init.meta.json");
+    let mut relevant_paths = vec![path.to_path_buf(), path.join("init.meta.json")];
 
-    let relevant_paths = vec![
-        path.to_path_buf(),
-        meta_path.clone(),
-        // TODO: We shouldn't need to know about Lua existing in this
-        // middleware. Should we figure out a way for that function to add
-        // relevant paths to this middleware?
-        path.join("init.lua"),
-        path.join("init.server.lua"),
-        path.join("init.client.lua"),
-    ];
-
-    let mut snapshot = InstanceSnapshot::new()
+    // TODO: We shouldn't need to know about Lua existing in this middleware.
+    // Should we figure out a way for that function to add relevant paths to
+    // this middleware?
+    relevant_paths.extend(init_script_paths(path));
+
+    Ok(InstanceSnapshot::new()
         .name(instance_name)
         .class_name("Folder")
         .children(snapshot_children)
@@ -60,14 +135,97 @@ pub fn snapshot_dir(context: &InstanceContext, vfs: &Vfs, path: &Path) -> Snapsh
                 .instigating_source(path)
                 .relevant_paths(relevant_paths)
                 .context(context),
-        );
+        ))
+}
+
+/// Something wrong with the Handle of a Tool or Accessory made from a
+/// directory. Roblox needs the Handle to be a part for the tool to be held or
+/// the accessory to be worn.
+#[derive(Debug, PartialEq, Eq)]
+enum HandleProblem {
+    Missing { class_name: String },
+    NotAPart { class_name: String },
+}
 
-    if let Some(meta_contents) = vfs.read(&meta_path).with_not_found()? {
-        let mut metadata = DirectoryMetadata::from_slice(&meta_contents, &meta_path)?;
-        metadata.apply_all(&mut snapshot);
+impl fmt::Display for HandleProblem {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HandleProblem::Missing { class_name } => write!(
+                formatter,
+                "is a {} without a child named Handle",
+                class_name
+            ),
+            HandleProblem::NotAPart { class_name } => write!(
+                formatter,
+                "has a Handle that is a {}, but it needs to be a BasePart like Part or MeshPart",
+                class_name
+            ),
+        }
     }
+}
 
-    Ok(Some(snapshot))
+/// Checks the Handle of a Tool or Accessory. Tools with RequiresHandle set to
+/// false don't need one.
+fn handle_problem(snapshot: &InstanceSnapshot) -> Option<HandleProblem> {
+    match snapshot.class_name.as_ref() {
+        "Tool" => {
+            if let Some(RbxValue::Bool { value: false }) = snapshot.properties.get("RequiresHandle")
+            {
+                return None;
+            }
+        }
+        "Accessory" => {}
+        _ => return None,
+    }
+
+    let handle = snapshot
+        .children
+        .iter()
+        .find(|child| child.name == "Handle");
+
+    match handle {
+        None => Some(HandleProblem::Missing {
+            class_name: snapshot.class_name.to_string(),
+        }),
+        Some(handle) if !is_subclass_of(&handle.class_name, "BasePart") => {
+            Some(HandleProblem::NotAPart {
+                class_name: handle.class_name.to_string(),
+            })
+        }
+        Some(_) => None,
+    }
+}
+
+/// Lists the `defaults.meta.json` files that can apply to a directory, from
+/// the outermost inwards.
+///
+/// Defaults are looked for in the directory itself and each of its parents up
+/// to the folder containing the innermost project file. When there's no
+/// project, only the directory itself is checked.
+///
+/// Properties are merged so that defaults closer to the directory win over
+/// ones further away, and the directory's own init.meta.json wins over all
+/// defaults.
+fn defaults_meta_paths(context: &InstanceContext, path: &Path) -> Vec<PathBuf> {
+    let project_folder = context
+        .project_stack
+        .last()
+        .and_then(|project_path| project_path.parent());
+
+    let mut paths = vec![path.join(DEFAULTS_META_NAME)];
+
+    if let Some(project_folder) = project_folder {
+        for ancestor in path.ancestors().skip(1) {
+            if !ancestor.starts_with(project_folder) {
+                break;
+            }
+
+            paths.push(ancestor.join(DEFAULTS_META_NAME));
+        }
+    }
+
+    paths.reverse();
+    paths
 }
 
 #[cfg(test)]
@@ -77,6 +235,8 @@ mod test {
     use maplit::hashmap;
     use memofs::{InMemoryFs, VfsSnapshot};
 
+    use crate::log_capture;
+
     #[test]
     fn empty_folder() {
         let mut imfs = InMemoryFs::new();
@@ -113,4 +273,296 @@ mod test {
 
         insta::assert_yaml_snapshot!(instance_snapshot);
     }
+
+    fn gui_vfs(properties: &str) -> Vfs {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/Hud",
+            VfsSnapshot::dir(hashmap! {
+                "init.meta.json" => VfsSnapshot::file(format!(r#"
+                    {{
+                        "className": "ScreenGui",
+                        "properties": {}
+                    }}
+                "#, properties)),
+                "Panel" => VfsSnapshot::dir(hashmap! {
+                    "init.meta.json" => VfsSnapshot::file(r#"
+                        {
+                            "className": "Frame"
+                        }
+                    "#),
+                }),
+            }),
+        )
+        .unwrap();
+
+        Vfs::new(imfs)
+    }
+
+    #[test]
+    fn screen_gui_with_properties() {
+        let vfs = gui_vfs(
+            r#"{
+                "ZIndexBehavior": "Sibling",
+                "ResetOnSpawn": false
+            }"#,
+        );
+
+        let instance_snapshot = snapshot_dir(&InstanceContext::default(), &vfs, Path::new("/Hud"))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(instance_snapshot.class_name, "ScreenGui");
+        assert_eq!(
+            instance_snapshot.properties.get("ZIndexBehavior"),
+            Some(&RbxValue::Enum { value: 1 })
+        );
+        assert_eq!(
+            instance_snapshot.properties.get("ResetOnSpawn"),
+            Some(&RbxValue::Bool { value: false })
+        );
+
+        assert_eq!(instance_snapshot.children.len(), 1);
+        assert_eq!(instance_snapshot.children[0].name, "Panel");
+        assert_eq!(instance_snapshot.children[0].class_name, "Frame");
+    }
+
+    #[test]
+    fn screen_gui_with_invalid_property() {
+        let vfs = gui_vfs(r#"{ "ZIndexBehavior": "Sideways" }"#);
+
+        let err = snapshot_dir(&InstanceContext::default(), &vfs, Path::new("/Hud")).unwrap_err();
+
+        assert!(err.to_string().contains("ScreenGui.ZIndexBehavior"));
+    }
+
+    #[test]
+    fn defaults_are_merged_with_init_meta() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "defaults.meta.json" => VfsSnapshot::file(r#"
+                    {
+                        "properties": {
+                            "ResetOnSpawn": false,
+                            "ZIndexBehavior": "Sibling"
+                        }
+                    }
+                "#),
+                "src" => VfsSnapshot::dir(hashmap! {
+                    "Menu" => VfsSnapshot::dir(hashmap! {
+                        "init.meta.json" => VfsSnapshot::file(r#"
+                            {
+                                "className": "ScreenGui",
+                                "properties": {
+                                    "ResetOnSpawn": true
+                                }
+                            }
+                        "#),
+                    }),
+                }),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let mut context = InstanceContext::default();
+        context.push_project(PathBuf::from("/foo/default.project.json"));
+
+        let instance_snapshot = snapshot_dir(&context, &vfs, Path::new("/foo/src"))
+            .unwrap()
+            .unwrap();
+
+        // Folders don't have either property, so the defaults are skipped.
+        assert_eq!(instance_snapshot.class_name, "Folder");
+        assert!(instance_snapshot.properties.is_empty());
+
+        let menu = &instance_snapshot.children[0];
+        assert_eq!(menu.class_name, "ScreenGui");
+        assert_eq!(
+            menu.properties.get("ResetOnSpawn"),
+            Some(&RbxValue::Bool { value: true })
+        );
+        assert_eq!(
+            menu.properties.get("ZIndexBehavior"),
+            Some(&RbxValue::Enum { value: 1 })
+        );
+        assert!(menu
+            .metadata
+            .relevant_paths
+            .contains(&PathBuf::from("/foo/defaults.meta.json")));
+    }
+
+    fn tool_vfs(children: HashMap<&'static str, VfsSnapshot>) -> Vfs {
+        let mut files = hashmap! {
+            "init.meta.json" => VfsSnapshot::file(r#"{ "className": "Tool" }"#),
+        };
+        files.extend(children);
+
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/Sword", VfsSnapshot::dir(files))
+            .unwrap();
+
+        Vfs::new(imfs)
+    }
+
+    /// Snapshots the Tool at /Sword, returning its snapshot and the warnings
+    /// that were logged while doing so.
+    fn snapshot_tool(vfs: &Vfs) -> (InstanceSnapshot, Vec<String>) {
+        let (snapshot, logs) = log_capture::capture(|| {
+            snapshot_dir(&InstanceContext::default(), vfs, Path::new("/Sword"))
+                .unwrap()
+                .unwrap()
+        });
+
+        let warnings = logs
+            .into_iter()
+            .filter(|(level, _)| *level == log::Level::Warn)
+            .map(|(_, message)| message)
+            .collect();
+
+        (snapshot, warnings)
+    }
+
+    #[test]
+    fn tool_with_handle() {
+        let vfs = tool_vfs(hashmap! {
+            "Handle.model.json" => VfsSnapshot::file(r#"{ "ClassName": "Part" }"#),
+        });
+
+        let (snapshot, warnings) = snapshot_tool(&vfs);
+
+        assert_eq!(snapshot.class_name, "Tool");
+        assert_eq!(snapshot.children.len(), 1);
+        assert_eq!(snapshot.children[0].name, "Handle");
+        assert_eq!(snapshot.children[0].class_name, "Part");
+        assert_eq!(handle_problem(&snapshot), None);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn tool_without_handle() {
+        let vfs = tool_vfs(hashmap! {
+            "Blade.model.json" => VfsSnapshot::file(r#"{ "ClassName": "Part" }"#),
+        });
+
+        let (snapshot, warnings) = snapshot_tool(&vfs);
+
+        assert_eq!(
+            handle_problem(&snapshot),
+            Some(HandleProblem::Missing {
+                class_name: "Tool".to_owned()
+            })
+        );
+        assert_eq!(
+            warnings,
+            vec!["/Sword is a Tool without a child named Handle".to_owned()]
+        );
+
+        // A Handle that can't be held is reported too.
+        let vfs = tool_vfs(hashmap! {
+            "Handle.model.json" => VfsSnapshot::file(r#"{ "ClassName": "Folder" }"#),
+        });
+
+        let (snapshot, warnings) = snapshot_tool(&vfs);
+
+        assert_eq!(
+            handle_problem(&snapshot),
+            Some(HandleProblem::NotAPart {
+                class_name: "Folder".to_owned()
+            })
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                "/Sword has a Handle that is a Folder, but it needs to be a BasePart like Part \
+                 or MeshPart"
+                    .to_owned()
+            ]
+        );
+    }
+
+    #[test]
+    fn colliding_children() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "Foo.lua" => VfsSnapshot::file("return {}"),
+                "Foo.model.json" => VfsSnapshot::file(r#"{ "ClassName": "Folder" }"#),
+                "Bar.lua" => VfsSnapshot::file("return {}"),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        match snapshot_dir(&InstanceContext::default(), &vfs, Path::new("/foo")) {
+            Err(SnapshotError::NameCollision { name, paths }) => {
+                assert_eq!(name, "Foo");
+                assert_eq!(
+                    paths,
+                    vec![
+                        PathBuf::from("/foo/Foo.lua"),
+                        PathBuf::from("/foo/Foo.model.json")
+                    ]
+                );
+            }
+            other => panic!("expected NameCollision, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parallel_matches_serial() {
+        // 50 folders of 100 files each, with a mix of middleware.
+        let folders = (0..50).map(|folder| {
+            let files = (0..100).map(|file| {
+                let (name, contents) = match file % 4 {
+                    0 => (format!("Module{}.lua", file), format!("return {}", file)),
+                    1 => (
+                        format!("Server{}.server.lua", file),
+                        format!("print({})", file),
+                    ),
+                    2 => (format!("Text{}.txt", file), format!("text {}", file)),
+                    _ => (format!("Data{}.json", file), format!("[{}]", file)),
+                };
+
+                (name, VfsSnapshot::file(contents))
+            });
+
+            (format!("Folder{}", folder), VfsSnapshot::dir(files))
+        });
+
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/root", VfsSnapshot::dir(folders))
+            .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        vfs.set_watch_enabled(false);
+
+        let snapshot = || {
+            snapshot_dir(&InstanceContext::default(), &vfs, Path::new("/root"))
+                .unwrap()
+                .unwrap()
+        };
+
+        // A pool with one thread runs every sibling one after another, in
+        // order.
+        let serial = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap()
+            .install(snapshot);
+
+        let parallel = snapshot();
+
+        assert_eq!(parallel.children.len(), 50);
+        assert!(parallel
+            .children
+            .iter()
+            .all(|folder| folder.children.len() == 100));
+        assert_eq!(parallel, serial);
+    }
 }
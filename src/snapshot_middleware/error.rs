@@ -1,8 +1,9 @@
-use std::{io, path::PathBuf};
+use std::{io, path::PathBuf, time::Duration};
 
+use rbx_dom_weak::RbxValueType;
 use thiserror::Error;
 
-use crate::project::ProjectError;
+use crate::{project::ProjectError, resolution::NumberSequenceError};
 
 #[derive(Debug, Error)]
 pub enum SnapshotError {
@@ -15,6 +16,18 @@ pub enum SnapshotError {
         path: PathBuf,
     },
 
+    #[error("project node {name} has a $path that does not exist: {}", .path.display())]
+    ProjectPathMissing { name: String, path: PathBuf },
+
+    #[error(
+        "project node {name} has a $path that does not exist ({}), and has no $className to create an empty instance from",
+        .path.display()
+    )]
+    ProjectPathMissingNoClassName { name: String, path: PathBuf },
+
+    #[error("project files include each other in a cycle: {}", display_project_chain(.chain))]
+    ProjectCycle { chain: Vec<PathBuf> },
+
     #[error("malformed project file at path {}", .path.display())]
     MalformedProject { source: ProjectError, path: PathBuf },
 
@@ -24,21 +37,110 @@ pub enum SnapshotError {
         path: PathBuf,
     },
 
+    #[error(
+        "{} nests instances more than {max_depth} levels deep, which is more than the project allows",
+        .path.display()
+    )]
+    ModelTooDeep { max_depth: usize, path: PathBuf },
+
+    #[error("malformed .anim.json file at path {}", .path.display())]
+    MalformedAnimationJson {
+        source: serde_json::Error,
+        path: PathBuf,
+    },
+
+    #[error("animation at path {} has an invalid priority {priority}, expected an AnimationPriority like Action or Movement", .path.display())]
+    InvalidAnimationPriority { priority: String, path: PathBuf },
+
     #[error("malformed .meta.json file at path {}", .path.display())]
     MalformedMetaJson {
         source: serde_json::Error,
         path: PathBuf,
     },
 
+    #[error(
+        "{} sets {field}, which only works in the meta file of a .txt file",
+        .path.display()
+    )]
+    TxtOnlyMetaField { field: &'static str, path: PathBuf },
+
     #[error("malformed JSON at path {}", .path.display())]
     MalformedJson {
         source: serde_json::Error,
         path: PathBuf,
     },
 
+    #[error("malformed YAML at path {}", .path.display())]
+    MalformedYaml {
+        source: serde_yaml::Error,
+        path: PathBuf,
+    },
+
+    #[error("YAML at path {} contains {description}, which can't be turned into Lua", .path.display())]
+    UnsupportedYaml { description: String, path: PathBuf },
+
+    #[error(
+        "{} sets the {property} property, which can't be set directly because it comes from where the instance is in the file structure",
+        .path.display()
+    )]
+    StructuralProperty { property: String, path: PathBuf },
+
+    #[error("{} has propertiesFrom set to {}, which does not exist", .meta_path.display(), .path.display())]
+    PropertiesFromMissing { path: PathBuf, meta_path: PathBuf },
+
+    #[error("{class_name}.{property} in {} has an invalid value", .path.display())]
+    InvalidMetaProperty {
+        source: rbx_reflection::ValueResolveError,
+        class_name: String,
+        property: String,
+        path: PathBuf,
+    },
+
+    #[error("{class_name}.{property} in {} is {value}, but {value_type:?} values must be {}", .path.display(), display_number_range(.value_type))]
+    NumberOutOfRange {
+        class_name: String,
+        property: String,
+        value: f64,
+        value_type: RbxValueType,
+        path: PathBuf,
+    },
+
+    #[error("{class_name}.{property} in {} has an invalid NumberSequence", .path.display())]
+    InvalidNumberSequence {
+        source: NumberSequenceError,
+        class_name: String,
+        property: String,
+        path: PathBuf,
+    },
+
     #[error("malformed CSV localization data at path {}", .path.display())]
     MalformedLocalizationCsv { source: csv::Error, path: PathBuf },
 
+    #[error(
+        "folder {} has more than one init script of the same kind, so Rojo can't tell which to use: {}",
+        .folder.display(),
+        display_paths(.paths)
+    )]
+    AmbiguousInitScript {
+        folder: PathBuf,
+        paths: Vec<PathBuf>,
+    },
+
+    #[error(
+        "more than one file would turn into an instance named {name}: {}",
+        display_paths(.paths)
+    )]
+    NameCollision { name: String, paths: Vec<PathBuf> },
+
+    #[error("Rojo doesn't know how to turn the file at {} into an instance", .path.display())]
+    UnknownFileType { path: PathBuf },
+
+    #[error("timed out after {}s while turning {} into instances", .timeout.as_secs_f32(), .path.display())]
+    MiddlewareTimeout { path: PathBuf, timeout: Duration },
+
+    #[error("Rojo crashed while turning {} into instances", .path.display())]
+    MiddlewarePanicked { path: PathBuf },
+
     #[error(transparent)]
     Io {
         #[from]
@@ -61,6 +163,56 @@ impl SnapshotError {
         }
     }
 
+    pub(crate) fn project_path_missing(name: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        Self::ProjectPathMissing {
+            name: name.into(),
+            path: path.into(),
+        }
+    }
+
+    pub(crate) fn project_path_missing_no_class_name(
+        name: impl Into<String>,
+        path: impl Into<PathBuf>,
+    ) -> Self {
+        Self::ProjectPathMissingNoClassName {
+            name: name.into(),
+            path: path.into(),
+        }
+    }
+
+    pub(crate) fn project_cycle(chain: Vec<PathBuf>) -> Self {
+        Self::ProjectCycle { chain }
+    }
+
+    pub(crate) fn ambiguous_init_script(folder: impl Into<PathBuf>, paths: Vec<PathBuf>) -> Self {
+        Self::AmbiguousInitScript {
+            folder: folder.into(),
+            paths,
+        }
+    }
+
+    pub(crate) fn name_collision(name: impl Into<String>, paths: Vec<PathBuf>) -> Self {
+        Self::NameCollision {
+            name: name.into(),
+            paths,
+        }
+    }
+
+    pub(crate) fn unknown_file_type(path: impl Into<PathBuf>) -> Self {
+        Self::UnknownFileType { path: path.into() }
+    }
+
+    pub(crate) fn middleware_timeout(path: impl Into<PathBuf>, timeout: Duration) -> Self {
+        Self::MiddlewareTimeout {
+            path: path.into(),
+            timeout,
+        }
+    }
+
+    pub(crate) fn middleware_panicked(path: impl Into<PathBuf>) -> Self {
+        Self::MiddlewarePanicked { path: path.into() }
+    }
+
     pub(crate) fn malformed_project(source: ProjectError, path: impl Into<PathBuf>) -> Self {
         Self::MalformedProject {
             source,
@@ -78,6 +230,33 @@ impl SnapshotError {
         }
     }
 
+    pub(crate) fn model_too_deep(max_depth: usize, path: impl Into<PathBuf>) -> Self {
+        Self::ModelTooDeep {
+            max_depth,
+            path: path.into(),
+        }
+    }
+
+    pub(crate) fn malformed_animation_json(
+        source: serde_json::Error,
+        path: impl Into<PathBuf>,
+    ) -> Self {
+        Self::MalformedAnimationJson {
+            source,
+            path: path.into(),
+        }
+    }
+
+    pub(crate) fn invalid_animation_priority(
+        priority: impl Into<String>,
+        path: impl Into<PathBuf>,
+    ) -> Self {
+        Self::InvalidAnimationPriority {
+            priority: priority.into(),
+            path: path.into(),
+        }
+    }
+
     pub(crate) fn malformed_meta_json(source: serde_json::Error, path: impl Into<PathBuf>) -> Self {
         Self::MalformedMetaJson {
             source,
@@ -85,6 +264,67 @@ impl SnapshotError {
         }
     }
 
+    pub(crate) fn txt_only_meta_field(field: &'static str, path: impl Into<PathBuf>) -> Self {
+        Self::TxtOnlyMetaField {
+            field,
+            path: path.into(),
+        }
+    }
+
+    pub(crate) fn properties_from_missing(
+        path: impl Into<PathBuf>,
+        meta_path: impl Into<PathBuf>,
+    ) -> Self {
+        Self::PropertiesFromMissing {
+            path: path.into(),
+            meta_path: meta_path.into(),
+        }
+    }
+
+    pub(crate) fn invalid_meta_property(
+        source: rbx_reflection::ValueResolveError,
+        class_name: impl Into<String>,
+        property: impl Into<String>,
+        path: impl Into<PathBuf>,
+    ) -> Self {
+        Self::InvalidMetaProperty {
+            source,
+            class_name: class_name.into(),
+            property: property.into(),
+            path: path.into(),
+        }
+    }
+
+    pub(crate) fn number_out_of_range(
+        class_name: impl Into<String>,
+        property: impl Into<String>,
+        value: f64,
+        value_type: RbxValueType,
+        path: impl Into<PathBuf>,
+    ) -> Self {
+        Self::NumberOutOfRange {
+            class_name: class_name.into(),
+            property: property.into(),
+            value,
+            value_type,
+            path: path.into(),
+        }
+    }
+
+    pub(crate) fn invalid_number_sequence(
+        source: NumberSequenceError,
+        class_name: impl Into<String>,
+        property: impl Into<String>,
+        path: impl Into<PathBuf>,
+    ) -> Self {
+        Self::InvalidNumberSequence {
+            source,
+            class_name: class_name.into(),
+            property: property.into(),
+            path: path.into(),
+        }
+    }
+
     pub(crate) fn malformed_json(source: serde_json::Error, path: impl Into<PathBuf>) -> Self {
         Self::MalformedJson {
             source,
@@ -92,6 +332,33 @@ impl SnapshotError {
         }
     }
 
+    pub(crate) fn malformed_yaml(source: serde_yaml::Error, path: impl Into<PathBuf>) -> Self {
+        Self::MalformedYaml {
+            source,
+            path: path.into(),
+        }
+    }
+
+    pub(crate) fn unsupported_yaml(
+        description: impl Into<String>,
+        path: impl Into<PathBuf>,
+    ) -> Self {
+        Self::UnsupportedYaml {
+            description: description.into(),
+            path: path.into(),
+        }
+    }
+
+    pub(crate) fn structural_property(
+        property: impl Into<String>,
+        path: impl Into<PathBuf>,
+    ) -> Self {
+        Self::StructuralProperty {
+            property: property.into(),
+            path: path.into(),
+        }
+    }
+
     pub(crate) fn malformed_l10n_csv(source: csv::Error, path: impl Into<PathBuf>) -> Self {
         Self::MalformedLocalizationCsv {
             source,
@@ -99,3 +366,28 @@ impl SnapshotError {
         }
     }
 }
+
+fn display_project_chain(chain: &[PathBuf]) -> String {
+    chain
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+fn display_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn display_number_range(value_type: &RbxValueType) -> String {
+    match value_type {
+        RbxValueType::Int32 => format!("between {} and {}", i32::MIN, i32::MAX),
+        RbxValueType::Int64 => format!("between {} and {}", i64::MIN, i64::MAX),
+        RbxValueType::Float32 => format!("between {:e} and {:e}", f32::MIN, f32::MAX),
+        _ => "in range".to_owned(),
+    }
+}
@@ -7,6 +7,7 @@ use rbx_dom_weak::RbxValue;
 use crate::{
     lua_ast::{Expression, Statement},
     snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot},
+    trace,
 };
 
 use super::{
@@ -19,12 +20,29 @@ pub fn snapshot_json(
     path: &Path,
     instance_name: &str,
 ) -> SnapshotInstanceResult {
+    let _span = trace::span("snapshot", || format!("snapshot_json {}", path.display()));
+
     let contents = vfs.read(path)?;
 
     let value: serde_json::Value = serde_json::from_slice(&contents)
         .map_err(|err| SnapshotError::malformed_json(err, path))?;
 
-    let as_lua = json_to_lua(value).to_string();
+    snapshot_data_module(context, vfs, path, instance_name, value)
+}
+
+/// Turns a parsed data file into a ModuleScript that returns its contents.
+/// Other data formats convert into JSON values so that they share this.
+pub(super) fn snapshot_data_module(
+    context: &InstanceContext,
+    vfs: &Vfs,
+    path: &Path,
+    instance_name: &str,
+    value: serde_json::Value,
+) -> SnapshotInstanceResult {
+    let (as_lua, chunks) = match context.max_generated_source_size {
+        Some(max_size) => json_to_chunked_lua(value, max_size),
+        None => (json_to_lua(value).to_string(), Vec::new()),
+    };
 
     let properties = hashmap! {
         "Source".to_owned() => RbxValue::String {
@@ -38,6 +56,7 @@ pub fn snapshot_json(
         .name(instance_name)
         .class_name("ModuleScript")
         .properties(properties)
+        .children(chunks)
         .metadata(
             InstanceMetadata::new()
                 .instigating_source(path)
@@ -47,7 +66,7 @@ pub fn snapshot_json(
 
     if let Some(meta_contents) = vfs.read(&meta_path).with_not_found()? {
         let mut metadata = AdjacentMetadata::from_slice(&meta_contents, &meta_path)?;
-        metadata.apply_all(&mut snapshot);
+        metadata.apply_all(vfs, &mut snapshot)?;
     }
 
     Ok(Some(snapshot))
@@ -57,6 +76,115 @@ fn json_to_lua(value: serde_json::Value) -> Statement {
     Statement::Return(json_to_lua_value(value))
 }
 
+/// Turns a JSON value into Lua source like `json_to_lua`. If that source would
+/// be longer than `max_size` bytes and the value is an object or array, its
+/// entries are instead split into chunk ModuleScripts of roughly `max_size`
+/// bytes each, and the returned source requires and merges them back together.
+fn json_to_chunked_lua(
+    value: serde_json::Value,
+    max_size: usize,
+) -> (String, Vec<InstanceSnapshot>) {
+    let statement = json_to_lua(value);
+    let source = statement.to_string();
+
+    if source.len() <= max_size {
+        return (source, Vec::new());
+    }
+
+    let Statement::Return(expression) = statement;
+
+    let (chunks, merge_source): (Vec<Expression>, _) = match expression {
+        Expression::Table(table) => {
+            let chunks = chunk_by_size(table.entries, max_size, |(key, value)| {
+                key.to_string().len() + value.to_string().len()
+            });
+
+            (
+                chunks.into_iter().map(Expression::table).collect(),
+                MERGE_TABLE_CHUNKS,
+            )
+        }
+        Expression::Array(values) => {
+            let chunks = chunk_by_size(values, max_size, |value| value.to_string().len());
+
+            (
+                chunks.into_iter().map(Expression::Array).collect(),
+                MERGE_ARRAY_CHUNKS,
+            )
+        }
+        _ => return (source, Vec::new()),
+    };
+
+    if chunks.len() < 2 {
+        return (source, Vec::new());
+    }
+
+    let aggregator = merge_source.replace("{count}", &chunks.len().to_string());
+
+    let children = chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            InstanceSnapshot::new()
+                .name(format!("Chunk{}", index + 1))
+                .class_name("ModuleScript")
+                .properties(hashmap! {
+                    "Source".to_owned() => RbxValue::String {
+                        value: Statement::Return(chunk).to_string(),
+                    },
+                })
+        })
+        .collect();
+
+    (aggregator, children)
+}
+
+static MERGE_TABLE_CHUNKS: &str = r#"-- This module was split into chunks because it was too large.
+local result = {}
+for index = 1, {count} do
+	for key, value in pairs(require(script["Chunk" .. index])) do
+		result[key] = value
+	end
+end
+return result"#;
+
+static MERGE_ARRAY_CHUNKS: &str = r#"-- This module was split into chunks because it was too large.
+local result = {}
+for index = 1, {count} do
+	for _, value in ipairs(require(script["Chunk" .. index])) do
+		table.insert(result, value)
+	end
+end
+return result"#;
+
+/// Groups items in order so that the total size of each group, as measured by
+/// `size_of`, is at most `max_size`. Items larger than `max_size` get a group
+/// of their own.
+fn chunk_by_size<T>(items: Vec<T>, max_size: usize, size_of: impl Fn(&T) -> usize) -> Vec<Vec<T>> {
+    let mut chunks = Vec::new();
+    let mut current = Vec::new();
+    let mut current_size = 0;
+
+    for item in items {
+        let size = size_of(&item);
+
+        if !current.is_empty() && current_size + size > max_size {
+            chunks.push(current);
+            current = Vec::new();
+            current_size = 0;
+        }
+
+        current_size += size;
+        current.push(item);
+    }
+
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
+}
+
 fn json_to_lua_value(value: serde_json::Value) -> Expression {
     use serde_json::Value;
 
@@ -118,4 +246,62 @@ mod test {
 
         insta::assert_yaml_snapshot!(instance_snapshot);
     }
+
+    #[test]
+    fn oversized_module_is_split() {
+        let entries: serde_json::Map<_, _> = (0..20)
+            .map(|index| {
+                (
+                    format!("key{}", index),
+                    serde_json::Value::String("x".repeat(40)),
+                )
+            })
+            .collect();
+
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/big.json",
+            VfsSnapshot::file(serde_json::to_string(&entries).unwrap()),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let context = InstanceContext {
+            max_generated_source_size: Some(200),
+            ..Default::default()
+        };
+
+        let instance_snapshot = snapshot_json(&context, &vfs, Path::new("/big.json"), "big")
+            .unwrap()
+            .unwrap();
+
+        let source = |snapshot: &InstanceSnapshot| match snapshot.properties.get("Source") {
+            Some(RbxValue::String { value }) => value.clone(),
+            other => panic!("expected a Source string, got {:?}", other),
+        };
+
+        let chunk_count = instance_snapshot.children.len();
+        assert!(chunk_count > 1);
+
+        let aggregator = source(&instance_snapshot);
+        assert!(aggregator.contains(&format!("for index = 1, {} do", chunk_count)));
+        assert!(aggregator.contains(r#"require(script["Chunk" .. index])"#));
+        assert!(aggregator.contains("result[key] = value"));
+        assert!(aggregator.ends_with("return result"));
+
+        let mut keys_seen = 0;
+        for (index, chunk) in instance_snapshot.children.iter().enumerate() {
+            assert_eq!(chunk.name, format!("Chunk{}", index + 1));
+            assert_eq!(chunk.class_name, "ModuleScript");
+
+            let chunk_source = source(chunk);
+            assert!(chunk_source.starts_with("return {"));
+            assert!(chunk_source.len() < 400);
+
+            keys_seen += chunk_source.matches(" = ").count();
+        }
+
+        assert_eq!(keys_seen, 20);
+    }
 }
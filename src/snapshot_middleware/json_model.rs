@@ -1,13 +1,20 @@
 use std::{borrow::Cow, collections::HashMap, path::Path};
 
 use memofs::Vfs;
-use rbx_dom_weak::UnresolvedRbxValue;
-use rbx_reflection::try_resolve_value;
-use serde::Deserialize;
+use rbx_dom_weak::{AmbiguousRbxValue, RbxValueType, UnresolvedRbxValue};
+use rbx_reflection::RbxPropertyTypeDescriptor;
+use serde::{de, Deserialize, Deserializer};
 
-use crate::snapshot::{InstanceContext, InstanceSnapshot};
+use crate::{
+    json, reflection_cache,
+    resolution::{resolve_number_sequence, resolve_value, Keypoint},
+    snapshot::{InstanceContext, InstanceSnapshot},
+    trace,
+};
 
-use super::{error::SnapshotError, middleware::SnapshotInstanceResult};
+use super::{
+    error::SnapshotError, middleware::SnapshotInstanceResult, util::reject_structural_property,
+};
 
 pub fn snapshot_json_model(
     context: &InstanceContext,
@@ -15,8 +22,12 @@ pub fn snapshot_json_model(
     path: &Path,
     instance_name: &str,
 ) -> SnapshotInstanceResult {
+    let _span = trace::span("snapshot", || {
+        format!("snapshot_json_model {}", path.display())
+    });
+
     let contents = vfs.read(path)?;
-    let instance: JsonModel = serde_json::from_slice(&contents)
+    let instance: JsonModel = json::from_slice_for_path(&contents, path)
         .map_err(|source| SnapshotError::malformed_model_json(source, path))?;
 
     if let Some(json_name) = &instance.name {
@@ -37,7 +48,10 @@ pub fn snapshot_json_model(
         }
     }
 
-    let mut snapshot = instance.core.into_snapshot(instance_name.to_owned());
+    let mut snapshot =
+        instance
+            .core
+            .into_snapshot(instance_name.to_owned(), path, 0, context.max_model_depth)?;
 
     snapshot.metadata = snapshot
         .metadata
@@ -75,36 +89,205 @@ struct JsonModelCore {
     children: Vec<JsonModelInstance>,
 
     #[serde(default = "HashMap::new", skip_serializing_if = "HashMap::is_empty")]
-    properties: HashMap<String, UnresolvedRbxValue>,
+    properties: HashMap<String, JsonModelProperty>,
+
+    /// Ref properties to set to the instance at a path in the tree, like
+    /// `game.ReplicatedStorage.Shared.Config`.
+    #[serde(default = "HashMap::new", skip_serializing_if = "HashMap::is_empty")]
+    refs: HashMap<String, String>,
+}
+
+#[derive(Debug)]
+enum JsonModelProperty {
+    Value(UnresolvedRbxValue),
+
+    /// A NumberSequence written as a list of keypoints, which can't be read as
+    /// an `UnresolvedRbxValue`.
+    NumberSequence(Vec<Keypoint>),
+}
+
+impl<'de> Deserialize<'de> for JsonModelProperty {
+    /// Lists of objects are read as keypoints, and everything else as an
+    /// `UnresolvedRbxValue`. Picking one up front, instead of trying each in
+    /// turn, keeps the error from the one that was meant, like which keypoint
+    /// field is missing.
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = serde_json::Value::deserialize(deserializer)?;
+
+        let is_keypoints = match &value {
+            serde_json::Value::Array(items) => {
+                !items.is_empty() && items.iter().all(serde_json::Value::is_object)
+            }
+            _ => false,
+        };
+
+        if is_keypoints {
+            Vec::<Keypoint>::deserialize(value)
+                .map(JsonModelProperty::NumberSequence)
+                .map_err(de::Error::custom)
+        } else {
+            UnresolvedRbxValue::deserialize(value)
+                .map(JsonModelProperty::Value)
+                .map_err(de::Error::custom)
+        }
+    }
 }
 
 impl JsonModelCore {
-    fn into_snapshot(self, name: String) -> InstanceSnapshot {
+    /// Turns this instance into a snapshot. `depth` is how many levels below
+    /// the top-level instance of the model it is.
+    fn into_snapshot(
+        self,
+        name: String,
+        path: &Path,
+        depth: usize,
+        max_depth: Option<usize>,
+    ) -> Result<InstanceSnapshot, SnapshotError> {
         let class_name = self.class_name;
 
+        if let Some(max_depth) = max_depth {
+            if depth >= max_depth && !self.children.is_empty() {
+                return Err(SnapshotError::model_too_deep(max_depth, path));
+            }
+        }
+
         let children = self
             .children
             .into_iter()
-            .map(|child| child.core.into_snapshot(child.name))
-            .collect();
-
-        let properties = self
-            .properties
-            .into_iter()
-            .map(|(key, value)| {
-                try_resolve_value(&class_name, &key, &value).map(|resolved| (key, resolved))
+            .map(|child| {
+                child
+                    .core
+                    .into_snapshot(child.name, path, depth + 1, max_depth)
             })
-            .collect::<Result<HashMap<_, _>, _>>()
-            .expect("TODO: Handle rbx_reflection errors");
+            .collect::<Result<_, _>>()?;
+
+        for key in self.properties.keys().chain(self.refs.keys()) {
+            reject_structural_property(key, path)?;
+        }
+
+        for (key, value) in &self.properties {
+            if let JsonModelProperty::Value(value) = value {
+                check_number_fits(&class_name, key, value, path)?;
+            }
+        }
 
-        InstanceSnapshot {
+        // TODO: Accept a friendly form for Font properties, like
+        // `{ "family": "...", "weight": "Bold", "style": "Italic" }`. This is
+        // blocked on rbx_dom_weak gaining a Font value type, since there's no
+        // RbxValue variant we could resolve it into yet.
+        let mut properties = HashMap::with_capacity(self.properties.len());
+
+        for (key, value) in self.properties {
+            let resolved = match value {
+                JsonModelProperty::Value(value) => resolve_value(&class_name, &key, &value)
+                    .expect("TODO: Handle rbx_reflection errors"),
+                JsonModelProperty::NumberSequence(keypoints) => {
+                    resolve_number_sequence(&class_name, &key, &keypoints).map_err(|source| {
+                        SnapshotError::invalid_number_sequence(source, &class_name, &key, path)
+                    })?
+                }
+            };
+
+            properties.insert(key, resolved);
+        }
+
+        Ok(InstanceSnapshot {
             snapshot_id: None,
             metadata: Default::default(),
             name: Cow::Owned(name),
             class_name: Cow::Owned(class_name),
             properties,
+            ref_paths: self.refs,
             children,
+        })
+    }
+}
+
+/// How a number written in a JSON model fits into the type of the property
+/// that it's for.
+#[derive(Debug, PartialEq)]
+enum NumberFit {
+    /// The number can be stored as it was written.
+    Fits,
+
+    /// The number is in range for a Float32, but some of its digits will be
+    /// lost.
+    LosesPrecision(f32),
+
+    /// The number is outside of the range of the property's type.
+    OutOfRange,
+}
+
+fn number_fit(value_type: RbxValueType, number: f64) -> NumberFit {
+    match value_type {
+        RbxValueType::Int32 if number < f64::from(i32::MIN) || number > f64::from(i32::MAX) => {
+            NumberFit::OutOfRange
+        }
+
+        // i64::MAX can't be written exactly as an f64, and rounds up to the
+        // first number that's out of range.
+        RbxValueType::Int64 if number < i64::MIN as f64 || number >= i64::MAX as f64 => {
+            NumberFit::OutOfRange
         }
+
+        RbxValueType::Float32 if number.abs() > f64::from(f32::MAX) => NumberFit::OutOfRange,
+
+        // Most decimals can't be stored exactly as either an f32 or an f64, so
+        // instead we check whether the digits that were written survive being
+        // turned into an f32 and back.
+        RbxValueType::Float32 => {
+            let rounded = number as f32;
+
+            if rounded.to_string().parse::<f64>() == Ok(number) {
+                NumberFit::Fits
+            } else {
+                NumberFit::LosesPrecision(rounded)
+            }
+        }
+
+        _ => NumberFit::Fits,
+    }
+}
+
+/// Makes sure that a number for a numeric property fits into the property's
+/// type, instead of letting it saturate or turn into infinity. Numbers that
+/// only lose precision are warned about.
+fn check_number_fits(
+    class_name: &str,
+    property: &str,
+    value: &UnresolvedRbxValue,
+    path: &Path,
+) -> Result<(), SnapshotError> {
+    let number = match value {
+        UnresolvedRbxValue::Ambiguous(AmbiguousRbxValue::Float1(number)) => *number,
+        _ => return Ok(()),
+    };
+
+    let value_type = match reflection_cache::property_type(class_name, property) {
+        Some(RbxPropertyTypeDescriptor::Data(value_type)) => *value_type,
+        _ => return Ok(()),
+    };
+
+    match number_fit(value_type, number) {
+        NumberFit::Fits => Ok(()),
+        NumberFit::LosesPrecision(rounded) => {
+            log::warn!(
+                "{}.{} in {} is {}, which will be rounded to {} as a Float32",
+                class_name,
+                property,
+                path.display(),
+                number,
+                rounded
+            );
+
+            Ok(())
+        }
+        NumberFit::OutOfRange => Err(SnapshotError::number_out_of_range(
+            class_name, property, number, value_type, path,
+        )),
     }
 }
 
@@ -113,6 +296,9 @@ mod test {
     use super::*;
 
     use memofs::{InMemoryFs, VfsSnapshot};
+    use rbx_dom_weak::{NumberSequence, NumberSequenceKeypoint, RbxValue};
+
+    use crate::resolution::NumberSequenceError;
 
     #[test]
     fn model_from_vfs() {
@@ -152,4 +338,300 @@ mod test {
 
         insta::assert_yaml_snapshot!(instance_snapshot);
     }
+
+    #[test]
+    fn parent_property_is_rejected() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo.model.json",
+            VfsSnapshot::file(
+                r#"
+                    {
+                      "ClassName": "Folder",
+                      "Children": [
+                        {
+                          "Name": "Child",
+                          "ClassName": "ObjectValue",
+                          "Properties": {
+                            "Parent": "foo"
+                          }
+                        }
+                      ]
+                    }
+                "#,
+            ),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let error = snapshot_json_model(
+            &InstanceContext::default(),
+            &vfs,
+            Path::new("/foo.model.json"),
+            "foo",
+        )
+        .unwrap_err();
+
+        match &error {
+            SnapshotError::StructuralProperty { property, path } => {
+                assert_eq!(property, "Parent");
+                assert_eq!(path, Path::new("/foo.model.json"));
+            }
+            other => panic!("expected StructuralProperty, got {:?}", other),
+        }
+
+        assert!(error.to_string().contains("file structure"));
+    }
+
+    #[test]
+    fn malformed_property_errors() {
+        let error_for = |property: &str| {
+            let mut imfs = InMemoryFs::new();
+            imfs.load_snapshot(
+                "/foo.model.json",
+                VfsSnapshot::file(format!(
+                    r#"{{ "ClassName": "ParticleEmitter", "Properties": {{ "Size": {} }} }}"#,
+                    property
+                )),
+            )
+            .unwrap();
+
+            let vfs = Vfs::new(imfs);
+
+            let error = snapshot_json_model(
+                &InstanceContext::default(),
+                &vfs,
+                Path::new("/foo.model.json"),
+                "foo",
+            )
+            .unwrap_err();
+
+            match error {
+                SnapshotError::MalformedModelJson { source, .. } => source.to_string(),
+                other => panic!("expected MalformedModelJson, got {:?}", other),
+            }
+        };
+
+        let error = error_for("null");
+        assert!(error.contains("expected Roblox value"), "{}", error);
+
+        let error = error_for(r#"[{ "time": 0 }, { "time": 1, "value": 1 }]"#);
+        assert!(error.contains("missing field `value`"), "{}", error);
+    }
+
+    /// Builds a model where each instance has one child, `depth` levels deep.
+    fn nested_model(depth: usize) -> String {
+        let mut model = r#"{ "Name": "Leaf", "ClassName": "Folder" }"#.to_owned();
+
+        for _ in 0..depth {
+            model = format!(
+                r#"{{ "Name": "Nested", "ClassName": "Folder", "Children": [{}] }}"#,
+                model
+            );
+        }
+
+        model
+    }
+
+    fn snapshot_nested(depth: usize, max_model_depth: usize) -> SnapshotInstanceResult {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/foo.model.json", VfsSnapshot::file(nested_model(depth)))
+            .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        let context = InstanceContext {
+            max_model_depth: Some(max_model_depth),
+            ..Default::default()
+        };
+
+        snapshot_json_model(&context, &vfs, Path::new("/foo.model.json"), "foo")
+    }
+
+    #[test]
+    fn max_model_depth() {
+        let mut snapshot = snapshot_nested(10, 10).unwrap().unwrap();
+        for _ in 0..10 {
+            snapshot = snapshot.children.remove(0);
+        }
+        assert_eq!(snapshot.name, "Leaf");
+
+        match snapshot_nested(11, 10) {
+            Err(SnapshotError::ModelTooDeep { max_depth, path }) => {
+                assert_eq!(max_depth, 10);
+                assert_eq!(path, Path::new("/foo.model.json"));
+            }
+            other => panic!("expected ModelTooDeep, got {:?}", other),
+        }
+    }
+
+    fn snapshot_emitter(properties: &str) -> SnapshotInstanceResult {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo.model.json",
+            VfsSnapshot::file(format!(
+                r#"{{ "ClassName": "ParticleEmitter", "Properties": {} }}"#,
+                properties
+            )),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        snapshot_json_model(
+            &InstanceContext::default(),
+            &vfs,
+            Path::new("/foo.model.json"),
+            "foo",
+        )
+    }
+
+    #[test]
+    fn number_range_and_sequence() {
+        let snapshot = snapshot_emitter(
+            r#"{
+                "Lifetime": [1, 3.5],
+                "Size": [
+                    { "time": 0, "value": 1 },
+                    { "time": 0.5, "value": 4, "envelope": 0.5 },
+                    { "time": 1, "value": 0 }
+                ]
+            }"#,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            snapshot.properties["Lifetime"],
+            RbxValue::NumberRange { value: (1.0, 3.5) }
+        );
+        assert_eq!(
+            snapshot.properties["Size"],
+            RbxValue::NumberSequence {
+                value: NumberSequence {
+                    keypoints: vec![
+                        NumberSequenceKeypoint {
+                            time: 0.0,
+                            value: 1.0,
+                            envelope: 0.0,
+                        },
+                        NumberSequenceKeypoint {
+                            time: 0.5,
+                            value: 4.0,
+                            envelope: 0.5,
+                        },
+                        NumberSequenceKeypoint {
+                            time: 1.0,
+                            value: 0.0,
+                            envelope: 0.0,
+                        },
+                    ],
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn unordered_number_sequence() {
+        let error = snapshot_emitter(
+            r#"{
+                "Size": [
+                    { "time": 0, "value": 1 },
+                    { "time": 0.75, "value": 0 },
+                    { "time": 0.5, "value": 4 },
+                    { "time": 1, "value": 0 }
+                ]
+            }"#,
+        )
+        .unwrap_err();
+
+        match error {
+            SnapshotError::InvalidNumberSequence {
+                source,
+                class_name,
+                property,
+                path,
+            } => {
+                assert_eq!(
+                    source,
+                    NumberSequenceError::NotAscending {
+                        previous: 0.75,
+                        time: 0.5,
+                    }
+                );
+                assert_eq!(class_name, "ParticleEmitter");
+                assert_eq!(property, "Size");
+                assert_eq!(path, Path::new("/foo.model.json"));
+            }
+            other => panic!("expected InvalidNumberSequence, got {:?}", other),
+        }
+    }
+
+    fn snapshot_model(model: &str) -> SnapshotInstanceResult {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/foo.model.json", VfsSnapshot::file(model))
+            .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        snapshot_json_model(
+            &InstanceContext::default(),
+            &vfs,
+            Path::new("/foo.model.json"),
+            "foo",
+        )
+    }
+
+    #[test]
+    fn int32_in_range() {
+        let snapshot =
+            snapshot_model(r#"{ "ClassName": "Beam", "Properties": { "Segments": -2147483648 } }"#)
+                .unwrap()
+                .unwrap();
+
+        assert_eq!(
+            snapshot.properties["Segments"],
+            RbxValue::Int32 { value: i32::MIN }
+        );
+    }
+
+    #[test]
+    fn int32_out_of_range() {
+        let error =
+            snapshot_model(r#"{ "ClassName": "Beam", "Properties": { "Segments": 3000000000 } }"#)
+                .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Beam.Segments in /foo.model.json is 3000000000, \
+             but Int32 values must be between -2147483648 and 2147483647"
+        );
+    }
+
+    #[test]
+    fn float32_precision() {
+        assert_eq!(number_fit(RbxValueType::Float32, 0.3), NumberFit::Fits);
+        assert_eq!(
+            number_fit(RbxValueType::Float32, 0.123_456_789_1),
+            NumberFit::LosesPrecision(0.123_456_79)
+        );
+        assert_eq!(
+            number_fit(RbxValueType::Float64, 0.123_456_789_1),
+            NumberFit::Fits
+        );
+
+        // Numbers that lose precision are only warned about.
+        let snapshot = snapshot_model(
+            r#"{ "ClassName": "Part", "Properties": { "Transparency": 0.1234567891 } }"#,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            snapshot.properties["Transparency"],
+            RbxValue::Float32 {
+                value: 0.123_456_79
+            }
+        );
+    }
 }
@@ -1,36 +1,121 @@
-use std::{path::Path, str};
+use std::{
+    path::{Path, PathBuf},
+    str,
+};
 
 use maplit::hashmap;
 use memofs::{IoResultExt, Vfs};
 use rbx_dom_weak::RbxValue;
 
-use crate::snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot};
+use crate::{
+    snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot},
+    trace,
+};
 
 use super::{
-    dir::snapshot_dir, meta_file::AdjacentMetadata, middleware::SnapshotInstanceResult,
-    util::match_trailing,
+    dir::snapshot_dir_without_meta, error::SnapshotError, meta_file::AdjacentMetadata,
+    middleware::SnapshotInstanceResult, util::match_trailing,
 };
 
+/// The extensions that Lua scripts can have.
+pub const SCRIPT_EXTENSIONS: &[&str] = &[".lua", ".luau"];
+
+/// The names of scripts that usurp the folder they're in, without their
+/// extension. If a folder has more than one kind, the first one here wins.
+const INIT_SCRIPT_NAMES: &[&str] = &["init", "init.server", "init.client"];
+
+/// If the file name ends with one of `SCRIPT_EXTENSIONS`, returns the part of
+/// it before the extension.
+pub fn match_script_extension(file_name: &str) -> Option<&str> {
+    SCRIPT_EXTENSIONS
+        .iter()
+        .find_map(|extension| match_trailing(file_name, extension))
+}
+
+/// Tells whether the path is an init script, which is snapshotted as part of
+/// its folder instead of as its own instance.
+pub fn is_init_script(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(match_script_extension)
+        .map_or(false, |name| INIT_SCRIPT_NAMES.contains(&name))
+}
+
+/// Every path that an init script inside of the folder could have.
+pub fn init_script_paths(folder: &Path) -> Vec<PathBuf> {
+    SCRIPT_EXTENSIONS
+        .iter()
+        .flat_map(|extension| {
+            INIT_SCRIPT_NAMES
+                .iter()
+                .map(move |name| folder.join(format!("{}{}", name, extension)))
+        })
+        .collect()
+}
+
+/// Finds the init script that the folder should be snapshotted from, if it has
+/// one.
+///
+/// A folder with the same kind of init script under more than one extension,
+/// like both `init.lua` and `init.luau`, is an error, since either one could
+/// be the script that the user meant.
+pub fn find_init_script(vfs: &Vfs, folder: &Path) -> Result<Option<PathBuf>, SnapshotError> {
+    for name in INIT_SCRIPT_NAMES {
+        let mut found = Vec::new();
+
+        for extension in SCRIPT_EXTENSIONS {
+            let path = folder.join(format!("{}{}", name, extension));
+
+            if vfs.metadata(&path).with_not_found()?.is_some() {
+                found.push(path);
+            }
+        }
+
+        match found.len() {
+            0 => continue,
+            1 => return Ok(found.pop()),
+            _ => return Err(SnapshotError::ambiguous_init_script(folder, found)),
+        }
+    }
+
+    Ok(None)
+}
+
 /// Core routine for turning Lua files into snapshots.
 pub fn snapshot_lua(context: &InstanceContext, vfs: &Vfs, path: &Path) -> SnapshotInstanceResult {
+    let _span = trace::span("snapshot", || format!("snapshot_lua {}", path.display()));
+
     let file_name = path.file_name().unwrap().to_string_lossy();
 
-    let (class_name, instance_name) = if let Some(name) = match_trailing(&file_name, ".server.lua")
-    {
+    let name = match match_script_extension(&file_name) {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+
+    let (class_name, instance_name) = if let Some(name) = match_trailing(name, ".server") {
         ("Script", name)
-    } else if let Some(name) = match_trailing(&file_name, ".client.lua") {
+    } else if let Some(name) = match_trailing(name, ".client") {
         ("LocalScript", name)
-    } else if let Some(name) = match_trailing(&file_name, ".lua") {
-        ("ModuleScript", name)
     } else {
-        return Ok(None);
+        (context.default_script_kind.class_name(), name)
     };
 
     let contents = vfs.read(path)?;
-    let contents_str = str::from_utf8(&contents)
+    let mut contents_str = str::from_utf8(&contents)
         // TODO: Turn into error type
-        .expect("File content was not valid UTF-8")
-        .to_string();
+        .expect("File content was not valid UTF-8");
+
+    if !context.keep_source_bom {
+        if let Some(stripped) = strip_bom(contents_str) {
+            log::warn!(
+                "Stripped a UTF-8 byte order mark from the start of {}",
+                path.display()
+            );
+            contents_str = stripped;
+        }
+    }
+
+    let contents_str = contents_str.to_string();
 
     let meta_path = path.with_file_name(format!("{}.meta.json", instance_name));
 
@@ -51,38 +136,56 @@ pub fn snapshot_lua(context: &InstanceContext, vfs: &Vfs, path: &Path) -> Snapsh
 
     if let Some(meta_contents) = vfs.read(&meta_path).with_not_found()? {
         let mut metadata = AdjacentMetadata::from_slice(&meta_contents, &meta_path)?;
-        metadata.apply_all(&mut snapshot);
+        metadata.apply_all(vfs, &mut snapshot)?;
     }
 
     Ok(Some(snapshot))
 }
 
+/// If the given string starts with a UTF-8 byte order mark, returns the string
+/// without it.
+///
+/// Editors on Windows sometimes add a BOM to files, but Roblox will fail to
+/// parse any script that starts with one.
+fn strip_bom(contents: &str) -> Option<&str> {
+    if contents.starts_with('\u{feff}') {
+        Some(&contents['\u{feff}'.len_utf8()..])
+    } else {
+        None
+    }
+}
+
 /// Attempts to snapshot an 'init' Lua script contained inside of a folder with
 /// the given name.
 ///
-/// Scripts named `init.lua`, `init.server.lua`, or `init.client.lua` usurp
-/// their parents, which acts similarly to `__init__.py` from the Python world.
+/// Scripts named `init.lua`, `init.server.lua`, or `init.client.lua`, or the
+/// same names ending in `.luau`, usurp their parents, which acts similarly to
+/// `__init__.py` from the Python world.
 pub fn snapshot_lua_init(
     context: &InstanceContext,
     vfs: &Vfs,
     init_path: &Path,
 ) -> SnapshotInstanceResult {
-    let folder_path = init_path.parent().unwrap();
-    let dir_snapshot = snapshot_dir(context, vfs, folder_path)?.unwrap();
+    let _span = trace::span("snapshot", || {
+        format!("snapshot_lua_init {}", init_path.display())
+    });
 
-    if dir_snapshot.class_name != "Folder" {
-        panic!(
-            "init.lua, init.server.lua, and init.client.lua can \
-             only be used if the instance produced by the parent \
-             directory would be a Folder."
-        );
-    }
+    // The init.meta.json next to the script is read by snapshot_lua as the
+    // script's own meta file, so it isn't applied to the directory as well.
+    let folder_path = init_path.parent().unwrap();
+    let dir_snapshot = snapshot_dir_without_meta(context, vfs, folder_path)?;
 
     let mut init_snapshot = snapshot_lua(context, vfs, init_path)?.unwrap();
 
     init_snapshot.name = dir_snapshot.name;
     init_snapshot.children = dir_snapshot.children;
-    init_snapshot.metadata = dir_snapshot.metadata;
+    init_snapshot.metadata.instigating_source = dir_snapshot.metadata.instigating_source;
+
+    for path in dir_snapshot.metadata.relevant_paths {
+        if !init_snapshot.metadata.relevant_paths.contains(&path) {
+            init_snapshot.metadata.relevant_paths.push(path);
+        }
+    }
 
     Ok(Some(init_snapshot))
 }
@@ -91,7 +194,16 @@ pub fn snapshot_lua_init(
 mod test {
     use super::*;
 
+    use std::path::PathBuf;
+
     use memofs::{InMemoryFs, VfsSnapshot};
+    use rbx_dom_weak::RbxInstanceProperties;
+
+    use crate::{
+        log_capture,
+        project::ScriptKind,
+        snapshot::{apply_patch_set, compute_patch_set, InstancePropertiesWithMeta, RojoTree},
+    };
 
     #[test]
     fn module_from_vfs() {
@@ -196,6 +308,57 @@ mod test {
         insta::assert_yaml_snapshot!(instance_snapshot);
     }
 
+    #[test]
+    fn module_with_user_data() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/foo.lua", VfsSnapshot::file("Hello there!"))
+            .unwrap();
+        imfs.load_snapshot(
+            "/foo.meta.json",
+            VfsSnapshot::file(
+                r#"
+                    {
+                        "$meta": {
+                            "owner": "tooling-secret"
+                        }
+                    }
+                "#,
+            ),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let instance_snapshot =
+            snapshot_lua(&InstanceContext::default(), &vfs, Path::new("/foo.lua"))
+                .unwrap()
+                .unwrap();
+
+        assert_eq!(
+            instance_snapshot.metadata.user_data.get("owner"),
+            Some(&serde_json::Value::from("tooling-secret"))
+        );
+
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "ROOT".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: Default::default(),
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+        let patch_set = compute_patch_set(&instance_snapshot, &tree, root_id);
+        apply_patch_set(&mut tree, patch_set);
+
+        let mut output = Vec::new();
+        rbx_xml::to_writer_default(&mut output, tree.inner(), &[root_id]).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("Hello there!"));
+        assert!(!output.contains("tooling-secret"));
+    }
+
     #[test]
     fn script_with_meta() {
         let mut imfs = InMemoryFs::new();
@@ -259,4 +422,204 @@ mod test {
             insta::assert_yaml_snapshot!(instance_snapshot);
         });
     }
+
+    #[test]
+    fn script_properties_from() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/scripts",
+            VfsSnapshot::dir(hashmap! {
+                "bar.server.lua" => VfsSnapshot::file("Hello there!"),
+                "bar.meta.json" => VfsSnapshot::file(r#"
+                    {
+                        "propertiesFrom": "config/bar.json"
+                    }
+                "#),
+                "config" => VfsSnapshot::dir(hashmap! {
+                    "bar.json" => VfsSnapshot::file(r#"
+                        {
+                            "Disabled": true
+                        }
+                    "#),
+                }),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let instance_snapshot = snapshot_lua(
+            &InstanceContext::default(),
+            &vfs,
+            Path::new("/scripts/bar.server.lua"),
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            instance_snapshot.properties.get("Disabled"),
+            Some(&RbxValue::Bool { value: true })
+        );
+        assert!(instance_snapshot
+            .metadata
+            .relevant_paths
+            .contains(&PathBuf::from("/scripts/config/bar.json")));
+    }
+
+    #[test]
+    fn script_properties_from_missing() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/bar.server.lua", VfsSnapshot::file("Hello there!"))
+            .unwrap();
+        imfs.load_snapshot(
+            "/bar.meta.json",
+            VfsSnapshot::file(r#"{ "propertiesFrom": "missing.json" }"#),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let err = snapshot_lua(
+            &InstanceContext::default(),
+            &vfs,
+            Path::new("/bar.server.lua"),
+        )
+        .unwrap_err();
+
+        assert!(err.to_string().contains("missing.json"));
+    }
+
+    #[test]
+    fn module_with_bom() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/foo.lua", VfsSnapshot::file("\u{feff}Hello there!"))
+            .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let (instance_snapshot, logs) = log_capture::capture(|| {
+            snapshot_lua(&InstanceContext::default(), &vfs, Path::new("/foo.lua"))
+                .unwrap()
+                .unwrap()
+        });
+
+        assert_eq!(
+            instance_snapshot.properties.get("Source"),
+            Some(&RbxValue::String {
+                value: "Hello there!".to_owned()
+            })
+        );
+        assert_eq!(
+            logs,
+            vec![(
+                log::Level::Warn,
+                "Stripped a UTF-8 byte order mark from the start of /foo.lua".to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn module_with_bom_kept() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/foo.lua", VfsSnapshot::file("\u{feff}Hello there!"))
+            .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let context = InstanceContext {
+            keep_source_bom: true,
+            ..Default::default()
+        };
+
+        let (instance_snapshot, logs) = log_capture::capture(|| {
+            snapshot_lua(&context, &vfs, Path::new("/foo.lua"))
+                .unwrap()
+                .unwrap()
+        });
+
+        assert_eq!(
+            instance_snapshot.properties.get("Source"),
+            Some(&RbxValue::String {
+                value: "\u{feff}Hello there!".to_owned()
+            })
+        );
+        assert!(logs.is_empty());
+    }
+
+    #[test]
+    fn default_script_kind() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "bare.lua" => VfsSnapshot::file("print()"),
+                "suffixed.server.lua" => VfsSnapshot::file("print()"),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let cases = [
+            (ScriptKind::Module, "ModuleScript"),
+            (ScriptKind::Server, "Script"),
+            (ScriptKind::Client, "LocalScript"),
+        ];
+
+        for &(default_script_kind, class_name) in &cases {
+            let context = InstanceContext {
+                default_script_kind,
+                ..Default::default()
+            };
+
+            let bare = snapshot_lua(&context, &vfs, Path::new("/foo/bare.lua"))
+                .unwrap()
+                .unwrap();
+            assert_eq!(bare.class_name, class_name);
+
+            // Suffixes always win over the default.
+            let suffixed = snapshot_lua(&context, &vfs, Path::new("/foo/suffixed.server.lua"))
+                .unwrap()
+                .unwrap();
+            assert_eq!(suffixed.class_name, "Script");
+        }
+    }
+
+    #[test]
+    fn strip_bom_only_at_start() {
+        assert_eq!(strip_bom("\u{feff}print()"), Some("print()"));
+        assert_eq!(strip_bom("print()\u{feff}"), None);
+        assert_eq!(strip_bom("print()"), None);
+    }
+
+    #[test]
+    fn luau_suffixes() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "module.luau" => VfsSnapshot::file("return {}"),
+                "server.server.luau" => VfsSnapshot::file("print()"),
+                "client.client.luau" => VfsSnapshot::file("print()"),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let cases = [
+            ("/foo/module.luau", "module", "ModuleScript"),
+            ("/foo/server.server.luau", "server", "Script"),
+            ("/foo/client.client.luau", "client", "LocalScript"),
+        ];
+
+        for &(path, name, class_name) in &cases {
+            let snapshot = snapshot_lua(&InstanceContext::default(), &vfs, Path::new(path))
+                .unwrap()
+                .unwrap();
+
+            assert_eq!(snapshot.name, name);
+            assert_eq!(snapshot.class_name, class_name);
+        }
+    }
 }
@@ -1,12 +1,21 @@
-use std::{borrow::Cow, collections::HashMap, path::Path};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
+use memofs::{IoResultExt, Vfs};
 use rbx_dom_weak::UnresolvedRbxValue;
-use rbx_reflection::try_resolve_value;
 use serde::{Deserialize, Serialize};
 
-use crate::snapshot::InstanceSnapshot;
+use crate::{
+    project::normalize_path,
+    reflection_cache,
+    resolution::resolve_value,
+    snapshot::{apply_attribute_changes, AttributeValue, InstanceSnapshot, ATTRIBUTES_PROPERTY},
+};
 
-use super::error::SnapshotError;
+use super::{error::SnapshotError, util::reject_structural_property};
 
 /// Represents metadata in a sibling file with the same basename.
 ///
@@ -18,14 +27,55 @@ pub struct AdjacentMetadata {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ignore_unknown_instances: Option<bool>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_only: Option<bool>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore_properties: Vec<String>,
+
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub properties: HashMap<String, UnresolvedRbxValue>,
+
+    /// A JSON file, relative to this meta file, containing more properties to
+    /// apply. Properties given in `properties` take precedence over it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub properties_from: Option<PathBuf>,
+
+    /// Attributes to set on the instance. They're merged into any attributes
+    /// that the instance already has.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub attributes: HashMap<String, MetaAttribute>,
+
+    /// Ref properties to set to the instance at a path in the tree, like
+    /// `game.ReplicatedStorage.Shared.Config`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub refs: HashMap<String, String>,
+
+    /// The class of the instance made from a .txt file, instead of
+    /// StringValue. It's an error for other files to set this.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class_name: Option<String>,
+
+    /// The property that the contents of a .txt file go into, instead of
+    /// Value. It's an error for other files to set this.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_property: Option<String>,
+
+    #[serde(rename = "$meta", default, skip_serializing_if = "HashMap::is_empty")]
+    pub user_data: HashMap<String, serde_json::Value>,
+
+    /// The path that this metadata was read from.
+    #[serde(skip)]
+    path: PathBuf,
 }
 
 impl AdjacentMetadata {
     pub fn from_slice(slice: &[u8], path: &Path) -> Result<Self, SnapshotError> {
-        serde_json::from_slice(slice)
-            .map_err(|source| SnapshotError::malformed_meta_json(source, path))
+        let mut metadata: Self = serde_json::from_slice(slice)
+            .map_err(|source| SnapshotError::malformed_meta_json(source, path))?;
+
+        metadata.path = path.to_path_buf();
+        Ok(metadata)
     }
 
     pub fn apply_ignore_unknown_instances(&mut self, snapshot: &mut InstanceSnapshot) {
@@ -34,23 +84,88 @@ impl AdjacentMetadata {
         }
     }
 
-    pub fn apply_properties(&mut self, snapshot: &mut InstanceSnapshot) {
-        let class_name = &snapshot.class_name;
+    pub fn apply_read_only(&mut self, snapshot: &mut InstanceSnapshot) {
+        if let Some(read_only) = self.read_only.take() {
+            snapshot.metadata.read_only = read_only;
+        }
+    }
 
-        let source_properties = self.properties.drain().map(|(key, value)| {
-            try_resolve_value(class_name, &key, &value)
-                .map(|resolved| (key, resolved))
-                .expect("TODO: Handle rbx_reflection errors")
-        });
+    pub fn apply_ignore_properties(&mut self, snapshot: &mut InstanceSnapshot) {
+        snapshot
+            .metadata
+            .ignore_properties
+            .append(&mut self.ignore_properties);
+    }
+
+    pub fn apply_properties(
+        &mut self,
+        snapshot: &mut InstanceSnapshot,
+    ) -> Result<(), SnapshotError> {
+        resolve_properties(&self.path, self.properties.drain(), snapshot)
+    }
+
+    pub fn apply_attributes(&mut self, snapshot: &mut InstanceSnapshot) {
+        merge_attributes(self.attributes.drain(), snapshot);
+    }
+
+    pub fn apply_refs(&mut self, snapshot: &mut InstanceSnapshot) {
+        snapshot.ref_paths.extend(self.refs.drain());
+    }
+
+    pub fn apply_user_data(&mut self, snapshot: &mut InstanceSnapshot) {
+        snapshot.metadata.user_data.extend(self.user_data.drain());
+    }
+
+    pub fn apply_properties_from(
+        &mut self,
+        vfs: &Vfs,
+        snapshot: &mut InstanceSnapshot,
+    ) -> Result<(), SnapshotError> {
+        if let Some(relative_path) = self.properties_from.take() {
+            let properties = read_properties_from(vfs, &self.path, &relative_path, snapshot)?;
+
+            for (key, value) in properties {
+                self.properties.entry(key).or_insert(value);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The txt middleware takes `class_name` and `text_property` before
+    /// applying the rest of the metadata, so if they're still here, they were
+    /// set in the meta file of something else and would have no effect.
+    fn reject_txt_only_fields(&self) -> Result<(), SnapshotError> {
+        if self.class_name.is_some() {
+            return Err(SnapshotError::txt_only_meta_field("className", &self.path));
+        }
 
-        for (key, value) in source_properties {
-            snapshot.properties.insert(key, value);
+        if self.text_property.is_some() {
+            return Err(SnapshotError::txt_only_meta_field(
+                "textProperty",
+                &self.path,
+            ));
         }
+
+        Ok(())
     }
 
-    pub fn apply_all(&mut self, snapshot: &mut InstanceSnapshot) {
+    pub fn apply_all(
+        &mut self,
+        vfs: &Vfs,
+        snapshot: &mut InstanceSnapshot,
+    ) -> Result<(), SnapshotError> {
+        self.reject_txt_only_fields()?;
         self.apply_ignore_unknown_instances(snapshot);
-        self.apply_properties(snapshot);
+        self.apply_read_only(snapshot);
+        self.apply_ignore_properties(snapshot);
+        self.apply_properties_from(vfs, snapshot)?;
+        self.apply_properties(snapshot)?;
+        self.apply_attributes(snapshot);
+        self.apply_refs(snapshot);
+        self.apply_user_data(snapshot);
+
+        Ok(())
     }
 
     // TODO: Add method to allow selectively applying parts of metadata and
@@ -67,23 +182,119 @@ pub struct DirectoryMetadata {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ignore_unknown_instances: Option<bool>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_only: Option<bool>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore_properties: Vec<String>,
+
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub properties: HashMap<String, UnresolvedRbxValue>,
 
+    /// A JSON file, relative to this meta file, containing more properties to
+    /// apply. Properties given in `properties` take precedence over it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub properties_from: Option<PathBuf>,
+
+    /// Attributes to set on the instance. They're merged into any attributes
+    /// that the instance already has.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub attributes: HashMap<String, MetaAttribute>,
+
+    /// Ref properties to set to the instance at a path in the tree, like
+    /// `game.ReplicatedStorage.Shared.Config`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub refs: HashMap<String, String>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub class_name: Option<String>,
+
+    #[serde(rename = "$meta", default, skip_serializing_if = "HashMap::is_empty")]
+    pub user_data: HashMap<String, serde_json::Value>,
+
+    /// The path that this metadata was read from.
+    #[serde(skip)]
+    path: PathBuf,
+
+    /// Properties from `defaults.meta.json` files that apply to this
+    /// directory, from the outermost file inwards, along with the path of the
+    /// file that each set came from.
+    #[serde(skip)]
+    defaults: Vec<(PathBuf, HashMap<String, UnresolvedRbxValue>)>,
 }
 
 impl DirectoryMetadata {
     pub fn from_slice(slice: &[u8], path: &Path) -> Result<Self, SnapshotError> {
-        serde_json::from_slice(slice)
-            .map_err(|source| SnapshotError::malformed_meta_json(source, path))
+        let mut metadata: Self = serde_json::from_slice(slice)
+            .map_err(|source| SnapshotError::malformed_meta_json(source, path))?;
+
+        metadata.path = path.to_path_buf();
+        Ok(metadata)
+    }
+
+    /// Creates metadata that doesn't change anything, for directories without
+    /// an init.meta.json file.
+    pub fn empty(path: &Path) -> Self {
+        DirectoryMetadata {
+            ignore_unknown_instances: None,
+            read_only: None,
+            ignore_properties: Vec::new(),
+            properties: HashMap::new(),
+            properties_from: None,
+            attributes: HashMap::new(),
+            refs: HashMap::new(),
+            class_name: None,
+            user_data: HashMap::new(),
+            path: path.to_path_buf(),
+            defaults: Vec::new(),
+        }
+    }
+
+    /// Adds the properties of a `defaults.meta.json` file underneath the
+    /// properties of this metadata. Defaults added later take precedence over
+    /// earlier ones, and every default is overridden by `properties` and
+    /// `propertiesFrom`.
+    ///
+    /// Only `properties` are taken from defaults files; any other fields in
+    /// them are ignored.
+    pub fn add_defaults(&mut self, defaults: DirectoryMetadata) {
+        self.defaults.push((defaults.path, defaults.properties));
     }
 
-    pub fn apply_all(&mut self, snapshot: &mut InstanceSnapshot) {
+    pub fn apply_all(
+        &mut self,
+        vfs: &Vfs,
+        snapshot: &mut InstanceSnapshot,
+    ) -> Result<(), SnapshotError> {
         self.apply_ignore_unknown_instances(snapshot);
+        self.apply_read_only(snapshot);
+        self.apply_ignore_properties(snapshot);
         self.apply_class_name(snapshot);
-        self.apply_properties(snapshot);
+        self.apply_defaults(snapshot)?;
+        self.apply_properties_from(vfs, snapshot)?;
+        self.apply_properties(snapshot)?;
+        self.apply_attributes(snapshot);
+        self.apply_refs(snapshot);
+        self.apply_user_data(snapshot);
+
+        Ok(())
+    }
+
+    /// Sets properties from defaults files. Defaults can be shared by
+    /// directories that turn into different classes, so properties that the
+    /// directory's class doesn't have are skipped.
+    fn apply_defaults(&mut self, snapshot: &mut InstanceSnapshot) -> Result<(), SnapshotError> {
+        for (defaults_path, properties) in self.defaults.drain(..) {
+            let class_name = snapshot.class_name.clone();
+            let applicable = properties
+                .into_iter()
+                .filter(|(key, _)| reflection_cache::property_type(&class_name, key).is_some());
+
+            resolve_properties(&defaults_path, applicable, snapshot)?;
+            snapshot.metadata.relevant_paths.push(defaults_path);
+        }
+
+        Ok(())
     }
 
     fn apply_class_name(&mut self, snapshot: &mut InstanceSnapshot) {
@@ -103,17 +314,269 @@ impl DirectoryMetadata {
         }
     }
 
-    fn apply_properties(&mut self, snapshot: &mut InstanceSnapshot) {
-        let class_name = &snapshot.class_name;
+    fn apply_read_only(&mut self, snapshot: &mut InstanceSnapshot) {
+        if let Some(read_only) = self.read_only.take() {
+            snapshot.metadata.read_only = read_only;
+        }
+    }
 
-        let source_properties = self.properties.drain().map(|(key, value)| {
-            try_resolve_value(class_name, &key, &value)
-                .map(|resolved| (key, resolved))
-                .expect("TODO: Handle rbx_reflection errors")
-        });
+    fn apply_ignore_properties(&mut self, snapshot: &mut InstanceSnapshot) {
+        snapshot
+            .metadata
+            .ignore_properties
+            .append(&mut self.ignore_properties);
+    }
+
+    fn apply_properties_from(
+        &mut self,
+        vfs: &Vfs,
+        snapshot: &mut InstanceSnapshot,
+    ) -> Result<(), SnapshotError> {
+        if let Some(relative_path) = self.properties_from.take() {
+            let properties = read_properties_from(vfs, &self.path, &relative_path, snapshot)?;
+
+            for (key, value) in properties {
+                self.properties.entry(key).or_insert(value);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn apply_properties(&mut self, snapshot: &mut InstanceSnapshot) -> Result<(), SnapshotError> {
+        resolve_properties(&self.path, self.properties.drain(), snapshot)
+    }
+
+    fn apply_attributes(&mut self, snapshot: &mut InstanceSnapshot) {
+        merge_attributes(self.attributes.drain(), snapshot);
+    }
+
+    fn apply_refs(&mut self, snapshot: &mut InstanceSnapshot) {
+        snapshot.ref_paths.extend(self.refs.drain());
+    }
+
+    fn apply_user_data(&mut self, snapshot: &mut InstanceSnapshot) {
+        snapshot.metadata.user_data.extend(self.user_data.drain());
+    }
+}
+
+/// The value of an attribute in a meta file. Strings, booleans, and numbers are
+/// written as themselves, while other types are written as an object with the
+/// name of the type, like `{ "Vector3": [1, 2, 3] }`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MetaAttribute {
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Typed(TypedMetaAttribute),
+}
 
-        for (key, value) in source_properties {
-            snapshot.properties.insert(key, value);
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TypedMetaAttribute {
+    Color3([f32; 3]),
+    Vector3([f32; 3]),
+}
+
+impl MetaAttribute {
+    fn to_attribute_value(&self) -> AttributeValue {
+        match self {
+            MetaAttribute::Bool(value) => AttributeValue::bool(*value),
+            MetaAttribute::Number(value) => AttributeValue::double(*value),
+            MetaAttribute::String(value) => AttributeValue::string(value),
+            MetaAttribute::Typed(TypedMetaAttribute::Color3(value)) => {
+                AttributeValue::color3(*value)
+            }
+            MetaAttribute::Typed(TypedMetaAttribute::Vector3(value)) => {
+                AttributeValue::vector3(*value)
+            }
         }
     }
 }
+
+/// Sets attributes on the snapshot, keeping any attributes that its
+/// serialized attributes property already has.
+fn merge_attributes(
+    attributes: impl Iterator<Item = (String, MetaAttribute)>,
+    snapshot: &mut InstanceSnapshot,
+) {
+    let changes: HashMap<_, _> = attributes
+        .map(|(name, value)| (name, Some(value.to_attribute_value())))
+        .collect();
+
+    if changes.is_empty() {
+        return;
+    }
+
+    let merged = apply_attribute_changes(snapshot.properties.get(ATTRIBUTES_PROPERTY), changes);
+    snapshot
+        .properties
+        .insert(ATTRIBUTES_PROPERTY.to_owned(), merged);
+}
+
+/// Resolves the given properties against the class of the snapshot using the
+/// reflection database and sets them on it.
+fn resolve_properties(
+    meta_path: &Path,
+    properties: impl Iterator<Item = (String, UnresolvedRbxValue)>,
+    snapshot: &mut InstanceSnapshot,
+) -> Result<(), SnapshotError> {
+    for (key, value) in properties {
+        reject_structural_property(&key, meta_path)?;
+
+        let resolved = resolve_value(&snapshot.class_name, &key, &value).map_err(|source| {
+            SnapshotError::invalid_meta_property(
+                source,
+                snapshot.class_name.as_ref(),
+                key.as_str(),
+                meta_path,
+            )
+        })?;
+
+        snapshot.properties.insert(key, resolved);
+    }
+
+    Ok(())
+}
+
+/// Reads the properties file referenced by a meta file's `propertiesFrom`
+/// field. The file is added to the snapshot's relevant paths so that changing
+/// it updates the instance.
+fn read_properties_from(
+    vfs: &Vfs,
+    meta_path: &Path,
+    relative_path: &Path,
+    snapshot: &mut InstanceSnapshot,
+) -> Result<HashMap<String, UnresolvedRbxValue>, SnapshotError> {
+    let path = match meta_path.parent() {
+        Some(parent) => normalize_path(&parent.join(relative_path)),
+        None => normalize_path(relative_path),
+    };
+
+    snapshot.metadata.relevant_paths.push(path.clone());
+
+    let contents = vfs
+        .read(&path)
+        .with_not_found()?
+        .ok_or_else(|| SnapshotError::properties_from_missing(&path, meta_path))?;
+
+    serde_json::from_slice(&contents).map_err(|source| SnapshotError::malformed_json(source, &path))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use maplit::hashmap;
+    use memofs::{InMemoryFs, VfsSnapshot};
+    use rbx_dom_weak::{RbxInstanceProperties, RbxValue};
+
+    use crate::{
+        snapshot::{
+            apply_patch_set, compute_patch_set, InstanceContext, InstancePropertiesWithMeta,
+            RojoTree,
+        },
+        snapshot_middleware::snapshot_from_vfs,
+    };
+
+    fn snapshot_with_meta(meta: &str) -> InstanceSnapshot {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "bar.lua" => VfsSnapshot::file("return nil"),
+                "bar.meta.json" => VfsSnapshot::file(meta),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        snapshot_from_vfs(&InstanceContext::default(), &vfs, Path::new("/foo/bar.lua"))
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn attributes_are_patched_individually() {
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "bar".to_owned(),
+                class_name: "ModuleScript".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        });
+        let root_id = tree.get_root_id();
+
+        let mut sync = |meta: &str| {
+            let snapshot = snapshot_with_meta(meta);
+            let patch_set = compute_patch_set(&snapshot, &tree, root_id);
+            apply_patch_set(&mut tree, patch_set.clone());
+            patch_set
+        };
+
+        sync(r#"{ "attributes": { "Title": "Sword" } }"#);
+
+        let added = sync(r#"{ "attributes": { "Title": "Sword", "Damage": 12 } }"#);
+        assert_eq!(
+            added.updated_instances[0].changed_attributes,
+            hashmap! {
+                "Damage".to_owned() => Some(AttributeValue::double(12.0)),
+            }
+        );
+
+        let removed = sync(r#"{ "attributes": { "Title": "Sword" } }"#);
+        assert_eq!(
+            removed.updated_instances[0].changed_attributes,
+            hashmap! {
+                "Damage".to_owned() => None,
+            }
+        );
+        assert!(removed.updated_instances[0].changed_properties.is_empty());
+
+        let expected = apply_attribute_changes(
+            None,
+            hashmap! {
+                "Title".to_owned() => Some(AttributeValue::string("Sword")),
+            },
+        );
+
+        let instance = tree.get_instance(root_id).unwrap();
+        assert_eq!(
+            instance.properties().get(ATTRIBUTES_PROPERTY),
+            Some(&expected)
+        );
+        assert!(matches!(
+            instance.properties().get("Source"),
+            Some(RbxValue::String { .. })
+        ));
+    }
+
+    #[test]
+    fn typed_attributes() {
+        let snapshot = snapshot_with_meta(
+            r#"{
+                "attributes": {
+                    "Enabled": true,
+                    "Offset": { "Vector3": [1, 2, 3] },
+                    "Tint": { "Color3": [1, 0.5, 0] }
+                }
+            }"#,
+        );
+
+        let expected = apply_attribute_changes(
+            None,
+            hashmap! {
+                "Enabled".to_owned() => Some(AttributeValue::bool(true)),
+                "Offset".to_owned() => Some(AttributeValue::vector3([1.0, 2.0, 3.0])),
+                "Tint".to_owned() => Some(AttributeValue::color3([1.0, 0.5, 0.0])),
+            },
+        );
+
+        assert_eq!(
+            snapshot.properties.get(ATTRIBUTES_PROPERTY),
+            Some(&expected)
+        );
+    }
+}
@@ -3,6 +3,7 @@
 
 #![allow(dead_code)]
 
+mod anim;
 mod csv;
 mod dir;
 mod error;
@@ -15,31 +16,41 @@ mod project;
 mod rbxm;
 mod rbxmx;
 mod txt;
+mod unknown;
 mod util;
+mod yaml;
 
-use std::path::Path;
+use std::{io, path::Path, thread, time::Duration};
 
+use crossbeam_channel::RecvTimeoutError;
 use memofs::{IoResultExt, Vfs};
 
-use crate::snapshot::InstanceContext;
+use crate::{project::Project, snapshot::InstanceContext};
 
 use self::{
+    anim::snapshot_anim_json,
     csv::snapshot_csv,
     dir::snapshot_dir,
     json::snapshot_json,
     json_model::snapshot_json_model,
-    lua::{snapshot_lua, snapshot_lua_init},
+    lua::{find_init_script, init_script_paths, is_init_script, snapshot_lua, snapshot_lua_init},
     middleware::SnapshotInstanceResult,
     project::snapshot_project,
     rbxm::snapshot_rbxm,
     rbxmx::snapshot_rbxmx,
     txt::snapshot_txt,
+    unknown::snapshot_unknown_file,
     util::match_file_name,
+    yaml::snapshot_yaml,
 };
 
 pub use self::error::*;
 pub use self::project::snapshot_project_node;
 
+/// How long middleware may spend turning a single file into instances before
+/// Rojo gives up on it, unless a project sets `middlewareTimeout`.
+pub const DEFAULT_MIDDLEWARE_TIMEOUT: Duration = Duration::from_secs(30);
+
 pub fn snapshot_from_vfs(
     context: &InstanceContext,
     vfs: &Vfs,
@@ -56,50 +67,449 @@ pub fn snapshot_from_vfs(
             return snapshot_project(context, vfs, &project_path);
         }
 
-        let init_path = path.join("init.lua");
-        if vfs.metadata(&init_path).with_not_found()?.is_some() {
+        if let Some(init_path) = find_init_script(vfs, path)? {
             return snapshot_lua_init(context, vfs, &init_path);
         }
 
-        let init_path = path.join("init.server.lua");
-        if vfs.metadata(&init_path).with_not_found()?.is_some() {
-            return snapshot_lua_init(context, vfs, &init_path);
+        snapshot_dir(context, vfs, path)
+    } else {
+        if Project::is_project_file(path) {
+            return snapshot_project(context, vfs, path);
         }
 
-        let init_path = path.join("init.client.lua");
-        if vfs.metadata(&init_path).with_not_found()?.is_some() {
-            return snapshot_lua_init(context, vfs, &init_path);
+        if is_init_script(path) {
+            // init scripts are handled elsewhere and should not turn into
+            // their own children.
+            return Ok(None);
         }
 
-        snapshot_dir(context, vfs, path)
+        if let Some(_name) = match_file_name(path, ".meta.json") {
+            // .meta.json files do not turn into their own instances.
+            return Ok(None);
+        }
+
+        if Project::is_local_overlay(path) {
+            // Local overlays are read along with the project they're next to,
+            // so they don't turn into their own instances either.
+            return Ok(None);
+        }
+
+        match FileMiddleware::for_path(path) {
+            Some(middleware) => snapshot_file_with_timeout(context, vfs, path, middleware),
+            None => snapshot_unknown_file(context, vfs, path),
+        }
+    }
+}
+
+/// Describes the middleware that `snapshot_from_vfs` picks for the given path,
+/// like `directory` or `.lua`, or returns `None` if nothing exists there.
+pub fn middleware_name(vfs: &Vfs, path: &Path) -> io::Result<Option<&'static str>> {
+    let meta = match vfs.metadata(path).with_not_found()? {
+        Some(meta) => meta,
+        None => return Ok(None),
+    };
+
+    if meta.is_dir() {
+        if vfs
+            .metadata(path.join("default.project.json"))
+            .with_not_found()?
+            .is_some()
+        {
+            return Ok(Some("project"));
+        }
+
+        for init_path in init_script_paths(path) {
+            if vfs.metadata(init_path).with_not_found()?.is_some() {
+                return Ok(Some("init script"));
+            }
+        }
+
+        Ok(Some("directory"))
+    } else if Project::is_project_file(path) {
+        Ok(Some("project"))
+    } else if Project::is_local_overlay(path) {
+        Ok(Some("project overlay"))
     } else {
-        if let Some(name) = match_file_name(path, ".lua") {
-            match name {
-                // init scripts are handled elsewhere and should not turn into
-                // their own children.
-                "init" | "init.client" | "init.server" => return Ok(None),
+        Ok(Some(
+            FileMiddleware::for_path(path).map_or("unknown file", FileMiddleware::extension),
+        ))
+    }
+}
+
+/// The middleware that turn single files into instances, as opposed to
+/// directories and projects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileMiddleware {
+    Lua,
+    Luau,
+    JsonModel,
+    Json5Model,
+    AnimJson,
+    Json,
+    Yaml,
+    Yml,
+    Csv,
+    Txt,
+    Rbxmx,
+    Rbxm,
+}
+
+impl FileMiddleware {
+    fn for_path(path: &Path) -> Option<Self> {
+        if match_file_name(path, ".lua").is_some() {
+            Some(Self::Lua)
+        } else if match_file_name(path, ".luau").is_some() {
+            Some(Self::Luau)
+        } else if match_file_name(path, ".model.json").is_some() {
+            Some(Self::JsonModel)
+        } else if match_file_name(path, ".model.json5").is_some() {
+            Some(Self::Json5Model)
+        } else if match_file_name(path, ".anim.json").is_some() {
+            Some(Self::AnimJson)
+        } else if match_file_name(path, ".json").is_some() {
+            Some(Self::Json)
+        } else if match_file_name(path, ".yaml").is_some() {
+            Some(Self::Yaml)
+        } else if match_file_name(path, ".yml").is_some() {
+            Some(Self::Yml)
+        } else if match_file_name(path, ".csv").is_some() {
+            Some(Self::Csv)
+        } else if match_file_name(path, ".txt").is_some() {
+            Some(Self::Txt)
+        } else if match_file_name(path, ".rbxmx").is_some() {
+            Some(Self::Rbxmx)
+        } else if match_file_name(path, ".rbxm").is_some() {
+            Some(Self::Rbxm)
+        } else {
+            None
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Lua => ".lua",
+            Self::Luau => ".luau",
+            Self::JsonModel => ".model.json",
+            Self::Json5Model => ".model.json5",
+            Self::AnimJson => ".anim.json",
+            Self::Json => ".json",
+            Self::Yaml => ".yaml",
+            Self::Yml => ".yml",
+            Self::Csv => ".csv",
+            Self::Txt => ".txt",
+            Self::Rbxmx => ".rbxmx",
+            Self::Rbxm => ".rbxm",
+        }
+    }
+
+    fn snapshot(self, context: &InstanceContext, vfs: &Vfs, path: &Path) -> SnapshotInstanceResult {
+        let name = match_file_name(path, self.extension())
+            .expect("middleware was picked for a path without its extension");
+
+        match self {
+            Self::Lua | Self::Luau => snapshot_lua(context, vfs, path),
+            Self::JsonModel | Self::Json5Model => snapshot_json_model(context, vfs, path, name),
+            Self::AnimJson => snapshot_anim_json(context, vfs, path, name),
+            Self::Json => snapshot_json(context, vfs, path, name),
+            Self::Yaml | Self::Yml => snapshot_yaml(context, vfs, path, name),
+            Self::Csv => snapshot_csv(context, vfs, path, name),
+            Self::Txt => snapshot_txt(context, vfs, path, name),
+            Self::Rbxmx => snapshot_rbxmx(context, vfs, path, name),
+            Self::Rbxm => snapshot_rbxm(context, vfs, path, name),
+        }
+    }
+}
+
+/// Runs the given middleware on the file at `path`, giving up with an error if
+/// it takes longer than the context's middleware timeout.
+///
+/// Without a timeout, the middleware runs on the current thread. With one, it
+/// runs on a new thread that shares `vfs` with this one.
+fn snapshot_file_with_timeout(
+    context: &InstanceContext,
+    vfs: &Vfs,
+    path: &Path,
+    middleware: FileMiddleware,
+) -> SnapshotInstanceResult {
+    let timeout = match context.middleware_timeout {
+        Some(timeout) => timeout,
+        None => return middleware.snapshot(context, vfs, path),
+    };
+
+    let context = context.clone();
+    let vfs = vfs.clone();
+    let owned_path = path.to_path_buf();
+
+    run_with_timeout(path, timeout, move || {
+        middleware.snapshot(&context, &vfs, &owned_path)
+    })
+}
+
+/// Runs `snapshot` on a new thread, returning a timeout error for `path` if it
+/// doesn't finish within `timeout`.
+///
+/// Middleware can't be interrupted, so if the timeout is hit, the thread is
+/// detached rather than joined. It keeps running in the background until the
+/// middleware returns, and its result is thrown away. Each file that times out
+/// can leave one such thread behind.
+fn run_with_timeout<F>(path: &Path, timeout: Duration, snapshot: F) -> SnapshotInstanceResult
+where
+    F: FnOnce() -> SnapshotInstanceResult + Send + 'static,
+{
+    let (sender, receiver) = crossbeam_channel::bounded(1);
+
+    thread::Builder::new()
+        .name("Rojo middleware".to_owned())
+        .spawn(move || {
+            // If we hit the timeout, the receiver is already gone and there's
+            // nobody left to care about the result.
+            let _ = sender.send(snapshot());
+        })?;
 
-                _ => return snapshot_lua(context, vfs, path),
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => Err(SnapshotError::middleware_timeout(path, timeout)),
+        Err(RecvTimeoutError::Disconnected) => Err(SnapshotError::middleware_panicked(path)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use maplit::hashmap;
+    use memofs::{InMemoryFs, VfsSnapshot};
+    use rbx_dom_weak::RbxValue;
+
+    #[test]
+    fn slow_middleware_times_out() {
+        let path = Path::new("/slow.rbxmx");
+
+        let result = run_with_timeout(path, Duration::from_millis(20), || {
+            thread::sleep(Duration::from_secs(2));
+            Ok(None)
+        });
+
+        match result {
+            Err(err @ SnapshotError::MiddlewareTimeout { .. }) => {
+                assert!(err.to_string().contains("/slow.rbxmx"));
             }
-        } else if let Some(_name) = match_file_name(path, ".project.json") {
-            return snapshot_project(context, vfs, path);
-        } else if let Some(name) = match_file_name(path, ".model.json") {
-            return snapshot_json_model(context, vfs, path, name);
-        } else if let Some(_name) = match_file_name(path, ".meta.json") {
-            // .meta.json files do not turn into their own instances.
-            return Ok(None);
-        } else if let Some(name) = match_file_name(path, ".json") {
-            return snapshot_json(context, vfs, path, name);
-        } else if let Some(name) = match_file_name(path, ".csv") {
-            return snapshot_csv(context, vfs, path, name);
-        } else if let Some(name) = match_file_name(path, ".txt") {
-            return snapshot_txt(context, vfs, path, name);
-        } else if let Some(name) = match_file_name(path, ".rbxmx") {
-            return snapshot_rbxmx(context, vfs, path, name);
-        } else if let Some(name) = match_file_name(path, ".rbxm") {
-            return snapshot_rbxm(context, vfs, path, name);
-        }
-
-        Ok(None)
+            other => panic!("expected a timeout error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn panicking_middleware_errors() {
+        let path = Path::new("/broken.rbxmx");
+
+        let result = run_with_timeout(path, Duration::from_secs(5), || panic!("oh no"));
+
+        match result {
+            Err(err @ SnapshotError::MiddlewarePanicked { .. }) => {
+                assert!(err.to_string().contains("/broken.rbxmx"));
+            }
+            other => panic!("expected a panic error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fast_middleware_finishes() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/foo.txt", VfsSnapshot::file("Hello!"))
+            .unwrap();
+        imfs.load_snapshot(
+            "/foo.meta.json",
+            VfsSnapshot::file(r#"{ "ignoreUnknownInstances": true }"#),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let context = InstanceContext {
+            middleware_timeout: Some(Duration::from_secs(5)),
+            ..InstanceContext::default()
+        };
+
+        let snapshot = snapshot_from_vfs(&context, &vfs, Path::new("/foo.txt"))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(snapshot.name, "foo");
+        assert!(snapshot.metadata.ignore_unknown_instances);
+    }
+
+    #[test]
+    fn properties_from_other_directory() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/root",
+            VfsSnapshot::dir(hashmap! {
+                "config" => VfsSnapshot::dir(hashmap! {
+                    "bar.json" => VfsSnapshot::file(r#"{ "Disabled": true }"#),
+                    "foo.json" => VfsSnapshot::file(r#"{ "Disabled": true }"#),
+                }),
+                "scripts" => VfsSnapshot::dir(hashmap! {
+                    "bar.server.lua" => VfsSnapshot::file("print('bar')"),
+                    "bar.meta.json" => VfsSnapshot::file(
+                        r#"{ "propertiesFrom": "../config/bar.json" }"#,
+                    ),
+                    "foo" => VfsSnapshot::dir(hashmap! {
+                        "init.server.lua" => VfsSnapshot::file("print('foo')"),
+                        "init.meta.json" => VfsSnapshot::file(
+                            r#"{ "propertiesFrom": "../../config/foo.json" }"#,
+                        ),
+                    }),
+                }),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        // Run the middleware on their own threads too, which used to only see
+        // the file and its meta files.
+        let context = InstanceContext {
+            middleware_timeout: Some(Duration::from_secs(5)),
+            ..InstanceContext::default()
+        };
+
+        let snapshot = snapshot_from_vfs(&context, &vfs, Path::new("/root/scripts"))
+            .unwrap()
+            .unwrap();
+
+        let mut children: Vec<_> = snapshot.children.iter().collect();
+        children.sort_by(|a, b| a.name.cmp(&b.name));
+
+        assert_eq!(children.len(), 2);
+
+        for (child, config) in children.iter().zip(&["bar.json", "foo.json"]) {
+            assert_eq!(child.class_name, "Script");
+            assert_eq!(
+                child.properties.get("Disabled"),
+                Some(&RbxValue::Bool { value: true })
+            );
+            assert!(child
+                .metadata
+                .relevant_paths
+                .contains(&Path::new("/root/config").join(config)));
+        }
+    }
+
+    #[test]
+    fn local_overlays_in_directories_are_ignored() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/root",
+            VfsSnapshot::dir(hashmap! {
+                "child.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "child",
+                        "tree": {
+                            "$className": "Folder"
+                        }
+                    }
+                "#),
+                "child.project.local.json" => VfsSnapshot::file(r#"{ "name": "local" }"#),
+                "other.project.local.json5" => VfsSnapshot::file("{}"),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let snapshot = snapshot_from_vfs(&InstanceContext::default(), &vfs, Path::new("/root"))
+            .unwrap()
+            .unwrap();
+
+        let names: Vec<_> = snapshot.children.iter().map(|child| &child.name).collect();
+        // The overlay is still applied to the project next to it.
+        assert_eq!(names, vec!["local"]);
+
+        assert_eq!(
+            middleware_name(&vfs, Path::new("/root/child.project.local.json")).unwrap(),
+            Some("project overlay")
+        );
+    }
+
+    #[test]
+    fn txt_only_meta_fields_error_elsewhere() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/root",
+            VfsSnapshot::dir(hashmap! {
+                "module.lua" => VfsSnapshot::file("return {}"),
+                "module.meta.json" => VfsSnapshot::file(r#"{ "textProperty": "Source" }"#),
+                "script" => VfsSnapshot::dir(hashmap! {
+                    "init.server.lua" => VfsSnapshot::file("print()"),
+                    "init.meta.json" => VfsSnapshot::file(r#"{ "className": "LocalScript" }"#),
+                }),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        let context = InstanceContext::default();
+
+        for (path, expected_field) in &[
+            ("/root/module.lua", "textProperty"),
+            ("/root/script", "className"),
+        ] {
+            match snapshot_from_vfs(&context, &vfs, Path::new(path)) {
+                Err(SnapshotError::TxtOnlyMetaField { field, .. }) => {
+                    assert_eq!(field, *expected_field)
+                }
+                other => panic!("expected TxtOnlyMetaField for {}, got {:?}", path, other),
+            }
+        }
+    }
+
+    #[test]
+    fn luau_init_script() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "init.server.luau" => VfsSnapshot::file("print()"),
+                "child.luau" => VfsSnapshot::file("return {}"),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let snapshot = snapshot_from_vfs(&InstanceContext::default(), &vfs, Path::new("/foo"))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(snapshot.name, "foo");
+        assert_eq!(snapshot.class_name, "Script");
+        assert_eq!(snapshot.children.len(), 1);
+        assert_eq!(snapshot.children[0].name, "child");
+        assert_eq!(snapshot.children[0].class_name, "ModuleScript");
+    }
+
+    #[test]
+    fn init_script_with_both_extensions() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "init.lua" => VfsSnapshot::file("return 1"),
+                "init.luau" => VfsSnapshot::file("return 2"),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        match snapshot_from_vfs(&InstanceContext::default(), &vfs, Path::new("/foo")) {
+            Err(SnapshotError::AmbiguousInitScript { paths, .. }) => {
+                assert_eq!(
+                    paths,
+                    vec![Path::new("/foo/init.lua"), Path::new("/foo/init.luau")]
+                );
+            }
+            other => panic!("expected AmbiguousInitScript, got {:?}", other),
+        }
     }
 }
@@ -1,26 +1,68 @@
-use std::{borrow::Cow, collections::HashMap, path::Path};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
-use memofs::Vfs;
-use rbx_reflection::{get_class_descriptor, try_resolve_value};
+use memofs::{IoResultExt, Vfs};
+use rbx_reflection::get_class_descriptor;
 
 use crate::{
-    project::{Project, ProjectNode},
+    project::{normalize_path, MissingPathBehavior, Project, ProjectNode},
+    resolution::resolve_value,
     snapshot::{
         InstanceContext, InstanceMetadata, InstanceSnapshot, InstigatingSource, PathIgnoreRule,
     },
+    trace,
 };
 
-use super::{error::SnapshotError, middleware::SnapshotInstanceResult, snapshot_from_vfs};
+use super::{
+    error::SnapshotError, middleware::SnapshotInstanceResult, snapshot_from_vfs,
+    util::reject_structural_property,
+};
 
 pub fn snapshot_project(
     context: &InstanceContext,
     vfs: &Vfs,
     path: &Path,
 ) -> SnapshotInstanceResult {
-    let project = Project::load_from_slice(&vfs.read(path)?, path)
-        .map_err(|err| SnapshotError::malformed_project(err, path))?;
+    let _span = trace::span("snapshot", || {
+        format!("snapshot_project {}", path.display())
+    });
+
+    // Projects can include other projects via $path. If a project ends up
+    // including itself, we'd recurse forever, so we bail out with the chain of
+    // projects that caused the cycle instead.
+    let normalized_path = normalize_path(path);
+    if context.is_project_in_stack(&normalized_path) {
+        let mut chain: Vec<PathBuf> = context
+            .project_stack
+            .iter()
+            .skip_while(|included| **included != normalized_path)
+            .cloned()
+            .collect();
+        chain.push(normalized_path);
+
+        return Err(SnapshotError::project_cycle(chain));
+    }
 
+    let overlay_path = Project::local_overlay_path(path);
+    let overlay_contents = vfs.read(&overlay_path).with_not_found()?;
+
+    let project = Project::load_with_overlay(
+        &vfs.read(path)?,
+        overlay_contents
+            .as_ref()
+            .map(|contents| contents.as_slice()),
+        path,
+    )
+    .map_err(|err| SnapshotError::malformed_project(err, path))?;
+
+    let outer_project_stack = Arc::clone(&context.project_stack);
     let mut context = context.clone();
+    context.push_project(normalized_path);
 
     let rules = project.glob_ignore_paths.iter().map(|glob| PathIgnoreRule {
         glob: glob.clone(),
@@ -28,11 +70,54 @@ pub fn snapshot_project(
     });
 
     context.add_path_ignore_rules(rules);
+    context.add_transform_rules(project.transforms.iter().cloned());
+    context.add_property_comparisons(
+        project
+            .property_comparisons
+            .iter()
+            .map(|(key, comparison)| (key.clone(), *comparison)),
+    );
+
+    if let Some(missing_path_behavior) = project.missing_path_behavior {
+        context.missing_path_behavior = missing_path_behavior;
+    }
+
+    if let Some(property_type_change_behavior) = project.property_type_change_behavior {
+        context.property_type_change_behavior = property_type_change_behavior;
+    }
+
+    if let Some(unknown_file_behavior) = project.unknown_file_behavior {
+        context.unknown_file_behavior = unknown_file_behavior;
+    }
+
+    if let Some(keep_source_bom) = project.keep_source_bom {
+        context.keep_source_bom = keep_source_bom;
+    }
+
+    if let Some(default_script_kind) = project.default_script_kind {
+        context.default_script_kind = default_script_kind;
+    }
+
+    if let Some(max_size) = project.max_generated_source_size {
+        context.max_generated_source_size = Some(max_size);
+    }
+
+    if let Some(max_depth) = project.max_model_depth {
+        context.max_model_depth = Some(max_depth);
+    }
+
+    if let Some(timeout) = project.middleware_timeout {
+        context.middleware_timeout = match timeout {
+            0 => None,
+            timeout => Some(Duration::from_secs(timeout)),
+        };
+    }
 
-    // TODO: If this project node is a path to an instance that Rojo doesn't
-    // understand, this may panic!
     let mut snapshot =
-        snapshot_project_node(&context, path, &project.name, &project.tree, vfs, None)?.unwrap();
+        match snapshot_project_node(&context, path, &project.name, &project.tree, vfs, None)? {
+            Some(snapshot) => snapshot,
+            None => return Ok(None),
+        };
 
     // Setting the instigating source to the project file path is a little
     // coarse.
@@ -51,6 +136,13 @@ pub fn snapshot_project(
     // nodes that aren't roots. They'll be updated as part of the project
     // file being updated.
     snapshot.metadata.relevant_paths.push(path.to_path_buf());
+    snapshot.metadata.relevant_paths.push(overlay_path);
+
+    // Because this project file is the instigating source of the root node,
+    // the root node will be snapshotted through this function again. This
+    // project shouldn't be on the stack when that happens, or we'd think that
+    // it included itself.
+    snapshot.metadata.context.project_stack = Arc::clone(&outer_project_stack);
 
     Ok(Some(snapshot))
 }
@@ -120,6 +212,41 @@ pub fn snapshot_project_node(
             // Take the snapshot's metadata as-is, which will be mutated later
             // on.
             metadata = snapshot.metadata;
+        } else if vfs.metadata(path.as_path()).with_not_found()?.is_none() {
+            let missing_path_behavior = if node.optional {
+                MissingPathBehavior::Warn
+            } else {
+                context.missing_path_behavior
+            };
+
+            match missing_path_behavior {
+                MissingPathBehavior::Error => {
+                    return Err(SnapshotError::project_path_missing(
+                        instance_name,
+                        path.as_ref(),
+                    ));
+                }
+                MissingPathBehavior::Warn => {
+                    log::warn!(
+                        "Skipping project node {} because its $path does not exist: {}",
+                        instance_name,
+                        path.display()
+                    );
+                    return Ok(None);
+                }
+                MissingPathBehavior::CreateEmpty => {
+                    if class_name.is_none() {
+                        return Err(SnapshotError::project_path_missing_no_class_name(
+                            instance_name,
+                            path.as_ref(),
+                        ));
+                    }
+
+                    // We still want to hear about the path being created so
+                    // that the empty instance can be replaced.
+                    metadata.relevant_paths.push(path.into_owned());
+                }
+            }
         } else {
             // TODO: Should this issue an error instead?
             log::warn!(
@@ -171,7 +298,9 @@ pub fn snapshot_project_node(
     }
 
     for (key, value) in &node.properties {
-        let resolved_value = try_resolve_value(&class_name, key, value)
+        reject_structural_property(key, project_path)?;
+
+        let resolved_value = resolve_value(&class_name, key, value)
             .expect("TODO: Properly handle value resolution errors");
 
         properties.insert(key.clone(), resolved_value);
@@ -203,6 +332,7 @@ pub fn snapshot_project_node(
         name,
         class_name,
         properties,
+        ref_paths: Default::default(),
         children,
         metadata,
     }))
@@ -216,10 +346,12 @@ mod test {
     use maplit::hashmap;
     use memofs::{InMemoryFs, VfsSnapshot};
 
+    use crate::snapshot_middleware::DEFAULT_MIDDLEWARE_TIMEOUT;
+
     #[ignore = "Functionality moved to root snapshot middleware"]
     #[test]
     fn project_from_folder() {
-        let _ = env_logger::try_init();
+        crate::log_capture::init();
 
         let mut imfs = InMemoryFs::new();
         imfs.load_snapshot(
@@ -249,7 +381,7 @@ mod test {
 
     #[test]
     fn project_from_direct_file() {
-        let _ = env_logger::try_init();
+        crate::log_capture::init();
 
         let mut imfs = InMemoryFs::new();
         imfs.load_snapshot(
@@ -282,7 +414,7 @@ mod test {
 
     #[test]
     fn project_with_resolved_properties() {
-        let _ = env_logger::try_init();
+        crate::log_capture::init();
 
         let mut imfs = InMemoryFs::new();
         imfs.load_snapshot(
@@ -321,7 +453,7 @@ mod test {
 
     #[test]
     fn project_with_unresolved_properties() {
-        let _ = env_logger::try_init();
+        crate::log_capture::init();
 
         let mut imfs = InMemoryFs::new();
         imfs.load_snapshot(
@@ -357,7 +489,7 @@ mod test {
 
     #[test]
     fn project_with_children() {
-        let _ = env_logger::try_init();
+        crate::log_capture::init();
 
         let mut imfs = InMemoryFs::new();
         imfs.load_snapshot(
@@ -394,7 +526,7 @@ mod test {
 
     #[test]
     fn project_with_path_to_txt() {
-        let _ = env_logger::try_init();
+        crate::log_capture::init();
 
         let mut imfs = InMemoryFs::new();
         imfs.load_snapshot(
@@ -428,7 +560,7 @@ mod test {
 
     #[test]
     fn project_with_path_to_project() {
-        let _ = env_logger::try_init();
+        crate::log_capture::init();
 
         let mut imfs = InMemoryFs::new();
         imfs.load_snapshot(
@@ -469,7 +601,7 @@ mod test {
 
     #[test]
     fn project_with_path_to_project_with_children() {
-        let _ = env_logger::try_init();
+        crate::log_capture::init();
 
         let mut imfs = InMemoryFs::new();
         imfs.load_snapshot(
@@ -517,7 +649,7 @@ mod test {
     /// precedence.
     #[test]
     fn project_path_property_overrides() {
-        let _ = env_logger::try_init();
+        crate::log_capture::init();
 
         let mut imfs = InMemoryFs::new();
         imfs.load_snapshot(
@@ -561,4 +693,253 @@ mod test {
 
         insta::assert_yaml_snapshot!(instance_snapshot);
     }
+
+    fn missing_path_vfs(behavior: &str) -> Vfs {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(format!(r#"
+                    {{
+                        "name": "missing-path",
+                        "missingPathBehavior": "{}",
+                        "tree": {{
+                            "$className": "Folder",
+                            "Missing": {{
+                                "$className": "Folder",
+                                "$path": "does-not-exist"
+                            }}
+                        }}
+                    }}
+                "#, behavior)),
+            }),
+        )
+        .unwrap();
+
+        Vfs::new(imfs)
+    }
+
+    #[test]
+    fn missing_path_error() {
+        crate::log_capture::init();
+
+        let vfs = missing_path_vfs("error");
+
+        let result = snapshot_project(
+            &InstanceContext::default(),
+            &vfs,
+            Path::new("/foo/default.project.json"),
+        );
+
+        match result {
+            Err(SnapshotError::ProjectPathMissing { name, path }) => {
+                assert_eq!(name, "Missing");
+                assert_eq!(path, Path::new("/foo/does-not-exist"));
+            }
+            other => panic!("expected ProjectPathMissing error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_path_warn() {
+        crate::log_capture::init();
+
+        let vfs = missing_path_vfs("warn");
+
+        let instance_snapshot = snapshot_project(
+            &InstanceContext::default(),
+            &vfs,
+            Path::new("/foo/default.project.json"),
+        )
+        .expect("snapshot error")
+        .expect("snapshot returned no instances");
+
+        assert!(instance_snapshot.children.is_empty());
+    }
+
+    #[test]
+    fn missing_path_create_empty() {
+        crate::log_capture::init();
+
+        let vfs = missing_path_vfs("createEmpty");
+
+        let instance_snapshot = snapshot_project(
+            &InstanceContext::default(),
+            &vfs,
+            Path::new("/foo/default.project.json"),
+        )
+        .expect("snapshot error")
+        .expect("snapshot returned no instances");
+
+        assert_eq!(instance_snapshot.children.len(), 1);
+
+        let child = &instance_snapshot.children[0];
+        assert_eq!(child.name, "Missing");
+        assert_eq!(child.class_name, "Folder");
+        assert!(child.properties.is_empty());
+        assert!(child.children.is_empty());
+        assert!(child
+            .metadata
+            .relevant_paths
+            .contains(&Path::new("/foo/does-not-exist").to_path_buf()));
+    }
+
+    fn optional_path_vfs(present: bool) -> Vfs {
+        let mut files = hashmap! {
+            "default.project.json" => VfsSnapshot::file(r#"
+                {
+                    "name": "optional-path",
+                    "tree": {
+                        "$className": "Folder",
+                        "Local": {
+                            "$path": "local",
+                            "$optional": true
+                        }
+                    }
+                }
+            "#),
+        };
+
+        if present {
+            files.insert("local", VfsSnapshot::empty_dir());
+        }
+
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/foo", VfsSnapshot::dir(files)).unwrap();
+
+        Vfs::new(imfs)
+    }
+
+    #[test]
+    fn optional_path_missing() {
+        crate::log_capture::init();
+
+        let vfs = optional_path_vfs(false);
+
+        let instance_snapshot = snapshot_project(
+            &InstanceContext::default(),
+            &vfs,
+            Path::new("/foo/default.project.json"),
+        )
+        .expect("snapshot error")
+        .expect("snapshot returned no instances");
+
+        assert!(instance_snapshot.children.is_empty());
+    }
+
+    #[test]
+    fn optional_path_present() {
+        crate::log_capture::init();
+
+        let vfs = optional_path_vfs(true);
+
+        let instance_snapshot = snapshot_project(
+            &InstanceContext::default(),
+            &vfs,
+            Path::new("/foo/default.project.json"),
+        )
+        .expect("snapshot error")
+        .expect("snapshot returned no instances");
+
+        assert_eq!(instance_snapshot.children.len(), 1);
+
+        let child = &instance_snapshot.children[0];
+        assert_eq!(child.name, "Local");
+        assert_eq!(child.class_name, "Folder");
+    }
+
+    #[test]
+    fn project_include_cycle() {
+        crate::log_capture::init();
+
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "a.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "a",
+                        "tree": {
+                            "$path": "b.project.json"
+                        }
+                    }
+                "#),
+                "b.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "b",
+                        "tree": {
+                            "$path": "a.project.json"
+                        }
+                    }
+                "#),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let err = snapshot_project(
+            &InstanceContext::default(),
+            &vfs,
+            Path::new("/foo/a.project.json"),
+        )
+        .unwrap_err();
+
+        match &err {
+            SnapshotError::ProjectCycle { chain } => {
+                assert_eq!(
+                    chain,
+                    &[
+                        PathBuf::from("/foo/a.project.json"),
+                        PathBuf::from("/foo/b.project.json"),
+                        PathBuf::from("/foo/a.project.json"),
+                    ]
+                );
+            }
+            _ => panic!("expected a project cycle error, got {}", err),
+        }
+
+        let message = err.to_string();
+        assert!(message.contains("a.project.json -> /foo/b.project.json"));
+    }
+
+    #[test]
+    fn middleware_timeout_defaults_until_overridden() {
+        crate::log_capture::init();
+
+        let timeout_for = |setting: &str| {
+            let mut imfs = InMemoryFs::new();
+            imfs.load_snapshot(
+                "/foo",
+                VfsSnapshot::dir(hashmap! {
+                    "default.project.json" => VfsSnapshot::file(format!(
+                        r#"{{ "name": "foo", {} "tree": {{ "$path": "foo.txt" }} }}"#,
+                        setting
+                    )),
+                    "foo.txt" => VfsSnapshot::file("Hello!"),
+                }),
+            )
+            .unwrap();
+
+            let vfs = Vfs::new(imfs);
+
+            snapshot_project(
+                &InstanceContext::default(),
+                &vfs,
+                Path::new("/foo/default.project.json"),
+            )
+            .expect("snapshot error")
+            .expect("snapshot returned no instances")
+            .metadata
+            .context
+            .middleware_timeout
+        };
+
+        assert_eq!(timeout_for(""), Some(DEFAULT_MIDDLEWARE_TIMEOUT));
+        assert_eq!(
+            timeout_for(r#""middlewareTimeout": 5,"#),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(timeout_for(r#""middlewareTimeout": 0,"#), None);
+    }
 }
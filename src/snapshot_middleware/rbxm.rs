@@ -3,7 +3,10 @@ use std::{collections::HashMap, path::Path};
 use memofs::Vfs;
 use rbx_dom_weak::{RbxInstanceProperties, RbxTree};
 
-use crate::snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot};
+use crate::{
+    snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot},
+    trace,
+};
 
 use super::middleware::SnapshotInstanceResult;
 
@@ -13,6 +16,8 @@ pub fn snapshot_rbxm(
     path: &Path,
     instance_name: &str,
 ) -> SnapshotInstanceResult {
+    let _span = trace::span("snapshot", || format!("snapshot_rbxm {}", path.display()));
+
     let mut temp_tree = RbxTree::new(RbxInstanceProperties {
         name: "DataModel".to_owned(),
         class_name: "DataModel".to_owned(),
@@ -27,6 +32,10 @@ pub fn snapshot_rbxm(
     let children = root_instance.get_children_ids();
 
     if children.len() == 1 {
+        // Snapshot IDs are the IDs from the temporary tree, so Ref properties
+        // would be resolved when the snapshot is applied just like they are
+        // for .rbxmx files. However, rbx_binary 0.5 skips Ref properties when
+        // decoding, so models lose them until we move to a newer version.
         let snapshot = InstanceSnapshot::from_tree(&temp_tree, children[0])
             .name(instance_name)
             .metadata(
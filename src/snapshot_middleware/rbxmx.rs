@@ -2,7 +2,10 @@ use std::path::Path;
 
 use memofs::Vfs;
 
-use crate::snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot};
+use crate::{
+    snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot},
+    trace,
+};
 
 use super::middleware::SnapshotInstanceResult;
 
@@ -12,6 +15,8 @@ pub fn snapshot_rbxmx(
     path: &Path,
     instance_name: &str,
 ) -> SnapshotInstanceResult {
+    let _span = trace::span("snapshot", || format!("snapshot_rbxmx {}", path.display()));
+
     let options = rbx_xml::DecodeOptions::new()
         .property_behavior(rbx_xml::DecodePropertyBehavior::ReadUnknown);
 
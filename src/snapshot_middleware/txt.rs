@@ -4,7 +4,10 @@ use maplit::hashmap;
 use memofs::{IoResultExt, Vfs};
 use rbx_dom_weak::RbxValue;
 
-use crate::snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot};
+use crate::{
+    snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot},
+    trace,
+};
 
 use super::{
     error::SnapshotError, meta_file::AdjacentMetadata, middleware::SnapshotInstanceResult,
@@ -16,23 +19,40 @@ pub fn snapshot_txt(
     path: &Path,
     instance_name: &str,
 ) -> SnapshotInstanceResult {
+    let _span = trace::span("snapshot", || format!("snapshot_txt {}", path.display()));
+
     let contents = vfs.read(path)?;
     let contents_str = str::from_utf8(&contents)
         .map_err(|err| SnapshotError::file_contents_bad_unicode(err, path))?
         .to_string();
 
-    let properties = hashmap! {
-        "Value".to_owned() => RbxValue::String {
-            value: contents_str,
-        },
+    let meta_path = path.with_file_name(format!("{}.meta.json", instance_name));
+
+    let mut metadata = match vfs.read(&meta_path).with_not_found()? {
+        Some(meta_contents) => Some(AdjacentMetadata::from_slice(&meta_contents, &meta_path)?),
+        None => None,
     };
 
-    let meta_path = path.with_file_name(format!("{}.meta.json", instance_name));
+    // The meta file can pick a different class and property for the text to
+    // go into, which we need to know before building the snapshot.
+    let class_name = metadata
+        .as_mut()
+        .and_then(|metadata| metadata.class_name.take())
+        .unwrap_or_else(|| "StringValue".to_owned());
+
+    let text_property = metadata
+        .as_mut()
+        .and_then(|metadata| metadata.text_property.take())
+        .unwrap_or_else(|| "Value".to_owned());
 
     let mut snapshot = InstanceSnapshot::new()
         .name(instance_name)
-        .class_name("StringValue")
-        .properties(properties)
+        .class_name(class_name)
+        .properties(hashmap! {
+            text_property => RbxValue::String {
+                value: contents_str,
+            },
+        })
         .metadata(
             InstanceMetadata::new()
                 .instigating_source(path)
@@ -40,9 +60,8 @@ pub fn snapshot_txt(
                 .context(context),
         );
 
-    if let Some(meta_contents) = vfs.read(&meta_path).with_not_found()? {
-        let mut metadata = AdjacentMetadata::from_slice(&meta_contents, &meta_path)?;
-        metadata.apply_all(&mut snapshot);
+    if let Some(metadata) = &mut metadata {
+        metadata.apply_all(vfs, &mut snapshot)?;
     }
 
     Ok(Some(snapshot))
@@ -73,4 +92,43 @@ mod test {
 
         insta::assert_yaml_snapshot!(instance_snapshot);
     }
+
+    #[test]
+    fn meta_overrides_class_and_property() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "notes.txt" => VfsSnapshot::file("Some notes"),
+                "notes.meta.json" => VfsSnapshot::file(r#"
+                    {
+                        "className": "ModuleScript",
+                        "textProperty": "Source"
+                    }
+                "#),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let instance_snapshot = snapshot_txt(
+            &InstanceContext::default(),
+            &vfs,
+            Path::new("/foo/notes.txt"),
+            "notes",
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(instance_snapshot.class_name, "ModuleScript");
+        assert_eq!(
+            instance_snapshot.properties,
+            hashmap! {
+                "Source".to_owned() => RbxValue::String {
+                    value: "Some notes".to_owned(),
+                },
+            }
+        );
+    }
 }
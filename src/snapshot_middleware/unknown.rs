@@ -0,0 +1,148 @@
+use std::{path::Path, str};
+
+use maplit::hashmap;
+use memofs::{IoResultExt, Vfs};
+use rbx_dom_weak::RbxValue;
+
+use crate::{
+    project::UnknownFileBehavior,
+    snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot},
+    trace,
+};
+
+use super::{
+    error::SnapshotError, meta_file::AdjacentMetadata, middleware::SnapshotInstanceResult,
+    txt::snapshot_txt,
+};
+
+/// Handles a file that no other middleware knows how to turn into an instance,
+/// according to the context's `UnknownFileBehavior`.
+pub fn snapshot_unknown_file(
+    context: &InstanceContext,
+    vfs: &Vfs,
+    path: &Path,
+) -> SnapshotInstanceResult {
+    let _span = trace::span("snapshot", || {
+        format!("snapshot_unknown_file {}", path.display())
+    });
+
+    let instance_name = path
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| SnapshotError::file_name_bad_unicode(path))?;
+
+    match context.unknown_file_behavior {
+        UnknownFileBehavior::ModuleScript => {
+            snapshot_unknown_module(context, vfs, path, instance_name)
+        }
+        UnknownFileBehavior::StringValue => snapshot_txt(context, vfs, path, instance_name),
+        UnknownFileBehavior::Ignore => Ok(None),
+        UnknownFileBehavior::Error => Err(SnapshotError::unknown_file_type(path)),
+    }
+}
+
+fn snapshot_unknown_module(
+    context: &InstanceContext,
+    vfs: &Vfs,
+    path: &Path,
+    instance_name: &str,
+) -> SnapshotInstanceResult {
+    let contents = vfs.read(path)?;
+    let contents_str = str::from_utf8(&contents)
+        .map_err(|err| SnapshotError::file_contents_bad_unicode(err, path))?
+        .to_string();
+
+    let properties = hashmap! {
+        "Source".to_owned() => RbxValue::String {
+            value: contents_str,
+        },
+    };
+
+    let meta_path = path.with_file_name(format!("{}.meta.json", instance_name));
+
+    let mut snapshot = InstanceSnapshot::new()
+        .name(instance_name)
+        .class_name("ModuleScript")
+        .properties(properties)
+        .metadata(
+            InstanceMetadata::new()
+                .instigating_source(path)
+                .relevant_paths(vec![path.to_path_buf(), meta_path.clone()])
+                .context(context),
+        );
+
+    if let Some(meta_contents) = vfs.read(&meta_path).with_not_found()? {
+        let mut metadata = AdjacentMetadata::from_slice(&meta_contents, &meta_path)?;
+        metadata.apply_all(vfs, &mut snapshot)?;
+    }
+
+    Ok(Some(snapshot))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use memofs::{InMemoryFs, VfsSnapshot};
+
+    fn snapshot_foobar(behavior: UnknownFileBehavior) -> SnapshotInstanceResult {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/data.foobar", VfsSnapshot::file("hello"))
+            .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let context = InstanceContext {
+            unknown_file_behavior: behavior,
+            ..Default::default()
+        };
+
+        snapshot_unknown_file(&context, &vfs, Path::new("/data.foobar"))
+    }
+
+    #[test]
+    fn module_script() {
+        let snapshot = snapshot_foobar(UnknownFileBehavior::ModuleScript)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(snapshot.name, "data");
+        assert_eq!(snapshot.class_name, "ModuleScript");
+        assert_eq!(
+            snapshot.properties.get("Source"),
+            Some(&RbxValue::String {
+                value: "hello".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn string_value() {
+        let snapshot = snapshot_foobar(UnknownFileBehavior::StringValue)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(snapshot.name, "data");
+        assert_eq!(snapshot.class_name, "StringValue");
+        assert_eq!(
+            snapshot.properties.get("Value"),
+            Some(&RbxValue::String {
+                value: "hello".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn ignore() {
+        assert!(snapshot_foobar(UnknownFileBehavior::Ignore)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn error() {
+        let err = snapshot_foobar(UnknownFileBehavior::Error).unwrap_err();
+
+        assert!(err.to_string().contains("/data.foobar"));
+    }
+}
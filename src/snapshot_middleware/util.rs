@@ -1,5 +1,21 @@
 use std::path::Path;
 
+use super::error::SnapshotError;
+
+/// Names that look like properties, but that Rojo decides based on how files
+/// are laid out instead.
+const STRUCTURAL_PROPERTIES: &[&str] = &["Parent", "Children"];
+
+/// Returns an error if a file tries to set one of the properties in
+/// `STRUCTURAL_PROPERTIES`, which would otherwise be dropped or misapplied.
+pub fn reject_structural_property(property: &str, path: &Path) -> Result<(), SnapshotError> {
+    if STRUCTURAL_PROPERTIES.contains(&property) {
+        Err(SnapshotError::structural_property(property, path))
+    } else {
+        Ok(())
+    }
+}
+
 /// If the given string ends up with the given suffix, returns the portion of
 /// the string before the suffix.
 pub fn match_trailing<'a>(input: &'a str, suffix: &str) -> Option<&'a str> {
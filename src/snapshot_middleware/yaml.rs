@@ -0,0 +1,183 @@
+use std::path::Path;
+
+use memofs::Vfs;
+
+use crate::{snapshot::InstanceContext, trace};
+
+use super::{error::SnapshotError, json::snapshot_data_module, middleware::SnapshotInstanceResult};
+
+pub fn snapshot_yaml(
+    context: &InstanceContext,
+    vfs: &Vfs,
+    path: &Path,
+    instance_name: &str,
+) -> SnapshotInstanceResult {
+    let _span = trace::span("snapshot", || format!("snapshot_yaml {}", path.display()));
+
+    let contents = vfs.read(path)?;
+
+    // Anchors and aliases are resolved while parsing, so aliased values show up
+    // as copies of what they refer to.
+    let value: serde_yaml::Value = serde_yaml::from_slice(&contents)
+        .map_err(|err| SnapshotError::malformed_yaml(err, path))?;
+
+    let value = yaml_to_json(value).map_err(|desc| SnapshotError::unsupported_yaml(desc, path))?;
+
+    snapshot_data_module(context, vfs, path, instance_name, value)
+}
+
+/// Converts a YAML value into the JSON value that the JSON middleware would
+/// have parsed from an equivalent file. Fails with a description of the value
+/// if it has no JSON equivalent.
+fn yaml_to_json(value: serde_yaml::Value) -> Result<serde_json::Value, String> {
+    use serde_yaml::Value;
+
+    Ok(match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Bool(value) => serde_json::Value::Bool(value),
+        Value::Number(number) => serde_json::Value::Number(yaml_number_to_json(&number)?),
+        Value::String(value) => serde_json::Value::String(value),
+        Value::Sequence(values) => serde_json::Value::Array(
+            values
+                .into_iter()
+                .map(yaml_to_json)
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Mapping(mapping) => serde_json::Value::Object(
+            mapping
+                .into_iter()
+                .map(|(key, value)| Ok((yaml_key_to_string(key)?, yaml_to_json(value)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+    })
+}
+
+fn yaml_number_to_json(number: &serde_yaml::Number) -> Result<serde_json::Number, String> {
+    if let Some(value) = number.as_u64() {
+        Ok(value.into())
+    } else if let Some(value) = number.as_i64() {
+        Ok(value.into())
+    } else {
+        let value = number.as_f64().unwrap();
+        serde_json::Number::from_f64(value).ok_or_else(|| format!("the number {}", value))
+    }
+}
+
+/// Lua tables would accept any key, but JSON objects only have string keys, so
+/// scalar keys are stringified and anything else is rejected.
+fn yaml_key_to_string(key: serde_yaml::Value) -> Result<String, String> {
+    use serde_yaml::Value;
+
+    match key {
+        Value::String(key) => Ok(key),
+        Value::Bool(key) => Ok(key.to_string()),
+        Value::Number(key) => Ok(key.to_string()),
+        Value::Null => Err("a null mapping key".to_owned()),
+        Value::Sequence(_) => Err("a sequence used as a mapping key".to_owned()),
+        Value::Mapping(_) => Err("a mapping used as a mapping key".to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use memofs::{InMemoryFs, VfsSnapshot};
+    use rbx_dom_weak::RbxValue;
+
+    use crate::lua_ast::{Expression, Statement};
+
+    fn snapshot_source(contents: &str) -> Result<String, SnapshotError> {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/foo.yaml", VfsSnapshot::file(contents))
+            .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let snapshot = snapshot_yaml(
+            &InstanceContext::default(),
+            &vfs,
+            Path::new("/foo.yaml"),
+            "foo",
+        )?
+        .unwrap();
+
+        assert_eq!(snapshot.name, "foo");
+        assert_eq!(snapshot.class_name, "ModuleScript");
+
+        match snapshot.properties.get("Source") {
+            Some(RbxValue::String { value }) => Ok(value.clone()),
+            other => panic!("expected a Source string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn simple_mapping() {
+        let source = snapshot_source(
+            "name: Sword\n\
+             damage: 12\n\
+             speed: 1.5\n\
+             enabled: true\n\
+             owner: ~\n\
+             tags:\n  - melee\n  - starter\n",
+        )
+        .unwrap();
+
+        let expected = Statement::Return(Expression::table(vec![
+            ("damage".into(), Expression::Number(12.0)),
+            ("enabled".into(), Expression::Bool(true)),
+            ("name".into(), Expression::String("Sword".to_owned())),
+            ("owner".into(), Expression::Nil),
+            ("speed".into(), Expression::Number(1.5)),
+            (
+                "tags".into(),
+                Expression::Array(vec![
+                    Expression::String("melee".to_owned()),
+                    Expression::String("starter".to_owned()),
+                ]),
+            ),
+        ]));
+
+        assert_eq!(source, expected.to_string());
+    }
+
+    #[test]
+    fn aliases_are_resolved() {
+        let yaml = snapshot_source(
+            "base: &base\n  health: 100\n\
+             first: *base\n\
+             second: *base\n",
+        )
+        .unwrap();
+
+        let expected = Statement::Return(Expression::table(vec![
+            ("base".into(), health_table()),
+            ("first".into(), health_table()),
+            ("second".into(), health_table()),
+        ]));
+
+        assert_eq!(yaml, expected.to_string());
+    }
+
+    fn health_table() -> Expression {
+        Expression::table(vec![("health".into(), Expression::Number(100.0))])
+    }
+
+    #[test]
+    fn malformed_yaml() {
+        match snapshot_source("key: [unclosed") {
+            Err(SnapshotError::MalformedYaml { path, .. }) => {
+                assert_eq!(path, Path::new("/foo.yaml"));
+            }
+            other => panic!("expected MalformedYaml, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unsupported_keys() {
+        match snapshot_source("? [1, 2]\n: value\n") {
+            Err(SnapshotError::UnsupportedYaml { .. }) => {}
+            other => panic!("expected UnsupportedYaml, got {:?}", other),
+        }
+    }
+}
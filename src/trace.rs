@@ -0,0 +1,153 @@
+//! A lightweight tracer for profiling Rojo itself. When enabled, spans are
+//! recorded as events in the Chrome Trace Event format, which can be opened in
+//! chrome://tracing or Perfetto.
+//!
+//! Tracing is disabled by default. While it's disabled, creating a span only
+//! checks a flag and doesn't allocate.
+
+use std::{
+    io::Write,
+    process,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+
+use crate::json;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+lazy_static! {
+    static ref START_TIME: Instant = Instant::now();
+    static ref EVENTS: Mutex<Vec<TraceEvent>> = Mutex::new(Vec::new());
+}
+
+thread_local! {
+    // Chrome traces need a numeric ID for each thread, which std doesn't
+    // expose on stable, so we hand them out ourselves.
+    static THREAD_ID: u64 = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A single event in the Chrome Trace Event format.
+#[derive(Debug, Clone, Serialize)]
+struct TraceEvent {
+    name: String,
+    cat: &'static str,
+    ph: &'static str,
+    ts: u64,
+    pid: u32,
+    tid: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TraceFile<'a> {
+    trace_events: &'a [TraceEvent],
+}
+
+/// Starts recording spans. Spans created before this is called are not
+/// recorded.
+pub fn enable() {
+    lazy_static::initialize(&START_TIME);
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Like `enable`, but recording stops and every recorded event is thrown away
+/// when the returned guard is dropped. Tests share a process, so this keeps
+/// one test that turns tracing on from affecting the others.
+pub fn enable_scoped() -> EnabledGuard {
+    enable();
+    EnabledGuard { _private: () }
+}
+
+/// Keeps tracing enabled until it's dropped. See `enable_scoped`.
+#[must_use = "tracing is disabled as soon as the guard is dropped"]
+pub struct EnabledGuard {
+    _private: (),
+}
+
+impl Drop for EnabledGuard {
+    fn drop(&mut self) {
+        ENABLED.store(false, Ordering::SeqCst);
+        clear();
+    }
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Begins a span in the given category. The span ends when the returned value
+/// is dropped.
+///
+/// The name is passed as a closure so that no work is done to build it when
+/// tracing is disabled.
+pub fn span<F>(category: &'static str, name: F) -> Span
+where
+    F: FnOnce() -> String,
+{
+    if !is_enabled() {
+        return Span { active: None };
+    }
+
+    let name = name();
+    record(category, "B", name.clone());
+
+    Span {
+        active: Some((category, name)),
+    }
+}
+
+/// Marks a region of time being traced. See `span`.
+#[must_use = "spans end as soon as they are dropped"]
+pub struct Span {
+    active: Option<(&'static str, String)>,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some((category, name)) = self.active.take() {
+            record(category, "E", name);
+        }
+    }
+}
+
+/// Writes all of the events recorded so far as a Chrome Trace Event JSON
+/// document.
+pub fn write_json<W: Write>(writer: W) -> serde_json::Result<()> {
+    let events = EVENTS.lock().unwrap();
+
+    json::to_writer_sorted(
+        writer,
+        &TraceFile {
+            trace_events: &events,
+        },
+    )
+}
+
+/// Throws away all of the events recorded so far, so that the next call to
+/// `write_json` only includes events that are recorded after this.
+pub fn clear() {
+    EVENTS.lock().unwrap().clear();
+}
+
+fn record(category: &'static str, phase: &'static str, name: String) {
+    let ts = START_TIME.elapsed().as_micros() as u64;
+
+    let event = TraceEvent {
+        name,
+        cat: category,
+        ph: phase,
+        ts,
+        pid: process::id(),
+        tid: THREAD_ID.with(|id| *id),
+    };
+
+    EVENTS.lock().unwrap().push(event);
+}
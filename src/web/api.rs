@@ -3,26 +3,40 @@
 
 use std::{collections::HashMap, fs, path::PathBuf, sync::Arc};
 
-use futures::{Future, Stream};
+use futures::{
+    future::{self, Loop},
+    Future,
+};
 
 use hyper::{service::Service, Body, Method, Request, StatusCode};
 use rbx_dom_weak::RbxId;
 
 use crate::{
     serve_session::ServeSession,
-    snapshot::{InstanceWithMeta, PatchSet, PatchUpdate},
+    snapshot::{
+        apply_attribute_changes, AppliedPatchSet, InstanceWithMeta, PatchSet, PatchUpdate,
+        RojoTree, ATTRIBUTES_PROPERTY,
+    },
     web::{
         interface::{
+            AffectedInstance, AffectedRequest, AffectedResponse, DriftAddition, DriftResponse,
             ErrorResponse, Instance, InstanceMetadata as WebInstanceMetadata, InstanceUpdate,
-            OpenResponse, ReadResponse, ServerInfoResponse, SubscribeMessage, SubscribeResponse,
-            WriteRequest, WriteResponse, PROTOCOL_VERSION, SERVER_VERSION,
+            OpenResponse, ReadResponse, RescanResponse, ServerInfoResponse, SubscribeMessage,
+            SubscribeResponse, WriteRequest, WriteResponse, PROTOCOL_VERSION, SERVER_VERSION,
         },
-        util::{json, json_ok},
+        util::{json, json_ok, read_body, ReadBodyError},
+        ws,
     },
 };
 
 pub struct ApiService {
     serve_session: Arc<ServeSession>,
+
+    /// The largest request body, in bytes, that endpoints will accept.
+    max_body_size: usize,
+
+    /// Whether changes sent to /api/write are rejected.
+    read_only: bool,
 }
 
 impl Service for ApiService {
@@ -39,11 +53,18 @@ impl Service for ApiService {
             (&Method::GET, path) if path.starts_with("/api/subscribe/") => {
                 self.handle_api_subscribe(request)
             }
+            (&Method::GET, path) if path.starts_with("/api/patches/") => {
+                self.handle_api_patches(request)
+            }
+            (&Method::GET, "/api/ws") => self.handle_api_ws(request),
             (&Method::POST, path) if path.starts_with("/api/open/") => {
                 self.handle_api_open(request)
             }
 
             (&Method::POST, "/api/write") => self.handle_api_write(request),
+            (&Method::POST, "/api/rescan") => self.handle_api_rescan(),
+            (&Method::GET, "/api/drift") => self.handle_api_drift(),
+            (&Method::POST, "/api/affected") => self.handle_api_affected(request),
 
             (_method, path) => json(
                 ErrorResponse::not_found(format!("Route not found: {}", path)),
@@ -54,8 +75,33 @@ impl Service for ApiService {
 }
 
 impl ApiService {
-    pub fn new(serve_session: Arc<ServeSession>) -> Self {
-        ApiService { serve_session }
+    pub fn new(serve_session: Arc<ServeSession>, max_body_size: usize, read_only: bool) -> Self {
+        ApiService {
+            serve_session,
+            max_body_size,
+            read_only,
+        }
+    }
+
+    /// The names of the API features this server supports, which are reported
+    /// to clients so that they can adapt to older or restricted servers.
+    fn capabilities(&self) -> Vec<String> {
+        let mut capabilities = vec![
+            "read",
+            "subscribe",
+            "patches",
+            "open",
+            "rescan",
+            "drift",
+            "affected",
+            "ws",
+        ];
+
+        if !self.read_only {
+            capabilities.push("write");
+        }
+
+        capabilities.into_iter().map(str::to_owned).collect()
     }
 
     /// Get a summary of information about the server
@@ -69,6 +115,7 @@ impl ApiService {
             session_id: self.serve_session.session_id(),
             expected_place_ids: self.serve_session.serve_place_ids().cloned(),
             root_instance_id,
+            capabilities: self.capabilities(),
         })
     }
 
@@ -88,7 +135,10 @@ impl ApiService {
 
         let session_id = self.serve_session.session_id();
 
-        let receiver = self.serve_session.message_queue().subscribe(input_cursor);
+        let receiver = match self.serve_session.message_queue().subscribe(input_cursor) {
+            Ok(receiver) => receiver,
+            Err(err) => return json(ErrorResponse::gone(err.to_string()), StatusCode::GONE),
+        };
 
         let tree_handle = self.serve_session.tree_handle();
 
@@ -96,47 +146,7 @@ impl ApiService {
             Ok((message_cursor, messages)) => {
                 let tree = tree_handle.lock().unwrap();
 
-                let api_messages = messages
-                    .into_iter()
-                    .map(|message| {
-                        let removed = message.removed;
-
-                        let mut added = HashMap::new();
-                        for id in message.added {
-                            let instance = tree.get_instance(id).unwrap();
-                            added.insert(id, Instance::from_rojo_instance(instance));
-
-                            for instance in tree.descendants(id) {
-                                added.insert(instance.id(), Instance::from_rojo_instance(instance));
-                            }
-                        }
-
-                        let updated = message
-                            .updated
-                            .into_iter()
-                            .map(|update| {
-                                let changed_metadata = update
-                                    .changed_metadata
-                                    .as_ref()
-                                    .map(WebInstanceMetadata::from_rojo_metadata);
-
-                                InstanceUpdate {
-                                    id: update.id,
-                                    changed_name: update.changed_name,
-                                    changed_class_name: update.changed_class_name,
-                                    changed_properties: update.changed_properties,
-                                    changed_metadata,
-                                }
-                            })
-                            .collect();
-
-                        SubscribeMessage {
-                            removed,
-                            added,
-                            updated,
-                        }
-                    })
-                    .collect();
+                let api_messages = subscribe_messages(&tree, messages);
 
                 json_ok(SubscribeResponse {
                     session_id,
@@ -151,11 +161,139 @@ impl ApiService {
         }))
     }
 
+    /// Retrieve every message past the given cursor index without waiting for
+    /// new ones, so that clients that were briefly disconnected can catch up
+    /// without reading the whole tree again.
+    ///
+    /// Rojo only keeps a limited number of messages. If the cursor is older
+    /// than all of them, this responds with 410 Gone and the client has to
+    /// read the tree again instead.
+    fn handle_api_patches(&self, request: Request<Body>) -> <Self as Service>::Future {
+        let argument = &request.uri().path()["/api/patches/".len()..];
+        let input_cursor: u32 = match argument.parse() {
+            Ok(v) => v,
+            Err(err) => {
+                return json(
+                    ErrorResponse::bad_request(format!("Malformed message cursor: {}", err)),
+                    StatusCode::BAD_REQUEST,
+                );
+            }
+        };
+
+        let (message_cursor, messages) = match self
+            .serve_session
+            .message_queue()
+            .messages_since(input_cursor)
+        {
+            Ok(result) => result,
+            Err(err) => return json(ErrorResponse::gone(err.to_string()), StatusCode::GONE),
+        };
+
+        let tree = self.serve_session.tree();
+
+        json_ok(SubscribeResponse {
+            session_id: self.serve_session.session_id(),
+            message_cursor,
+            messages: subscribe_messages(&tree, messages),
+        })
+    }
+
+    /// Upgrade to a WebSocket connection that pushes every message after the
+    /// given cursor as it happens, using the same format as /api/subscribe.
+    ///
+    /// Clients pass the cursor as `?cursor=N` to get any messages they missed
+    /// first. Without one, only new messages are sent.
+    fn handle_api_ws(&self, request: Request<Body>) -> <Self as Service>::Future {
+        let cursor = match request.uri().query().and_then(cursor_from_query) {
+            Some(Ok(cursor)) => cursor,
+            Some(Err(err)) => {
+                return json(
+                    ErrorResponse::bad_request(format!("Malformed message cursor: {}", err)),
+                    StatusCode::BAD_REQUEST,
+                );
+            }
+            None => self.serve_session.message_queue().cursor(),
+        };
+
+        if let Err(err) = self.serve_session.message_queue().messages_since(cursor) {
+            return json(ErrorResponse::gone(err.to_string()), StatusCode::GONE);
+        }
+
+        let response = match ws::upgrade_response(&request) {
+            Some(response) => response,
+            None => {
+                return json(
+                    ErrorResponse::bad_request("Expected a WebSocket upgrade request"),
+                    StatusCode::BAD_REQUEST,
+                );
+            }
+        };
+
+        let serve_session = Arc::clone(&self.serve_session);
+
+        let connection = request
+            .into_body()
+            .on_upgrade()
+            .map_err(|err| log::debug!("WebSocket upgrade failed: {}", err))
+            .and_then(move |upgraded| {
+                future::loop_fn((upgraded, cursor), move |(upgraded, cursor)| {
+                    let serve_session = Arc::clone(&serve_session);
+                    let receiver = future::result(serve_session.message_queue().subscribe(cursor))
+                        .map_err(|err| log::debug!("Closing WebSocket: {}", err))
+                        .and_then(|receiver| {
+                            receiver.map_err(|_| log::debug!("Message queue disconnected sender"))
+                        });
+
+                    receiver.and_then(move |(message_cursor, messages)| {
+                        let frame = {
+                            let tree = serve_session.tree();
+
+                            let response = SubscribeResponse {
+                                session_id: serve_session.session_id(),
+                                message_cursor,
+                                messages: subscribe_messages(&tree, messages),
+                            };
+
+                            ws::text_frame(&serde_json::to_string(&response).unwrap())
+                        };
+
+                        tokio::io::write_all(upgraded, frame)
+                            .map_err(|err| log::debug!("WebSocket client went away: {}", err))
+                            .map(move |(upgraded, _)| {
+                                Loop::<(), _>::Continue((upgraded, message_cursor))
+                            })
+                    })
+                })
+            });
+
+        hyper::rt::spawn(connection);
+
+        Box::new(future::ok(response))
+    }
+
     fn handle_api_write(&self, request: Request<Body>) -> <Self as Service>::Future {
+        if self.read_only {
+            return json(
+                ErrorResponse::forbidden("This server is read-only"),
+                StatusCode::FORBIDDEN,
+            );
+        }
+
         let session_id = self.serve_session.session_id();
         let tree_mutation_sender = self.serve_session.tree_mutation_sender();
 
-        Box::new(request.into_body().concat2().and_then(move |body| {
+        Box::new(read_body(request, self.max_body_size).then(move |body| {
+            let body = match body {
+                Ok(body) => body,
+                Err(ReadBodyError::TooLarge) => {
+                    return json(
+                        ErrorResponse::payload_too_large("Request body is too large"),
+                        StatusCode::PAYLOAD_TOO_LARGE,
+                    );
+                }
+                Err(ReadBodyError::Hyper(err)) => return Box::new(future::err(err)),
+            };
+
             let request: WriteRequest = match serde_json::from_slice(&body) {
                 Ok(request) => request,
                 Err(err) => {
@@ -181,6 +319,8 @@ impl ApiService {
                     changed_class_name: update.changed_class_name,
                     changed_name: update.changed_name,
                     changed_properties: update.changed_properties,
+                    changed_attributes: Default::default(),
+                    changed_ref_paths: Default::default(),
                     changed_metadata: None,
                 })
                 .collect();
@@ -197,6 +337,103 @@ impl ApiService {
         }))
     }
 
+    /// Snapshot the project from the filesystem again, patching the tree to
+    /// match it.
+    fn handle_api_rescan(&self) -> <Self as Service>::Future {
+        if let Err(err) = self.serve_session.rescan() {
+            return json(
+                ErrorResponse::internal_error(format!("Could not rescan project: {}", err)),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+
+        json_ok(&RescanResponse {
+            session_id: self.serve_session.session_id(),
+        })
+    }
+
+    /// Compare the tree to the project on the filesystem without changing the
+    /// tree, listing everything that a rescan would change.
+    fn handle_api_drift(&self) -> <Self as Service>::Future {
+        let patch_set = match self.serve_session.drift() {
+            Ok(patch_set) => patch_set,
+            Err(err) => {
+                return json(
+                    ErrorResponse::internal_error(format!("Could not snapshot project: {}", err)),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                );
+            }
+        };
+
+        let tree = self.serve_session.tree();
+
+        let added = patch_set
+            .added_instances
+            .into_iter()
+            .map(|add| DriftAddition {
+                parent_id: add.parent_id,
+                name: add.instance.name.into_owned(),
+                class_name: add.instance.class_name.into_owned(),
+            })
+            .collect();
+
+        let updated = patch_set
+            .updated_instances
+            .into_iter()
+            .map(|update| drift_update(&tree, update))
+            .collect();
+
+        json_ok(&DriftResponse {
+            session_id: self.serve_session.session_id(),
+            removed: patch_set.removed_instances,
+            added,
+            updated,
+        })
+    }
+
+    /// Lists the instances that a change to the given file would update, so
+    /// that editors can show them before the file is saved.
+    fn handle_api_affected(&self, request: Request<Body>) -> <Self as Service>::Future {
+        let serve_session = Arc::clone(&self.serve_session);
+
+        Box::new(read_body(request, self.max_body_size).then(move |body| {
+            let body = match body {
+                Ok(body) => body,
+                Err(ReadBodyError::TooLarge) => {
+                    return json(
+                        ErrorResponse::payload_too_large("Request body is too large"),
+                        StatusCode::PAYLOAD_TOO_LARGE,
+                    );
+                }
+                Err(ReadBodyError::Hyper(err)) => return Box::new(future::err(err)),
+            };
+
+            let request: AffectedRequest = match serde_json::from_slice(&body) {
+                Ok(request) => request,
+                Err(err) => {
+                    return json(
+                        ErrorResponse::bad_request(format!("Invalid body: {}", err)),
+                        StatusCode::BAD_REQUEST,
+                    );
+                }
+            };
+
+            let instances = serve_session
+                .instances_affected_by(&request.path)
+                .into_iter()
+                .map(|instance_path| AffectedInstance {
+                    id: instance_path.id,
+                    path: instance_path.to_string(),
+                })
+                .collect();
+
+            json_ok(&AffectedResponse {
+                session_id: serve_session.session_id(),
+                instances,
+            })
+        }))
+    }
+
     fn handle_api_read(&self, request: Request<Body>) -> <Self as Service>::Future {
         let argument = &request.uri().path()["/api/read/".len()..];
         let requested_ids: Option<Vec<RbxId>> = argument.split(',').map(RbxId::parse_str).collect();
@@ -288,8 +525,8 @@ fn pick_script_path(instance: InstanceWithMeta<'_>) -> Option<PathBuf> {
         _ => return None,
     }
 
-    // Pick the first listed relevant path that has an extension of .lua that
-    // exists.
+    // Pick the first listed relevant path that has an extension of .lua or
+    // .luau that exists.
     instance
         .metadata()
         .relevant_paths
@@ -297,7 +534,7 @@ fn pick_script_path(instance: InstanceWithMeta<'_>) -> Option<PathBuf> {
         .find(|path| {
             // We should only ever open Lua files to be safe.
             match path.extension().and_then(|ext| ext.to_str()) {
-                Some("lua") => {}
+                Some("lua") | Some("luau") => {}
                 _ => return false,
             }
 
@@ -307,3 +544,95 @@ fn pick_script_path(instance: InstanceWithMeta<'_>) -> Option<PathBuf> {
         })
         .map(|path| path.to_owned())
 }
+
+/// Converts messages from the message queue into the form sent by
+/// /api/subscribe, looking up added instances in the tree.
+fn subscribe_messages(
+    tree: &RojoTree,
+    messages: Vec<AppliedPatchSet>,
+) -> Vec<SubscribeMessage<'_>> {
+    messages
+        .into_iter()
+        .map(|message| {
+            let removed = message.removed;
+
+            let mut added = HashMap::new();
+            for id in message.added {
+                // Clients catching up on several messages at once can get
+                // messages that add instances that a later message removed.
+                let instance = match tree.get_instance(id) {
+                    Some(instance) => instance,
+                    None => continue,
+                };
+                added.insert(id, Instance::from_rojo_instance(instance));
+
+                for instance in tree.descendants(id) {
+                    added.insert(instance.id(), Instance::from_rojo_instance(instance));
+                }
+            }
+
+            let updated = message
+                .updated
+                .into_iter()
+                .map(|update| {
+                    let changed_metadata = update
+                        .changed_metadata
+                        .as_ref()
+                        .map(WebInstanceMetadata::from_rojo_metadata);
+
+                    InstanceUpdate {
+                        id: update.id,
+                        changed_name: update.changed_name,
+                        changed_class_name: update.changed_class_name,
+                        changed_properties: update.changed_properties,
+                        changed_metadata,
+                    }
+                })
+                .collect();
+
+            SubscribeMessage {
+                removed,
+                added,
+                updated,
+                warnings: message.warnings,
+            }
+        })
+        .collect()
+}
+
+/// Converts an update from a drift patch into the form clients already know.
+/// Attribute changes are folded into the property they're stored in, since
+/// that's all that clients see.
+fn drift_update(tree: &RojoTree, update: PatchUpdate) -> InstanceUpdate {
+    let mut changed_properties = update.changed_properties;
+
+    if !update.changed_attributes.is_empty() {
+        let current = tree
+            .get_instance(update.id)
+            .and_then(|instance| instance.properties().get(ATTRIBUTES_PROPERTY));
+
+        changed_properties.insert(
+            ATTRIBUTES_PROPERTY.to_owned(),
+            Some(apply_attribute_changes(current, update.changed_attributes)),
+        );
+    }
+
+    InstanceUpdate {
+        id: update.id,
+        changed_name: update.changed_name,
+        changed_class_name: update.changed_class_name,
+        changed_properties,
+        changed_metadata: update
+            .changed_metadata
+            .as_ref()
+            .map(WebInstanceMetadata::from_rojo_metadata),
+    }
+}
+
+/// Finds the `cursor` parameter in a query string, if there is one.
+fn cursor_from_query(query: &str) -> Option<Result<u32, std::num::ParseIntError>> {
+    query
+        .split('&')
+        .find(|pair| pair.starts_with("cursor="))
+        .map(|pair| pair["cursor=".len()..].parse())
+}
@@ -3,6 +3,7 @@
 use std::{
     borrow::Cow,
     collections::{HashMap, HashSet},
+    path::PathBuf,
 };
 
 use rbx_dom_weak::{RbxId, RbxValue};
@@ -10,7 +11,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     session_id::SessionId,
-    snapshot::{InstanceMetadata as RojoInstanceMetadata, InstanceWithMeta},
+    snapshot::{InstanceMetadata as RojoInstanceMetadata, InstanceWithMeta, PatchApplyWarning},
 };
 
 /// Server version to report over the API, not exposed outside this crate.
@@ -26,6 +27,11 @@ pub struct SubscribeMessage<'a> {
     pub removed: Vec<RbxId>,
     pub added: HashMap<RbxId, Instance<'a>>,
     pub updated: Vec<InstanceUpdate>,
+
+    /// Parts of the change that couldn't be applied to Rojo's tree. Clients
+    /// that see any should request the whole tree again.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<PatchApplyWarning>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -91,6 +97,10 @@ pub struct ServerInfoResponse {
     pub protocol_version: u64,
     pub expected_place_ids: Option<HashSet<u64>>,
     pub root_instance_id: RbxId,
+
+    /// The API features this server has enabled, like `write`. Plugins should
+    /// check this instead of the server version before using a feature.
+    pub capabilities: Vec<String>,
 }
 
 /// Response body from /api/read/{id}
@@ -119,7 +129,66 @@ pub struct WriteResponse {
     pub session_id: SessionId,
 }
 
-/// Response body from /api/subscribe/{cursor}
+/// Response body from /api/rescan
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RescanResponse {
+    pub session_id: SessionId,
+}
+
+/// Response body from /api/drift
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DriftResponse {
+    pub session_id: SessionId,
+
+    /// Instances in the tree that the filesystem doesn't have.
+    pub removed: Vec<RbxId>,
+
+    /// Instances that the filesystem has, but the tree doesn't.
+    pub added: Vec<DriftAddition>,
+
+    /// Differences between instances in the tree and the filesystem, given as
+    /// the values from the filesystem.
+    pub updated: Vec<InstanceUpdate>,
+}
+
+/// An instance that's on the filesystem, but missing from the tree. Its
+/// descendants are missing too, but aren't listed.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DriftAddition {
+    pub parent_id: RbxId,
+    pub name: String,
+    pub class_name: String,
+}
+
+/// Request body for /api/affected
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AffectedRequest {
+    pub path: PathBuf,
+}
+
+/// Response body from /api/affected
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AffectedResponse {
+    pub session_id: SessionId,
+    pub instances: Vec<AffectedInstance>,
+}
+
+/// An instance that would be updated by a change to a file.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AffectedInstance {
+    pub id: RbxId,
+
+    /// The names of the instance and its ancestors, separated by slashes.
+    pub path: String,
+}
+
+/// Response body from /api/subscribe/{cursor} and /api/patches/{cursor}
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SubscribeResponse<'a> {
@@ -158,6 +227,27 @@ impl ErrorResponse {
         }
     }
 
+    pub fn payload_too_large<S: Into<String>>(details: S) -> Self {
+        Self {
+            kind: ErrorResponseKind::PayloadTooLarge,
+            details: details.into(),
+        }
+    }
+
+    pub fn forbidden<S: Into<String>>(details: S) -> Self {
+        Self {
+            kind: ErrorResponseKind::Forbidden,
+            details: details.into(),
+        }
+    }
+
+    pub fn gone<S: Into<String>>(details: S) -> Self {
+        Self {
+            kind: ErrorResponseKind::Gone,
+            details: details.into(),
+        }
+    }
+
     pub fn internal_error<S: Into<String>>(details: S) -> Self {
         Self {
             kind: ErrorResponseKind::InternalError,
@@ -170,5 +260,8 @@ impl ErrorResponse {
 pub enum ErrorResponseKind {
     NotFound,
     BadRequest,
+    PayloadTooLarge,
+    Forbidden,
+    Gone,
     InternalError,
 }
@@ -3,6 +3,7 @@ mod assets;
 pub mod interface;
 mod ui;
 mod util;
+mod ws;
 
 use std::sync::Arc;
 
@@ -10,16 +11,18 @@ use futures::{
     future::{self, FutureResult},
     Future,
 };
-use hyper::{service::Service, Body, Request, Response, Server};
+use hyper::{service::Service, Body, Request, Response, Server, StatusCode};
 use log::trace;
 
 use crate::serve_session::ServeSession;
 
-use self::{api::ApiService, ui::UiService};
+use self::{api::ApiService, interface::ErrorResponse, ui::UiService, util::json};
 
 pub struct RootService {
     api: ApiService,
-    ui: UiService,
+
+    /// The browser UI, which is only served if it was turned on.
+    ui: Option<UiService>,
 }
 
 impl Service for RootService {
@@ -32,29 +35,78 @@ impl Service for RootService {
         trace!("{} {}", request.method(), request.uri().path());
 
         if request.uri().path().starts_with("/api") {
-            self.api.call(request)
-        } else {
-            self.ui.call(request)
+            return self.api.call(request);
+        }
+
+        match &mut self.ui {
+            Some(ui) => ui.call(request),
+            None => json(
+                ErrorResponse::not_found(format!("Route not found: {}", request.uri().path())),
+                StatusCode::NOT_FOUND,
+            ),
         }
     }
 }
 
 impl RootService {
-    pub fn new(serve_session: Arc<ServeSession>) -> Self {
+    pub fn new(
+        serve_session: Arc<ServeSession>,
+        max_body_size: usize,
+        read_only: bool,
+        web_ui: bool,
+    ) -> Self {
+        let ui = if web_ui {
+            Some(UiService::new(Arc::clone(&serve_session)))
+        } else {
+            None
+        };
+
         RootService {
-            api: ApiService::new(Arc::clone(&serve_session)),
-            ui: UiService::new(Arc::clone(&serve_session)),
+            api: ApiService::new(Arc::clone(&serve_session), max_body_size, read_only),
+            ui,
         }
     }
 }
 
+/// The largest request body, in bytes, that the server will accept by default.
+pub const DEFAULT_MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
+
 pub struct LiveServer {
     serve_session: Arc<ServeSession>,
+    max_body_size: usize,
+    read_only: bool,
+    web_ui: bool,
 }
 
 impl LiveServer {
     pub fn new(serve_session: Arc<ServeSession>) -> Self {
-        LiveServer { serve_session }
+        LiveServer {
+            serve_session,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            read_only: false,
+            web_ui: false,
+        }
+    }
+
+    /// Sets the largest request body, in bytes, that the server will accept.
+    /// Larger requests are rejected with 413 Payload Too Large.
+    pub fn max_body_size(self, max_body_size: usize) -> Self {
+        LiveServer {
+            max_body_size,
+            ..self
+        }
+    }
+
+    /// Sets whether the server rejects changes sent by clients. Read-only
+    /// servers don't report the `write` capability.
+    pub fn read_only(self, read_only: bool) -> Self {
+        LiveServer { read_only, ..self }
+    }
+
+    /// Sets whether the server hosts a page at `/` for browsing the instance
+    /// tree. When it doesn't, everything outside of `/api` returns 404.
+    pub fn web_ui(self, web_ui: bool) -> Self {
+        LiveServer { web_ui, ..self }
     }
 
     pub fn start(self, port: u16) {
@@ -62,8 +114,12 @@ impl LiveServer {
 
         let server = Server::bind(&address)
             .serve(move || {
-                let service: FutureResult<_, hyper::Error> =
-                    future::ok(RootService::new(Arc::clone(&self.serve_session)));
+                let service: FutureResult<_, hyper::Error> = future::ok(RootService::new(
+                    Arc::clone(&self.serve_session),
+                    self.max_body_size,
+                    self.read_only,
+                    self.web_ui,
+                ));
                 service
             })
             .map_err(|e| eprintln!("Server error: {}", e));
@@ -1,5 +1,8 @@
-use futures::{future, Future};
-use hyper::{header::CONTENT_TYPE, Body, Response, StatusCode};
+use futures::{future, Future, Stream};
+use hyper::{
+    header::{CONTENT_LENGTH, CONTENT_TYPE},
+    Body, Request, Response, StatusCode,
+};
 use serde::Serialize;
 
 fn response_json<T: Serialize>(value: T, code: StatusCode) -> Response<Body> {
@@ -33,3 +36,51 @@ pub fn json_ok<T: Serialize>(
 ) -> Box<dyn Future<Item = hyper::Response<hyper::Body>, Error = hyper::Error> + Send> {
     json(value, StatusCode::OK)
 }
+
+/// The ways that reading a request body with `read_body` can fail.
+#[derive(Debug)]
+pub enum ReadBodyError {
+    /// The body was larger than the allowed size.
+    TooLarge,
+
+    Hyper(hyper::Error),
+}
+
+impl From<hyper::Error> for ReadBodyError {
+    fn from(error: hyper::Error) -> Self {
+        ReadBodyError::Hyper(error)
+    }
+}
+
+/// Reads the entire body of the given request into memory, stopping early if
+/// it turns out to be larger than `max_size` bytes.
+pub fn read_body(
+    request: Request<Body>,
+    max_size: usize,
+) -> Box<dyn Future<Item = Vec<u8>, Error = ReadBodyError> + Send> {
+    let declared_length = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+
+    if let Some(length) = declared_length {
+        if length > max_size {
+            return Box::new(future::err(ReadBodyError::TooLarge));
+        }
+    }
+
+    Box::new(
+        request
+            .into_body()
+            .from_err()
+            .fold(Vec::new(), move |mut body, chunk| {
+                if body.len() + chunk.len() > max_size {
+                    return Err(ReadBodyError::TooLarge);
+                }
+
+                body.extend_from_slice(&chunk);
+                Ok(body)
+            }),
+    )
+}
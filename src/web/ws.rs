@@ -0,0 +1,104 @@
+//! Just enough of the WebSocket protocol for Rojo to push messages to clients.
+//!
+//! Rojo only ever sends unfragmented text frames. Anything that clients send
+//! is never read, so connections are only noticed to be closed when a write to
+//! them fails.
+
+use hyper::{
+    header::{CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, UPGRADE},
+    Body, Request, Response, StatusCode,
+};
+use sha1::{Digest, Sha1};
+
+/// Appended to the key that a client sends when computing the accept key, as
+/// described in RFC 6455.
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Computes the value of the Sec-WebSocket-Accept header for the given
+/// Sec-WebSocket-Key.
+fn accept_key(key: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.input(key);
+    hasher.input(ACCEPT_GUID.as_bytes());
+
+    base64::encode(&hasher.result())
+}
+
+/// Creates the response that accepts a WebSocket upgrade request, or returns
+/// `None` if the request isn't a WebSocket upgrade.
+pub fn upgrade_response(request: &Request<Body>) -> Option<Response<Body>> {
+    let headers = request.headers();
+
+    let is_upgrade = headers
+        .get(UPGRADE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.eq_ignore_ascii_case("websocket"))
+        .unwrap_or(false);
+
+    if !is_upgrade {
+        return None;
+    }
+
+    let key = headers.get(SEC_WEBSOCKET_KEY)?;
+
+    let response = Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(UPGRADE, "websocket")
+        .header(CONNECTION, "Upgrade")
+        .header(SEC_WEBSOCKET_ACCEPT, accept_key(key.as_bytes()))
+        .body(Body::empty())
+        .unwrap();
+
+    Some(response)
+}
+
+/// Encodes a text message as a single frame. Frames sent by servers aren't
+/// masked.
+pub fn text_frame(text: &str) -> Vec<u8> {
+    let payload = text.as_bytes();
+
+    // FIN bit set, text opcode.
+    let mut frame = vec![0x81];
+
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+
+    frame.extend_from_slice(payload);
+    frame
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn accept_key_matches_rfc() {
+        // This example comes from section 1.3 of RFC 6455.
+        assert_eq!(
+            accept_key(b"dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn frame_lengths() {
+        assert_eq!(text_frame("hi"), vec![0x81, 2, b'h', b'i']);
+
+        let medium = "a".repeat(300);
+        assert_eq!(&text_frame(&medium)[..4], &[0x81, 126, 1, 44]);
+
+        let large = "a".repeat(70_000);
+        let frame = text_frame(&large);
+        assert_eq!(&frame[..2], &[0x81, 127]);
+        assert_eq!(&frame[2..10], &70_000u64.to_be_bytes());
+    }
+}
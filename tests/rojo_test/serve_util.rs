@@ -1,5 +1,7 @@
 use std::{
     fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
     path::{Path, PathBuf},
     process::Command,
     sync::atomic::{AtomicUsize, Ordering},
@@ -26,11 +28,20 @@ use crate::rojo_test::io_util::{
 /// The passed in callback is where the actual test body should go. Setup and
 /// cleanup happens automatically.
 pub fn run_serve_test(test_name: &str, callback: impl FnOnce(TestServeSession, RedactionMap)) {
+    run_serve_test_with_args(test_name, &[], callback)
+}
+
+/// Like `run_serve_test`, but passes extra arguments to `rojo serve`.
+pub fn run_serve_test_with_args(
+    test_name: &str,
+    extra_args: &[&str],
+    callback: impl FnOnce(TestServeSession, RedactionMap),
+) {
     let _ = env_logger::try_init();
 
     let mut redactions = RedactionMap::new();
 
-    let mut session = TestServeSession::new(test_name);
+    let mut session = TestServeSession::new(test_name, extra_args);
     let info = session.wait_to_come_online();
 
     redactions.intern(info.session_id);
@@ -61,7 +72,7 @@ pub struct TestServeSession {
 }
 
 impl TestServeSession {
-    pub fn new(name: &str) -> Self {
+    pub fn new(name: &str, extra_args: &[&str]) -> Self {
         let working_dir = get_working_dir_path();
 
         let source_path = Path::new(SERVE_TESTS_PATH).join(name);
@@ -89,6 +100,7 @@ impl TestServeSession {
                 "--port",
                 port_string.as_str(),
             ])
+            .args(extra_args)
             .current_dir(working_dir)
             .spawn()
             .expect("Couldn't start Rojo");
@@ -139,6 +151,11 @@ impl TestServeSession {
         panic!("Rojo server did not respond after {} tries.", MAX_TRIES);
     }
 
+    pub fn get_root(&self) -> Result<reqwest::Response, reqwest::Error> {
+        let url = format!("http://localhost:{}/", self.port);
+        reqwest::get(&url)
+    }
+
     pub fn get_api_rojo(&self) -> Result<ServerInfoResponse, reqwest::Error> {
         let url = format!("http://localhost:{}/api/rojo", self.port);
         let body = reqwest::get(&url)?.text()?;
@@ -161,6 +178,99 @@ impl TestServeSession {
 
         reqwest::get(&url)?.json()
     }
+
+    /// Connects to /api/ws, asking for messages after the given cursor.
+    pub fn connect_api_ws(&self, cursor: u32) -> io::Result<WebSocketClient> {
+        let mut stream = TcpStream::connect(("localhost", self.port as u16))?;
+        stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+
+        write!(
+            stream,
+            "GET /api/ws?cursor={} HTTP/1.1\r\n\
+             Host: localhost\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+             Sec-WebSocket-Version: 13\r\n\
+             \r\n",
+            cursor
+        )?;
+
+        let mut reader = BufReader::new(stream);
+
+        let mut status_line = String::new();
+        reader.read_line(&mut status_line)?;
+        assert_eq!(status_line.trim(), "HTTP/1.1 101 Switching Protocols");
+
+        // Skip the rest of the response headers.
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+
+            if line.trim().is_empty() {
+                break;
+            }
+        }
+
+        Ok(WebSocketClient { reader })
+    }
+
+    /// Starts a POST to /api/write that claims to have a body of the given
+    /// length, then returns the status line that the server responds with.
+    ///
+    /// Only a small part of the body is actually sent, which lets us check
+    /// that the server rejects large bodies without reading them.
+    pub fn post_api_write_with_length(&self, content_length: usize) -> io::Result<String> {
+        let mut stream = TcpStream::connect(("localhost", self.port as u16))?;
+
+        write!(
+            stream,
+            "POST /api/write HTTP/1.1\r\n\
+             Host: localhost\r\n\
+             Content-Length: {}\r\n\
+             \r\n\
+             {{",
+            content_length
+        )?;
+
+        let mut status_line = String::new();
+        BufReader::new(stream).read_line(&mut status_line)?;
+
+        Ok(status_line.trim().to_owned())
+    }
+}
+
+/// A bare-bones WebSocket client that can only receive the unmasked text
+/// frames that /api/ws sends.
+pub struct WebSocketClient {
+    reader: BufReader<TcpStream>,
+}
+
+impl WebSocketClient {
+    pub fn receive(&mut self) -> io::Result<SubscribeResponse<'static>> {
+        let mut header = [0; 2];
+        self.reader.read_exact(&mut header)?;
+        assert_eq!(header[0], 0x81, "Expected a text frame");
+
+        let len = match header[1] {
+            126 => {
+                let mut len = [0; 2];
+                self.reader.read_exact(&mut len)?;
+                u16::from_be_bytes(len) as usize
+            }
+            127 => {
+                let mut len = [0; 8];
+                self.reader.read_exact(&mut len)?;
+                u64::from_be_bytes(len) as usize
+            }
+            len => len as usize,
+        };
+
+        let mut payload = vec![0; len];
+        self.reader.read_exact(&mut payload)?;
+
+        Ok(serde_json::from_slice(&payload).expect("Server sent a malformed message"))
+    }
 }
 
 /// Probably-okay way to generate random enough port numbers for running the
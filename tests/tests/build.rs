@@ -1,4 +1,8 @@
-use std::{fs, path::Path, process::Command};
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Output},
+};
 
 use insta::assert_snapshot;
 use tempfile::tempdir;
@@ -21,6 +25,7 @@ macro_rules! gen_build_tests {
 }
 
 gen_build_tests! {
+    anim_json,
     client_in_folder,
     client_init,
     csv_bug_145,
@@ -88,3 +93,53 @@ fn run_build_test(test_name: &str) {
         assert_snapshot!(test_name, contents);
     });
 }
+
+fn run_rojo(args: &[&str]) -> Output {
+    Command::new(ROJO_PATH)
+        .args(args)
+        .env_remove("RUST_LOG")
+        .current_dir(get_working_dir_path())
+        .output()
+        .expect("Couldn't start Rojo")
+}
+
+#[test]
+fn quiet_build_only_reports_errors() {
+    let input_path = Path::new(BUILD_TESTS_PATH).join("txt");
+    let output_dir = tempdir().expect("couldn't create temporary directory");
+    let output_path = output_dir.path().join("txt.rbxmx");
+
+    let build_args = [
+        "build",
+        input_path.to_str().unwrap(),
+        "-o",
+        output_path.to_str().unwrap(),
+    ];
+
+    let loud = run_rojo(&build_args);
+    assert!(loud.status.success(), "Rojo did not exit successfully");
+    assert!(String::from_utf8_lossy(&loud.stderr).contains("Built project"));
+
+    let mut quiet_args = vec!["--quiet"];
+    quiet_args.extend_from_slice(&build_args);
+
+    let quiet = run_rojo(&quiet_args);
+    assert!(quiet.status.success(), "Rojo did not exit successfully");
+    assert!(quiet.stdout.is_empty());
+    assert!(quiet.stderr.is_empty());
+
+    let missing_path = output_dir.path().join("missing");
+    let failed = run_rojo(&[
+        "--quiet",
+        "build",
+        missing_path.to_str().unwrap(),
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+    assert!(!failed.status.success());
+    assert!(String::from_utf8_lossy(&failed.stderr).contains("ERROR"));
+
+    quiet_args.push("--verbose");
+    let contradictory = run_rojo(&quiet_args);
+    assert!(!contradictory.status.success());
+}
@@ -1,9 +1,13 @@
 use std::fs;
 
 use insta::assert_yaml_snapshot;
+use rbx_dom_weak::RbxValue;
 use tempfile::tempdir;
 
-use crate::rojo_test::{internable::InternAndRedact, serve_util::run_serve_test};
+use crate::rojo_test::{
+    internable::InternAndRedact,
+    serve_util::{run_serve_test, run_serve_test_with_args},
+};
 
 #[test]
 fn empty() {
@@ -21,6 +25,74 @@ fn empty() {
     });
 }
 
+#[test]
+fn write_body_too_large() {
+    run_serve_test("empty", |session, _redactions| {
+        let status_line = session.post_api_write_with_length(5 * 1024 * 1024).unwrap();
+
+        assert_eq!(status_line, "HTTP/1.1 413 Payload Too Large");
+    });
+}
+
+#[test]
+fn read_only_capabilities() {
+    run_serve_test("empty", |session, _redactions| {
+        let info = session.get_api_rojo().unwrap();
+        assert!(info.capabilities.iter().any(|name| name == "write"));
+    });
+
+    run_serve_test_with_args("empty", &["--read-only"], |session, _redactions| {
+        let info = session.get_api_rojo().unwrap();
+        assert!(info.capabilities.iter().any(|name| name == "read"));
+        assert!(!info.capabilities.iter().any(|name| name == "write"));
+
+        let status_line = session.post_api_write_with_length(1).unwrap();
+        assert_eq!(status_line, "HTTP/1.1 403 Forbidden");
+    });
+}
+
+#[test]
+fn web_ui() {
+    run_serve_test("empty", |session, _redactions| {
+        let response = session.get_root().unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    });
+
+    run_serve_test_with_args("empty", &["--web-ui"], |session, _redactions| {
+        let mut response = session.get_root().unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/html");
+        assert!(response.text().unwrap().starts_with("<!DOCTYPE html>"));
+    });
+}
+
+#[test]
+fn websocket_pushes_patches() {
+    run_serve_test("scripts", |session, _redactions| {
+        let mut client = session.connect_api_ws(0).unwrap();
+
+        fs::write(session.path().join("src/foo.lua"), "Updated foo!").unwrap();
+
+        let response = client.receive().unwrap();
+        assert_eq!(response.message_cursor, 1);
+        assert_eq!(response.messages.len(), 1);
+
+        let update = &response.messages[0].updated[0];
+        assert_eq!(
+            update.changed_properties["Source"],
+            Some(RbxValue::String {
+                value: "Updated foo!".to_owned()
+            })
+        );
+
+        // Clients that reconnect with an old cursor get what they missed.
+        let mut reconnected = session.connect_api_ws(0).unwrap();
+        let backfill = reconnected.receive().unwrap();
+        assert_eq!(backfill.message_cursor, 1);
+        assert_eq!(backfill.messages.len(), 1);
+    });
+}
+
 #[test]
 fn scripts() {
     run_serve_test("scripts", |session, mut redactions| {